@@ -1023,6 +1023,18 @@ fn new_cgroup(h: Box<dyn cgroups::Hierarchy>, path: &str) -> Result<Cgroup> {
 
 impl Manager {
     pub fn new(cpath: &str) -> Result<Self> {
+        let cg = new_cgroup(cgroups::hierarchies::auto(), cpath)?;
+        Self::with_cgroup(cpath, cg)
+    }
+
+    /// Create a manager for the cgroup without creating it. It's used by rootless containers,
+    /// which have no permission to create the cgroup if the cgroup subtree is not delegated.
+    pub fn new_rootless(cpath: &str) -> Result<Self> {
+        let cg = cgroups::Cgroup::load(cgroups::hierarchies::auto(), cpath.trim_start_matches('/'));
+        Self::with_cgroup(cpath, cg)
+    }
+
+    fn with_cgroup(cpath: &str, cg: Cgroup) -> Result<Self> {
         let mut m = HashMap::new();
 
         let paths = get_paths()?;
@@ -1040,8 +1052,6 @@ impl Manager {
             m.insert(key.to_string(), p);
         }
 
-        let cg = new_cgroup(cgroups::hierarchies::auto(), cpath)?;
-
         Ok(Self {
             paths: m,
             mounts,
@@ -80,4 +80,8 @@ impl Manager {
             cpath: cpath.to_string(),
         })
     }
+
+    pub fn new_rootless(cpath: &str) -> Result<Self> {
+        Self::new(cpath)
+    }
 }
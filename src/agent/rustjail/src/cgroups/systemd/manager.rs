@@ -17,11 +17,11 @@ use std::vec;
 
 use super::super::fs::Manager as FsManager;
 
-use super::cgroups_path::CgroupsPath;
-use super::common::{CgroupHierarchy, Properties};
-use super::dbus_client::{DBusClient, SystemdInterface};
-use super::subsystem::transformer::Transformer;
-use super::subsystem::{cpu::Cpu, cpuset::CpuSet, memory::Memory, pids::Pids};
+use kata_sys_util::systemd::subsystem::transformer::Transformer;
+use kata_sys_util::systemd::subsystem::{cpu::Cpu, cpuset::CpuSet, memory::Memory, pids::Pids};
+use kata_sys_util::systemd::{
+    CgroupHierarchy, CgroupsPath, DBusClient, Properties, SystemdInterface,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Manager {
@@ -48,6 +48,7 @@ impl CgroupManager for Manager {
                 (pid as u32).try_into().unwrap(),
                 self.cgroups_path.slice.as_str(),
                 self.unit_name.as_str(),
+                "kata-agent container",
                 &self.cg_hierarchy,
             )?;
         }
@@ -4,9 +4,3 @@
 //
 
 pub mod manager;
-
-mod cgroups_path;
-mod common;
-mod dbus_client;
-mod interface;
-mod subsystem;
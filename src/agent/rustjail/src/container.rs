@@ -1057,6 +1057,8 @@ impl BaseContainer for LinuxContainer {
             &p,
             self.cgroup_manager.as_ref(),
             self.config.use_systemd_cgroup,
+            self.config.rootless_euid,
+            self.config.rootless_cgroup,
             &st,
             &mut pipe_w,
             &mut pipe_r,
@@ -1325,6 +1327,8 @@ async fn join_namespaces(
     p: &Process,
     cm: &(dyn Manager + Send + Sync),
     use_systemd_cgroup: bool,
+    rootless_euid: bool,
+    rootless_cgroup: bool,
     st: &OCIState,
     pipe_w: &mut PipeStream,
     pipe_r: &mut PipeStream,
@@ -1370,30 +1374,37 @@ async fn join_namespaces(
 
     if userns {
         info!(logger, "setup uid/gid mappings");
-        // setup uid/gid mappings
-        write_mappings(
-            &logger,
-            &format!("/proc/{}/uid_map", p.pid),
-            &linux.uid_mappings,
-        )?;
-        write_mappings(
-            &logger,
-            &format!("/proc/{}/gid_map", p.pid),
-            &linux.gid_mappings,
-        )?;
+        setup_id_mappings(&logger, p.pid, linux, rootless_euid)?;
     }
 
     // apply cgroups
     // For FsManger, it's no matter about the order of apply and set.
     // For SystemdManger, apply must be precede set because we can only create a systemd unit with specific processes(pids).
+    // For rootless containers, the errors are ignored since the cgroups may not be delegated.
     if res.is_some() {
         info!(logger, "apply processes to cgroups!");
-        cm.apply(p.pid)?;
+        if let Err(e) = cm.apply(p.pid) {
+            if !rootless_cgroup {
+                return Err(e);
+            }
+            warn!(
+                logger,
+                "fail to apply processes to cgroups, ignore it: {:?}", e
+            );
+        }
     }
 
     if p.init && res.is_some() {
         info!(logger, "set properties to cgroups!");
-        cm.set(res.unwrap(), false)?;
+        if let Err(e) = cm.set(res.unwrap(), false) {
+            if !rootless_cgroup {
+                return Err(e);
+            }
+            warn!(
+                logger,
+                "fail to set properties to cgroups, ignore it: {:?}", e
+            );
+        }
     }
 
     info!(logger, "notify child to continue");
@@ -1427,6 +1438,31 @@ async fn join_namespaces(
     Ok(())
 }
 
+// Write the uid/gid mappings of a process in a new user namespace. Since Linux 3.19, an
+// unprivileged process can only write the gid mappings after setgroups is denied in the
+// namespace.
+fn setup_id_mappings(
+    logger: &Logger,
+    pid: pid_t,
+    linux: &Linux,
+    rootless_euid: bool,
+) -> Result<()> {
+    write_mappings(
+        logger,
+        &format!("/proc/{}/uid_map", pid),
+        &linux.uid_mappings,
+    )?;
+    if rootless_euid {
+        let path = format!("/proc/{}/setgroups", pid);
+        fs::write(&path, "deny").with_context(|| format!("failed to write {}", path))?;
+    }
+    write_mappings(
+        logger,
+        &format!("/proc/{}/gid_map", pid),
+        &linux.gid_mappings,
+    )
+}
+
 fn write_mappings(logger: &Logger, path: &str, maps: &[LinuxIdMapping]) -> Result<()> {
     let data = maps
         .iter()
@@ -1521,12 +1557,25 @@ impl LinuxContainer {
                 ))
             })?)
         } else {
-            Box::new(FsManager::new(cpath.as_str()).map_err(|e| {
-                anyhow!(format!(
-                    "fail to create cgroup manager with path {}: {:}",
-                    cpath, e
-                ))
-            })?)
+            let cgroup_manager = match FsManager::new(cpath.as_str()) {
+                Ok(cgm) => cgm,
+                // Same as runc, the rootless container runs without cgroups if it has no
+                // permission to create them.
+                Err(e) if config.rootless_cgroup => {
+                    warn!(
+                        logger,
+                        "fail to create cgroup {}, ignore it: {:?}", cpath, e
+                    );
+                    FsManager::new_rootless(cpath.as_str())?
+                }
+                Err(e) => {
+                    return Err(anyhow!(format!(
+                        "fail to create cgroup manager with path {}: {:}",
+                        cpath, e
+                    )))
+                }
+            };
+            Box::new(cgroup_manager)
         };
         info!(logger, "new cgroup_manager {:?}", &cgroup_manager);
 
@@ -1594,7 +1643,7 @@ mod tests {
     use std::os::unix::fs::MetadataExt;
     use std::os::unix::io::AsRawFd;
     use tempfile::tempdir;
-    use test_utils::skip_if_not_root;
+    use test_utils::{skip_if_not_root, skip_if_root};
 
     macro_rules! sl {
         () => {
@@ -1620,6 +1669,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_setup_id_mappings_rootless() {
+        skip_if_root!();
+
+        let uid = Uid::effective().as_raw();
+        let gid = Gid::effective().as_raw();
+        let linux = Linux {
+            uid_mappings: vec![LinuxIdMapping {
+                container_id: 0,
+                host_id: uid,
+                size: 1,
+            }],
+            gid_mappings: vec![LinuxIdMapping {
+                container_id: 0,
+                host_id: gid,
+                size: 1,
+            }],
+            ..Default::default()
+        };
+
+        // The child enters a new user namespace, then waits for its mappings to be written.
+        let (ready_r, ready_w) = unistd::pipe().unwrap();
+        let (done_r, done_w) = unistd::pipe().unwrap();
+        let child = match unsafe { fork() }.unwrap() {
+            ForkResult::Child => {
+                let ready = sched::unshare(CloneFlags::CLONE_NEWUSER).is_ok() as u8;
+                let _ = unistd::write(ready_w, &[ready]);
+                let _ = unistd::read(done_r, &mut [0u8]);
+                unsafe { libc::_exit(0) };
+            }
+            ForkResult::Parent { child } => child,
+        };
+
+        let mut ready = [0u8];
+        unistd::read(ready_r, &mut ready).unwrap();
+        let result = setup_id_mappings(&sl!(), child.as_raw(), &linux, true);
+        let read_proc = |name: &str| fs::read_to_string(format!("/proc/{}/{}", child, name));
+        let (uid_map, gid_map, setgroups) = (
+            read_proc("uid_map"),
+            read_proc("gid_map"),
+            read_proc("setgroups"),
+        );
+        unistd::write(done_w, &[0]).unwrap();
+        nix::sys::wait::waitpid(child, None).unwrap();
+
+        assert_eq!(ready[0], 1, "failed to create user namespace");
+        result.unwrap();
+        let fields = |map: String| map.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            fields(uid_map.unwrap()),
+            vec!["0".to_string(), uid.to_string(), "1".to_string()]
+        );
+        assert_eq!(
+            fields(gid_map.unwrap()),
+            vec!["0".to_string(), gid.to_string(), "1".to_string()]
+        );
+        assert_eq!(setgroups.unwrap().trim(), "deny");
+    }
+
     #[test]
     fn test_set_stdio_permissions() {
         skip_if_not_root!();
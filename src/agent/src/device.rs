@@ -248,6 +248,42 @@ pub async fn get_virtio_blk_pci_device_name(
     Ok(format!("{}/{}", SYSTEM_DEV_PATH, &uev.devname))
 }
 
+#[derive(Debug)]
+struct VirtioBlkMmioMatcher {
+    devname: String,
+}
+
+impl VirtioBlkMmioMatcher {
+    fn new(devname: &str) -> VirtioBlkMmioMatcher {
+        VirtioBlkMmioMatcher {
+            devname: devname.to_string(),
+        }
+    }
+}
+
+impl UeventMatcher for VirtioBlkMmioMatcher {
+    fn is_match(&self, uev: &Uevent) -> bool {
+        uev.subsystem == "block" && uev.devname == self.devname
+    }
+}
+
+// vm_path is the predicted device path of the virtio-mmio block device (/dev/vda, /dev/vdb, ...)
+#[instrument]
+pub async fn get_virtio_mmio_blk_device_name(
+    sandbox: &Arc<Mutex<Sandbox>>,
+    vm_path: &str,
+) -> Result<String> {
+    let devname = vm_path
+        .strip_prefix(SYSTEM_DEV_PATH)
+        .map(|dev| dev.trim_start_matches('/'))
+        .filter(|dev| !dev.is_empty())
+        .ok_or_else(|| anyhow!("Invalid path {} for virtio mmio blk device", vm_path))?;
+
+    let matcher = VirtioBlkMmioMatcher::new(devname);
+    let uev = wait_for_uevent(sandbox, matcher).await?;
+    Ok(format!("{}/{}", SYSTEM_DEV_PATH, &uev.devname))
+}
+
 #[cfg(target_arch = "s390x")]
 #[derive(Debug)]
 struct VirtioBlkCCWMatcher {
@@ -1375,6 +1411,26 @@ mod tests {
         assert!(!matcher_a.is_match(&uev_b));
     }
 
+    #[tokio::test]
+    async fn test_virtio_blk_mmio_matcher() {
+        let mut uev_a = crate::uevent::Uevent::default();
+        uev_a.action = crate::linux_abi::U_EVENT_ACTION_ADD.to_string();
+        uev_a.subsystem = "block".to_string();
+        uev_a.devname = "vdb".to_string();
+        uev_a.devpath = "/devices/virtio-mmio-cmdline/virtio-mmio.1/virtio1/block/vdb".to_string();
+        let matcher_a = VirtioBlkMmioMatcher::new("vdb");
+
+        let mut uev_b = uev_a.clone();
+        uev_b.devname = "vdc".to_string();
+        uev_b.devpath = "/devices/virtio-mmio-cmdline/virtio-mmio.2/virtio2/block/vdc".to_string();
+        let matcher_b = VirtioBlkMmioMatcher::new("vdc");
+
+        assert!(matcher_a.is_match(&uev_a));
+        assert!(matcher_b.is_match(&uev_b));
+        assert!(!matcher_b.is_match(&uev_a));
+        assert!(!matcher_a.is_match(&uev_b));
+    }
+
     #[cfg(target_arch = "s390x")]
     #[tokio::test]
     async fn test_virtio_blk_ccw_matcher() {
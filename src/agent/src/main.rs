@@ -52,6 +52,7 @@ mod signal;
 mod uevent;
 mod util;
 mod version;
+mod watchdog;
 mod watcher;
 
 use mount::{cgroups_mount, general_mount};
@@ -335,6 +336,14 @@ async fn start_sandbox(
 
     tasks.push(uevents_handler_task);
 
+    let watchdog_task = tokio::spawn(watchdog::feed_watchdog(
+        logger.clone(),
+        watchdog::WATCHDOG_DEV,
+        shutdown.clone(),
+    ));
+
+    tasks.push(watchdog_task);
+
     let (tx, rx) = tokio::sync::oneshot::channel();
     sandbox.lock().await.sender = Some(tx);
 
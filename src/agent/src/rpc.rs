@@ -47,7 +47,8 @@ use nix::unistd::{self, Pid};
 use rustjail::process::ProcessOperations;
 
 use crate::device::{
-    add_devices, get_virtio_blk_pci_device_name, update_device_cgroup, update_env_pci,
+    add_devices, get_virtio_blk_pci_device_name, get_virtio_mmio_blk_device_name,
+    update_device_cgroup, update_env_pci,
 };
 use crate::linux_abi::*;
 use crate::metrics::get_metrics;
@@ -1461,6 +1462,24 @@ impl agent_ttrpc::AgentService for AgentService {
         }
     }
 
+    async fn set_log_level(
+        &self,
+        ctx: &TtrpcContext,
+        req: protocols::agent::SetLogLevelRequest,
+    ) -> ttrpc::Result<Empty> {
+        trace_rpc_call!(ctx, "set_log_level", req);
+        is_allowed!(req);
+
+        let level = logging::level_name_to_slog_level(&req.level)
+            .map_err(|e| ttrpc_error!(ttrpc::Code::INVALID_ARGUMENT, e))?;
+        logging::set_log_level(&req.subsystem, level)
+            .map_err(|e| ttrpc_error!(ttrpc::Code::INTERNAL, e))?;
+
+        info!(sl!(), "log level changed"; "log-subsystem" => &req.subsystem, "log-level" => &req.level);
+
+        Ok(Empty::new())
+    }
+
     async fn get_oom_event(
         &self,
         _ctx: &TtrpcContext,
@@ -1934,12 +1953,16 @@ fn do_copy_file(req: &CopyFileRequest) -> Result<()> {
 }
 
 async fn do_add_swap(sandbox: &Arc<Mutex<Sandbox>>, req: &AddSwapRequest) -> Result<()> {
-    let mut slots = Vec::new();
-    for slot in &req.PCIPath {
-        slots.push(pci::SlotFn::new(*slot, 0)?);
-    }
-    let pcipath = pci::Path::new(slots)?;
-    let dev_name = get_virtio_blk_pci_device_name(sandbox, &pcipath).await?;
+    let dev_name = if req.PCIPath.is_empty() && !req.vm_path.is_empty() {
+        get_virtio_mmio_blk_device_name(sandbox, &req.vm_path).await?
+    } else {
+        let mut slots = Vec::new();
+        for slot in &req.PCIPath {
+            slots.push(pci::SlotFn::new(*slot, 0)?);
+        }
+        let pcipath = pci::Path::new(slots)?;
+        get_virtio_blk_pci_device_name(sandbox, &pcipath).await?
+    };
 
     let c_str = CString::new(dev_name)?;
     let ret = unsafe { libc::swapon(c_str.as_ptr() as *const c_char, 0) };
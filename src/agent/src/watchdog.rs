@@ -0,0 +1,105 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use slog::Logger;
+use tokio::select;
+use tokio::sync::watch::Receiver;
+use tracing::instrument;
+
+pub const WATCHDOG_DEV: &str = "/dev/watchdog";
+
+// The default timeout of the ib700wdt driver, used if the driver can't tell.
+const DEFAULT_WATCHDOG_TIMEOUT_SECS: u64 = 30;
+
+// A write of this character before closing the device disarms the watchdog.
+const WATCHDOG_MAGIC_CLOSE: &[u8] = b"V";
+const WATCHDOG_KEEPALIVE: &[u8] = b"1";
+
+nix::ioctl_read!(wdioc_gettimeout, b'W', 7, libc::c_int);
+
+fn watchdog_timeout(dev: &File) -> Duration {
+    let mut timeout: libc::c_int = 0;
+    match unsafe { wdioc_gettimeout(dev.as_raw_fd(), &mut timeout) } {
+        Ok(_) if timeout > 0 => Duration::from_secs(timeout as u64),
+        _ => Duration::from_secs(DEFAULT_WATCHDOG_TIMEOUT_SECS),
+    }
+}
+
+/// Arm the watchdog of the VM if there is one, and keep it fed until the agent shuts down, so
+/// that the VMM notices when the guest hangs.
+#[instrument]
+pub async fn feed_watchdog(logger: Logger, path: &str, mut shutdown: Receiver<bool>) -> Result<()> {
+    let logger = logger.new(o!("subsystem" => "watchdog"));
+
+    if !Path::new(path).exists() {
+        info!(logger, "no watchdog device");
+        return Ok(());
+    }
+
+    // Opening the device arms the watchdog. It may be driven by another guest process already.
+    let mut dev = match OpenOptions::new().write(true).open(path) {
+        Ok(dev) => dev,
+        Err(e) => {
+            warn!(logger, "failed to open watchdog device"; "error" => format!("{}", e));
+            return Ok(());
+        }
+    };
+
+    let timeout = watchdog_timeout(&dev);
+    info!(logger, "watchdog armed"; "timeout" => format!("{:?}", timeout));
+    let mut interval = tokio::time::interval(timeout / 2);
+
+    loop {
+        select! {
+            _ = shutdown.changed() => {
+                info!(logger, "got shutdown request");
+                break;
+            }
+            _ = interval.tick() => {
+                dev.write_all(WATCHDOG_KEEPALIVE).context("feed watchdog")?;
+            }
+        }
+    }
+
+    dev.write_all(WATCHDOG_MAGIC_CLOSE)
+        .context("disarm watchdog")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::watch::channel;
+
+    #[tokio::test]
+    async fn test_feed_watchdog() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let dir = tempfile::tempdir().unwrap();
+
+        // No watchdog device.
+        let (_tx, rx) = channel(true);
+        let path = dir.path().join("watchdog");
+        feed_watchdog(logger.clone(), path.to_str().unwrap(), rx)
+            .await
+            .unwrap();
+
+        // The device is fed right away, then disarmed on shutdown.
+        File::create(&path).unwrap();
+        let (tx, rx) = channel(true);
+        let path_str = path.to_str().unwrap().to_string();
+        let task = tokio::spawn(async move { feed_watchdog(logger, &path_str, rx).await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        tx.send(true).unwrap();
+        task.await.unwrap().unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"1V");
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright 2019-2020 Alibaba Cloud. All rights reserved.
+// Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Portions Copyright 2017 The Chromium OS Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style license that can be
+// found in the THIRD-PARTY file.
+
+mod device;
+pub use self::device::*;
+mod handler;
+pub(crate) use self::handler::*;
+mod request;
+pub(crate) use self::request::*;
+mod ufile;
+pub use self::ufile::*;
+
+use std::sync::mpsc;
+
+use dbs_utils::metric::SharedIncMetric;
+use dbs_utils::rate_limiter::BucketUpdate;
+use serde::Serialize;
+
+/// Block deriver name.
+pub const BLK_DRIVER_NAME: &str = "virtio-blk";
+
+pub(crate) const SECTOR_SHIFT: u8 = 9;
+/// The size of sector
+pub const SECTOR_SIZE: u64 = (0x01u64) << (SECTOR_SHIFT as u64);
+
+/// Metrics specific to the block device.
+#[derive(Default, Serialize)]
+pub struct BlockDeviceMetrics {
+    /// Number of read requests.
+    pub read_count: SharedIncMetric,
+    /// Number of bytes read.
+    pub read_bytes: SharedIncMetric,
+    /// Number of write requests.
+    pub write_count: SharedIncMetric,
+    /// Number of bytes written.
+    pub write_bytes: SharedIncMetric,
+    /// Number of flush requests.
+    pub flush_count: SharedIncMetric,
+    /// Number of requests which failed to execute.
+    pub execute_fails: SharedIncMetric,
+    /// Number of times the requests were throttled by the rate limiter.
+    pub rate_limiter_throttled_count: SharedIncMetric,
+}
+
+pub(crate) enum KillEvent {
+    Kill,
+    BucketUpdate(BucketUpdate, BucketUpdate),
+    /// Stop processing the queue, the sender is notified once all the in-flight requests are
+    /// completed.
+    Pause(mpsc::Sender<()>),
+    Resume,
+}
@@ -27,7 +27,7 @@ use dbs_address_space::{
     AddressSpaceRegionType, NumaNode, NumaNodeInfo, MPOL_MF_MOVE, MPOL_PREFERRED,
 };
 use dbs_allocator::Constraint;
-use kvm_bindings::kvm_userspace_memory_region;
+use kvm_bindings::{kvm_userspace_memory_region, KVM_MEM_LOG_DIRTY_PAGES};
 use kvm_ioctls::VmFd;
 use log::{debug, error, info, warn};
 use nix::sys::mman;
@@ -35,7 +35,7 @@ use nix::unistd::dup;
 #[cfg(feature = "atomic-guest-memory")]
 use vm_memory::GuestMemoryAtomic;
 use vm_memory::{
-    address::Address, FileOffset, GuestAddress, GuestAddressSpace, GuestMemoryMmap,
+    address::Address, FileOffset, GuestAddress, GuestAddressSpace, GuestMemory, GuestMemoryMmap,
     GuestMemoryRegion, GuestRegionMmap, GuestUsize, MemoryRegionAddress, MmapRegion,
 };
 
@@ -136,6 +136,10 @@ pub enum AddressManagerError {
     #[error("address manager failed to configure KVM memory slot")]
     KvmSetMemorySlot(#[source] kvm_ioctls::Error),
 
+    /// Failed to get KVM dirty page log.
+    #[error("address manager failed to get KVM dirty page log")]
+    KvmGetDirtyLog(#[source] kvm_ioctls::Error),
+
     /// Failed to set madvise on AddressSpaceRegion
     #[error("address manager failed to set madvice() on guest memory region")]
     Madvise(#[source] nix::Error),
@@ -403,7 +407,11 @@ impl AddressSpaceMgr {
             let host_addr = mmap_reg
                 .get_host_address(MemoryRegionAddress(0))
                 .map_err(|_e| AddressManagerError::InvalidOperation)?;
-            let flags = 0u32;
+            let flags = if param.dirty_page_logging {
+                KVM_MEM_LOG_DIRTY_PAGES
+            } else {
+                0u32
+            };
 
             let mem_region = kvm_userspace_memory_region {
                 slot,
@@ -632,6 +640,76 @@ impl AddressSpaceMgr {
         self.base_to_slot.clone()
     }
 
+    /// Enable/disable KVM dirty page logging for all guest memory regions at runtime.
+    pub fn set_dirty_page_logging(&self, vmfd: &VmFd, logging: bool) -> Result<()> {
+        let vm_memory = self
+            .vm_memory()
+            .ok_or(AddressManagerError::GuestMemoryNotInitialized)?;
+        let base_to_slot = self.base_to_slot.lock().unwrap();
+        let flags = if logging { KVM_MEM_LOG_DIRTY_PAGES } else { 0 };
+
+        for region in vm_memory.iter() {
+            let base = region.start_addr().raw_value();
+            let slot = *base_to_slot
+                .get(&base)
+                .ok_or(AddressManagerError::InvalidOperation)?;
+            let host_addr = region
+                .get_host_address(MemoryRegionAddress(0))
+                .map_err(|_e| AddressManagerError::InvalidOperation)?;
+            let mem_region = kvm_userspace_memory_region {
+                slot,
+                guest_phys_addr: base,
+                memory_size: region.len(),
+                userspace_addr: host_addr as u64,
+                flags,
+            };
+            // Safe because the memory slot is reconfigured with exactly the same mapping.
+            unsafe { vmfd.set_user_memory_region(mem_region) }
+                .map_err(AddressManagerError::KvmSetMemorySlot)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fetch and clear the KVM dirty page log, return the dirty guest memory ranges as
+    /// `(guest physical address, length)` pairs.
+    ///
+    /// Dirty page logging must have been enabled by [`AddressSpaceMgr::set_dirty_page_logging`].
+    pub fn get_dirty_ranges(&self, vmfd: &VmFd) -> Result<Vec<(u64, u64)>> {
+        const PAGE_SIZE: u64 = 4096;
+
+        let vm_memory = self
+            .vm_memory()
+            .ok_or(AddressManagerError::GuestMemoryNotInitialized)?;
+        let base_to_slot = self.base_to_slot.lock().unwrap();
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+
+        for region in vm_memory.iter() {
+            let base = region.start_addr().raw_value();
+            let slot = *base_to_slot
+                .get(&base)
+                .ok_or(AddressManagerError::InvalidOperation)?;
+            let bitmap = vmfd
+                .get_dirty_log(slot, region.len() as usize)
+                .map_err(AddressManagerError::KvmGetDirtyLog)?;
+            let npages = region.len() / PAGE_SIZE;
+            for page in 0..npages {
+                let word = bitmap[(page / 64) as usize];
+                if word & (1u64 << (page % 64)) == 0 {
+                    continue;
+                }
+                let addr = base + page * PAGE_SIZE;
+                match ranges.last_mut() {
+                    // Merge with the previous range if it's contiguous.
+                    Some((start, len)) if *start + *len == addr => *len += PAGE_SIZE,
+                    _ => ranges.push((addr, PAGE_SIZE)),
+                }
+            }
+        }
+
+        Ok(ranges)
+    }
+
     /// get numa nodes infos from address space manager.
     pub fn get_numa_nodes(&self) -> &BTreeMap<u32, NumaNode> {
         &self.numa_nodes
@@ -13,7 +13,13 @@ use log::{debug, error, info, warn};
 
 use crate::error::{Result, StartMicroVmError, StopMicrovmError};
 use crate::event_manager::EventManager;
-use crate::vm::{CpuTopology, KernelConfigInfo, VmConfigInfo};
+use crate::metric::VmmMetricsInfo;
+use crate::vcpu::VcpuManagerError;
+use crate::vm::{CpuTopology, KernelConfigInfo, NumaRegionInfo, VmConfigInfo};
+#[cfg(target_arch = "x86_64")]
+use crate::vm::{GuestMemoryDumpError, GuestMemoryDumpInfo};
+#[cfg(all(target_arch = "x86_64", feature = "live-migration"))]
+use crate::vm::{MigrationConfigInfo, MigrationError};
 use crate::vmm::Vmm;
 
 use self::VmConfigError::*;
@@ -68,6 +74,25 @@ pub enum VmmActionError {
     #[error("failed to shutdown the VM: {0}")]
     StopMicrovm(#[source] StopMicrovmError),
 
+    /// The action `PauseMicroVm` or `ResumeMicroVm` failed because the VM is not in the expected
+    /// state.
+    #[error("the VM is not in a state which allows pausing or resuming it")]
+    InvalidPauseResumeState,
+
+    /// The action `PauseMicroVm` or `ResumeMicroVm` failed because of an internal error.
+    #[error("failed to pause or resume the VM: {0}")]
+    PauseResumeMicroVm(#[source] VcpuManagerError),
+
+    #[cfg(all(target_arch = "x86_64", feature = "live-migration"))]
+    /// The action `SendMigration` or `ReceiveMigration` failed.
+    #[error("failed to live migrate the VM: {0}")]
+    Migration(#[source] MigrationError),
+
+    #[cfg(target_arch = "x86_64")]
+    /// The action `DumpGuestMemory` failed.
+    #[error("failed to dump guest memory: {0}")]
+    DumpGuestMemory(#[source] GuestMemoryDumpError),
+
     /// One of the actions `GetVmConfiguration` or `SetVmConfiguration` failed either because of bad
     /// input or an internal error.
     #[error("failed to set configuration for the VM: {0}")]
@@ -115,6 +140,34 @@ pub enum VmmAction {
     /// shutdown the vcpu threads and destory all of the object.
     ShutdownMicroVm,
 
+    /// Pause all vCPUs of the microVM. This action can only be called after the microVM has
+    /// booted.
+    PauseMicroVm,
+
+    /// Resume all vCPUs of a paused microVM.
+    ResumeMicroVm,
+
+    #[cfg(all(target_arch = "x86_64", feature = "live-migration"))]
+    /// Live migrate the running microVM to the destination listening on the given url. The
+    /// microVM is left paused on success.
+    SendMigration(MigrationConfigInfo),
+
+    #[cfg(all(target_arch = "x86_64", feature = "live-migration"))]
+    /// Receive a live migrated microVM on the given url instead of booting it. This action can
+    /// only be called before the microVM has booted.
+    ReceiveMigration(MigrationConfigInfo),
+
+    #[cfg(target_arch = "x86_64")]
+    /// Dump guest memory and vCPU states into an ELF core file. This action can only be called
+    /// when the microVM is paused.
+    DumpGuestMemory(GuestMemoryDumpInfo),
+
+    /// Get the most recent lines of the guest kernel log.
+    GetGuestKernelLog,
+
+    /// Get the metrics of the VMM and of the devices of the microVM.
+    GetVmmMetrics,
+
     /// Get the configuration of the microVM.
     GetVmConfiguration,
 
@@ -182,6 +235,10 @@ pub enum VmmData {
     Empty,
     /// The microVM configuration represented by `VmConfigInfo`.
     MachineConfiguration(Box<VmConfigInfo>),
+    /// The most recent lines of the guest kernel log.
+    GuestKernelLog(Vec<String>),
+    /// The metrics of the VMM and of the devices of the microVM.
+    VmmMetrics(Box<VmmMetricsInfo>),
 }
 
 /// Request data type used to communicate between the API and the VMM.
@@ -233,6 +290,18 @@ impl VmmService {
             }
             VmmAction::StartMicroVm => self.start_microvm(vmm, event_mgr),
             VmmAction::ShutdownMicroVm => self.shutdown_microvm(vmm),
+            VmmAction::PauseMicroVm => self.pause_microvm(vmm),
+            VmmAction::ResumeMicroVm => self.resume_microvm(vmm),
+            #[cfg(all(target_arch = "x86_64", feature = "live-migration"))]
+            VmmAction::SendMigration(migration_cfg) => self.send_migration(vmm, migration_cfg),
+            #[cfg(all(target_arch = "x86_64", feature = "live-migration"))]
+            VmmAction::ReceiveMigration(migration_cfg) => {
+                self.receive_migration(vmm, event_mgr, migration_cfg)
+            }
+            #[cfg(target_arch = "x86_64")]
+            VmmAction::DumpGuestMemory(dump_cfg) => self.dump_guest_memory(vmm, dump_cfg),
+            VmmAction::GetGuestKernelLog => self.get_guest_kernel_log(vmm),
+            VmmAction::GetVmmMetrics => self.get_vmm_metrics(vmm),
             VmmAction::GetVmConfiguration => Ok(VmmData::MachineConfiguration(Box::new(
                 self.machine_config.clone(),
             ))),
@@ -349,6 +418,77 @@ impl VmmService {
         Ok(VmmData::Empty)
     }
 
+    fn pause_microvm(&mut self, vmm: &mut Vmm) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        if !vm.is_vm_running() {
+            return Err(VmmActionError::InvalidPauseResumeState);
+        }
+
+        vm.pause_all_vcpus_with_downtime()
+            .map_err(VmmActionError::PauseResumeMicroVm)?;
+        vm.set_instance_state(InstanceState::Paused);
+
+        Ok(VmmData::Empty)
+    }
+
+    fn resume_microvm(&mut self, vmm: &mut Vmm) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        if vm.instance_state() != InstanceState::Paused {
+            return Err(VmmActionError::InvalidPauseResumeState);
+        }
+
+        vm.resume_all_vcpus_with_downtime()
+            .map_err(VmmActionError::PauseResumeMicroVm)?;
+        vm.set_instance_state(InstanceState::Running);
+
+        Ok(VmmData::Empty)
+    }
+
+    #[cfg(all(target_arch = "x86_64", feature = "live-migration"))]
+    fn send_migration(&mut self, vmm: &mut Vmm, config: MigrationConfigInfo) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        vm.send_migration(&config)
+            .map(|_| VmmData::Empty)
+            .map_err(VmmActionError::Migration)
+    }
+
+    #[cfg(all(target_arch = "x86_64", feature = "live-migration"))]
+    fn receive_migration(
+        &mut self,
+        vmm: &mut Vmm,
+        event_mgr: &mut EventManager,
+        config: MigrationConfigInfo,
+    ) -> VmmRequestResult {
+        let vmm_seccomp_filter = vmm.vmm_seccomp_filter();
+        let vcpu_seccomp_filter = vmm.vcpu_seccomp_filter();
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        vm.receive_migration(event_mgr, vmm_seccomp_filter, vcpu_seccomp_filter, &config)
+            .map(|_| VmmData::Empty)
+            .map_err(VmmActionError::Migration)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn dump_guest_memory(
+        &mut self,
+        vmm: &mut Vmm,
+        config: GuestMemoryDumpInfo,
+    ) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        vm.dump_guest_memory(&config)
+            .map(|_| VmmData::Empty)
+            .map_err(VmmActionError::DumpGuestMemory)
+    }
+
+    fn get_guest_kernel_log(&mut self, vmm: &mut Vmm) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        Ok(VmmData::GuestKernelLog(vm.guest_kernel_log()))
+    }
+
+    fn get_vmm_metrics(&mut self, vmm: &mut Vmm) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        Ok(VmmData::VmmMetrics(Box::new(vm.vmm_metrics())))
+    }
+
     /// Set virtual machine configuration.
     pub fn set_vm_configuration(
         &mut self,
@@ -418,12 +558,28 @@ impl VmmService {
         config.mem_size_mib = mem_size_mib_value;
 
         config.mem_file_path = machine_config.mem_file_path.clone();
+        config.pause_on_panic = machine_config.pause_on_panic;
 
         if config.mem_type == "hugetlbfs" && config.mem_file_path.is_empty() {
             return Err(MachineConfig(InvalidMemFilePath("".to_owned())));
         }
         config.vpmu_feature = machine_config.vpmu_feature;
 
+        if !machine_config.numa_regions.is_empty() {
+            check_numa_regions(
+                &machine_config.numa_regions,
+                config.mem_size_mib,
+                config.max_vcpu_count,
+            )?;
+        }
+        config.numa_regions = machine_config.numa_regions;
+
+        #[cfg(not(target_arch = "x86_64"))]
+        if machine_config.watchdog.is_some() {
+            return Err(MachineConfig(WatchdogNotSupported));
+        }
+        config.watchdog = machine_config.watchdog;
+
         // If serial_path is:
         // - None, legacy_manager will create_stdio_console.
         // - Some(path), legacy_manager will create_socket_console on that path.
@@ -674,6 +830,33 @@ fn handle_cpu_topology(
     Ok(cpu_topology)
 }
 
+// The NUMA regions must cover the whole guest memory and all the possible vCPUs.
+fn check_numa_regions(
+    numa_regions: &[NumaRegionInfo],
+    mem_size_mib: usize,
+    max_vcpu_count: u8,
+) -> std::result::Result<(), VmmActionError> {
+    let mem_size: u64 = numa_regions.iter().map(|r| r.size).sum();
+    if mem_size != mem_size_mib as u64 {
+        return Err(MachineConfig(InvalidNumaRegionMemorySize(
+            mem_size as usize,
+        )));
+    }
+
+    let vcpu_count: usize = numa_regions.iter().map(|r| r.vcpu_ids.len()).sum();
+    if vcpu_count != max_vcpu_count as usize {
+        return Err(MachineConfig(InvalidNumaRegionCpuCount(vcpu_count as u16)));
+    }
+
+    if let Some(max_id) = numa_regions.iter().flat_map(|r| r.vcpu_ids.iter()).max() {
+        if *max_id >= max_vcpu_count as u32 {
+            return Err(MachineConfig(InvalidNumaRegionCpuMaxId(*max_id as u16)));
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::{Arc, Mutex};
@@ -1008,6 +1191,70 @@ mod tests {
                     assert_eq!(err_string, expected_err);
                 },
             ),
+            // invalid NUMA regions memory size
+            TestData::new(
+                VmmAction::SetVmConfiguration(VmConfigInfo {
+                    vcpu_count: 2,
+                    max_vcpu_count: 2,
+                    mem_size_mib: 256,
+                    numa_regions: vec![
+                        NumaRegionInfo {
+                            size: 128,
+                            host_numa_node_id: Some(0),
+                            guest_numa_node_id: Some(0),
+                            vcpu_ids: vec![0],
+                        },
+                        NumaRegionInfo {
+                            size: 64,
+                            host_numa_node_id: Some(1),
+                            guest_numa_node_id: Some(1),
+                            vcpu_ids: vec![1],
+                        },
+                    ],
+                    ..Default::default()
+                }),
+                InstanceState::Uninitialized,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::MachineConfig(
+                            VmConfigError::InvalidNumaRegionMemorySize(192)
+                        ))
+                    ));
+                },
+            ),
+            // invalid NUMA regions vCPU id
+            TestData::new(
+                VmmAction::SetVmConfiguration(VmConfigInfo {
+                    vcpu_count: 2,
+                    max_vcpu_count: 2,
+                    mem_size_mib: 256,
+                    numa_regions: vec![
+                        NumaRegionInfo {
+                            size: 128,
+                            host_numa_node_id: Some(0),
+                            guest_numa_node_id: Some(0),
+                            vcpu_ids: vec![0],
+                        },
+                        NumaRegionInfo {
+                            size: 128,
+                            host_numa_node_id: Some(1),
+                            guest_numa_node_id: Some(1),
+                            vcpu_ids: vec![2],
+                        },
+                    ],
+                    ..Default::default()
+                }),
+                InstanceState::Uninitialized,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::MachineConfig(
+                            VmConfigError::InvalidNumaRegionCpuMaxId(2)
+                        ))
+                    ));
+                },
+            ),
             // success
             TestData::new(
                 VmmAction::SetVmConfiguration(VmConfigInfo::default()),
@@ -24,6 +24,7 @@ use crate::config_manager::{ConfigItem, DeviceConfigInfo, RateLimiterConfigInfo}
 use crate::device_manager::blk_dev_mgr::BlockDeviceError::InvalidDeviceId;
 use crate::device_manager::{DeviceManager, DeviceMgrError, DeviceOpContext};
 use crate::get_bucket_update;
+use crate::metric::{self, DeviceMetricsInfo};
 use crate::vm::KernelConfigInfo;
 
 use super::DbsMmioV2Device;
@@ -48,6 +49,9 @@ macro_rules! error(
 /// Default queue size for VirtIo block devices.
 pub const QUEUE_SIZE: u16 = 128;
 
+/// Device type of virtio-blk devices in `DeviceMetricsInfo`.
+pub const VIRTIO_BLK_DEVICE_TYPE: &str = "virtio-blk";
+
 /// Errors associated with the operations allowed on a drive.
 #[derive(Debug, thiserror::Error)]
 pub enum BlockDeviceError {
@@ -569,6 +573,37 @@ impl BlockDeviceMgr {
         )?))
     }
 
+    /// Gets the metrics of the attached virtio-blk devices, counted by the devices. vhost-user-blk
+    /// devices are skipped, as their requests are handled out of the VMM.
+    pub fn device_metrics(&self) -> Vec<DeviceMetricsInfo> {
+        self.info_list
+            .iter()
+            .filter_map(|info| {
+                let device = info.device.as_ref()?;
+                let mmio_dev = device.as_any().downcast_ref::<DbsMmioV2Device>()?;
+                let guard = mmio_dev.state();
+                let blk_dev = guard
+                    .get_inner_device()
+                    .as_any()
+                    .downcast_ref::<Block<GuestAddressSpaceImpl>>()?;
+                let m = blk_dev.metrics();
+                Some(DeviceMetricsInfo {
+                    device_type: VIRTIO_BLK_DEVICE_TYPE.to_owned(),
+                    device_id: info.config.drive_id.clone(),
+                    counters: metric::counters(&[
+                        ("read_count", &m.read_count),
+                        ("read_bytes", &m.read_bytes),
+                        ("write_count", &m.write_count),
+                        ("write_bytes", &m.write_bytes),
+                        ("flush_count", &m.flush_count),
+                        ("execute_fails", &m.execute_fails),
+                        ("rate_limiter_throttled", &m.rate_limiter_throttled_count),
+                    ]),
+                })
+            })
+            .collect()
+    }
+
     /// Generated guest kernel commandline related to root block device.
     pub fn generate_kernel_boot_args(
         &self,
@@ -11,6 +11,7 @@
 //! A virtual console are composed up of two parts: frontend in virtual machine and backend in
 //! host OS. A frontend may be serial port, virtio-console etc, a backend may be stdio or Unix
 //! domain socket. The manager connects the frontend with the backend.
+use std::collections::VecDeque;
 use std::io::{self, Read};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::Path;
@@ -21,6 +22,7 @@ use dbs_legacy_devices::{ConsoleHandler, SerialDevice};
 use dbs_utils::epoll_manager::{
     EpollManager, EventOps, EventSet, Events, MutEventSubscriber, SubscriberId,
 };
+use vmm_sys_util::eventfd::EventFd;
 use vmm_sys_util::terminal::Terminal;
 
 use super::{DeviceMgrError, Result};
@@ -30,6 +32,13 @@ const EPOLL_EVENT_SERIAL_DATA: u32 = 1;
 const EPOLL_EVENT_STDIN: u32 = 2;
 // Maximal backend throughput for every data transaction.
 const MAX_BACKEND_THROUGHPUT: usize = 64;
+// Message printed by the guest kernel when it panics.
+const GUEST_PANIC_MESSAGE: &str = "Kernel panic - not syncing";
+// Max number of guest kernel log lines kept in memory.
+const GUEST_KERNEL_LOG_LINES: usize = 1000;
+
+/// Recent guest kernel log lines, shared between the dmesg writer and its owner.
+pub type GuestKernelLog = Arc<Mutex<VecDeque<String>>>;
 
 /// Errors related to Console manager operations.
 #[derive(Debug, thiserror::Error)]
@@ -358,6 +367,9 @@ impl MutEventSubscriber for ConsoleEpollHandler {
 pub struct DmesgWriter {
     buf: BytesMut,
     logger: slog::Logger,
+    history: Option<GuestKernelLog>,
+    panic_evt: Option<EventFd>,
+    panicked: bool,
 }
 
 impl DmesgWriter {
@@ -366,6 +378,40 @@ impl DmesgWriter {
         Self {
             buf: BytesMut::with_capacity(1024),
             logger: logger.new(slog::o!("subsystem" => "dmesg")),
+            history: None,
+            panic_evt: None,
+            panicked: false,
+        }
+    }
+
+    /// Keep the most recent guest kernel log lines in `history`.
+    pub fn set_history(&mut self, history: GuestKernelLog) {
+        self.history = Some(history);
+    }
+
+    /// Notify `panic_evt` when the guest kernel panics.
+    pub fn set_panic_eventfd(&mut self, panic_evt: EventFd) {
+        self.panic_evt = Some(panic_evt);
+    }
+
+    fn log_line(&mut self, line: String) {
+        slog::info!(self.logger, "{}", line);
+
+        if !self.panicked && line.contains(GUEST_PANIC_MESSAGE) {
+            self.panicked = true;
+            if let Some(evt) = self.panic_evt.as_ref() {
+                if let Err(e) = evt.write(1) {
+                    slog::error!(self.logger, "failed to notify guest panic: {}", e);
+                }
+            }
+        }
+
+        if let Some(history) = self.history.as_ref() {
+            let mut history = history.lock().unwrap();
+            if history.len() >= GUEST_KERNEL_LOG_LINES {
+                history.pop_front();
+            }
+            history.push_back(line);
         }
     }
 }
@@ -386,21 +432,20 @@ impl io::Write for DmesgWriter {
         for (i, sub) in arr.iter().enumerate() {
             if sub.is_empty() {
                 if !self.buf.is_empty() {
-                    slog::info!(
-                        self.logger,
-                        "{}",
-                        String::from_utf8_lossy(self.buf.as_ref()).trim_end()
-                    );
+                    let line = String::from_utf8_lossy(self.buf.as_ref())
+                        .trim_end()
+                        .to_string();
                     self.buf.clear();
+                    self.log_line(line);
                 }
             } else if sub.len() < buf.len() && i < count - 1 {
-                slog::info!(
-                    self.logger,
+                let line = format!(
                     "{}{}",
                     String::from_utf8_lossy(self.buf.as_ref()).trim_end(),
                     String::from_utf8_lossy(sub).trim_end(),
                 );
                 self.buf.clear();
+                self.log_line(line);
             } else {
                 self.buf.put_slice(sub);
             }
@@ -432,6 +477,9 @@ mod tests {
         let mut writer = DmesgWriter {
             buf: Default::default(),
             logger: create_logger(),
+            history: None,
+            panic_evt: None,
+            panicked: false,
         };
 
         writer.flush().unwrap();
@@ -445,5 +493,33 @@ mod tests {
         writer.flush().unwrap();
     }
 
+    #[test]
+    fn test_dmesg_writer_guest_panic() {
+        let history = GuestKernelLog::default();
+        let panic_evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let mut writer = DmesgWriter::new(&create_logger());
+        writer.set_history(history.clone());
+        writer.set_panic_eventfd(panic_evt.try_clone().unwrap());
+
+        writer
+            .write_all("[    1.000000] booting\r\n".as_bytes())
+            .unwrap();
+        assert!(panic_evt.read().is_err());
+
+        writer
+            .write_all(
+                "[    2.000000] Kernel panic - not syncing: Attempted to kill init!\r\n".as_bytes(),
+            )
+            .unwrap();
+        writer
+            .write_all("[    2.000001] ---[ end Kernel panic - not syncing ]---\r\n".as_bytes())
+            .unwrap();
+        assert_eq!(panic_evt.read().unwrap(), 1);
+
+        let history = history.lock().unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0], "[    1.000000] booting");
+    }
+
     // TODO: add unit tests for console manager
 }
@@ -18,6 +18,7 @@ use crate::device_manager::{
     DbsMmioV2Device, DeviceManager, DeviceMgrError, DeviceOpContext, DeviceVirtioRegionHandler,
 };
 use crate::get_bucket_update;
+use crate::metric::{self, DeviceMetricsInfo};
 
 use super::DbsVirtioDevice;
 
@@ -32,6 +33,9 @@ const VHOSTUSER_FS_MODE: &str = "vhostuser";
 // We have 2 supported fs device mode, vhostuser and virtio
 const VIRTIO_FS_MODE: &str = "virtio";
 
+/// Device type of virtio-fs devices in `DeviceMetricsInfo`.
+pub const VIRTIO_FS_DEVICE_TYPE: &str = "virtio-fs";
+
 /// Errors associated with `FsDeviceConfig`.
 #[derive(Debug, thiserror::Error)]
 pub enum FsDeviceError {
@@ -482,6 +486,36 @@ impl FsDeviceMgr {
             .position(|info| info.config.id().eq(tag))
     }
 
+    /// Gets the metrics of the attached virtio-fs devices, counted by the devices. vhost-user-fs
+    /// devices are skipped, as their requests are handled out of the VMM.
+    pub fn device_metrics(&self) -> Vec<DeviceMetricsInfo> {
+        self.info_list
+            .iter()
+            .filter_map(|info| {
+                let device = info.device.as_ref()?;
+                let mmio_dev = device.as_any().downcast_ref::<DbsMmioV2Device>()?;
+                let guard = mmio_dev.state();
+                let fs_dev = guard
+                    .get_inner_device()
+                    .as_any()
+                    .downcast_ref::<virtio::fs::VirtioFs<GuestAddressSpaceImpl>>()?;
+                let m = fs_dev.metrics();
+                Some(DeviceMetricsInfo {
+                    device_type: VIRTIO_FS_DEVICE_TYPE.to_owned(),
+                    device_id: info.config.tag.clone(),
+                    counters: metric::counters(&[
+                        ("request_count", &m.request_count),
+                        ("read_count", &m.read_count),
+                        ("read_bytes", &m.read_bytes),
+                        ("write_count", &m.write_count),
+                        ("write_bytes", &m.write_bytes),
+                        ("rate_limiter_throttled", &m.rate_limiter_throttled_count),
+                    ]),
+                })
+            })
+            .collect()
+    }
+
     /// Update the ratelimiter settings of a virtio fs device.
     pub fn update_device_ratelimiters(
         device_mgr: &mut DeviceManager,
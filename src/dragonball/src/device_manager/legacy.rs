@@ -34,16 +34,22 @@ pub enum Error {
     /// Failed to register/deregister interrupt.
     #[error("failure while managing interrupt for legacy device")]
     IrqManager(#[source] vmm_sys_util::errno::Error),
+
+    /// Cannot create the watchdog device.
+    #[error("failure while creating watchdog device")]
+    Watchdog(#[source] io::Error),
 }
 
 /// The `LegacyDeviceManager` is a wrapper that is used for registering legacy devices
 /// on an I/O Bus.
 ///
-/// It currently manages the uart and i8042 devices. The `LegacyDeviceManger` should be initialized
-/// only by using the constructor.
+/// It currently manages the uart, i8042 and watchdog devices. The `LegacyDeviceManger` should be
+/// initialized only by using the constructor.
 pub struct LegacyDeviceManager {
     #[cfg(target_arch = "x86_64")]
     i8042_reset_eventfd: EventFd,
+    #[cfg(target_arch = "x86_64")]
+    watchdog_eventfd: Option<EventFd>,
     #[cfg(target_arch = "aarch64")]
     pub(crate) _rtc_device: Arc<Mutex<RTCDevice>>,
     #[cfg(target_arch = "aarch64")]
@@ -74,6 +80,8 @@ pub(crate) mod x86_64 {
     use dbs_legacy_devices::{EventFdTrigger, I8042Device, I8042DeviceMetrics};
     use kvm_ioctls::VmFd;
 
+    use crate::device_manager::watchdog::{Ib700Watchdog, WATCHDOG_PORT_BASE, WATCHDOG_PORT_SIZE};
+
     pub(crate) const COM1_IRQ: u32 = 4;
     pub(crate) const COM1_PORT1: u16 = 0x3f8;
     pub(crate) const COM2_IRQ: u32 = 3;
@@ -105,6 +113,7 @@ pub(crate) mod x86_64 {
 
             Ok(LegacyDeviceManager {
                 i8042_reset_eventfd: exit_evt,
+                watchdog_eventfd: None,
                 com1_device,
                 _com1_eventfd: com1_eventfd,
                 com2_device,
@@ -117,6 +126,32 @@ pub(crate) mod x86_64 {
             self.i8042_reset_eventfd.try_clone().map_err(Error::EventFd)
         }
 
+        /// Create the IB700 watchdog device, which the guest kernel arms to detect guest hangs.
+        pub fn create_watchdog_device(&mut self, bus: &mut IoManager) -> Result<()> {
+            let watchdog_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?;
+            let device = Arc::new(Mutex::new(
+                Ib700Watchdog::new(watchdog_evt.try_clone().map_err(Error::EventFd)?)
+                    .map_err(Error::Watchdog)?,
+            ));
+            let resources = [Resource::PioAddressRange {
+                base: WATCHDOG_PORT_BASE,
+                size: WATCHDOG_PORT_SIZE,
+            }];
+            bus.register_device_io(device, &resources)
+                .map_err(Error::BusError)?;
+
+            self.watchdog_eventfd = Some(watchdog_evt);
+            Ok(())
+        }
+
+        /// Get the eventfd for watchdog expiry notification, if the watchdog device exists.
+        pub fn get_watchdog_eventfd(&self) -> Result<Option<EventFd>> {
+            self.watchdog_eventfd
+                .as_ref()
+                .map(|evt| evt.try_clone().map_err(Error::EventFd))
+                .transpose()
+        }
+
         fn create_com_device(
             bus: &mut IoManager,
             vm_fd: Option<&Arc<VmFd>>,
@@ -240,7 +275,11 @@ mod tests {
     #[cfg(target_arch = "x86_64")]
     fn test_create_legacy_device_manager() {
         let mut bus = dbs_device::device_manager::IoManager::new();
-        let mgr = LegacyDeviceManager::create_manager(&mut bus, None).unwrap();
+        let mut mgr = LegacyDeviceManager::create_manager(&mut bus, None).unwrap();
         let _exit_fd = mgr.get_reset_eventfd().unwrap();
+
+        assert!(mgr.get_watchdog_eventfd().unwrap().is_none());
+        mgr.create_watchdog_device(&mut bus).unwrap();
+        assert!(mgr.get_watchdog_eventfd().unwrap().is_some());
     }
 }
@@ -45,7 +45,15 @@ use dbs_upcall::{
 use dbs_virtio_devices::vsock::backend::VsockInnerConnector;
 
 use crate::address_space_manager::GuestAddressSpaceImpl;
+#[cfg(any(
+    feature = "virtio-blk",
+    feature = "virtio-fs",
+    feature = "virtio-net",
+    feature = "virtio-vsock"
+))]
+use crate::config_manager::ConfigItem;
 use crate::error::StartMicroVmError;
+use crate::metric::DeviceMetricsInfo;
 use crate::resource_manager::ResourceManager;
 use crate::vm::{KernelConfigInfo, Vm};
 use crate::IoManagerCached;
@@ -58,6 +66,9 @@ pub use self::console_manager::ConsoleManager;
 mod legacy;
 pub use self::legacy::{Error as LegacyDeviceError, LegacyDeviceManager};
 
+#[cfg(target_arch = "x86_64")]
+mod watchdog;
+
 #[cfg(target_arch = "aarch64")]
 pub use self::legacy::aarch64::{COM1, COM2, RTC};
 
@@ -531,11 +542,13 @@ impl DeviceManager {
         &self.logger
     }
 
-    /// Create legacy devices associted virtual machine
+    /// Create legacy devices associted virtual machine, with the watchdog device if `watchdog` is
+    /// set (x86_64 only).
     #[allow(unused_variables)]
     pub fn create_legacy_devices(
         &mut self,
         ctx: &mut DeviceOpContext,
+        watchdog: bool,
     ) -> std::result::Result<(), StartMicroVmError> {
         #[cfg(any(
             target_arch = "x86_64",
@@ -550,7 +563,13 @@ impl DeviceManager {
                 legacy_manager = LegacyDeviceManager::create_manager(
                     &mut tx.io_manager,
                     Some(self.vm_fd.clone()),
-                );
+                )
+                .and_then(|mut mgr| {
+                    if watchdog {
+                        mgr.create_watchdog_device(&mut tx.io_manager)?;
+                    }
+                    Ok(mgr)
+                });
             }
 
             #[cfg(target_arch = "aarch64")]
@@ -630,6 +649,19 @@ impl DeviceManager {
         self.con_manager.reset_console()
     }
 
+    /// Get the metrics of the virtio devices, counted by the devices.
+    pub fn device_metrics(&self) -> Vec<DeviceMetricsInfo> {
+        #[allow(unused_mut)]
+        let mut devices = Vec::new();
+        #[cfg(feature = "virtio-blk")]
+        devices.extend(self.block_manager.device_metrics());
+        #[cfg(feature = "virtio-fs")]
+        devices.extend(self.fs_manager.lock().unwrap().device_metrics());
+        #[cfg(feature = "virtio-net")]
+        devices.extend(self.virtio_net_manager.device_metrics());
+        devices
+    }
+
     /// Create all registered devices when booting the associated virtual machine.
     pub fn create_devices(
         &mut self,
@@ -637,6 +669,7 @@ impl DeviceManager {
         epoll_mgr: EpollManager,
         kernel_config: &mut KernelConfigInfo,
         com1_sock_path: Option<String>,
+        watchdog: bool,
         dmesg_fifo: Option<Box<dyn io::Write + Send>>,
         address_space: Option<&AddressSpace>,
     ) -> std::result::Result<(), StartMicroVmError> {
@@ -648,7 +681,7 @@ impl DeviceManager {
             false,
         );
 
-        self.create_legacy_devices(&mut ctx)?;
+        self.create_legacy_devices(&mut ctx, watchdog)?;
         self.init_legacy_devices(dmesg_fifo, com1_sock_path, &mut ctx)?;
 
         #[cfg(feature = "virtio-blk")]
@@ -716,6 +749,45 @@ impl DeviceManager {
         self.block_manager.remove_devices(&mut ctx)?;
         Ok(())
     }
+
+    /// Get the virtio devices with the id of their configuration, in a stable order: block,
+    /// virtio-fs, virtio-net and vsock devices.
+    pub fn get_virtio_devices(&self) -> Vec<(String, Arc<dyn DeviceIo>)> {
+        #[allow(unused_mut)]
+        let mut devices = Vec::new();
+
+        #[cfg(feature = "virtio-blk")]
+        devices.extend(
+            self.block_manager
+                .iter()
+                .filter_map(|info| Some((info.config.id().to_string(), info.device.clone()?))),
+        );
+        #[cfg(feature = "virtio-fs")]
+        devices.extend(
+            self.fs_manager
+                .lock()
+                .unwrap()
+                .info_list
+                .iter()
+                .filter_map(|info| Some((info.config.id().to_string(), info.device.clone()?))),
+        );
+        #[cfg(feature = "virtio-net")]
+        devices.extend(
+            self.virtio_net_manager
+                .info_list
+                .iter()
+                .filter_map(|info| Some((info.config.id().to_string(), info.device.clone()?))),
+        );
+        #[cfg(feature = "virtio-vsock")]
+        devices.extend(
+            self.vsock_manager
+                .info_list
+                .iter()
+                .filter_map(|info| Some((info.config.id().to_string(), info.device.clone()?))),
+        );
+
+        devices
+    }
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -732,6 +804,17 @@ impl DeviceManager {
             )))
         }
     }
+
+    /// Get the underlying eventfd for guest watchdog expiry notification, if the watchdog device
+    /// exists.
+    pub fn get_watchdog_eventfd(&self) -> Result<Option<vmm_sys_util::eventfd::EventFd>> {
+        match self.legacy_manager.as_ref() {
+            Some(legacy) => legacy
+                .get_watchdog_eventfd()
+                .map_err(DeviceMgrError::LegacyManager),
+            None => Ok(None),
+        }
+    }
 }
 
 #[cfg(target_arch = "aarch64")]
@@ -1073,6 +1156,9 @@ mod tests {
             mem_type: "shmem".to_string(),
             mem_file_path: "".to_string(),
             mem_size_mib: 16,
+            pause_on_panic: false,
+            numa_regions: Vec::new(),
+            watchdog: None,
             serial_path: None,
             cpu_topology: CpuTopology {
                 threads_per_core: 1,
@@ -1108,6 +1194,7 @@ mod tests {
             event_mgr.epoll_manager(),
             &mut cmdline,
             None,
+            false,
             None,
             address_space.as_ref(),
         )
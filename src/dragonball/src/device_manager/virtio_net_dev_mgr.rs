@@ -22,6 +22,7 @@ use crate::config_manager::{
 };
 use crate::device_manager::{DeviceManager, DeviceMgrError, DeviceOpContext};
 use crate::get_bucket_update;
+use crate::metric::{self, DeviceMetricsInfo};
 
 use super::DbsMmioV2Device;
 
@@ -34,6 +35,9 @@ const USE_SHARED_IRQ: bool = true;
 // The flag of whether to use the generic irq.
 const USE_GENERIC_IRQ: bool = true;
 
+/// Device type of virtio-net devices in `DeviceMetricsInfo`.
+pub const VIRTIO_NET_DEVICE_TYPE: &str = "virtio-net";
+
 /// Errors associated with virtio net device operations.
 #[derive(Debug, thiserror::Error)]
 pub enum VirtioNetDeviceError {
@@ -349,6 +353,44 @@ impl VirtioNetDeviceMgr {
         Ok(())
     }
 
+    /// Gets the metrics of the attached virtio-net devices, counted by the devices, as seen from
+    /// the guest.
+    pub fn device_metrics(&self) -> Vec<DeviceMetricsInfo> {
+        self.info_list
+            .iter()
+            .filter_map(|info| {
+                let device = info.device.as_ref()?;
+                let mmio_dev = device.as_any().downcast_ref::<DbsMmioV2Device>()?;
+                let guard = mmio_dev.state();
+                let net_dev = guard
+                    .get_inner_device()
+                    .as_any()
+                    .downcast_ref::<virtio::net::Net<GuestAddressSpaceImpl>>()?;
+                let m = net_dev.metrics();
+                Some(DeviceMetricsInfo {
+                    device_type: VIRTIO_NET_DEVICE_TYPE.to_owned(),
+                    device_id: info.config.iface_id.clone(),
+                    counters: metric::counters(&[
+                        ("rx_bytes", &m.rx_bytes_count),
+                        ("rx_packets", &m.rx_packets_count),
+                        ("rx_fails", &m.rx_fails),
+                        (
+                            "rx_rate_limiter_throttled",
+                            &m.rx_rate_limiter_throttled_count,
+                        ),
+                        ("tx_bytes", &m.tx_bytes_count),
+                        ("tx_packets", &m.tx_packets_count),
+                        ("tx_fails", &m.tx_fails),
+                        (
+                            "tx_rate_limiter_throttled",
+                            &m.tx_rate_limiter_throttled_count,
+                        ),
+                    ]),
+                })
+            })
+            .collect()
+    }
+
     fn create_device(
         cfg: &VirtioNetDeviceConfigInfo,
         ctx: &mut DeviceOpContext,
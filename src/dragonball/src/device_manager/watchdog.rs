@@ -0,0 +1,146 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Emulated iBASE IB700 watchdog timer.
+//!
+//! It's the simplest watchdog the guest kernel can drive, with the `ib700wdt` driver, through two
+//! I/O ports: a write to the start port arms or rearms the timer, a write to the stop port disarms
+//! it. The eventfd is written when the timer expires, i.e. when the guest is hung.
+
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use dbs_device::{DeviceIoMut, PioAddress};
+use log::error;
+use vmm_sys_util::eventfd::EventFd;
+
+/// Base of the I/O ports of the watchdog.
+pub(crate) const WATCHDOG_PORT_BASE: u16 = 0x441;
+/// Size of the I/O port range of the watchdog, from the stop port 0x441 to the start port 0x443.
+pub(crate) const WATCHDOG_PORT_SIZE: u16 = 0x3;
+
+const WATCHDOG_STOP_OFFSET: u16 = 0x0;
+const WATCHDOG_START_OFFSET: u16 = 0x2;
+
+// The timeout is encoded in the low 4 bits written to the start port, from 30s down to 0s.
+const WATCHDOG_MAX_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Default)]
+struct TimerState {
+    deadline: Option<Instant>,
+    stopped: bool,
+}
+
+type Timer = Arc<(Mutex<TimerState>, Condvar)>;
+
+/// The IB700 watchdog device, with a timer thread writing the expiry eventfd.
+pub struct Ib700Watchdog {
+    timer: Timer,
+    timer_thread: Option<JoinHandle<()>>,
+}
+
+impl Ib700Watchdog {
+    /// Create a disarmed watchdog, which writes `expiry_evt` when it expires.
+    pub fn new(expiry_evt: EventFd) -> io::Result<Self> {
+        let timer: Timer = Arc::new((Mutex::new(TimerState::default()), Condvar::new()));
+        let thread_timer = timer.clone();
+        let timer_thread = thread::Builder::new()
+            .name("db_watchdog".to_owned())
+            .spawn(move || run_timer(&thread_timer, &expiry_evt))?;
+
+        Ok(Ib700Watchdog {
+            timer,
+            timer_thread: Some(timer_thread),
+        })
+    }
+
+    fn set_deadline(&self, deadline: Option<Instant>) {
+        let (lock, cvar) = &*self.timer;
+        lock.lock().unwrap().deadline = deadline;
+        cvar.notify_one();
+    }
+
+    fn is_armed(&self) -> bool {
+        self.timer.0.lock().unwrap().deadline.is_some()
+    }
+}
+
+impl Drop for Ib700Watchdog {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.timer;
+        lock.lock().unwrap().stopped = true;
+        cvar.notify_one();
+        if let Some(thread) = self.timer_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl DeviceIoMut for Ib700Watchdog {
+    fn pio_write(&mut self, _base: PioAddress, offset: PioAddress, data: &[u8]) {
+        match offset.raw_value() {
+            WATCHDOG_STOP_OFFSET => self.set_deadline(None),
+            WATCHDOG_START_OFFSET => {
+                if let Some(v) = data.first() {
+                    let timeout = WATCHDOG_MAX_TIMEOUT_SECS - 2 * u64::from(v & 0xf);
+                    self.set_deadline(Some(Instant::now() + Duration::from_secs(timeout)));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Wait for the deadline, which may be moved by the guest at any time, then notify the expiry.
+fn run_timer(timer: &Timer, expiry_evt: &EventFd) {
+    let (lock, cvar) = &**timer;
+    let mut state = lock.lock().unwrap();
+    while !state.stopped {
+        match state.deadline {
+            None => state = cvar.wait(state).unwrap(),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now < deadline {
+                    state = cvar.wait_timeout(state, deadline - now).unwrap().0;
+                    continue;
+                }
+
+                // The watchdog fires once, the guest has to arm it again.
+                state.deadline = None;
+                if let Err(e) = expiry_evt.write(1) {
+                    error!("watchdog: failed to notify expiry, {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ib700_watchdog() {
+        let evt = EventFd::new(0).unwrap();
+        let mut watchdog = Ib700Watchdog::new(evt.try_clone().unwrap()).unwrap();
+        let base = PioAddress(WATCHDOG_PORT_BASE);
+        assert!(!watchdog.is_armed());
+
+        // Arm the watchdog with the longest timeout, then disarm it.
+        watchdog.pio_write(base, PioAddress(WATCHDOG_START_OFFSET), &[0x0]);
+        assert!(watchdog.is_armed());
+        watchdog.pio_write(base, PioAddress(WATCHDOG_STOP_OFFSET), &[0x0]);
+        assert!(!watchdog.is_armed());
+
+        // A timeout of 0s expires right away.
+        watchdog.pio_write(base, PioAddress(WATCHDOG_START_OFFSET), &[0xf]);
+        assert_eq!(evt.read().unwrap(), 1);
+        assert!(!watchdog.is_armed());
+
+        // Writes to the port in the middle are ignored.
+        watchdog.pio_write(base, PioAddress(0x1), &[0x0]);
+        assert!(!watchdog.is_armed());
+    }
+}
@@ -63,6 +63,11 @@ pub enum Error {
     #[error("failed to write MP table to guest memory: {0}")]
     MpTableSetup(#[source] dbs_boot::mptable::Error),
 
+    /// Error writing the ACPI tables to memory.
+    #[cfg(target_arch = "x86_64")]
+    #[error("failed to write ACPI tables to guest memory: {0}")]
+    AcpiTableSetup(#[source] crate::vm::AcpiError),
+
     /// Create pmu device error
     #[cfg(target_arch = "aarch64")]
     #[error("Create pmu device error: {0}")]
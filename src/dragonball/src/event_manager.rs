@@ -23,12 +23,16 @@ use crate::vmm::Vmm;
 // Statically assigned epoll slot for VMM events.
 pub(crate) const EPOLL_EVENT_EXIT: u32 = 0;
 pub(crate) const EPOLL_EVENT_API_REQUEST: u32 = 1;
+pub(crate) const EPOLL_EVENT_GUEST_PANIC: u32 = 2;
+pub(crate) const EPOLL_EVENT_WATCHDOG: u32 = 3;
 
 /// Shared information between vmm::vmm_thread_event_loop() and VmmEpollHandler.
 pub(crate) struct EventContext {
     pub api_event_fd: EventFd,
     pub api_event_triggered: bool,
     pub exit_evt_triggered: bool,
+    pub guest_panic_triggered: bool,
+    pub watchdog_triggered: bool,
 }
 
 impl EventContext {
@@ -38,6 +42,8 @@ impl EventContext {
             api_event_fd,
             api_event_triggered: false,
             exit_evt_triggered: false,
+            guest_panic_triggered: false,
+            watchdog_triggered: false,
         })
     }
 }
@@ -98,6 +104,30 @@ impl EventManager {
             .map_err(EpollError::EpollMgr)
     }
 
+    /// Registry the eventfd for guest panic notification.
+    pub fn register_guest_panic_eventfd(
+        &mut self,
+        panic_evt: &EventFd,
+    ) -> std::result::Result<(), EpollError> {
+        let events = Events::with_data(panic_evt, EPOLL_EVENT_GUEST_PANIC, EventSet::IN);
+
+        self.epoll_mgr
+            .add_event(self.subscriber_id, events)
+            .map_err(EpollError::EpollMgr)
+    }
+
+    /// Registry the eventfd for guest watchdog expiry notification.
+    pub fn register_watchdog_eventfd(
+        &mut self,
+        watchdog_evt: &EventFd,
+    ) -> std::result::Result<(), EpollError> {
+        let events = Events::with_data(watchdog_evt, EPOLL_EVENT_WATCHDOG, EventSet::IN);
+
+        self.epoll_mgr
+            .add_event(self.subscriber_id, events)
+            .map_err(EpollError::EpollMgr)
+    }
+
     /// Poll pending events and invoke registered event handler.
     ///
     /// # Arguments:
@@ -146,6 +176,32 @@ impl MutEventSubscriber for VmmEpollHandler {
                 vmm.event_ctx.exit_evt_triggered = true;
                 self.vmm_event_count.fetch_add(1, Ordering::AcqRel);
             }
+            EPOLL_EVENT_GUEST_PANIC => {
+                let vm = vmm.get_vm().unwrap();
+                match vm.get_guest_panic_eventfd() {
+                    Some(ev) => {
+                        if let Err(e) = ev.read() {
+                            error!("event_manager: failed to read guest panic eventfd, {:?}", e);
+                        }
+                    }
+                    None => warn!("event_manager: leftover guest panic event in epoll context!"),
+                }
+                vmm.event_ctx.guest_panic_triggered = true;
+                self.vmm_event_count.fetch_add(1, Ordering::AcqRel);
+            }
+            EPOLL_EVENT_WATCHDOG => {
+                let vm = vmm.get_vm().unwrap();
+                match vm.get_watchdog_eventfd() {
+                    Some(ev) => {
+                        if let Err(e) = ev.read() {
+                            error!("event_manager: failed to read watchdog eventfd, {:?}", e);
+                        }
+                    }
+                    None => warn!("event_manager: leftover watchdog event in epoll context!"),
+                }
+                vmm.event_ctx.watchdog_triggered = true;
+                self.vmm_event_count.fetch_add(1, Ordering::AcqRel);
+            }
             _ => error!("event_manager: unknown epoll slot number {}", events.data()),
         }
     }
@@ -2,6 +2,8 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::BTreeMap;
+
 use dbs_utils::metric::SharedIncMetric;
 use lazy_static::lazy_static;
 use serde::Serialize;
@@ -56,3 +58,66 @@ pub struct DragonballMetrics {
     /// Metrics related to signals.
     pub signals: SignalMetrics,
 }
+
+/// Metrics of a device of the VM.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DeviceMetricsInfo {
+    /// Type of the device, e.g. "virtio-blk".
+    pub device_type: String,
+    /// ID of the device, e.g. the drive ID of a block device.
+    pub device_id: String,
+    /// Values of the counters of the device by name, e.g. "read_bytes".
+    pub counters: BTreeMap<String, u64>,
+}
+
+/// Metrics of the VMM, as returned by `VmmAction::GetVmmMetrics`.
+///
+/// The values are the totals since the VMM started, unlike the serialization of
+/// `DragonballMetrics` which reports the values since the last serialization.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct VmmMetricsInfo {
+    /// Values of the counters of the VMM by "<group>_<name>", e.g. "vcpu_exit_io_in".
+    pub vmm: BTreeMap<String, u64>,
+    /// Metrics of the devices of the VM.
+    pub devices: Vec<DeviceMetricsInfo>,
+}
+
+impl DragonballMetrics {
+    /// Get the current values of the counters by "<group>_<name>".
+    pub fn counters(&self) -> BTreeMap<String, u64> {
+        counters(&[
+            ("vcpu_exit_io_in", &self.vcpu.exit_io_in),
+            ("vcpu_exit_io_out", &self.vcpu.exit_io_out),
+            ("vcpu_exit_mmio_read", &self.vcpu.exit_mmio_read),
+            ("vcpu_exit_mmio_write", &self.vcpu.exit_mmio_write),
+            ("vcpu_failures", &self.vcpu.failures),
+            ("vcpu_filter_cpuid", &self.vcpu.filter_cpuid),
+            ("seccomp_num_faults", &self.seccomp.num_faults),
+            ("signals_sigbus", &self.signals.sigbus),
+            ("signals_sigsegv", &self.signals.sigsegv),
+        ])
+    }
+}
+
+/// Get the current values of the given counters by name.
+pub(crate) fn counters(metrics: &[(&str, &SharedIncMetric)]) -> BTreeMap<String, u64> {
+    metrics
+        .iter()
+        .map(|(name, metric)| (name.to_string(), metric.count() as u64))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dragonball_metrics_counters() {
+        let metrics = DragonballMetrics::default();
+        metrics.vcpu.exit_io_in.add(3);
+
+        let counters = metrics.counters();
+        assert_eq!(counters["vcpu_exit_io_in"], 3);
+        assert_eq!(counters["signals_sigsegv"], 0);
+    }
+}
@@ -31,6 +31,9 @@ pub mod tests {
             mem_type: "shmem".to_string(),
             mem_file_path: "".to_string(),
             mem_size_mib: 1,
+            pause_on_panic: false,
+            numa_regions: Vec::new(),
+            watchdog: None,
             serial_path: None,
             cpu_topology: CpuTopology {
                 threads_per_core: 1,
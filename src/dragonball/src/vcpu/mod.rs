@@ -13,6 +13,9 @@ pub use vcpu_manager::{VcpuManager, VcpuManagerError, VcpuResizeInfo};
 #[cfg(feature = "hotplug")]
 pub use vcpu_manager::VcpuResizeError;
 
+#[cfg(target_arch = "x86_64")]
+pub use vcpu_impl::VcpuState;
+
 /// vcpu config collection
 pub struct VcpuConfig {
     /// initial vcpu count
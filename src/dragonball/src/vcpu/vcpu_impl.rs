@@ -32,6 +32,8 @@ use crate::IoManagerCached;
 #[cfg(target_arch = "x86_64")]
 #[path = "x86_64.rs"]
 mod x86_64;
+#[cfg(target_arch = "x86_64")]
+pub use self::x86_64::VcpuState;
 
 #[cfg(target_arch = "aarch64")]
 #[path = "aarch64.rs"]
@@ -33,6 +33,8 @@ use crate::vcpu::vcpu_impl::{
     Vcpu, VcpuError, VcpuEvent, VcpuHandle, VcpuResizeResult, VcpuResponse, VcpuStateEvent,
 };
 use crate::vcpu::VcpuConfig;
+#[cfg(target_arch = "x86_64")]
+use crate::vcpu::VcpuState;
 use crate::vm::VmConfigInfo;
 use crate::IoManagerCached;
 
@@ -443,6 +445,40 @@ impl VcpuManager {
         self.pause_vcpus(&self.present_vcpus())
     }
 
+    /// pause all vcpus and wait until all of them have been paused
+    pub fn pause_all_vcpus_sync(&mut self) -> Result<()> {
+        let cpu_indexes = self.present_vcpus();
+        // Drop stale responses of previous pause/resume requests, which don't wait for responses.
+        for cpu_id in &cpu_indexes {
+            if let Some(handle) = &self.vcpu_infos[*cpu_id as usize].handle {
+                while handle.response_receiver().try_recv().is_ok() {}
+            }
+        }
+
+        self.pause_vcpus(&cpu_indexes)?;
+
+        for cpu_id in &cpu_indexes {
+            if let Some(handle) = &self.vcpu_infos[*cpu_id as usize].handle {
+                match handle
+                    .response_receiver()
+                    .recv_timeout(Duration::from_millis(CPU_RECV_TIMEOUT_MS))
+                {
+                    Ok(VcpuResponse::Paused) => {}
+                    Err(e) => {
+                        error!("vCPU {} pause error! {:?}", cpu_id, e);
+                        return Err(VcpuManagerError::VcpuResponseTimeout(e));
+                    }
+                    _ => {
+                        error!("vCPU {} pause error!", cpu_id);
+                        return Err(VcpuManagerError::VcpuPause);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// resume all vcpus
     pub fn resume_all_vcpus(&mut self) -> Result<()> {
         self.resume_vcpus(&self.present_vcpus())
@@ -760,6 +796,48 @@ impl VcpuManager {
 
 #[cfg(target_arch = "x86_64")]
 impl VcpuManager {
+    /// Save the state of all present vCPUs, which must have been paused.
+    pub fn save_vcpu_states(&self, msr_indices: &[u32]) -> Result<Vec<VcpuState>> {
+        let mut states = Vec::new();
+        for cpu_id in self.present_vcpus() {
+            let vcpu_fd = self.vcpu_infos[cpu_id as usize]
+                .vcpu_fd
+                .as_ref()
+                .ok_or(VcpuManagerError::VcpuNotCreate)?;
+            let state = VcpuState::save(vcpu_fd, msr_indices).map_err(|e| {
+                error!("failed to save state of vcpu {}: {:?}", cpu_id, e);
+                VcpuManagerError::VcpuSave
+            })?;
+            states.push(state);
+        }
+
+        Ok(states)
+    }
+
+    /// Restore the state of created but not yet started vCPUs, in the order of vCPU index.
+    pub fn restore_vcpu_states(&self, states: &[VcpuState]) -> Result<()> {
+        let vcpu_fds: Vec<&Arc<VcpuFd>> = self
+            .vcpu_infos
+            .iter()
+            .filter(|info| info.vcpu.is_some())
+            .filter_map(|info| info.vcpu_fd.as_ref())
+            .collect();
+        if vcpu_fds.len() != states.len() {
+            error!(
+                "vcpu state count {} doesn't match created vcpu count {}",
+                states.len(),
+                vcpu_fds.len()
+            );
+            return Err(VcpuManagerError::UnexpectedVcpuResponse);
+        }
+
+        for (vcpu_fd, state) in vcpu_fds.into_iter().zip(states) {
+            state.restore(vcpu_fd).map_err(VcpuManagerError::Vcpu)?;
+        }
+
+        Ok(())
+    }
+
     fn create_vcpu_arch(
         &self,
         cpu_index: u8,
@@ -1113,6 +1191,9 @@ mod tests {
             mem_type: "shmem".to_string(),
             mem_file_path: "".to_string(),
             mem_size_mib: 100,
+            pause_on_panic: false,
+            numa_regions: Vec::new(),
+            watchdog: None,
             serial_path: None,
             cpu_topology: CpuTopology {
                 threads_per_core: 1,
@@ -1161,6 +1242,9 @@ mod tests {
             mem_type: "shmem".to_string(),
             mem_file_path: "".to_string(),
             mem_size_mib: 1,
+            pause_on_panic: false,
+            numa_regions: Vec::new(),
+            watchdog: None,
             serial_path: None,
             cpu_topology: CpuTopology {
                 threads_per_core: 1,
@@ -12,7 +12,10 @@ use std::sync::Arc;
 use dbs_arch::cpuid::{process_cpuid, VmSpec};
 use dbs_arch::gdt::gdt_entry;
 use dbs_utils::time::TimestampUs;
-use kvm_bindings::CpuId;
+use kvm_bindings::{
+    kvm_fpu, kvm_lapic_state, kvm_mp_state, kvm_msr_entry, kvm_regs, kvm_sregs, kvm_vcpu_events,
+    kvm_xcrs, kvm_xsave, CpuId, Msrs,
+};
 use kvm_ioctls::{VcpuFd, VmFd};
 use log::error;
 use vm_memory::{Address, GuestAddress, GuestAddressSpace};
@@ -147,3 +150,86 @@ impl Vcpu {
             .map_err(VcpuError::SetSupportedCpusFailed)
     }
 }
+
+/// Architectural state of a x86_64 vCPU, used to migrate a paused vCPU to another VM instance.
+#[derive(Clone, Default)]
+pub struct VcpuState {
+    /// General purpose registers.
+    pub regs: kvm_regs,
+    /// Special registers.
+    pub sregs: kvm_sregs,
+    /// Floating point registers.
+    pub fpu: kvm_fpu,
+    /// Local APIC state.
+    pub lapic: kvm_lapic_state,
+    /// Extended control registers.
+    pub xcrs: kvm_xcrs,
+    /// XSAVE area.
+    pub xsave: kvm_xsave,
+    /// Pending exceptions, interrupts and NMIs.
+    pub vcpu_events: kvm_vcpu_events,
+    /// Multiprocessing state.
+    pub mp_state: kvm_mp_state,
+    /// Model specific registers.
+    pub msrs: Vec<kvm_msr_entry>,
+}
+
+impl VcpuState {
+    /// Save the state of a vCPU, the vCPU must not be running.
+    ///
+    /// `msr_indices` is the list of MSRs to save, usually the list reported by
+    /// `KVM_GET_MSR_INDEX_LIST`. MSRs which can't be read are skipped.
+    pub fn save(fd: &VcpuFd, msr_indices: &[u32]) -> Result<Self> {
+        // KVM_GET_MSRS stops at the first MSR it fails to read, so skip the offending MSR and
+        // carry on with the rest of the list.
+        let mut msrs = Vec::with_capacity(msr_indices.len());
+        let mut pending = msr_indices;
+        while !pending.is_empty() {
+            let entries: Vec<kvm_msr_entry> = pending
+                .iter()
+                .map(|index| kvm_msr_entry {
+                    index: *index,
+                    ..Default::default()
+                })
+                .collect();
+            let mut kvm_msrs = Msrs::from_entries(&entries).map_err(VcpuError::Msr)?;
+            let nmsrs = fd.get_msrs(&mut kvm_msrs).map_err(VcpuError::Kvm)?;
+            msrs.extend_from_slice(&kvm_msrs.as_slice()[..nmsrs]);
+            pending = &pending[std::cmp::min(nmsrs + 1, pending.len())..];
+        }
+
+        Ok(VcpuState {
+            // Get the multiprocessing state first, it may flush pending events into the
+            // registers.
+            mp_state: fd.get_mp_state().map_err(VcpuError::Kvm)?,
+            regs: fd.get_regs().map_err(VcpuError::Kvm)?,
+            sregs: fd.get_sregs().map_err(VcpuError::Kvm)?,
+            fpu: fd.get_fpu().map_err(VcpuError::Kvm)?,
+            lapic: fd.get_lapic().map_err(VcpuError::Kvm)?,
+            xcrs: fd.get_xcrs().map_err(VcpuError::Kvm)?,
+            xsave: fd.get_xsave().map_err(VcpuError::Kvm)?,
+            vcpu_events: fd.get_vcpu_events().map_err(VcpuError::Kvm)?,
+            msrs,
+        })
+    }
+
+    /// Restore the state of a vCPU, which must have been configured but not started yet.
+    pub fn restore(&self, fd: &VcpuFd) -> Result<()> {
+        fd.set_mp_state(self.mp_state).map_err(VcpuError::Kvm)?;
+        fd.set_regs(&self.regs).map_err(VcpuError::Kvm)?;
+        fd.set_sregs(&self.sregs).map_err(VcpuError::Kvm)?;
+        fd.set_xsave(&self.xsave).map_err(VcpuError::Kvm)?;
+        fd.set_xcrs(&self.xcrs).map_err(VcpuError::Kvm)?;
+        fd.set_fpu(&self.fpu).map_err(VcpuError::Kvm)?;
+        fd.set_lapic(&self.lapic).map_err(VcpuError::Kvm)?;
+        let msrs = Msrs::from_entries(&self.msrs).map_err(VcpuError::Msr)?;
+        let nmsrs = fd.set_msrs(&msrs).map_err(VcpuError::Kvm)?;
+        if nmsrs != self.msrs.len() {
+            error!("vcpu: only restored {} of {} MSRs", nmsrs, self.msrs.len());
+        }
+        fd.set_vcpu_events(&self.vcpu_events)
+            .map_err(VcpuError::Kvm)?;
+
+        Ok(())
+    }
+}
@@ -178,6 +178,11 @@ impl Vm {
         event_mgr
             .register_exit_eventfd(reset_evt)
             .map_err(|_| StartMicroVmError::RegisterEvent)?;
+        if let Some(panic_evt) = self.get_guest_panic_eventfd() {
+            event_mgr
+                .register_guest_panic_eventfd(panic_evt)
+                .map_err(|_| StartMicroVmError::RegisterEvent)?;
+        }
 
         Ok(())
     }
@@ -0,0 +1,310 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! ACPI tables describing the guest NUMA topology.
+//!
+//! The guest finds the RSDP through `boot_params.acpi_rsdp_addr`, or by scanning the BIOS area,
+//! and the XSDT only lists the SRAT and SLIT. Without a MADT, the guest keeps enumerating the
+//! CPUs and the IOAPIC from the MP table, and the SRAT maps the APIC IDs of the MP table, which
+//! are the vCPU ids, to the guest NUMA nodes.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use dbs_address_space::NumaNode;
+use vm_memory::{Address, Bytes, GuestAddress, GuestMemory};
+
+/// Guest physical address of the RSDP, in the BIOS read-only memory area scanned by the guest.
+pub const ACPI_RSDP_START: u64 = 0x000e_0000;
+/// End of the area holding the ACPI tables.
+const ACPI_TABLES_END: u64 = 0x0010_0000;
+
+const OEM_ID: &[u8; 6] = b"DRAGON";
+const OEM_TABLE_ID: &[u8; 8] = b"DBNUMA  ";
+const OEM_REVISION: u32 = 1;
+const CREATOR_ID: &[u8; 4] = b"DBVM";
+const CREATOR_REVISION: u32 = 1;
+
+const RSDP_LEN: usize = 36;
+const SDT_HEADER_LEN: usize = 36;
+
+// SRAT structure types and flags.
+const SRAT_PROCESSOR_APIC_AFFINITY: u8 = 0;
+const SRAT_MEMORY_AFFINITY: u8 = 1;
+const SRAT_ENABLED: u32 = 1;
+
+// Distances between the localities in the SLIT.
+const LOCAL_DISTANCE: u8 = 10;
+const REMOTE_DISTANCE: u8 = 20;
+
+/// Errors associated with the setup of the ACPI tables.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum AcpiError {
+    /// The ACPI tables don't fit in the BIOS area.
+    #[error("ACPI tables of {0} bytes are too large")]
+    TooLarge(usize),
+
+    /// An APIC ID doesn't fit in the Processor Local APIC Affinity structure.
+    #[error("vCPU {0} can't be described by the SRAT")]
+    InvalidVcpuId(u32),
+
+    /// Failed to write the ACPI tables to the guest memory.
+    #[error("failed to write ACPI tables to guest memory")]
+    WriteTables,
+}
+
+type Result<T> = std::result::Result<T, AcpiError>;
+
+fn checksum(data: &[u8]) -> u8 {
+    0u8.wrapping_sub(data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)))
+}
+
+// A System Description Table, the header is completed when the table is done.
+struct Sdt {
+    data: Vec<u8>,
+}
+
+impl Sdt {
+    fn new(signature: &[u8; 4], revision: u8) -> Self {
+        let mut data = Vec::with_capacity(SDT_HEADER_LEN);
+        data.extend_from_slice(signature);
+        // length, set by finish()
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.push(revision);
+        // checksum, set by finish()
+        data.push(0);
+        data.extend_from_slice(OEM_ID);
+        data.extend_from_slice(OEM_TABLE_ID);
+        data.extend_from_slice(&OEM_REVISION.to_le_bytes());
+        data.extend_from_slice(CREATOR_ID);
+        data.extend_from_slice(&CREATOR_REVISION.to_le_bytes());
+        Sdt { data }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = self.data.len() as u32;
+        self.data[4..8].copy_from_slice(&len.to_le_bytes());
+        self.data[9] = checksum(&self.data);
+        self.data
+    }
+}
+
+fn build_rsdp(xsdt_addr: u64) -> Vec<u8> {
+    let mut data = Vec::with_capacity(RSDP_LEN);
+    data.extend_from_slice(b"RSD PTR ");
+    // checksum of the ACPI 1.0 part, set below
+    data.push(0);
+    data.extend_from_slice(OEM_ID);
+    // revision 2 for ACPI 2.0 and later, the RSDT isn't provided
+    data.push(2);
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&(RSDP_LEN as u32).to_le_bytes());
+    data.extend_from_slice(&xsdt_addr.to_le_bytes());
+    // extended checksum, set below
+    data.push(0);
+    data.extend_from_slice(&[0u8; 3]);
+    data[8] = checksum(&data[..20]);
+    data[32] = checksum(&data);
+    data
+}
+
+fn build_xsdt(tables: &[u64]) -> Vec<u8> {
+    let mut xsdt = Sdt::new(b"XSDT", 1);
+    for addr in tables {
+        xsdt.push(&addr.to_le_bytes());
+    }
+    xsdt.finish()
+}
+
+fn build_srat(numa_nodes: &BTreeMap<u32, NumaNode>) -> Result<Vec<u8>> {
+    let mut srat = Sdt::new(b"SRAT", 3);
+    // reserved, must be 1 for backward compatibility
+    srat.push(&1u32.to_le_bytes());
+    srat.push(&[0u8; 8]);
+
+    for (node_id, node) in numa_nodes {
+        let domain = node_id.to_le_bytes();
+        for vcpu_id in node.vcpu_ids() {
+            let apic_id = u8::try_from(*vcpu_id).map_err(|_| AcpiError::InvalidVcpuId(*vcpu_id))?;
+            srat.push(&[SRAT_PROCESSOR_APIC_AFFINITY, 16, domain[0], apic_id]);
+            srat.push(&SRAT_ENABLED.to_le_bytes());
+            // local SAPIC EID, then the high bytes of the proximity domain
+            srat.push(&[0, domain[1], domain[2], domain[3]]);
+            // clock domain
+            srat.push(&0u32.to_le_bytes());
+        }
+
+        for info in node.region_infos() {
+            srat.push(&[SRAT_MEMORY_AFFINITY, 40]);
+            srat.push(&node_id.to_le_bytes());
+            srat.push(&[0u8; 2]);
+            srat.push(&info.base.raw_value().to_le_bytes());
+            srat.push(&info.size.to_le_bytes());
+            srat.push(&[0u8; 4]);
+            srat.push(&SRAT_ENABLED.to_le_bytes());
+            srat.push(&[0u8; 8]);
+        }
+    }
+
+    Ok(srat.finish())
+}
+
+fn build_slit(numa_nodes: &BTreeMap<u32, NumaNode>) -> Vec<u8> {
+    // The localities are indexed by the proximity domains.
+    let count = numa_nodes.keys().max().map_or(0, |id| *id as usize + 1);
+    let mut slit = Sdt::new(b"SLIT", 1);
+    slit.push(&(count as u64).to_le_bytes());
+    for i in 0..count {
+        for j in 0..count {
+            slit.push(&[if i == j {
+                LOCAL_DISTANCE
+            } else {
+                REMOTE_DISTANCE
+            }]);
+        }
+    }
+    slit.finish()
+}
+
+/// Write the RSDP, XSDT, SRAT and SLIT describing the guest NUMA nodes to the guest memory.
+///
+/// Return the guest physical address of the RSDP.
+pub fn setup_acpi_tables<M: GuestMemory>(
+    mem: &M,
+    numa_nodes: &BTreeMap<u32, NumaNode>,
+) -> Result<GuestAddress> {
+    let rsdp_addr = GuestAddress(ACPI_RSDP_START);
+    // The tables are aligned on 8 bytes, following the RSDP.
+    let align = |addr: u64| (addr + 7) & !7;
+
+    let srat = build_srat(numa_nodes)?;
+    let slit = build_slit(numa_nodes);
+    let srat_addr = align(ACPI_RSDP_START + RSDP_LEN as u64);
+    let slit_addr = align(srat_addr + srat.len() as u64);
+    let xsdt_addr = align(slit_addr + slit.len() as u64);
+    let xsdt = build_xsdt(&[srat_addr, slit_addr]);
+    let end = xsdt_addr + xsdt.len() as u64;
+    if end > ACPI_TABLES_END {
+        return Err(AcpiError::TooLarge((end - ACPI_RSDP_START) as usize));
+    }
+
+    for (addr, table) in [
+        (rsdp_addr.raw_value(), build_rsdp(xsdt_addr)),
+        (srat_addr, srat),
+        (slit_addr, slit),
+        (xsdt_addr, xsdt),
+    ] {
+        mem.write_slice(&table, GuestAddress(addr))
+            .map_err(|_| AcpiError::WriteTables)?;
+    }
+
+    Ok(rsdp_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use dbs_address_space::NumaNodeInfo;
+    use vm_memory::GuestMemoryMmap;
+
+    use super::*;
+
+    fn numa_nodes() -> BTreeMap<u32, NumaNode> {
+        let mut nodes = BTreeMap::new();
+        let mut node0 = NumaNode::new();
+        node0.add_info(&NumaNodeInfo {
+            base: GuestAddress(0),
+            size: 0x4000_0000,
+        });
+        node0.add_vcpu_ids(&[0, 1]);
+        nodes.insert(0, node0);
+        let mut node1 = NumaNode::new();
+        node1.add_info(&NumaNodeInfo {
+            base: GuestAddress(0x4000_0000),
+            size: 0x4000_0000,
+        });
+        node1.add_vcpu_ids(&[2, 3]);
+        nodes.insert(1, node1);
+        nodes
+    }
+
+    fn read_table(mem: &GuestMemoryMmap, addr: u64) -> Vec<u8> {
+        let mut header = [0u8; SDT_HEADER_LEN];
+        mem.read_slice(&mut header, GuestAddress(addr)).unwrap();
+        let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+        let mut table = vec![0u8; len];
+        mem.read_slice(&mut table, GuestAddress(addr)).unwrap();
+        assert_eq!(checksum(&table), 0);
+        table
+    }
+
+    #[test]
+    fn test_setup_acpi_tables() {
+        let mem = GuestMemoryMmap::<()>::from_ranges(&[(GuestAddress(0), 0x10_0000)]).unwrap();
+        let rsdp_addr = setup_acpi_tables(&mem, &numa_nodes()).unwrap();
+        assert_eq!(rsdp_addr, GuestAddress(ACPI_RSDP_START));
+
+        let mut rsdp = [0u8; RSDP_LEN];
+        mem.read_slice(&mut rsdp, rsdp_addr).unwrap();
+        assert_eq!(&rsdp[..8], b"RSD PTR ");
+        assert_eq!(checksum(&rsdp[..20]), 0);
+        assert_eq!(checksum(&rsdp), 0);
+        assert_eq!(rsdp[15], 2);
+        let xsdt_addr = u64::from_le_bytes(rsdp[24..32].try_into().unwrap());
+
+        let xsdt = read_table(&mem, xsdt_addr);
+        assert_eq!(&xsdt[..4], b"XSDT");
+        assert_eq!(xsdt.len(), SDT_HEADER_LEN + 16);
+        let table_addr = |i: usize| {
+            let off = SDT_HEADER_LEN + i * 8;
+            u64::from_le_bytes(xsdt[off..off + 8].try_into().unwrap())
+        };
+
+        let srat = read_table(&mem, table_addr(0));
+        assert_eq!(&srat[..4], b"SRAT");
+        assert_eq!(srat.len(), SDT_HEADER_LEN + 12 + 4 * 16 + 2 * 40);
+        // the structures of a node follow the ones of the previous node, and the third vCPU is
+        // the first one of the second node
+        let cpu = &srat[SDT_HEADER_LEN + 12 + 2 * 16 + 40..];
+        assert_eq!(&cpu[..4], &[SRAT_PROCESSOR_APIC_AFFINITY, 16, 1, 2]);
+        // the memory of the second node follows its vCPUs, then it's the end of the table
+        let mem_affinity = &srat[SDT_HEADER_LEN + 12 + 4 * 16 + 40..];
+        assert_eq!(&mem_affinity[..2], &[SRAT_MEMORY_AFFINITY, 40]);
+        assert_eq!(&mem_affinity[2..6], &1u32.to_le_bytes());
+        assert_eq!(&mem_affinity[8..16], &0x4000_0000u64.to_le_bytes());
+        assert_eq!(&mem_affinity[16..24], &0x4000_0000u64.to_le_bytes());
+        assert_eq!(&mem_affinity[28..32], &SRAT_ENABLED.to_le_bytes());
+        assert_eq!(mem_affinity.len(), 40);
+
+        let slit = read_table(&mem, table_addr(1));
+        assert_eq!(&slit[..4], b"SLIT");
+        assert_eq!(
+            &slit[SDT_HEADER_LEN..SDT_HEADER_LEN + 8],
+            &2u64.to_le_bytes()
+        );
+        assert_eq!(&slit[SDT_HEADER_LEN + 8..], &[10, 20, 20, 10]);
+    }
+
+    #[test]
+    fn test_setup_acpi_tables_invalid() {
+        let mem = GuestMemoryMmap::<()>::from_ranges(&[(GuestAddress(0), 0x10_0000)]).unwrap();
+        let mut nodes = numa_nodes();
+        nodes.get_mut(&1).unwrap().add_vcpu_ids(&[256]);
+        assert_eq!(
+            setup_acpi_tables(&mem, &nodes),
+            Err(AcpiError::InvalidVcpuId(256))
+        );
+
+        // the BIOS area isn't in the guest memory
+        let mem = GuestMemoryMmap::<()>::from_ranges(&[(GuestAddress(0), 0x8_0000)]).unwrap();
+        assert_eq!(
+            setup_acpi_tables(&mem, &numa_nodes()),
+            Err(AcpiError::WriteTables)
+        );
+    }
+}
@@ -0,0 +1,576 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Dump guest memory and vCPU state into an ELF core file.
+//!
+//! The core file follows the layout generated by `dump-guest-memory` of QEMU, so it can be
+//! analyzed by `crash` or `gdb`. Each vCPU is described by a `NT_PRSTATUS` note and a `QEMU`
+//! note carrying the system registers, and guest memory is described by `PT_LOAD` segments with
+//! guest physical addresses. If paging is requested, the guest kernel page tables are walked to
+//! describe the guest kernel virtual address mappings instead.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+
+use kvm_bindings::{kvm_dtable, kvm_segment};
+use serde_derive::{Deserialize, Serialize};
+use slog::{info, warn};
+use vm_memory::{Address, Bytes, GuestAddress, GuestMemory, GuestMemoryRegion};
+
+use crate::address_space_manager::AddressManagerError;
+use crate::api::v1::InstanceState;
+use crate::vcpu::{VcpuManagerError, VcpuState};
+use crate::vm::Vm;
+
+const ELF_HEADER_SIZE: usize = 64;
+const ELF_PHDR_SIZE: usize = 56;
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_RWX: u32 = 7;
+const NT_PRSTATUS: u32 = 1;
+const NOTE_NAME_CORE: &[u8] = b"CORE\0";
+const NOTE_NAME_QEMU: &[u8] = b"QEMU\0";
+// e_phnum is 16 bits wide, one entry is taken by the note segment.
+const MAX_LOAD_SEGMENTS: usize = u16::MAX as usize - 1;
+
+// Size of `struct elf_prstatus` on x86_64 and the offsets of its fields.
+const PRSTATUS_SIZE: usize = 336;
+const PRSTATUS_PID_OFFSET: usize = 32;
+const PRSTATUS_REGS_OFFSET: usize = 112;
+// Version and size of `QEMUCPUState`.
+const QEMU_CPU_STATE_VERSION: u32 = 1;
+const QEMU_CPU_STATE_SIZE: usize = 440;
+
+const MSR_KERNEL_GS_BASE: u32 = 0xc000_0102;
+
+const PAGE_SIZE: u64 = 0x1000;
+const PTE_PRESENT: u64 = 1;
+const PTE_HUGE_PAGE: u64 = 1 << 7;
+const PTE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+const PAGING_LEVELS: u32 = 4;
+// The kernel lives in the upper half of the canonical address space.
+const KERNEL_PML4_INDEX_START: u64 = 256;
+const CR0_PG: u64 = 1 << 31;
+const CR4_LA57: u64 = 1 << 12;
+const EFER_LMA: u64 = 1 << 10;
+
+/// Configuration information for dumping guest memory.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestMemoryDumpInfo {
+    /// Path of the ELF core file to create.
+    pub path: String,
+    /// Describe the guest kernel virtual address mappings by walking the guest page tables.
+    pub paging: bool,
+}
+
+/// Errors associated with dumping guest memory.
+#[derive(Debug, thiserror::Error)]
+pub enum GuestMemoryDumpError {
+    /// The VM is not paused.
+    #[error("guest memory can only be dumped when the VM is paused")]
+    InvalidState,
+
+    /// Failed to write the core file.
+    #[error("failed to write guest memory dump: {0}")]
+    Io(#[from] io::Error),
+
+    /// Failed to access guest memory.
+    #[error("failed to access guest memory: {0}")]
+    GuestMemory(#[source] vm_memory::GuestMemoryError),
+
+    /// Guest memory is not initialized.
+    #[error("failed to get guest memory: {0}")]
+    AddressManager(#[source] AddressManagerError),
+
+    /// The guest is not running with 4-level paging.
+    #[error("guest is not running with 4-level paging")]
+    UnsupportedPaging,
+
+    /// Failed to save the vCPU states.
+    #[error("failed to get vcpu states: {0}")]
+    Vcpu(#[source] VcpuManagerError),
+}
+
+type Result<T> = std::result::Result<T, GuestMemoryDumpError>;
+
+/// A `PT_LOAD` segment of the core file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Segment {
+    vaddr: u64,
+    paddr: u64,
+    len: u64,
+}
+
+impl Vm {
+    /// Dump guest memory and vCPU states of the paused VM into an ELF core file.
+    pub fn dump_guest_memory(&self, config: &GuestMemoryDumpInfo) -> Result<()> {
+        if self.instance_state() != InstanceState::Paused {
+            return Err(GuestMemoryDumpError::InvalidState);
+        }
+
+        let vm_memory =
+            self.address_space
+                .vm_memory()
+                .ok_or(GuestMemoryDumpError::AddressManager(
+                    AddressManagerError::GuestMemoryNotInitialized,
+                ))?;
+        let vcpus = self
+            .vcpu_manager()
+            .map_err(GuestMemoryDumpError::Vcpu)?
+            .save_vcpu_states(&[MSR_KERNEL_GS_BASE])
+            .map_err(GuestMemoryDumpError::Vcpu)?;
+
+        let physical: Vec<Segment> = vm_memory
+            .iter()
+            .map(|region| Segment {
+                vaddr: 0,
+                paddr: region.start_addr().raw_value(),
+                len: region.len(),
+            })
+            .collect();
+        let segments = match vcpus.first() {
+            Some(vcpu) if config.paging => match kernel_mappings(&*vm_memory, vcpu) {
+                Ok(mappings) if mappings.len() <= MAX_LOAD_SEGMENTS => mappings,
+                Ok(mappings) => {
+                    warn!(
+                        self.logger,
+                        "VM: too many guest kernel mappings ({}), dump without paging",
+                        mappings.len()
+                    );
+                    physical.clone()
+                }
+                Err(e) => {
+                    warn!(
+                        self.logger,
+                        "VM: failed to walk guest page tables, dump without paging: {}", e
+                    );
+                    physical.clone()
+                }
+            },
+            _ => physical.clone(),
+        };
+
+        info!(
+            self.logger,
+            "VM: dump guest memory to {}, {} segments",
+            config.path,
+            segments.len()
+        );
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&config.path)?;
+
+        let notes = build_notes(&vcpus);
+        let data_offset =
+            (ELF_HEADER_SIZE + ELF_PHDR_SIZE * (segments.len() + 1) + notes.len()) as u64;
+        let mut header = Vec::with_capacity(data_offset as usize);
+        put_elf_header(&mut header, segments.len() + 1);
+        put_phdr(
+            &mut header,
+            PT_NOTE,
+            0,
+            (ELF_HEADER_SIZE + ELF_PHDR_SIZE * (segments.len() + 1)) as u64,
+            &Segment {
+                vaddr: 0,
+                paddr: 0,
+                len: notes.len() as u64,
+            },
+        );
+        for segment in segments.iter() {
+            let offset = data_offset + file_offset(&physical, segment.paddr);
+            put_phdr(&mut header, PT_LOAD, PF_RWX, offset, segment);
+        }
+        header.extend_from_slice(&notes);
+        file.write_all(&header)?;
+
+        // Guest physical memory follows the headers, region by region.
+        for region in physical.iter() {
+            vm_memory
+                .write_all_to(GuestAddress(region.paddr), &mut file, region.len as usize)
+                .map_err(GuestMemoryDumpError::GuestMemory)?;
+        }
+        file.sync_all()?;
+
+        Ok(())
+    }
+}
+
+/// Offset of guest physical address `paddr` in the guest memory data of the core file.
+fn file_offset(physical: &[Segment], paddr: u64) -> u64 {
+    let mut offset = 0;
+    for region in physical {
+        if paddr >= region.paddr && paddr < region.paddr + region.len {
+            return offset + paddr - region.paddr;
+        }
+        offset += region.len;
+    }
+    offset
+}
+
+fn put_elf_header(buf: &mut Vec<u8>, phnum: usize) {
+    // e_ident: magic, ELFCLASS64, ELFDATA2LSB, EV_CURRENT, ELFOSABI_NONE.
+    buf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    buf.extend_from_slice(&[0u8; 8]);
+    buf.extend_from_slice(&ET_CORE.to_le_bytes());
+    buf.extend_from_slice(&EM_X86_64.to_le_bytes());
+    buf.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    buf.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    buf.extend_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes()); // e_phoff
+    buf.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    buf.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    buf.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    buf.extend_from_slice(&(ELF_PHDR_SIZE as u16).to_le_bytes());
+    buf.extend_from_slice(&(phnum as u16).to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes()); // e_shentsize
+    buf.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    buf.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+}
+
+fn put_phdr(buf: &mut Vec<u8>, p_type: u32, flags: u32, offset: u64, segment: &Segment) {
+    buf.extend_from_slice(&p_type.to_le_bytes());
+    buf.extend_from_slice(&flags.to_le_bytes());
+    buf.extend_from_slice(&offset.to_le_bytes());
+    buf.extend_from_slice(&segment.vaddr.to_le_bytes());
+    buf.extend_from_slice(&segment.paddr.to_le_bytes());
+    buf.extend_from_slice(&segment.len.to_le_bytes()); // p_filesz
+    buf.extend_from_slice(&segment.len.to_le_bytes()); // p_memsz
+    buf.extend_from_slice(&0u64.to_le_bytes()); // p_align
+}
+
+fn put_note(buf: &mut Vec<u8>, name: &[u8], n_type: u32, desc: &[u8]) {
+    buf.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&n_type.to_le_bytes());
+    for data in [name, desc] {
+        buf.extend_from_slice(data);
+        buf.resize((buf.len() + 3) & !3, 0);
+    }
+}
+
+fn build_notes(vcpus: &[VcpuState]) -> Vec<u8> {
+    let mut notes = Vec::new();
+    for (index, vcpu) in vcpus.iter().enumerate() {
+        put_note(
+            &mut notes,
+            NOTE_NAME_CORE,
+            NT_PRSTATUS,
+            &prstatus(index, vcpu),
+        );
+    }
+    for vcpu in vcpus {
+        put_note(&mut notes, NOTE_NAME_QEMU, 0, &qemu_cpu_state(vcpu));
+    }
+    notes
+}
+
+/// Build the `struct elf_prstatus` of a vCPU.
+fn prstatus(index: usize, vcpu: &VcpuState) -> Vec<u8> {
+    let regs = &vcpu.regs;
+    let sregs = &vcpu.sregs;
+    // Registers in the order of `struct user_regs_struct`.
+    let user_regs: [u64; 27] = [
+        regs.r15,
+        regs.r14,
+        regs.r13,
+        regs.r12,
+        regs.rbp,
+        regs.rbx,
+        regs.r11,
+        regs.r10,
+        regs.r9,
+        regs.r8,
+        regs.rax,
+        regs.rcx,
+        regs.rdx,
+        regs.rsi,
+        regs.rdi,
+        0, // orig_rax
+        regs.rip,
+        sregs.cs.selector as u64,
+        regs.rflags,
+        regs.rsp,
+        sregs.ss.selector as u64,
+        sregs.fs.base,
+        sregs.gs.base,
+        sregs.ds.selector as u64,
+        sregs.es.selector as u64,
+        sregs.fs.selector as u64,
+        sregs.gs.selector as u64,
+    ];
+
+    let mut buf = vec![0u8; PRSTATUS_SIZE];
+    buf[PRSTATUS_PID_OFFSET..PRSTATUS_PID_OFFSET + 4]
+        .copy_from_slice(&(index as u32 + 1).to_le_bytes());
+    for (i, reg) in user_regs.iter().enumerate() {
+        let offset = PRSTATUS_REGS_OFFSET + i * 8;
+        buf[offset..offset + 8].copy_from_slice(&reg.to_le_bytes());
+    }
+    buf
+}
+
+fn put_segment(buf: &mut Vec<u8>, seg: &kvm_segment) {
+    // Descriptor attributes in the layout of the high dword of a segment descriptor.
+    let flags = (seg.type_ as u32) << 8
+        | (seg.s as u32) << 12
+        | (seg.dpl as u32) << 13
+        | (seg.present as u32) << 15
+        | (seg.avl as u32) << 20
+        | (seg.l as u32) << 21
+        | (seg.db as u32) << 22
+        | (seg.g as u32) << 23;
+    buf.extend_from_slice(&(seg.selector as u32).to_le_bytes());
+    buf.extend_from_slice(&seg.limit.to_le_bytes());
+    buf.extend_from_slice(&flags.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.extend_from_slice(&seg.base.to_le_bytes());
+}
+
+fn put_dtable(buf: &mut Vec<u8>, table: &kvm_dtable) {
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.extend_from_slice(&(table.limit as u32).to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.extend_from_slice(&table.base.to_le_bytes());
+}
+
+/// Build the `QEMUCPUState` of a vCPU, which carries the system registers needed by `crash`.
+fn qemu_cpu_state(vcpu: &VcpuState) -> Vec<u8> {
+    let regs = &vcpu.regs;
+    let sregs = &vcpu.sregs;
+    let mut buf = Vec::with_capacity(QEMU_CPU_STATE_SIZE);
+
+    buf.extend_from_slice(&QEMU_CPU_STATE_VERSION.to_le_bytes());
+    buf.extend_from_slice(&(QEMU_CPU_STATE_SIZE as u32).to_le_bytes());
+    for reg in [
+        regs.rax,
+        regs.rbx,
+        regs.rcx,
+        regs.rdx,
+        regs.rsi,
+        regs.rdi,
+        regs.rsp,
+        regs.rbp,
+        regs.r8,
+        regs.r9,
+        regs.r10,
+        regs.r11,
+        regs.r12,
+        regs.r13,
+        regs.r14,
+        regs.r15,
+        regs.rip,
+        regs.rflags,
+    ] {
+        buf.extend_from_slice(&reg.to_le_bytes());
+    }
+    for seg in [
+        &sregs.cs, &sregs.ds, &sregs.es, &sregs.fs, &sregs.gs, &sregs.ss, &sregs.ldt, &sregs.tr,
+    ] {
+        put_segment(&mut buf, seg);
+    }
+    put_dtable(&mut buf, &sregs.gdt);
+    put_dtable(&mut buf, &sregs.idt);
+    for cr in [sregs.cr0, 0, sregs.cr2, sregs.cr3, sregs.cr4] {
+        buf.extend_from_slice(&cr.to_le_bytes());
+    }
+    let kernel_gs_base = vcpu
+        .msrs
+        .iter()
+        .find(|msr| msr.index == MSR_KERNEL_GS_BASE)
+        .map(|msr| msr.data)
+        .unwrap_or_default();
+    buf.extend_from_slice(&kernel_gs_base.to_le_bytes());
+
+    buf
+}
+
+/// Collect the guest kernel virtual address mappings of guest memory, by walking the 4-level
+/// page tables the vCPU is running on.
+fn kernel_mappings<M: GuestMemory>(mem: &M, vcpu: &VcpuState) -> Result<Vec<Segment>> {
+    let sregs = &vcpu.sregs;
+    if sregs.cr0 & CR0_PG == 0 || sregs.efer & EFER_LMA == 0 || sregs.cr4 & CR4_LA57 != 0 {
+        return Err(GuestMemoryDumpError::UnsupportedPaging);
+    }
+
+    let mut mappings = Vec::new();
+    walk_page_table(
+        mem,
+        sregs.cr3 & PTE_ADDR_MASK,
+        PAGING_LEVELS,
+        0,
+        &mut mappings,
+    )?;
+    Ok(mappings)
+}
+
+fn walk_page_table<M: GuestMemory>(
+    mem: &M,
+    table: u64,
+    level: u32,
+    vbase: u64,
+    mappings: &mut Vec<Segment>,
+) -> Result<()> {
+    let mut entries = [0u8; PAGE_SIZE as usize];
+    mem.read_slice(&mut entries, GuestAddress(table))
+        .map_err(GuestMemoryDumpError::GuestMemory)?;
+    let shift = 12 + 9 * (level - 1);
+    let start = if level == PAGING_LEVELS {
+        KERNEL_PML4_INDEX_START
+    } else {
+        0
+    };
+
+    for index in start..512 {
+        let offset = index as usize * 8;
+        let mut pte = [0u8; 8];
+        pte.copy_from_slice(&entries[offset..offset + 8]);
+        let pte = u64::from_le_bytes(pte);
+        if pte & PTE_PRESENT == 0 {
+            continue;
+        }
+
+        let mut vaddr = vbase | index << shift;
+        if level == PAGING_LEVELS && vaddr & (1 << 47) != 0 {
+            // Sign extend to a canonical address.
+            vaddr |= 0xffff_0000_0000_0000;
+        }
+        let paddr = pte & PTE_ADDR_MASK;
+        if level == 1 || (level <= 3 && pte & PTE_HUGE_PAGE != 0) {
+            let len = 1u64 << shift;
+            // Huge pages are aligned to their size, drop the attribute bits above 4K.
+            let paddr = paddr & !(len - 1);
+            add_mapping(mem, mappings, vaddr, paddr, len);
+        } else if level > 1 {
+            walk_page_table(mem, paddr, level - 1, vaddr, mappings)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn add_mapping<M: GuestMemory>(
+    mem: &M,
+    mappings: &mut Vec<Segment>,
+    vaddr: u64,
+    paddr: u64,
+    len: u64,
+) {
+    // Skip mappings of MMIO and other non guest memory ranges.
+    if mem.check_range(GuestAddress(paddr), len as usize) {
+        match mappings.last_mut() {
+            Some(last) if last.vaddr + last.len == vaddr && last.paddr + last.len == paddr => {
+                last.len += len
+            }
+            _ => mappings.push(Segment { vaddr, paddr, len }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vm_memory::GuestMemoryMmap;
+
+    #[test]
+    fn test_core_file_layout() {
+        let mut header = Vec::new();
+        put_elf_header(&mut header, 3);
+        assert_eq!(header.len(), ELF_HEADER_SIZE);
+        put_phdr(
+            &mut header,
+            PT_LOAD,
+            PF_RWX,
+            0x1000,
+            &Segment {
+                vaddr: 0,
+                paddr: 0x100000,
+                len: 0x2000,
+            },
+        );
+        assert_eq!(header.len(), ELF_HEADER_SIZE + ELF_PHDR_SIZE);
+
+        let vcpus = vec![VcpuState::default(); 2];
+        assert_eq!(prstatus(0, &vcpus[0]).len(), PRSTATUS_SIZE);
+        assert_eq!(qemu_cpu_state(&vcpus[0]).len(), QEMU_CPU_STATE_SIZE);
+        let notes = build_notes(&vcpus);
+        assert_eq!(notes.len() % 4, 0);
+        assert_eq!(
+            notes.len(),
+            2 * (12 + 8 + PRSTATUS_SIZE) + 2 * (12 + 8 + QEMU_CPU_STATE_SIZE)
+        );
+
+        let physical = [
+            Segment {
+                vaddr: 0,
+                paddr: 0,
+                len: 0x10000,
+            },
+            Segment {
+                vaddr: 0,
+                paddr: 0x100000,
+                len: 0x10000,
+            },
+        ];
+        assert_eq!(file_offset(&physical, 0x1000), 0x1000);
+        assert_eq!(file_offset(&physical, 0x101000), 0x11000);
+    }
+
+    #[test]
+    fn test_kernel_mappings() {
+        let mem = GuestMemoryMmap::<()>::from_ranges(&[(GuestAddress(0), 0x40_0000)]).unwrap();
+        // PML4 at 0x1000, PDPT at 0x2000, PD at 0x3000, PT at 0x4000.
+        let pml4_index = 256u64;
+        mem.write_obj(
+            0x2000u64 | PTE_PRESENT,
+            GuestAddress(0x1000 + pml4_index * 8),
+        )
+        .unwrap();
+        mem.write_obj(0x3000u64 | PTE_PRESENT, GuestAddress(0x2000))
+            .unwrap();
+        // A 2M huge page mapping physical [0x200000, 0x400000).
+        mem.write_obj(
+            0x20_0000u64 | PTE_PRESENT | PTE_HUGE_PAGE,
+            GuestAddress(0x3000),
+        )
+        .unwrap();
+        // Two 4K pages following the huge page, physically contiguous with it.
+        mem.write_obj(0x4000u64 | PTE_PRESENT, GuestAddress(0x3008))
+            .unwrap();
+        mem.write_obj(0x40_0000u64 | PTE_PRESENT, GuestAddress(0x4000))
+            .unwrap();
+        mem.write_obj(0x1000u64 | PTE_PRESENT, GuestAddress(0x4008))
+            .unwrap();
+
+        let mut vcpu = VcpuState::default();
+        vcpu.sregs.cr0 = CR0_PG;
+        vcpu.sregs.efer = EFER_LMA;
+        vcpu.sregs.cr3 = 0x1000;
+        let mappings = kernel_mappings(&mem, &vcpu).unwrap();
+
+        let base = 0xffff_8000_0000_0000u64;
+        assert_eq!(
+            mappings,
+            vec![
+                Segment {
+                    vaddr: base,
+                    paddr: 0x20_0000,
+                    len: 0x20_0000,
+                },
+                // 0x400000 is out of guest memory and skipped.
+                Segment {
+                    vaddr: base + 0x20_1000,
+                    paddr: 0x1000,
+                    len: 0x1000,
+                },
+            ]
+        );
+
+        vcpu.sregs.cr0 = 0;
+        assert!(kernel_mappings(&mem, &vcpu).is_err());
+    }
+}
@@ -0,0 +1,1189 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pre-copy live migration of a virtual machine instance.
+//!
+//! The source VM keeps running while its guest memory is copied to the destination VM. KVM dirty
+//! page logging is used to track pages written by the guest during the copy, and dirty pages are
+//! copied again iteratively until the amount of dirty memory is small enough or the iteration
+//! limit is reached. Then the source vCPUs are paused, the remaining dirty pages, the vCPU states
+//! and the in-kernel device states are sent over, and the destination VM resumes the guest.
+//!
+//! The virtio devices write to guest memory without going through KVM dirty page logging, so they
+//! are paused during the whole migration: the guest may queue requests, but they are only
+//! processed once the devices have been restored on the destination. The virtio-mmio transport
+//! state, the queues and the negotiated features of the block, virtio-fs, virtio-net and vsock
+//! devices are migrated, the backends are opened again by the destination VM.
+//!
+//! Live migration is only built with the `live-migration` feature.
+//!
+//! Limitations:
+//! - only x86_64 is supported.
+//! - the destination VM must be configured with the same machine configuration, boot source and
+//!   devices as the source VM, the guest kernel is not loaded again on the destination.
+//! - the disk images and the shared directories must be reachable from both hosts, their content
+//!   is not copied.
+//! - vsock connections are reset on the destination, the guest is told so by a transport reset
+//!   event.
+//! - virtio-fs devices can only be migrated before the guest has mounted them, the FUSE session
+//!   lives in the file system server. Vhost-user devices can't be migrated.
+//! - the registers of the legacy serial ports are migrated, but not the bytes pending in their
+//!   FIFOs. The i8042 device has no state to migrate.
+//! - the source VM is left paused after a successful migration, and the caller is responsible
+//!   for shutting it down.
+
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Mutex};
+
+use dbs_device::DeviceIo;
+use dbs_legacy_devices::SerialDevice;
+use dbs_utils::time::TimestampUs;
+use dbs_virtio_devices::mmio::MmioV2MigrationState;
+use dbs_virtio_devices::Error as VirtioError;
+use kvm_bindings::{
+    kvm_clock_data, kvm_fpu, kvm_irqchip, kvm_lapic_state, kvm_mp_state, kvm_msr_entry,
+    kvm_pit_state2, kvm_regs, kvm_sregs, kvm_vcpu_events, kvm_xcrs, kvm_xsave,
+};
+use seccompiler::BpfProgram;
+use serde_derive::{Deserialize, Serialize};
+use slog::{error, info, warn};
+use vm_memory::{Address, Bytes, GuestAddress, GuestMemory, GuestMemoryRegion};
+
+use crate::address_space_manager::AddressManagerError;
+use crate::api::v1::InstanceState;
+use crate::device_manager::DbsMmioV2Device;
+use crate::error::StartMicroVmError;
+use crate::event_manager::EventManager;
+use crate::vcpu::{VcpuManagerError, VcpuState};
+use crate::vm::{Vm, VmConfigInfo};
+
+/// Magic at the beginning of a migration stream, including the stream format version.
+const MIGRATION_MAGIC: &[u8; 8] = b"DBMIGR03";
+
+const CMD_CONFIG: u32 = 1;
+const CMD_MEMORY: u32 = 2;
+const CMD_STATE: u32 = 3;
+const CMD_COMPLETE: u32 = 4;
+const CMD_ACK: u32 = 5;
+const CMD_ERROR: u32 = 6;
+const CMD_DEVICES: u32 = 7;
+
+/// Upper bound of the payload of a non-memory frame.
+const MAX_PAYLOAD_SIZE: u64 = 64 << 20;
+const PAGE_SIZE: u64 = 4096;
+/// Number of in-kernel interrupt controllers: master PIC, slave PIC and IOAPIC.
+const IRQCHIP_COUNT: usize = 3;
+
+// Registers of the 8250 serial port, see vm-superio.
+const SERIAL_DLL_OFFSET: u8 = 0;
+const SERIAL_DLM_OFFSET: u8 = 1;
+const SERIAL_IER_OFFSET: u8 = 1;
+const SERIAL_LCR_OFFSET: u8 = 3;
+const SERIAL_MCR_OFFSET: u8 = 4;
+const SERIAL_SCR_OFFSET: u8 = 7;
+// Divisor latch access bit of the line control register.
+const SERIAL_LCR_DLAB: u8 = 0x80;
+
+/// Default max number of iterative memory copies before stopping the source VM.
+pub const DEFAULT_MIGRATION_MAX_ITERATIONS: u32 = 10;
+/// Default number of dirty pages below which the source VM is stopped for the last copy.
+pub const DEFAULT_MIGRATION_DOWNTIME_PAGES: u64 = 256;
+
+/// Errors associated with live migration.
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    /// Invalid migration url.
+    #[error("invalid migration url: {0}, expect unix:<path> or tcp:<host:port>")]
+    InvalidUrl(String),
+
+    /// Failed to transfer data over the migration stream.
+    #[error("failed to transfer migration data: {0}")]
+    Io(#[from] io::Error),
+
+    /// The migration stream is malformed.
+    #[error("invalid migration stream: {0}")]
+    InvalidStream(String),
+
+    /// The VM is not in a state which allows the migration.
+    #[error("the VM is not in a state which allows migration")]
+    InvalidState,
+
+    /// Failed to save or restore the state of a virtio device.
+    #[error("failed to migrate the state of device {0}: {1}")]
+    DeviceState(String, #[source] VirtioError),
+
+    /// The source and destination VMs are configured differently.
+    #[error("VM configuration mismatch: {0}")]
+    ConfigMismatch(String),
+
+    /// Failed to access guest memory.
+    #[error("failed to access guest memory: {0}")]
+    GuestMemory(#[source] vm_memory::GuestMemoryError),
+
+    /// Failed to manage guest memory.
+    #[error("failed to manage guest memory: {0}")]
+    AddressManager(#[source] AddressManagerError),
+
+    /// Failed to save or restore vCPUs.
+    #[error("failed to migrate vcpus: {0}")]
+    Vcpu(#[source] VcpuManagerError),
+
+    /// Failed to save or restore the in-kernel VM state.
+    #[error("failed to migrate VM state: {0}")]
+    VmState(#[source] kvm_ioctls::Error),
+
+    /// Failed to restore the state of a legacy serial port.
+    #[error("failed to migrate serial port state: {0}")]
+    SerialState(String),
+
+    /// Failed to set up the destination VM.
+    #[error("failed to set up the destination VM: {0}")]
+    StartMicroVm(#[source] StartMicroVmError),
+
+    /// The peer reported an error.
+    #[error("migration peer reported an error: {0}")]
+    Remote(String),
+}
+
+type Result<T> = std::result::Result<T, MigrationError>;
+
+/// Configuration information for live migration.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MigrationConfigInfo {
+    /// Migration channel, `unix:<path>` or `tcp:<host:port>`. The destination listens on it and
+    /// the source connects to it.
+    pub url: String,
+    /// Max number of iterative memory copies before stopping the source VM.
+    pub max_iterations: u32,
+    /// Number of dirty pages below which the source VM is stopped for the last copy.
+    pub downtime_pages: u64,
+}
+
+impl Default for MigrationConfigInfo {
+    fn default() -> Self {
+        MigrationConfigInfo {
+            url: String::new(),
+            max_iterations: DEFAULT_MIGRATION_MAX_ITERATIONS,
+            downtime_pages: DEFAULT_MIGRATION_DOWNTIME_PAGES,
+        }
+    }
+}
+
+/// Machine configuration which must be identical on both sides of the migration.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct MigrationVmInfo {
+    mem_size_mib: usize,
+    vcpu_count: u8,
+    max_vcpu_count: u8,
+}
+
+impl From<&VmConfigInfo> for MigrationVmInfo {
+    fn from(config: &VmConfigInfo) -> Self {
+        MigrationVmInfo {
+            mem_size_mib: config.mem_size_mib,
+            vcpu_count: config.vcpu_count,
+            max_vcpu_count: config.max_vcpu_count,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MigrationUrl<'a> {
+    Unix(&'a str),
+    Tcp(&'a str),
+}
+
+impl<'a> MigrationUrl<'a> {
+    fn parse(url: &'a str) -> Result<Self> {
+        match url.split_once(':') {
+            Some(("unix", path)) if !path.is_empty() => Ok(MigrationUrl::Unix(path)),
+            Some(("tcp", addr)) if !addr.is_empty() => Ok(MigrationUrl::Tcp(addr)),
+            _ => Err(MigrationError::InvalidUrl(url.to_string())),
+        }
+    }
+}
+
+enum MigrationStream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl MigrationStream {
+    fn connect(url: &str) -> Result<Self> {
+        let stream = match MigrationUrl::parse(url)? {
+            MigrationUrl::Unix(path) => MigrationStream::Unix(UnixStream::connect(path)?),
+            MigrationUrl::Tcp(addr) => MigrationStream::Tcp(TcpStream::connect(addr)?),
+        };
+
+        Ok(stream)
+    }
+
+    /// Listen on `url` and accept one incoming connection.
+    fn accept(url: &str) -> Result<Self> {
+        let stream = match MigrationUrl::parse(url)? {
+            MigrationUrl::Unix(path) => {
+                let _ = std::fs::remove_file(path);
+                let listener = UnixListener::bind(path)?;
+                let result = listener.accept();
+                let _ = std::fs::remove_file(path);
+                MigrationStream::Unix(result?.0)
+            }
+            MigrationUrl::Tcp(addr) => {
+                let listener = TcpListener::bind(addr)?;
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                MigrationStream::Tcp(stream)
+            }
+        };
+
+        Ok(stream)
+    }
+}
+
+impl Read for MigrationStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MigrationStream::Unix(s) => s.read(buf),
+            MigrationStream::Tcp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for MigrationStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            MigrationStream::Unix(s) => s.write(buf),
+            MigrationStream::Tcp(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            MigrationStream::Unix(s) => s.flush(),
+            MigrationStream::Tcp(s) => s.flush(),
+        }
+    }
+}
+
+fn write_frame_header<W: Write>(stream: &mut W, cmd: u32, len: u64) -> io::Result<()> {
+    stream.write_all(&cmd.to_le_bytes())?;
+    stream.write_all(&len.to_le_bytes())
+}
+
+fn read_frame_header<R: Read>(stream: &mut R) -> io::Result<(u32, u64)> {
+    let mut cmd = [0u8; 4];
+    let mut len = [0u8; 8];
+    stream.read_exact(&mut cmd)?;
+    stream.read_exact(&mut len)?;
+    Ok((u32::from_le_bytes(cmd), u64::from_le_bytes(len)))
+}
+
+fn send_frame<W: Write>(stream: &mut W, cmd: u32, payload: &[u8]) -> io::Result<()> {
+    write_frame_header(stream, cmd, payload.len() as u64)?;
+    stream.write_all(payload)
+}
+
+fn read_payload<R: Read>(stream: &mut R, len: u64) -> Result<Vec<u8>> {
+    if len > MAX_PAYLOAD_SIZE {
+        return Err(MigrationError::InvalidStream(format!(
+            "frame payload too large: {}",
+            len
+        )));
+    }
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Wait for the peer to acknowledge the previous frame.
+fn wait_for_ack<R: Read>(stream: &mut R) -> Result<()> {
+    match read_frame_header(stream)? {
+        (CMD_ACK, 0) => Ok(()),
+        (CMD_ERROR, len) => {
+            let msg = read_payload(stream, len)?;
+            Err(MigrationError::Remote(
+                String::from_utf8_lossy(&msg).into_owned(),
+            ))
+        }
+        (cmd, _) => Err(MigrationError::InvalidStream(format!(
+            "unexpected command {} while waiting for ack",
+            cmd
+        ))),
+    }
+}
+
+/// Plain old data which can be copied to and from the migration stream as raw bytes.
+///
+/// # Safety
+/// Implementors must be `repr(C)` types for which any bit pattern is a valid value.
+unsafe trait Pod: Copy + Default {}
+
+unsafe impl Pod for u32 {}
+unsafe impl Pod for kvm_clock_data {}
+unsafe impl Pod for kvm_fpu {}
+unsafe impl Pod for kvm_irqchip {}
+unsafe impl Pod for kvm_lapic_state {}
+unsafe impl Pod for kvm_mp_state {}
+unsafe impl Pod for kvm_msr_entry {}
+unsafe impl Pod for kvm_pit_state2 {}
+unsafe impl Pod for kvm_regs {}
+unsafe impl Pod for kvm_sregs {}
+unsafe impl Pod for kvm_vcpu_events {}
+unsafe impl Pod for kvm_xcrs {}
+unsafe impl Pod for kvm_xsave {}
+unsafe impl Pod for SerialState {}
+
+fn put<T: Pod>(buf: &mut Vec<u8>, val: &T) {
+    // Safe because `T` is plain old data and the slice covers exactly the memory of `val`.
+    let bytes =
+        unsafe { std::slice::from_raw_parts(val as *const T as *const u8, mem::size_of::<T>()) };
+    buf.extend_from_slice(bytes);
+}
+
+fn get<T: Pod>(buf: &mut &[u8]) -> Result<T> {
+    let size = mem::size_of::<T>();
+    if buf.len() < size {
+        return Err(MigrationError::InvalidStream(
+            "truncated VM state".to_string(),
+        ));
+    }
+    let mut val = T::default();
+    // Safe because `T` is plain old data, any bit pattern is a valid value, and we have checked
+    // that the source buffer is large enough.
+    unsafe {
+        std::ptr::copy_nonoverlapping(buf.as_ptr(), &mut val as *mut T as *mut u8, size);
+    }
+    *buf = &buf[size..];
+    Ok(val)
+}
+
+/// Registers of a legacy serial port, set up by the guest driver.
+///
+/// The other registers only report the transient state of the FIFOs and of the interrupts, they
+/// can't be written and are rebuilt from the registers below by the device model.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct SerialState {
+    dll: u8,
+    dlm: u8,
+    ier: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+}
+
+impl SerialState {
+    fn save(device: &mut SerialDevice) -> Self {
+        let serial = &mut device.serial;
+        // None of these registers has side effects when read, and writing the line control
+        // register never fails.
+        let lcr = serial.read(SERIAL_LCR_OFFSET);
+        let _ = serial.write(SERIAL_LCR_OFFSET, lcr | SERIAL_LCR_DLAB);
+        let dll = serial.read(SERIAL_DLL_OFFSET);
+        let dlm = serial.read(SERIAL_DLM_OFFSET);
+        let _ = serial.write(SERIAL_LCR_OFFSET, lcr);
+
+        SerialState {
+            dll,
+            dlm,
+            ier: serial.read(SERIAL_IER_OFFSET),
+            lcr,
+            mcr: serial.read(SERIAL_MCR_OFFSET),
+            scr: serial.read(SERIAL_SCR_OFFSET),
+        }
+    }
+
+    fn restore(&self, device: &mut SerialDevice) -> Result<()> {
+        let serial = &mut device.serial;
+        for (offset, value) in [
+            (SERIAL_LCR_OFFSET, self.lcr | SERIAL_LCR_DLAB),
+            (SERIAL_DLL_OFFSET, self.dll),
+            (SERIAL_DLM_OFFSET, self.dlm),
+            (SERIAL_LCR_OFFSET, self.lcr),
+            (SERIAL_MCR_OFFSET, self.mcr),
+            (SERIAL_SCR_OFFSET, self.scr),
+            // Enabling the interrupts last raises the pending ones, as the real device would.
+            (SERIAL_IER_OFFSET, self.ier),
+        ] {
+            serial
+                .write(offset, value)
+                .map_err(|e| MigrationError::SerialState(format!("{:?}", e)))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// State of a VM which is not kept in guest memory: in-kernel state and legacy device state.
+#[derive(Default)]
+struct VmState {
+    pit: kvm_pit_state2,
+    irqchips: [kvm_irqchip; IRQCHIP_COUNT],
+    clock: kvm_clock_data,
+    vcpus: Vec<VcpuState>,
+    serials: Vec<SerialState>,
+}
+
+impl VmState {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put(&mut buf, &self.pit);
+        for irqchip in self.irqchips.iter() {
+            put(&mut buf, irqchip);
+        }
+        put(&mut buf, &self.clock);
+        put(&mut buf, &(self.vcpus.len() as u32));
+        for vcpu in self.vcpus.iter() {
+            put(&mut buf, &vcpu.regs);
+            put(&mut buf, &vcpu.sregs);
+            put(&mut buf, &vcpu.fpu);
+            put(&mut buf, &vcpu.lapic);
+            put(&mut buf, &vcpu.xcrs);
+            put(&mut buf, &vcpu.xsave);
+            put(&mut buf, &vcpu.vcpu_events);
+            put(&mut buf, &vcpu.mp_state);
+            put(&mut buf, &(vcpu.msrs.len() as u32));
+            for msr in vcpu.msrs.iter() {
+                put(&mut buf, msr);
+            }
+        }
+        put(&mut buf, &(self.serials.len() as u32));
+        for serial in self.serials.iter() {
+            put(&mut buf, serial);
+        }
+        buf
+    }
+
+    fn deserialize(mut buf: &[u8]) -> Result<Self> {
+        let buf = &mut buf;
+        let mut state = VmState {
+            pit: get(buf)?,
+            ..Default::default()
+        };
+        for irqchip in state.irqchips.iter_mut() {
+            *irqchip = get(buf)?;
+        }
+        state.clock = get(buf)?;
+        let vcpu_count: u32 = get(buf)?;
+        for _ in 0..vcpu_count {
+            let mut vcpu = VcpuState {
+                regs: get(buf)?,
+                sregs: get(buf)?,
+                fpu: get(buf)?,
+                lapic: get(buf)?,
+                xcrs: get(buf)?,
+                xsave: get(buf)?,
+                vcpu_events: get(buf)?,
+                mp_state: get(buf)?,
+                msrs: Vec::new(),
+            };
+            let msr_count: u32 = get(buf)?;
+            for _ in 0..msr_count {
+                vcpu.msrs.push(get(buf)?);
+            }
+            state.vcpus.push(vcpu);
+        }
+        let serial_count: u32 = get(buf)?;
+        for _ in 0..serial_count {
+            state.serials.push(get(buf)?);
+        }
+        if !buf.is_empty() {
+            return Err(MigrationError::InvalidStream(
+                "trailing data in VM state".to_string(),
+            ));
+        }
+
+        Ok(state)
+    }
+}
+
+/// State of a virtio-mmio device, identified by the id of its configuration.
+#[derive(Debug, Serialize, Deserialize)]
+struct DeviceState {
+    id: String,
+    mmio_ranges: Vec<(u64, u64)>,
+    irq: Option<u32>,
+    state: MmioV2MigrationState,
+}
+
+fn mmio_device<'a>(id: &str, device: &'a Arc<dyn DeviceIo>) -> Result<&'a DbsMmioV2Device> {
+    device
+        .as_any()
+        .downcast_ref::<DbsMmioV2Device>()
+        .ok_or_else(|| {
+            MigrationError::DeviceState(
+                id.to_string(),
+                VirtioError::Migration("not a virtio-mmio device".to_string()),
+            )
+        })
+}
+
+impl Vm {
+    /// Live migrate the running VM to the destination VM listening on `config.url`.
+    ///
+    /// The VM is left paused after a successful migration, or resumed if the migration failed
+    /// after pausing it.
+    pub fn send_migration(&mut self, config: &MigrationConfigInfo) -> Result<()> {
+        if !self.is_vm_running() {
+            return Err(MigrationError::InvalidState);
+        }
+
+        info!(self.logger, "VM: start migration to {}", config.url);
+        let mut stream = MigrationStream::connect(&config.url)?;
+        self.pause_virtio_devices()?;
+        if let Err(e) = self.address_space.set_dirty_page_logging(&self.vm_fd, true) {
+            self.resume_virtio_devices();
+            return Err(MigrationError::AddressManager(e));
+        }
+
+        let mut paused = false;
+        let result = self.do_send_migration(&mut stream, config, &mut paused);
+
+        if let Err(e) = self
+            .address_space
+            .set_dirty_page_logging(&self.vm_fd, false)
+        {
+            warn!(
+                self.logger,
+                "VM: failed to disable dirty page logging: {}", e
+            );
+        }
+        match &result {
+            Ok(()) => {
+                self.set_instance_state(InstanceState::Paused);
+                info!(self.logger, "VM: migration completed");
+            }
+            Err(e) => {
+                error!(self.logger, "VM: migration failed: {}", e);
+                let _ = send_frame(&mut stream, CMD_ERROR, e.to_string().as_bytes());
+                self.resume_virtio_devices();
+                if paused {
+                    if let Err(e) = self.resume_all_vcpus_with_downtime() {
+                        error!(self.logger, "VM: failed to resume vcpus: {}", e);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    fn do_send_migration(
+        &mut self,
+        stream: &mut MigrationStream,
+        config: &MigrationConfigInfo,
+        paused: &mut bool,
+    ) -> Result<()> {
+        stream.write_all(MIGRATION_MAGIC)?;
+        let info = serde_json::to_vec(&MigrationVmInfo::from(&self.vm_config))
+            .map_err(|e| MigrationError::InvalidStream(e.to_string()))?;
+        send_frame(stream, CMD_CONFIG, &info)?;
+        wait_for_ack(stream)?;
+
+        // Copy the whole guest memory while the guest keeps running.
+        let vm_memory = self
+            .address_space
+            .vm_memory()
+            .ok_or(MigrationError::AddressManager(
+                AddressManagerError::GuestMemoryNotInitialized,
+            ))?;
+        let ranges: Vec<(u64, u64)> = vm_memory
+            .iter()
+            .map(|region| (region.start_addr().raw_value(), region.len()))
+            .collect();
+        self.send_memory(stream, &ranges)?;
+
+        // Copy pages dirtied by the guest in the meantime, until the remaining dirty memory is
+        // small enough to be copied while the guest is stopped.
+        for iteration in 0..config.max_iterations {
+            let ranges = self
+                .address_space
+                .get_dirty_ranges(&self.vm_fd)
+                .map_err(MigrationError::AddressManager)?;
+            let pages = ranges.iter().map(|(_, len)| len / PAGE_SIZE).sum::<u64>();
+            info!(
+                self.logger,
+                "VM: migration iteration {}, {} dirty pages", iteration, pages
+            );
+            self.send_memory(stream, &ranges)?;
+            if pages <= config.downtime_pages {
+                break;
+            }
+        }
+
+        self.start_instance_downtime = TimestampUs::default().time_us;
+        self.vcpu_manager()
+            .map_err(MigrationError::Vcpu)?
+            .pause_all_vcpus_sync()
+            .map_err(MigrationError::Vcpu)?;
+        *paused = true;
+
+        let ranges = self
+            .address_space
+            .get_dirty_ranges(&self.vm_fd)
+            .map_err(MigrationError::AddressManager)?;
+        self.send_memory(stream, &ranges)?;
+
+        let state = self.save_vm_state()?;
+        send_frame(stream, CMD_STATE, &state.serialize())?;
+        let devices = serde_json::to_vec(&self.save_virtio_devices()?)
+            .map_err(|e| MigrationError::InvalidStream(e.to_string()))?;
+        send_frame(stream, CMD_DEVICES, &devices)?;
+        send_frame(stream, CMD_COMPLETE, &[])?;
+        wait_for_ack(stream)
+    }
+
+    /// Pause the virtio devices, so they don't write to guest memory behind the dirty page
+    /// logging. The devices already paused are resumed on failure.
+    fn pause_virtio_devices(&self) -> Result<()> {
+        let devices = self.device_manager.get_virtio_devices();
+        for (index, (id, device)) in devices.iter().enumerate() {
+            if let Err(e) = mmio_device(id, device).and_then(|mmio_dev| {
+                mmio_dev
+                    .pause()
+                    .map_err(|e| MigrationError::DeviceState(id.clone(), e))
+            }) {
+                for (id, device) in devices[..index].iter() {
+                    self.resume_virtio_device(id, device);
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resume_virtio_devices(&self) {
+        for (id, device) in self.device_manager.get_virtio_devices().iter() {
+            self.resume_virtio_device(id, device);
+        }
+    }
+
+    fn resume_virtio_device(&self, id: &str, device: &Arc<dyn DeviceIo>) {
+        if let Err(e) = mmio_device(id, device).and_then(|mmio_dev| {
+            mmio_dev
+                .resume()
+                .map_err(|e| MigrationError::DeviceState(id.to_string(), e))
+        }) {
+            error!(self.logger, "VM: failed to resume device: {}", e);
+        }
+    }
+
+    fn save_virtio_devices(&self) -> Result<Vec<DeviceState>> {
+        self.device_manager
+            .get_virtio_devices()
+            .iter()
+            .map(|(id, device)| {
+                let state = mmio_device(id, device)?
+                    .save_state()
+                    .map_err(|e| MigrationError::DeviceState(id.clone(), e))?;
+                let resources = device.get_assigned_resources();
+                Ok(DeviceState {
+                    id: id.clone(),
+                    mmio_ranges: resources.get_mmio_address_ranges(),
+                    irq: resources.get_legacy_irq(),
+                    state,
+                })
+            })
+            .collect()
+    }
+
+    fn send_memory(&self, stream: &mut MigrationStream, ranges: &[(u64, u64)]) -> Result<()> {
+        let vm_memory = self
+            .address_space
+            .vm_memory()
+            .ok_or(MigrationError::AddressManager(
+                AddressManagerError::GuestMemoryNotInitialized,
+            ))?;
+        for (gpa, len) in ranges {
+            write_frame_header(stream, CMD_MEMORY, mem::size_of::<u64>() as u64 + len)?;
+            stream.write_all(&gpa.to_le_bytes())?;
+            vm_memory
+                .write_all_to(GuestAddress(*gpa), stream, *len as usize)
+                .map_err(MigrationError::GuestMemory)?;
+        }
+
+        Ok(())
+    }
+
+    fn save_vm_state(&self) -> Result<VmState> {
+        let msr_list = self
+            .kvm
+            .supported_msrs(0)
+            .map_err(MigrationError::VmState)?;
+        let vcpus = self
+            .vcpu_manager()
+            .map_err(MigrationError::Vcpu)?
+            .save_vcpu_states(msr_list.as_slice())
+            .map_err(MigrationError::Vcpu)?;
+
+        let serials = self
+            .serial_devices()
+            .iter()
+            .map(|device| SerialState::save(&mut device.lock().unwrap()))
+            .collect();
+
+        let mut state = VmState {
+            pit: self.vm_fd.get_pit2().map_err(MigrationError::VmState)?,
+            clock: self.vm_fd.get_clock().map_err(MigrationError::VmState)?,
+            vcpus,
+            serials,
+            ..Default::default()
+        };
+        for (chip_id, irqchip) in state.irqchips.iter_mut().enumerate() {
+            irqchip.chip_id = chip_id as u32;
+            self.vm_fd
+                .get_irqchip(irqchip)
+                .map_err(MigrationError::VmState)?;
+        }
+
+        Ok(state)
+    }
+
+    /// Receive a live migrated VM on `config.url` and resume it.
+    ///
+    /// The VM must have been configured exactly as the source VM but not started yet.
+    pub fn receive_migration(
+        &mut self,
+        event_mgr: &mut EventManager,
+        vmm_seccomp_filter: BpfProgram,
+        vcpu_seccomp_filter: BpfProgram,
+        config: &MigrationConfigInfo,
+    ) -> Result<()> {
+        if self.is_vm_initialized() {
+            return Err(MigrationError::InvalidState);
+        }
+
+        info!(
+            self.logger,
+            "VM: wait for incoming migration on {}", config.url
+        );
+        let mut stream = MigrationStream::accept(&config.url)?;
+        let result = self.do_receive_migration(
+            &mut stream,
+            event_mgr,
+            vmm_seccomp_filter,
+            vcpu_seccomp_filter,
+        );
+
+        match &result {
+            Ok(()) => {
+                send_frame(&mut stream, CMD_ACK, &[])?;
+                info!(self.logger, "VM: incoming migration completed");
+            }
+            Err(e) => {
+                error!(self.logger, "VM: incoming migration failed: {}", e);
+                let _ = send_frame(&mut stream, CMD_ERROR, e.to_string().as_bytes());
+            }
+        }
+
+        result
+    }
+
+    fn do_receive_migration(
+        &mut self,
+        stream: &mut MigrationStream,
+        event_mgr: &mut EventManager,
+        vmm_seccomp_filter: BpfProgram,
+        vcpu_seccomp_filter: BpfProgram,
+    ) -> Result<()> {
+        let mut magic = [0u8; 8];
+        stream.read_exact(&mut magic)?;
+        if &magic != MIGRATION_MAGIC {
+            return Err(MigrationError::InvalidStream(
+                "bad migration magic".to_string(),
+            ));
+        }
+
+        let info = match read_frame_header(stream)? {
+            (CMD_CONFIG, len) => {
+                let payload = read_payload(stream, len)?;
+                serde_json::from_slice::<MigrationVmInfo>(&payload)
+                    .map_err(|e| MigrationError::InvalidStream(e.to_string()))?
+            }
+            (cmd, _) => {
+                return Err(MigrationError::InvalidStream(format!(
+                    "unexpected command {} while waiting for config",
+                    cmd
+                )))
+            }
+        };
+        let local_info = MigrationVmInfo::from(&self.vm_config);
+        if info != local_info {
+            return Err(MigrationError::ConfigMismatch(format!(
+                "source {:?}, destination {:?}",
+                info, local_info
+            )));
+        }
+        send_frame(stream, CMD_ACK, &[])?;
+
+        self.init_migration_target(event_mgr, vcpu_seccomp_filter)
+            .map_err(MigrationError::StartMicroVm)?;
+        let vm_memory = self
+            .address_space
+            .vm_memory()
+            .ok_or(MigrationError::AddressManager(
+                AddressManagerError::GuestMemoryNotInitialized,
+            ))?;
+
+        let mut vcpu_count = None;
+        let mut devices = None;
+        loop {
+            match read_frame_header(stream)? {
+                (CMD_MEMORY, len) if len >= mem::size_of::<u64>() as u64 => {
+                    let mut gpa = [0u8; 8];
+                    stream.read_exact(&mut gpa)?;
+                    let size = len - mem::size_of::<u64>() as u64;
+                    vm_memory
+                        .read_exact_from(
+                            GuestAddress(u64::from_le_bytes(gpa)),
+                            stream,
+                            size as usize,
+                        )
+                        .map_err(MigrationError::GuestMemory)?;
+                }
+                (CMD_STATE, len) => {
+                    let state = VmState::deserialize(&read_payload(stream, len)?)?;
+                    self.restore_vm_state(&state)?;
+                    vcpu_count = Some(state.vcpus.len() as u8);
+                }
+                (CMD_DEVICES, len) => {
+                    let payload = read_payload(stream, len)?;
+                    devices = Some(
+                        serde_json::from_slice::<Vec<DeviceState>>(&payload)
+                            .map_err(|e| MigrationError::InvalidStream(e.to_string()))?,
+                    );
+                }
+                (CMD_COMPLETE, _) => break,
+                (CMD_ERROR, len) => {
+                    let msg = read_payload(stream, len)?;
+                    return Err(MigrationError::Remote(
+                        String::from_utf8_lossy(&msg).into_owned(),
+                    ));
+                }
+                (cmd, _) => {
+                    return Err(MigrationError::InvalidStream(format!(
+                        "unexpected command {}",
+                        cmd
+                    )))
+                }
+            }
+        }
+        let vcpu_count = vcpu_count
+            .ok_or_else(|| MigrationError::InvalidStream("missing VM state".to_string()))?;
+        // Activating the devices may raise interrupts and write to guest memory, so they are
+        // restored after the guest memory and the interrupt controllers.
+        let devices = devices
+            .ok_or_else(|| MigrationError::InvalidStream("missing device state".to_string()))?;
+        self.restore_virtio_devices(&devices)?;
+
+        info!(self.logger, "VM: register events");
+        self.register_events(event_mgr)
+            .map_err(MigrationError::StartMicroVm)?;
+
+        info!(self.logger, "VM: start vcpus");
+        self.vcpu_manager()
+            .map_err(MigrationError::Vcpu)?
+            .start_vcpus(vcpu_count, vmm_seccomp_filter, true)
+            .map_err(MigrationError::Vcpu)?;
+        self.set_instance_state(InstanceState::Running);
+
+        Ok(())
+    }
+
+    /// Prepare the VM to receive the migrated guest, same as `init_microvm()` except that the
+    /// guest kernel is not loaded and the vCPUs are not created yet.
+    fn init_migration_target(
+        &mut self,
+        event_mgr: &mut EventManager,
+        vcpu_seccomp_filter: BpfProgram,
+    ) -> std::result::Result<(), StartMicroVmError> {
+        self.init_dmesg_logger();
+        self.check_health()?;
+        self.set_instance_state(InstanceState::Starting);
+
+        self.init_guest_memory()?;
+        let vm_as = self
+            .vm_as()
+            .cloned()
+            .ok_or(StartMicroVmError::AddressManagerError(
+                AddressManagerError::GuestMemoryNotInitialized,
+            ))?;
+        self.init_vcpu_manager(vm_as, vcpu_seccomp_filter)
+            .map_err(StartMicroVmError::Vcpu)?;
+
+        self.init_tss()?;
+        self.setup_interrupt_controller()?;
+        self.create_pit()?;
+        self.init_devices(event_mgr.epoll_manager())?;
+
+        let reset_event_fd = self.device_manager.get_reset_eventfd().unwrap();
+        self.vcpu_manager()
+            .map_err(StartMicroVmError::Vcpu)?
+            .set_reset_event_fd(reset_event_fd)
+            .map_err(StartMicroVmError::Vcpu)
+    }
+
+    fn restore_virtio_devices(&self, states: &[DeviceState]) -> Result<()> {
+        let devices = self.device_manager.get_virtio_devices();
+        let ids = |ids: Vec<&str>| ids.join(",");
+        if devices.len() != states.len()
+            || devices
+                .iter()
+                .zip(states.iter())
+                .any(|((id, _), state)| *id != state.id)
+        {
+            return Err(MigrationError::ConfigMismatch(format!(
+                "source has virtio devices [{}], destination has [{}]",
+                ids(states.iter().map(|state| state.id.as_str()).collect()),
+                ids(devices.iter().map(|(id, _)| id.as_str()).collect())
+            )));
+        }
+
+        for ((id, device), state) in devices.iter().zip(states.iter()) {
+            let resources = device.get_assigned_resources();
+            if resources.get_mmio_address_ranges() != state.mmio_ranges
+                || resources.get_legacy_irq() != state.irq
+            {
+                return Err(MigrationError::ConfigMismatch(format!(
+                    "device {} resources {:?} irq {:?}, source has {:?} irq {:?}",
+                    id,
+                    resources.get_mmio_address_ranges(),
+                    resources.get_legacy_irq(),
+                    state.mmio_ranges,
+                    state.irq
+                )));
+            }
+            mmio_device(id, device)?
+                .restore_state(&state.state)
+                .map_err(|e| MigrationError::DeviceState(id.clone(), e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Legacy serial ports of the VM, COM1 first.
+    fn serial_devices(&self) -> Vec<Arc<Mutex<SerialDevice>>> {
+        match self.device_manager.legacy_manager.as_ref() {
+            Some(legacy) => vec![legacy.get_com1_serial(), legacy.get_com2_serial()],
+            None => Vec::new(),
+        }
+    }
+
+    fn restore_vm_state(&mut self, state: &VmState) -> Result<()> {
+        let serials = self.serial_devices();
+        if serials.len() != state.serials.len() {
+            return Err(MigrationError::ConfigMismatch(format!(
+                "source has {} serial ports, destination has {}",
+                state.serials.len(),
+                serials.len()
+            )));
+        }
+        for (device, serial) in serials.iter().zip(state.serials.iter()) {
+            serial.restore(&mut device.lock().unwrap())?;
+        }
+
+        {
+            let mut vcpu_manager = self.vcpu_manager().map_err(MigrationError::Vcpu)?;
+            vcpu_manager
+                .create_vcpus(state.vcpus.len() as u8, Some(TimestampUs::default()), None)
+                .map_err(MigrationError::Vcpu)?;
+            vcpu_manager
+                .restore_vcpu_states(&state.vcpus)
+                .map_err(MigrationError::Vcpu)?;
+        }
+
+        for irqchip in state.irqchips.iter() {
+            self.vm_fd
+                .set_irqchip(irqchip)
+                .map_err(MigrationError::VmState)?;
+        }
+        self.vm_fd
+            .set_pit2(&state.pit)
+            .map_err(MigrationError::VmState)?;
+        // The flags reported by KVM_GET_CLOCK are not accepted by KVM_SET_CLOCK.
+        let clock = kvm_clock_data {
+            clock: state.clock.clock,
+            ..Default::default()
+        };
+        self.vm_fd
+            .set_clock(&clock)
+            .map_err(MigrationError::VmState)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "virtio-blk")]
+    use dbs_device::IoAddress;
+    #[cfg(feature = "virtio-blk")]
+    use dbs_utils::epoll_manager::EpollManager;
+    #[cfg(feature = "virtio-blk")]
+    use dbs_virtio_devices::mmio::{
+        REG_MMIO_DRIVER_FEATURE, REG_MMIO_DRIVER_FEATURES_S, REG_MMIO_QUEUE_AVAIL_LOW,
+        REG_MMIO_QUEUE_DESC_LOW, REG_MMIO_QUEUE_NUM, REG_MMIO_QUEUE_READY, REG_MMIO_QUEUE_SEL,
+        REG_MMIO_QUEUE_USED_LOW, REG_MMIO_STATUS,
+    };
+    #[cfg(feature = "virtio-blk")]
+    use dbs_virtio_devices::{
+        DEVICE_ACKNOWLEDGE, DEVICE_DRIVER, DEVICE_DRIVER_OK, DEVICE_FEATURES_OK,
+    };
+    #[cfg(feature = "virtio-blk")]
+    use test_utils::skip_if_not_root;
+    #[cfg(feature = "virtio-blk")]
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+    #[cfg(feature = "virtio-blk")]
+    use crate::device_manager::blk_dev_mgr::{
+        BlockDeviceConfigInfo, BlockDeviceMgr, BlockDeviceType,
+    };
+    #[cfg(feature = "virtio-blk")]
+    use crate::device_manager::DeviceOpContext;
+    #[cfg(feature = "virtio-blk")]
+    use crate::test_utils::tests::create_vm_for_test;
+
+    #[cfg(feature = "virtio-blk")]
+    fn create_vm_with_block_device(disk: &TempFile) -> Vm {
+        let mut vm = create_vm_for_test();
+        let ctx = DeviceOpContext::create_boot_ctx(&vm, None);
+        BlockDeviceMgr::insert_device(
+            vm.device_manager_mut(),
+            ctx,
+            BlockDeviceConfigInfo {
+                path_on_host: disk.as_path().to_owned(),
+                device_type: BlockDeviceType::RawBlock,
+                drive_id: String::from("data"),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        vm.setup_interrupt_controller().unwrap();
+        vm.init_devices(EpollManager::default()).unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_migration_url() {
+        assert_eq!(
+            MigrationUrl::parse("unix:/tmp/migrate.sock").unwrap(),
+            MigrationUrl::Unix("/tmp/migrate.sock")
+        );
+        assert_eq!(
+            MigrationUrl::parse("tcp:127.0.0.1:4444").unwrap(),
+            MigrationUrl::Tcp("127.0.0.1:4444")
+        );
+        assert!(MigrationUrl::parse("unix:").is_err());
+        assert!(MigrationUrl::parse("vsock:3:1024").is_err());
+        assert!(MigrationUrl::parse("/tmp/migrate.sock").is_err());
+    }
+
+    #[test]
+    fn test_migration_frame() {
+        let mut buf = Vec::new();
+        send_frame(&mut buf, CMD_ACK, &[]).unwrap();
+        send_frame(&mut buf, CMD_ERROR, b"oops").unwrap();
+
+        let mut reader = buf.as_slice();
+        wait_for_ack(&mut reader).unwrap();
+        assert!(matches!(
+            wait_for_ack(&mut reader),
+            Err(MigrationError::Remote(msg)) if msg == "oops"
+        ));
+    }
+
+    #[test]
+    fn test_vm_state_serialize() {
+        let mut state = VmState::default();
+        state.clock.clock = 0x1234;
+        state.irqchips[2].chip_id = 2;
+        let mut vcpu = VcpuState::default();
+        vcpu.regs.rip = 0x100000;
+        vcpu.msrs.push(kvm_msr_entry {
+            index: 0x10,
+            data: 0xabcd,
+            ..Default::default()
+        });
+        state.vcpus.push(vcpu);
+        state.serials.push(SerialState {
+            lcr: 0x03,
+            scr: 0x5a,
+            ..Default::default()
+        });
+
+        let buf = state.serialize();
+        let restored = VmState::deserialize(&buf).unwrap();
+        assert_eq!(restored.clock.clock, 0x1234);
+        assert_eq!(restored.irqchips[2].chip_id, 2);
+        assert_eq!(restored.vcpus.len(), 1);
+        assert_eq!(restored.vcpus[0].regs.rip, 0x100000);
+        assert_eq!(restored.vcpus[0].msrs[0].data, 0xabcd);
+        assert_eq!(restored.serials, state.serials);
+
+        assert!(VmState::deserialize(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[cfg(feature = "virtio-blk")]
+    #[test]
+    fn test_virtio_device_migration() {
+        skip_if_not_root!();
+        let disk = TempFile::new().unwrap();
+        disk.as_file().set_len(0x100000).unwrap();
+
+        // Set up the block device as the guest driver would.
+        let source = create_vm_with_block_device(&disk);
+        let (id, device) = source.device_manager.get_virtio_devices().pop().unwrap();
+        assert_eq!(id, "data");
+        let base = IoAddress(device.get_assigned_resources().get_mmio_address_ranges()[0].0);
+        let write = |offset: u64, value: u32| {
+            device.write(base, IoAddress(offset), &value.to_le_bytes());
+        };
+        write(REG_MMIO_STATUS, DEVICE_ACKNOWLEDGE);
+        write(REG_MMIO_STATUS, DEVICE_ACKNOWLEDGE | DEVICE_DRIVER);
+        // VIRTIO_F_VERSION_1
+        write(REG_MMIO_DRIVER_FEATURES_S, 1);
+        write(REG_MMIO_DRIVER_FEATURE, 1);
+        write(
+            REG_MMIO_STATUS,
+            DEVICE_ACKNOWLEDGE | DEVICE_DRIVER | DEVICE_FEATURES_OK,
+        );
+        write(REG_MMIO_QUEUE_SEL, 0);
+        write(REG_MMIO_QUEUE_NUM, 16);
+        write(REG_MMIO_QUEUE_DESC_LOW, 0x1000);
+        write(REG_MMIO_QUEUE_AVAIL_LOW, 0x2000);
+        write(REG_MMIO_QUEUE_USED_LOW, 0x3000);
+        write(REG_MMIO_QUEUE_READY, 1);
+        let driver_status =
+            DEVICE_ACKNOWLEDGE | DEVICE_DRIVER | DEVICE_FEATURES_OK | DEVICE_DRIVER_OK;
+        write(REG_MMIO_STATUS, driver_status);
+
+        source.pause_virtio_devices().unwrap();
+        let buf = serde_json::to_vec(&source.save_virtio_devices().unwrap()).unwrap();
+        source.resume_virtio_devices();
+        let states: Vec<DeviceState> = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(states.len(), 1);
+        assert_eq!(states[0].id, "data");
+        assert_eq!(states[0].state.driver_status, driver_status);
+        assert_eq!(states[0].state.device.acked_features, 1 << 32);
+        assert_eq!(states[0].state.queues[0].size, 16);
+        assert_eq!(states[0].state.queues[0].desc_table, 0x1000);
+        assert!(states[0].state.queues[0].ready);
+
+        // The destination device is activated with the same state.
+        let destination = create_vm_with_block_device(&disk);
+        destination.restore_virtio_devices(&states).unwrap();
+        let restored = destination.save_virtio_devices().unwrap();
+        assert_eq!(restored[0].state, states[0].state);
+
+        // The destination must have the same devices.
+        let destination = create_vm_for_test();
+        assert!(matches!(
+            destination.restore_virtio_devices(&states),
+            Err(MigrationError::ConfigMismatch(_))
+        ));
+    }
+}
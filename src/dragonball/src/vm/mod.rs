@@ -19,7 +19,7 @@ use kvm_ioctls::VmFd;
 use linux_loader::loader::{KernelLoader, KernelLoaderResult};
 use seccompiler::BpfProgram;
 use serde_derive::{Deserialize, Serialize};
-use slog::{error, info};
+use slog::{error, info, warn};
 use vm_memory::{Bytes, GuestAddress, GuestAddressSpace};
 use vmm_sys_util::eventfd::EventFd;
 
@@ -33,11 +33,12 @@ use crate::address_space_manager::{
     GuestMemoryImpl,
 };
 use crate::api::v1::{InstanceInfo, InstanceState};
-use crate::device_manager::console_manager::DmesgWriter;
+use crate::device_manager::console_manager::{DmesgWriter, GuestKernelLog};
 use crate::device_manager::{DeviceManager, DeviceMgrError, DeviceOpContext};
 use crate::error::{LoadInitrdError, Result, StartMicroVmError, StopMicrovmError};
 use crate::event_manager::EventManager;
 use crate::kvm_context::KvmContext;
+use crate::metric::{VmmMetricsInfo, METRICS};
 use crate::resource_manager::ResourceManager;
 use crate::vcpu::{VcpuManager, VcpuManagerError};
 #[cfg(feature = "hotplug")]
@@ -56,6 +57,21 @@ mod aarch64;
 #[path = "x86_64.rs"]
 mod x86_64;
 
+#[cfg(target_arch = "x86_64")]
+mod acpi;
+#[cfg(target_arch = "x86_64")]
+pub use self::acpi::AcpiError;
+
+#[cfg(all(target_arch = "x86_64", feature = "live-migration"))]
+mod migration;
+#[cfg(all(target_arch = "x86_64", feature = "live-migration"))]
+pub use self::migration::{MigrationConfigInfo, MigrationError};
+
+#[cfg(target_arch = "x86_64")]
+mod dump;
+#[cfg(target_arch = "x86_64")]
+pub use self::dump::{GuestMemoryDumpError, GuestMemoryDumpInfo};
+
 /// Errors associated with virtual machine instance related operations.
 #[derive(Debug, thiserror::Error)]
 pub enum VmError {
@@ -115,6 +131,17 @@ impl Default for CpuTopology {
     }
 }
 
+/// Action taken when the guest watchdog expires, i.e. when the guest is hung.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum WatchdogAction {
+    /// Reset the guest, which stops the VMM as guest reboot is not supported.
+    Reset,
+    /// Pause the vCPUs, so that the hung guest could be inspected.
+    Stop,
+    /// Keep the guest running.
+    Notify,
+}
+
 /// Configuration information for virtual machine instance.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct VmConfigInfo {
@@ -135,6 +162,14 @@ pub struct VmConfigInfo {
     pub mem_file_path: String,
     /// The memory size in MiB.
     pub mem_size_mib: usize,
+    /// Pause the vCPUs when the guest kernel panics, so that the guest memory can be dumped.
+    pub pause_on_panic: bool,
+    /// Guest NUMA nodes, with their memory and vCPUs. All the memory and vCPUs are in the guest
+    /// node 0, not bound to any host node, if empty.
+    pub numa_regions: Vec<NumaRegionInfo>,
+    /// Create the guest watchdog device (x86_64 only), and the action taken when it expires. The
+    /// VMM user is notified of the expiry whatever the action.
+    pub watchdog: Option<WatchdogAction>,
 
     /// sock path
     pub serial_path: Option<String>,
@@ -156,6 +191,9 @@ impl Default for VmConfigInfo {
             mem_type: String::from("shmem"),
             mem_file_path: String::from(""),
             mem_size_mib: 128,
+            pause_on_panic: false,
+            numa_regions: Vec::new(),
+            watchdog: None,
             serial_path: None,
         }
     }
@@ -184,6 +222,11 @@ pub struct Vm {
     address_space: AddressSpaceMgr,
     device_manager: DeviceManager,
     dmesg_fifo: Option<Box<dyn io::Write + Send>>,
+    guest_kernel_log: GuestKernelLog,
+    guest_panic_eventfd: Option<EventFd>,
+    guest_panic_notifier: Option<EventFd>,
+    watchdog_eventfd: Option<EventFd>,
+    watchdog_notifier: Option<EventFd>,
     kernel_config: Option<KernelConfigInfo>,
     logger: slog::Logger,
     reset_eventfd: Option<EventFd>,
@@ -232,6 +275,11 @@ impl Vm {
             address_space: AddressSpaceMgr::default(),
             device_manager,
             dmesg_fifo: None,
+            guest_kernel_log: GuestKernelLog::default(),
+            guest_panic_eventfd: None,
+            guest_panic_notifier: None,
+            watchdog_eventfd: None,
+            watchdog_notifier: None,
             kernel_config: None,
             logger,
             reset_eventfd: None,
@@ -343,6 +391,22 @@ impl Vm {
         instance_state == InstanceState::Running
     }
 
+    /// Get the state of the VM instance.
+    pub fn instance_state(&self) -> InstanceState {
+        self.shared_info
+            .read()
+            .expect("Failed to get instance state because shared info couldn't be read due to poisoned lock")
+            .state
+    }
+
+    /// Set the state of the VM instance.
+    pub fn set_instance_state(&mut self, mstate: InstanceState) {
+        self.shared_info
+            .write()
+            .expect("Failed to set instance state because shared info couldn't be written due to poisoned lock")
+            .state = mstate;
+    }
+
     /// Save VM instance exit state
     pub fn vm_exit(&self, exit_code: i32) {
         if let Ok(mut info) = self.shared_info.write() {
@@ -476,6 +540,7 @@ impl Vm {
             epoll_manager,
             kernel_config,
             com1_sock_path,
+            self.vm_config.watchdog.is_some(),
             self.dmesg_fifo.take(),
             self.address_space.address_space(),
         )?;
@@ -522,8 +587,132 @@ impl Vm {
     }
 
     /// dmesg write to logger
-    fn dmesg_logger(&self) -> Box<dyn io::Write + Send> {
-        Box::new(DmesgWriter::new(&self.logger))
+    fn dmesg_logger(&mut self) -> Box<dyn io::Write + Send> {
+        let mut writer = DmesgWriter::new(&self.logger);
+        writer.set_history(self.guest_kernel_log.clone());
+
+        let panic_evt = EventFd::new(libc::EFD_NONBLOCK).and_then(|evt| {
+            writer.set_panic_eventfd(evt.try_clone()?);
+            Ok(evt)
+        });
+        match panic_evt {
+            Ok(evt) => self.guest_panic_eventfd = Some(evt),
+            Err(e) => warn!(
+                self.logger,
+                "VM: failed to create guest panic eventfd, guest panic won't be detected: {}", e
+            ),
+        }
+
+        Box::new(writer)
+    }
+
+    /// Get the eventfd notified by the dmesg writer when the guest kernel panics.
+    pub(crate) fn get_guest_panic_eventfd(&self) -> Option<&EventFd> {
+        self.guest_panic_eventfd.as_ref()
+    }
+
+    /// Set an eventfd to notify the VMM user when the guest kernel panics.
+    pub fn set_guest_panic_notifier(&mut self, notifier: EventFd) {
+        self.guest_panic_notifier = Some(notifier);
+    }
+
+    /// Get the eventfd notified by the watchdog device when it expires.
+    pub(crate) fn get_watchdog_eventfd(&self) -> Option<&EventFd> {
+        self.watchdog_eventfd.as_ref()
+    }
+
+    /// Set an eventfd to notify the VMM user when the guest watchdog expires.
+    pub fn set_watchdog_notifier(&mut self, notifier: EventFd) {
+        self.watchdog_notifier = Some(notifier);
+    }
+
+    /// Get the most recent guest kernel log lines.
+    pub fn guest_kernel_log(&self) -> Vec<String> {
+        self.guest_kernel_log
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Get the metrics of the VMM and of the devices of the VM.
+    pub fn vmm_metrics(&self) -> VmmMetricsInfo {
+        VmmMetricsInfo {
+            vmm: METRICS.counters(),
+            devices: self.device_manager.device_metrics(),
+        }
+    }
+
+    /// Handle guest kernel panic detected from the guest kernel log.
+    ///
+    /// vCPUs are paused if `pause_on_panic` is configured, so that the panicked guest could be
+    /// inspected before it reboots, then the VMM user is notified.
+    pub(crate) fn handle_guest_panic(&mut self) {
+        error!(self.logger, "VM: guest kernel panicked");
+
+        if self.vm_config.pause_on_panic && self.is_vm_running() {
+            self.start_instance_downtime = TimestampUs::default().time_us;
+            match self
+                .vcpu_manager()
+                .and_then(|mut mgr| mgr.pause_all_vcpus_sync())
+            {
+                Ok(()) => self.set_instance_state(InstanceState::Paused),
+                Err(e) => error!(self.logger, "VM: failed to pause panicked guest: {}", e),
+            }
+        }
+
+        if let Some(notifier) = self.guest_panic_notifier.as_ref() {
+            if let Err(e) = notifier.write(1) {
+                error!(self.logger, "VM: failed to notify guest panic: {}", e);
+            }
+        }
+    }
+
+    /// Handle the expiry of the guest watchdog, which means the guest is hung.
+    ///
+    /// The configured action is applied to the guest, then the VMM user is notified.
+    pub(crate) fn handle_watchdog_expiry(&mut self) {
+        let action = match self.vm_config.watchdog {
+            Some(action) => action,
+            None => return,
+        };
+        error!(
+            self.logger,
+            "VM: guest watchdog expired, action {:?}", action
+        );
+
+        match action {
+            WatchdogAction::Reset => {
+                if let Some(reset_evt) = self.reset_eventfd.as_ref() {
+                    if let Err(e) = reset_evt.write(1) {
+                        error!(self.logger, "VM: failed to reset hung guest: {}", e);
+                    }
+                }
+            }
+            WatchdogAction::Stop => {
+                if self.is_vm_running() {
+                    self.start_instance_downtime = TimestampUs::default().time_us;
+                    match self
+                        .vcpu_manager()
+                        .and_then(|mut mgr| mgr.pause_all_vcpus_sync())
+                    {
+                        Ok(()) => self.set_instance_state(InstanceState::Paused),
+                        Err(e) => error!(self.logger, "VM: failed to pause hung guest: {}", e),
+                    }
+                }
+            }
+            WatchdogAction::Notify => {}
+        }
+
+        if let Some(notifier) = self.watchdog_notifier.as_ref() {
+            if let Err(e) = notifier.write(1) {
+                error!(
+                    self.logger,
+                    "VM: failed to notify guest watchdog expiry: {}", e
+                );
+            }
+        }
     }
 
     pub(crate) fn init_guest_memory(&mut self) -> std::result::Result<(), StartMicroVmError> {
@@ -546,20 +735,22 @@ impl Vm {
             mem_file_path.push_str(shared_info.id.as_str());
         }
 
-        let mut vcpu_ids: Vec<u32> = Vec::new();
-        for i in 0..self.vm_config().max_vcpu_count {
-            vcpu_ids.push(i as u32);
-        }
+        let numa_regions = if self.vm_config.numa_regions.is_empty() {
+            let mut vcpu_ids: Vec<u32> = Vec::new();
+            for i in 0..self.vm_config().max_vcpu_count {
+                vcpu_ids.push(i as u32);
+            }
 
-        // init default regions.
-        let mut numa_regions = Vec::with_capacity(1);
-        let numa_node = NumaRegionInfo {
-            size: self.vm_config.mem_size_mib as u64,
-            host_numa_node_id: None,
-            guest_numa_node_id: Some(0),
-            vcpu_ids,
+            // init default regions.
+            vec![NumaRegionInfo {
+                size: self.vm_config.mem_size_mib as u64,
+                host_numa_node_id: None,
+                guest_numa_node_id: Some(0),
+                vcpu_ids,
+            }]
+        } else {
+            self.vm_config.numa_regions.clone()
         };
-        numa_regions.push(numa_node);
 
         info!(
             self.logger,
@@ -869,15 +1060,6 @@ pub mod tests {
     use super::*;
     use crate::test_utils::tests::create_vm_for_test;
 
-    impl Vm {
-        pub fn set_instance_state(&mut self, mstate: InstanceState) {
-            self.shared_info
-            .write()
-            .expect("Failed to start microVM because shared info couldn't be written due to poisoned lock")
-            .state = mstate;
-        }
-    }
-
     pub fn create_vm_instance() -> Vm {
         let instance_info = Arc::new(RwLock::new(InstanceInfo::default()));
         let epoll_manager = EpollManager::default();
@@ -906,6 +1088,9 @@ pub mod tests {
             mem_type: "shmem".to_string(),
             mem_file_path: "".to_string(),
             mem_size_mib: 16,
+            pause_on_panic: false,
+            numa_regions: Vec::new(),
+            watchdog: None,
             serial_path: None,
             cpu_topology: CpuTopology {
                 threads_per_core: 1,
@@ -932,6 +1117,9 @@ pub mod tests {
             mem_type: "shmem".to_string(),
             mem_file_path: "".to_string(),
             mem_size_mib: 16,
+            pause_on_panic: false,
+            numa_regions: Vec::new(),
+            watchdog: None,
             serial_path: None,
             cpu_topology: CpuTopology {
                 threads_per_core: 1,
@@ -953,6 +1141,56 @@ pub mod tests {
         assert_eq!(read_val, 67u8);
     }
 
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_vm_configure_numa_acpi_tables() {
+        skip_if_not_root!();
+        let numa_region = |node: u32, vcpu_ids: Vec<u32>| NumaRegionInfo {
+            size: 16,
+            host_numa_node_id: None,
+            guest_numa_node_id: Some(node),
+            vcpu_ids,
+        };
+        let vm_config = VmConfigInfo {
+            vcpu_count: 2,
+            max_vcpu_count: 4,
+            cpu_pm: "off".to_string(),
+            mem_type: "shmem".to_string(),
+            mem_file_path: "".to_string(),
+            mem_size_mib: 32,
+            pause_on_panic: false,
+            numa_regions: vec![numa_region(0, vec![0, 2]), numa_region(1, vec![1, 3])],
+            watchdog: None,
+            serial_path: None,
+            cpu_topology: CpuTopology {
+                threads_per_core: 1,
+                cores_per_die: 1,
+                dies_per_socket: 1,
+                sockets: 1,
+            },
+            vpmu_feature: 0,
+        };
+
+        let mut vm = create_vm_instance();
+        vm.set_vm_config(vm_config);
+        vm.init_guest_memory().unwrap();
+        let vm_memory = vm.address_space.vm_memory().unwrap();
+        assert_eq!(vm.address_space.get_numa_nodes().len(), 2);
+        vm.configure_system_arch(vm_memory.deref(), &Cmdline::new(64), None)
+            .unwrap();
+
+        let params: dbs_boot::BootParamsWrapper = vm_memory
+            .read_obj(GuestAddress(dbs_boot::layout::ZERO_PAGE_START))
+            .unwrap();
+        let rsdp_addr = params.0.acpi_rsdp_addr;
+        assert_eq!(rsdp_addr, acpi::ACPI_RSDP_START);
+        let mut signature = [0u8; 8];
+        vm_memory
+            .read_slice(&mut signature, GuestAddress(rsdp_addr))
+            .unwrap();
+        assert_eq!(&signature, b"RSD PTR ");
+    }
+
     #[test]
     fn test_vm_create_devices() {
         skip_if_not_root!();
@@ -971,6 +1209,9 @@ pub mod tests {
             mem_type: "shmem".to_string(),
             mem_file_path: "".to_string(),
             mem_size_mib: 16,
+            pause_on_panic: false,
+            numa_regions: Vec::new(),
+            watchdog: None,
             serial_path: None,
             cpu_topology: CpuTopology {
                 threads_per_core: 1,
@@ -1012,6 +1253,44 @@ pub mod tests {
         assert!(vm.remove_devices().is_ok());
     }
 
+    #[cfg(feature = "virtio-blk")]
+    #[test]
+    fn test_vm_device_metrics() {
+        use crate::device_manager::blk_dev_mgr::{
+            BlockDeviceConfigInfo, BlockDeviceMgr, BlockDeviceType, VIRTIO_BLK_DEVICE_TYPE,
+        };
+
+        skip_if_not_root!();
+        let mut vm = create_vm_for_test();
+        let epoll_mgr = EpollManager::default();
+
+        let disk_file = TempFile::new().unwrap();
+        disk_file.as_file().set_len(0x100000).unwrap();
+        let ctx = DeviceOpContext::create_boot_ctx(&vm, None);
+        BlockDeviceMgr::insert_device(
+            vm.device_manager_mut(),
+            ctx,
+            BlockDeviceConfigInfo {
+                path_on_host: disk_file.as_path().to_owned(),
+                device_type: BlockDeviceType::RawBlock,
+                drive_id: String::from("data"),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        vm.setup_interrupt_controller().unwrap();
+        vm.init_devices(epoll_mgr).unwrap();
+
+        let metrics = vm.vmm_metrics();
+        assert!(metrics.vmm.contains_key("vcpu_exit_io_in"));
+        assert_eq!(metrics.devices.len(), 1);
+        assert_eq!(metrics.devices[0].device_type, VIRTIO_BLK_DEVICE_TYPE);
+        assert_eq!(metrics.devices[0].device_id, "data");
+        assert_eq!(metrics.devices[0].counters["read_count"], 0);
+        assert_eq!(metrics.devices[0].counters["rate_limiter_throttled"], 0);
+    }
+
     #[cfg(target_arch = "x86_64")]
     #[test]
     fn test_run_code() {
@@ -1041,6 +1320,9 @@ pub mod tests {
             mem_type: "shmem".to_string(),
             mem_file_path: "".to_string(),
             mem_size_mib: 10,
+            pause_on_panic: false,
+            numa_regions: Vec::new(),
+            watchdog: None,
             serial_path: None,
             cpu_topology: CpuTopology {
                 threads_per_core: 1,
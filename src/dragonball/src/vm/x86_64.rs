@@ -6,11 +6,12 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the THIRD-PARTY file.
 
+use std::collections::BTreeMap;
 use std::convert::TryInto;
 use std::mem;
 use std::ops::Deref;
 
-use dbs_address_space::AddressSpace;
+use dbs_address_space::{AddressSpace, NumaNode};
 use dbs_boot::{add_e820_entry, bootparam, layout, mptable, BootParamsWrapper, InitrdConfig};
 use dbs_utils::epoll_manager::EpollManager;
 use dbs_utils::time::TimestampUs;
@@ -22,7 +23,7 @@ use vm_memory::{Address, Bytes, GuestAddress, GuestAddressSpace, GuestMemory};
 use crate::address_space_manager::{GuestAddressSpaceImpl, GuestMemoryImpl};
 use crate::error::{Error, Result, StartMicroVmError};
 use crate::event_manager::EventManager;
-use crate::vm::{Vm, VmError};
+use crate::vm::{acpi, Vm, VmError};
 
 /// Configures the system and should be called once per vm before starting vcpu
 /// threads.
@@ -38,7 +39,8 @@ use crate::vm::{Vm, VmError};
 ///   `guest_mem`.
 /// * `boot_cpus` - Number of virtual CPUs the guest will have at boot time.
 /// * `max_cpus` - Max number of virtual CPUs the guest will have.
-/// * `rsv_mem_bytes` - Reserve memory from microVM..
+/// * `numa_nodes` - The guest NUMA nodes described to the guest by the ACPI SRAT and SLIT, if
+///   any.
 #[allow(clippy::too_many_arguments)]
 fn configure_system<M: GuestMemory>(
     guest_mem: &M,
@@ -48,6 +50,7 @@ fn configure_system<M: GuestMemory>(
     initrd: &Option<InitrdConfig>,
     boot_cpus: u8,
     max_cpus: u8,
+    numa_nodes: Option<&BTreeMap<u32, NumaNode>>,
 ) -> super::Result<()> {
     const KERNEL_BOOT_FLAG_MAGIC: u16 = 0xaa55;
     const KERNEL_HDR_MAGIC: u32 = 0x5372_6448;
@@ -73,6 +76,11 @@ fn configure_system<M: GuestMemory>(
         params.0.hdr.ramdisk_image = initrd_config.address.raw_value() as u32;
         params.0.hdr.ramdisk_size = initrd_config.size as u32;
     }
+    if let Some(numa_nodes) = numa_nodes {
+        let rsdp_addr =
+            acpi::setup_acpi_tables(guest_mem, numa_nodes).map_err(Error::AcpiTableSetup)?;
+        params.0.acpi_rsdp_addr = rsdp_addr.raw_value();
+    }
 
     add_e820_entry(&mut params.0, 0, layout::EBDA_START, bootparam::E820_RAM)
         .map_err(Error::BootSystem)?;
@@ -231,6 +239,8 @@ impl Vm {
             &initrd,
             self.vm_config.vcpu_count,
             self.vm_config.max_vcpu_count,
+            // The guest only sees NUMA nodes when they are configured.
+            (!self.vm_config.numa_regions.is_empty()).then(|| self.address_space.get_numa_nodes()),
         )
         .map_err(StartMicroVmError::ConfigureSystem)
     }
@@ -281,6 +291,23 @@ impl Vm {
             .map_err(|_| StartMicroVmError::RegisterEvent)?;
         self.reset_eventfd = Some(reset_evt);
 
+        if let Some(panic_evt) = self.get_guest_panic_eventfd() {
+            event_mgr
+                .register_guest_panic_eventfd(panic_evt)
+                .map_err(|_| StartMicroVmError::RegisterEvent)?;
+        }
+
+        if let Some(watchdog_evt) = self
+            .device_manager
+            .get_watchdog_eventfd()
+            .map_err(StartMicroVmError::DeviceManager)?
+        {
+            event_mgr
+                .register_watchdog_eventfd(&watchdog_evt)
+                .map_err(|_| StartMicroVmError::RegisterEvent)?;
+            self.watchdog_eventfd = Some(watchdog_evt);
+        }
+
         Ok(())
     }
 }
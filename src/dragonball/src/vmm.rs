@@ -135,6 +135,18 @@ impl Vmm {
                                 warn!("got spurious notification from api thread");
                             });
                     }
+                    if v.event_ctx.guest_panic_triggered {
+                        v.event_ctx.guest_panic_triggered = false;
+                        if let Some(vm) = v.get_vm_mut() {
+                            vm.handle_guest_panic();
+                        }
+                    }
+                    if v.event_ctx.watchdog_triggered {
+                        v.event_ctx.watchdog_triggered = false;
+                        if let Some(vm) = v.get_vm_mut() {
+                            vm.handle_watchdog_expiry();
+                        }
+                    }
                     if v.event_ctx.exit_evt_triggered {
                         info!("Gracefully terminated VMM control loop");
                         return v.stop(EXIT_CODE_OK as i32);
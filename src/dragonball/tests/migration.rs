@@ -0,0 +1,317 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! End to end live migration test between two VMM instances running a tiny guest.
+
+#![cfg(all(
+    target_arch = "x86_64",
+    feature = "live-migration",
+    feature = "virtio-blk"
+))]
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::io::IntoRawFd;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use dbs_device::{DeviceIo, IoAddress};
+use dbs_virtio_devices::mmio::{
+    REG_MMIO_DRIVER_FEATURE, REG_MMIO_DRIVER_FEATURES_S, REG_MMIO_QUEUE_AVAIL_LOW,
+    REG_MMIO_QUEUE_DESC_LOW, REG_MMIO_QUEUE_NUM, REG_MMIO_QUEUE_READY, REG_MMIO_QUEUE_SEL,
+    REG_MMIO_QUEUE_USED_LOW, REG_MMIO_STATUS,
+};
+use dbs_virtio_devices::{DEVICE_ACKNOWLEDGE, DEVICE_DRIVER, DEVICE_DRIVER_OK, DEVICE_FEATURES_OK};
+use dragonball::api::v1::{
+    BlockDeviceConfigInfo, BootSourceConfig, InstanceInfo, VmmAction, VmmData, VmmRequest,
+    VmmResponse, VmmService,
+};
+use dragonball::device_manager::blk_dev_mgr::BlockDeviceType;
+use dragonball::vm::{MigrationConfigInfo, VmConfigInfo};
+use dragonball::Vmm;
+use test_utils::skip_if_not_root;
+use vm_memory::{Bytes, GuestAddress, GuestAddressSpace};
+use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::tempdir::TempDir;
+use vmm_sys_util::tempfile::TempFile;
+
+const GUEST_CODE_ADDR: u64 = 0x100000;
+const GUEST_COUNTER_ADDR: u64 = GUEST_CODE_ADDR + 0x18;
+const GUEST_SCRATCH_ADDR: u64 = GUEST_CODE_ADDR + 0x1c;
+const GUEST_MEM_SIZE: u64 = 0x20;
+const SERIAL_SCRATCH: u8 = 0x5a;
+const BLOCK_DRIVER_STATUS: u32 =
+    DEVICE_ACKNOWLEDGE | DEVICE_DRIVER | DEVICE_FEATURES_OK | DEVICE_DRIVER_OK;
+// Virtio queue of the block device, set up by the test instead of a guest driver.
+const QUEUE_DESC_ADDR: u32 = 0x20_0000;
+const QUEUE_AVAIL_ADDR: u32 = 0x20_1000;
+const QUEUE_USED_ADDR: u32 = 0x20_2000;
+const QUEUE_SIZE: u32 = 16;
+// 64-bit guest code storing a value in the scratch register of COM1, then incrementing the
+// counter and copying the scratch register back to memory forever.
+const GUEST_CODE: [u8; 0x16] = [
+    0x66,
+    0xba,
+    0xff,
+    0x03, /* mov dx, 0x3ff */
+    0xb0,
+    SERIAL_SCRATCH, /* mov al, 0x5a */
+    0xee,           /* out dx, al */
+    0xff,
+    0x05,
+    0x0b,
+    0x00,
+    0x00,
+    0x00, /* inc dword [rip + 0xb] (counter) */
+    0xec, /* in al, dx */
+    0x88,
+    0x05,
+    0x08,
+    0x00,
+    0x00,
+    0x00, /* mov [rip + 0x8], al (scratch) */
+    0xeb,
+    0xf1, /* jmp inc */
+];
+
+/// Build an ELF64 image with a single segment loading `GUEST_CODE` at `GUEST_CODE_ADDR`.
+fn write_guest_kernel(path: &Path) {
+    const EHDR_SIZE: u16 = 64;
+    const PHDR_SIZE: u16 = 56;
+    let code_offset = (EHDR_SIZE + PHDR_SIZE) as u64;
+
+    let mut image = Vec::new();
+    // ELF header: 64-bit, little endian, executable for x86_64.
+    image.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    image.extend_from_slice(&[0u8; 8]);
+    image.extend_from_slice(&2u16.to_le_bytes()); // e_type: ET_EXEC
+    image.extend_from_slice(&62u16.to_le_bytes()); // e_machine: EM_X86_64
+    image.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    image.extend_from_slice(&GUEST_CODE_ADDR.to_le_bytes()); // e_entry
+    image.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes()); // e_phoff
+    image.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    image.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    image.extend_from_slice(&EHDR_SIZE.to_le_bytes()); // e_ehsize
+    image.extend_from_slice(&PHDR_SIZE.to_le_bytes()); // e_phentsize
+    image.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    image.extend_from_slice(&64u16.to_le_bytes()); // e_shentsize
+    image.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    image.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+                                                  // Program header: a single PT_LOAD segment.
+    image.extend_from_slice(&1u32.to_le_bytes()); // p_type: PT_LOAD
+    image.extend_from_slice(&7u32.to_le_bytes()); // p_flags: RWX
+    image.extend_from_slice(&code_offset.to_le_bytes()); // p_offset
+    image.extend_from_slice(&GUEST_CODE_ADDR.to_le_bytes()); // p_vaddr
+    image.extend_from_slice(&GUEST_CODE_ADDR.to_le_bytes()); // p_paddr
+    image.extend_from_slice(&(GUEST_CODE.len() as u64).to_le_bytes()); // p_filesz
+    image.extend_from_slice(&GUEST_MEM_SIZE.to_le_bytes()); // p_memsz
+    image.extend_from_slice(&0x1000u64.to_le_bytes()); // p_align
+    image.extend_from_slice(&GUEST_CODE);
+
+    let mut file = std::fs::File::create(path).unwrap();
+    file.write_all(&image).unwrap();
+}
+
+struct TestVmm {
+    vmm: Arc<Mutex<Vmm>>,
+    to_vmm: Sender<VmmRequest>,
+    from_vmm: Receiver<VmmResponse>,
+    to_vmm_fd: EventFd,
+}
+
+impl TestVmm {
+    fn new() -> Self {
+        let kvm = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/kvm")
+            .unwrap();
+        let (to_vmm, from_api) = unbounded();
+        let (to_api, from_vmm) = unbounded();
+        let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let vmm = Vmm::new(
+            Arc::new(RwLock::new(InstanceInfo::default())),
+            to_vmm_fd.try_clone().unwrap(),
+            Vec::new(),
+            Vec::new(),
+            Some(kvm.into_raw_fd()),
+        )
+        .unwrap();
+        let vmm = Arc::new(Mutex::new(vmm));
+        let service = VmmService::new(from_api, to_api);
+        let event_loop_vmm = vmm.clone();
+        thread::spawn(move || Vmm::run_vmm_event_loop(event_loop_vmm, service));
+
+        TestVmm {
+            vmm,
+            to_vmm,
+            from_vmm,
+            to_vmm_fd,
+        }
+    }
+
+    fn request(&self, action: VmmAction) -> Result<VmmData, String> {
+        self.to_vmm.send(Box::new(action)).unwrap();
+        self.to_vmm_fd.write(1).unwrap();
+        (*self.from_vmm.recv().unwrap()).map_err(|e| e.to_string())
+    }
+
+    fn configure(&self, kernel_path: &Path, disk_path: &Path) {
+        self.request(VmmAction::SetVmConfiguration(VmConfigInfo {
+            mem_size_mib: 16,
+            ..Default::default()
+        }))
+        .unwrap();
+        self.request(VmmAction::ConfigureBootSource(BootSourceConfig {
+            kernel_path: kernel_path.display().to_string(),
+            initrd_path: None,
+            boot_args: None,
+        }))
+        .unwrap();
+        self.request(VmmAction::InsertBlockDevice(BlockDeviceConfigInfo {
+            drive_id: String::from("data"),
+            device_type: BlockDeviceType::RawBlock,
+            path_on_host: disk_path.to_owned(),
+            ..Default::default()
+        }))
+        .unwrap();
+    }
+
+    fn block_device(&self) -> (IoAddress, Arc<dyn DeviceIo>) {
+        let vmm = self.vmm.lock().unwrap();
+        let (id, device) = vmm
+            .get_vm()
+            .unwrap()
+            .device_manager()
+            .get_virtio_devices()
+            .pop()
+            .unwrap();
+        assert_eq!(id, "data");
+        let base = device.get_assigned_resources().get_mmio_address_ranges()[0].0;
+        (IoAddress(base), device)
+    }
+
+    fn write_block_device_reg(&self, offset: u64, value: u32) {
+        let (base, device) = self.block_device();
+        device.write(base, IoAddress(offset), &value.to_le_bytes());
+    }
+
+    /// Set up the block device and its queue as a guest driver would.
+    fn activate_block_device(&self) {
+        let write = |offset: u64, value: u32| self.write_block_device_reg(offset, value);
+        write(REG_MMIO_STATUS, DEVICE_ACKNOWLEDGE);
+        write(REG_MMIO_STATUS, DEVICE_ACKNOWLEDGE | DEVICE_DRIVER);
+        // VIRTIO_F_VERSION_1
+        write(REG_MMIO_DRIVER_FEATURES_S, 1);
+        write(REG_MMIO_DRIVER_FEATURE, 1);
+        write(
+            REG_MMIO_STATUS,
+            DEVICE_ACKNOWLEDGE | DEVICE_DRIVER | DEVICE_FEATURES_OK,
+        );
+        write(REG_MMIO_QUEUE_SEL, 0);
+        write(REG_MMIO_QUEUE_NUM, QUEUE_SIZE);
+        write(REG_MMIO_QUEUE_DESC_LOW, QUEUE_DESC_ADDR);
+        write(REG_MMIO_QUEUE_AVAIL_LOW, QUEUE_AVAIL_ADDR);
+        write(REG_MMIO_QUEUE_USED_LOW, QUEUE_USED_ADDR);
+        write(REG_MMIO_QUEUE_READY, 1);
+        write(REG_MMIO_STATUS, BLOCK_DRIVER_STATUS);
+    }
+
+    fn read_block_device_reg(&self, offset: u64) -> u32 {
+        let (base, device) = self.block_device();
+        let mut data = [0u8; 4];
+        device.read(base, IoAddress(offset), &mut data);
+        u32::from_le_bytes(data)
+    }
+
+    fn read_guest_u32(&self, addr: u64) -> u32 {
+        let vmm = self.vmm.lock().unwrap();
+        let vm_as = vmm.get_vm().unwrap().vm_as().unwrap().clone();
+        vm_as.memory().read_obj(GuestAddress(addr)).unwrap()
+    }
+
+    fn write_guest_u32(&self, addr: u64, value: u32) {
+        let vmm = self.vmm.lock().unwrap();
+        let vm_as = vmm.get_vm().unwrap().vm_as().unwrap().clone();
+        vm_as.memory().write_obj(value, GuestAddress(addr)).unwrap();
+    }
+
+    fn wait_for_counter(&self) -> u32 {
+        let start = self.read_guest_u32(GUEST_COUNTER_ADDR);
+        for _ in 0..100 {
+            thread::sleep(Duration::from_millis(10));
+            let counter = self.read_guest_u32(GUEST_COUNTER_ADDR);
+            if counter != start {
+                return counter;
+            }
+        }
+        panic!("guest counter is stuck at {}", start);
+    }
+}
+
+#[test]
+fn test_live_migration() {
+    skip_if_not_root!();
+
+    let dir = TempDir::new().unwrap();
+    let kernel_path = dir.as_path().join("vmlinux");
+    write_guest_kernel(&kernel_path);
+    // The disk image is shared by the source and destination VMs.
+    let disk = TempFile::new_in(dir.as_path()).unwrap();
+    disk.as_file().set_len(0x100000).unwrap();
+    let config = MigrationConfigInfo {
+        url: format!("unix:{}", dir.as_path().join("migrate.sock").display()),
+        ..Default::default()
+    };
+
+    let src = TestVmm::new();
+    src.configure(&kernel_path, disk.as_path());
+    src.request(VmmAction::StartMicroVm).unwrap();
+    src.activate_block_device();
+    src.wait_for_counter();
+    assert_eq!(
+        src.read_guest_u32(GUEST_SCRATCH_ADDR),
+        SERIAL_SCRATCH as u32
+    );
+
+    let dst = Arc::new(TestVmm::new());
+    dst.configure(&kernel_path, disk.as_path());
+    let receiver = {
+        let dst = dst.clone();
+        let config = config.clone();
+        thread::spawn(move || dst.request(VmmAction::ReceiveMigration(config)))
+    };
+    let socket = dir.as_path().join("migrate.sock");
+    while !socket.exists() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    src.request(VmmAction::SendMigration(config)).unwrap();
+    receiver.join().unwrap().unwrap();
+
+    // The source is left paused, while the destination runs the guest from the migrated memory
+    // and vCPU state.
+    let src_counter = src.read_guest_u32(GUEST_COUNTER_ADDR);
+    dst.wait_for_counter();
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(src.read_guest_u32(GUEST_COUNTER_ADDR), src_counter);
+
+    // The guest only reads the serial scratch register on the destination, so the value copied
+    // back to memory comes from the migrated serial port state.
+    dst.write_guest_u32(GUEST_SCRATCH_ADDR, 0);
+    dst.wait_for_counter();
+    assert_eq!(
+        dst.read_guest_u32(GUEST_SCRATCH_ADDR),
+        SERIAL_SCRATCH as u32
+    );
+
+    // The block device has been activated on the destination with the migrated queue.
+    assert_eq!(
+        dst.read_block_device_reg(REG_MMIO_STATUS),
+        BLOCK_DRIVER_STATUS
+    );
+    dst.write_block_device_reg(REG_MMIO_QUEUE_SEL, 0);
+    assert_eq!(dst.read_block_device_reg(REG_MMIO_QUEUE_READY), 1);
+}
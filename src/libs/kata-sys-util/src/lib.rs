@@ -14,6 +14,7 @@ pub mod mount;
 pub mod numa;
 pub mod rand;
 pub mod spec;
+pub mod systemd;
 pub mod validate;
 
 // Convenience macro to obtain the scoped logger
@@ -6,6 +6,7 @@
 use anyhow::{anyhow, Result};
 
 use super::common::{DEFAULT_SLICE, SCOPE_SUFFIX, SLICE_SUFFIX};
+use serde::{Deserialize, Serialize};
 use std::string::String;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -43,6 +44,15 @@ impl CgroupsPath {
     }
 }
 
+/// Check whether the cgroups path follows the `slice:prefix:name` format used by the systemd
+/// cgroup driver, as opposed to a cgroupfs path.
+pub fn is_systemd_cgroups_path(cgroups_path_str: &str) -> bool {
+    !cgroups_path_str.contains('/')
+        && CgroupsPath::new(cgroups_path_str)
+            .and_then(|p| p.parse())
+            .is_ok()
+}
+
 fn parse_parent(slice: String) -> Result<String> {
     if !slice.ends_with(SLICE_SUFFIX) || slice.contains('/') {
         return Err(anyhow!("invalid slice name: {}", slice));
@@ -75,7 +85,7 @@ fn get_unit_name(prefix: String, name: String) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::CgroupsPath;
+    use super::{is_systemd_cgroups_path, CgroupsPath};
 
     #[test]
     fn test_cgroup_path_parse() {
@@ -92,4 +102,15 @@ mod tests {
         assert_eq!(format!("{}", slice), parent_slice);
         assert_eq!(format!("{}-{}.scope", prefix, name), unit_name);
     }
+
+    #[test]
+    fn test_is_systemd_cgroups_path() {
+        assert!(is_systemd_cgroups_path(
+            "kubepods-besteffort-pod123.slice:cri-containerd:456"
+        ));
+        assert!(is_systemd_cgroups_path(":kata:456"));
+        assert!(!is_systemd_cgroups_path("/kubepods/besteffort/pod123/456"));
+        assert!(!is_systemd_cgroups_path("kata_overhead/456"));
+        assert!(!is_systemd_cgroups_path("system:kata:456"));
+    }
 }
@@ -9,6 +9,7 @@ use super::common::CgroupHierarchy;
 use super::common::{Properties, SLICE_SUFFIX, UNIT_MODE};
 use super::interface::system::ManagerProxyBlocking as SystemManager;
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use zbus::zvariant::Value;
 
 pub trait SystemdInterface {
@@ -17,6 +18,7 @@ pub trait SystemdInterface {
         pid: i32,
         parent: &str,
         unit_name: &str,
+        description: &str,
         cg_hierarchy: &CgroupHierarchy,
     ) -> Result<()>;
 
@@ -48,6 +50,7 @@ impl SystemdInterface for DBusClient {
         pid: i32,
         parent: &str,
         unit_name: &str,
+        description: &str,
         cg_hierarchy: &CgroupHierarchy,
     ) -> Result<()> {
         let proxy = self.build_proxy()?;
@@ -58,7 +61,7 @@ impl SystemdInterface for DBusClient {
             ("DefaultDependencies", Value::Bool(false)),
             ("MemoryAccounting", Value::Bool(true)),
             ("TasksAccounting", Value::Bool(true)),
-            ("Description", Value::Str("kata-agent container".into())),
+            ("Description", Value::Str(description.into())),
             ("PIDs", Value::Array(vec![pid as u32].into())),
         ];
 
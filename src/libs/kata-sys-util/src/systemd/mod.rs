@@ -0,0 +1,16 @@
+// Copyright 2021-2022 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Manage cgroups as transient systemd units, through the systemd D-Bus API.
+
+mod cgroups_path;
+mod common;
+mod dbus_client;
+mod interface;
+pub mod subsystem;
+
+pub use cgroups_path::{is_systemd_cgroups_path, CgroupsPath};
+pub use common::{CgroupHierarchy, Properties, DEFAULT_SLICE, SCOPE_SUFFIX, SLICE_SUFFIX};
+pub use dbus_client::{DBusClient, SystemdInterface};
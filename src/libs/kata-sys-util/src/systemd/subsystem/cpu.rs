@@ -126,7 +126,7 @@ fn resolve_cpuquota(quota: i64, period: u64) -> u64 {
 
 #[cfg(test)]
 mod tests {
-    use crate::cgroups::systemd::subsystem::cpu::resolve_cpuquota;
+    use crate::systemd::subsystem::cpu::resolve_cpuquota;
 
     #[test]
     fn test_unified_cpuquota() {
@@ -108,7 +108,7 @@ fn adjust_index(idx: usize) -> usize {
 mod tests {
     use std::convert::TryInto;
 
-    use crate::cgroups::systemd::subsystem::cpuset::BitMask;
+    use crate::systemd::subsystem::cpuset::BitMask;
 
     #[test]
     fn test_bitmask_conversion() {
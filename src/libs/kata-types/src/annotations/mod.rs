@@ -20,7 +20,9 @@ use crate::config::hypervisor::get_hypervisor_plugin;
 use crate::config::TomlConfig;
 use crate::sl;
 
-use self::cri_containerd::{SANDBOX_CPU_PERIOD_KEY, SANDBOX_CPU_QUOTA_KEY, SANDBOX_MEM_KEY};
+use self::cri_containerd::{
+    SANDBOX_CPU_PERIOD_KEY, SANDBOX_CPU_QUOTA_KEY, SANDBOX_CPU_SHARE_KEY, SANDBOX_MEM_KEY,
+};
 
 /// CRI-containerd specific annotations.
 pub mod cri_containerd;
@@ -187,6 +189,9 @@ pub const KATA_ANNO_CFG_HYPERVISOR_DEFAULT_VCPUS: &str =
 /// A sandbox annotation that specifies the maximum number of vCPUs allocated for the VM by the hypervisor.
 pub const KATA_ANNO_CFG_HYPERVISOR_DEFAULT_MAX_VCPUS: &str =
     "io.katacontainers.config.hypervisor.default_max_vcpus";
+/// A sandbox annotation to specify the host CPUs whose NUMA nodes the guest NUMA topology mirrors.
+pub const KATA_ANNO_CFG_HYPERVISOR_NUMA_CPUS: &str =
+    "io.katacontainers.config.hypervisor.numa_cpus";
 
 // Hypervisor Device related annotations
 /// A sandbox annotation used to indicate if devices need to be hotplugged on the root bus instead
@@ -302,6 +307,15 @@ pub const KATA_ANNO_CFG_INTER_NETWORK_MODEL: &str =
 /// SandboxCgroupOnly is a sandbox annotation that determines if kata processes are managed only in sandbox cgroup.
 pub const KATA_ANNO_CFG_SANDBOX_CGROUP_ONLY: &str =
     "io.katacontainers.config.runtime.sandbox_cgroup_only";
+/// A sandbox annotation to specify the CPU overhead of the VMM, in milli-CPUs.
+pub const KATA_ANNO_CFG_VMM_OVERHEAD_CPU: &str =
+    "io.katacontainers.config.runtime.vmm_overhead_cpu";
+/// A sandbox annotation to specify the memory overhead of the VMM, in MiB.
+pub const KATA_ANNO_CFG_VMM_OVERHEAD_MEMORY: &str =
+    "io.katacontainers.config.runtime.vmm_overhead_memory";
+/// A sandbox annotation that determines if the vCPU threads are pinned to the host CPUs.
+pub const KATA_ANNO_CFG_ENABLE_VCPUS_PINNING: &str =
+    "io.katacontainers.config.runtime.enable_vcpus_pinning";
 /// A sandbox annotation that determines if create a netns for hypervisor process.
 pub const KATA_ANNO_CFG_DISABLE_NEW_NETNS: &str =
     "io.katacontainers.config.runtime.disable_new_netns";
@@ -398,6 +412,14 @@ impl Annotation {
         value.unwrap_or(0)
     }
 
+    /// Get the annotation of cpu shares for sandbox
+    pub fn get_sandbox_cpu_shares(&self) -> u64 {
+        let value = self
+            .get_value::<u64>(SANDBOX_CPU_SHARE_KEY)
+            .unwrap_or(Some(0));
+        value.unwrap_or(0)
+    }
+
     /// Get the annotation of memory for sandbox
     pub fn get_sandbox_mem(&self) -> i64 {
         let value = self.get_value::<i64>(SANDBOX_MEM_KEY).unwrap_or(Some(0));
@@ -627,6 +649,9 @@ impl Annotation {
                             }
                         }
                     }
+                    KATA_ANNO_CFG_HYPERVISOR_NUMA_CPUS => {
+                        hv.cpu_info.numa_cpus = value.to_string();
+                    }
                     // Hypervisor Device related annotations
                     KATA_ANNO_CFG_HYPERVISOR_HOTPLUG_VFIO_ON_ROOT_BUS => {
                         match self.get_value::<bool>(key) {
@@ -928,6 +953,30 @@ impl Annotation {
                             return Err(bool_err);
                         }
                     },
+                    KATA_ANNO_CFG_VMM_OVERHEAD_CPU => match self.get_value::<u32>(key) {
+                        Ok(r) => {
+                            config.runtime.vmm_overhead_cpu = r.unwrap_or_default();
+                        }
+                        Err(_e) => {
+                            return Err(u32_err);
+                        }
+                    },
+                    KATA_ANNO_CFG_VMM_OVERHEAD_MEMORY => match self.get_value::<u32>(key) {
+                        Ok(r) => {
+                            config.runtime.vmm_overhead_memory = r.unwrap_or_default();
+                        }
+                        Err(_e) => {
+                            return Err(u32_err);
+                        }
+                    },
+                    KATA_ANNO_CFG_ENABLE_VCPUS_PINNING => match self.get_value::<bool>(key) {
+                        Ok(r) => {
+                            config.runtime.enable_vcpus_pinning = r.unwrap_or_default();
+                        }
+                        Err(_e) => {
+                            return Err(bool_err);
+                        }
+                    },
                     KATA_ANNO_CFG_DISABLE_NEW_NETNS => match self.get_value::<bool>(key) {
                         Ok(r) => {
                             config.runtime.disable_new_netns = r.unwrap_or_default();
@@ -30,6 +30,11 @@ pub const DEFAULT_HYPERVISOR: &str = HYPERVISOR_NAME_DRAGONBALL;
 
 pub const DEFAULT_INTERNETWORKING_MODEL: &str = "tcfilter";
 
+pub const DEFAULT_HEALTH_CHECK_INTERVAL_SECS: u32 = 30;
+pub const DEFAULT_HEALTH_CHECK_TIMEOUT_MS: u32 = 10_000;
+pub const DEFAULT_HEALTH_CHECK_FAILURE_THRESHOLD: u32 = 3;
+pub const DEFAULT_HEALTH_CHECK_BACKOFF_MS: u32 = 1_000;
+
 pub const DEFAULT_BLOCK_DEVICE_TYPE: &str = "virtio-blk";
 pub const DEFAULT_VHOST_USER_STORE_PATH: &str = "/var/run/vhost-user";
 pub const DEFAULT_BLOCK_NVDIMM_MEM_OFFSET: u64 = 0;
@@ -116,6 +116,11 @@ impl ConfigPlugin for DragonballConfig {
                     db.blockdev_info.block_device_driver
                 ));
             }
+            if db.blockdev_info.enable_vhost_user_store {
+                return Err(eother!(
+                    "dragonball hypervisor does not support vhost-user store"
+                ));
+            }
 
             if db.boot_info.kernel.is_empty() {
                 return Err(eother!(
@@ -167,12 +172,6 @@ impl ConfigPlugin for DragonballConfig {
                 return Err(eother!("dragonball hypervisor does not support pflashes"));
             }
 
-            if db.memory_info.enable_guest_swap {
-                return Err(eother!(
-                    "dragonball hypervisor doesn't support enable_guest_swap"
-                ));
-            }
-
             if db.security_info.rootless {
                 return Err(eother!(
                     "dragonball hypervisor does not support rootless mode"
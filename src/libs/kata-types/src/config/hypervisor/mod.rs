@@ -25,6 +25,7 @@
 use std::collections::HashMap;
 use std::io::{self, Result};
 use std::path::Path;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
 use lazy_static::lazy_static;
@@ -32,6 +33,7 @@ use regex::RegexSet;
 
 use super::{default, ConfigOps, ConfigPlugin, TomlConfig};
 use crate::annotations::KATA_ANNO_CFG_HYPERVISOR_PREFIX;
+use crate::cpu::CpuSet;
 use crate::{eother, resolve_path, sl, validate_path};
 
 mod dragonball;
@@ -53,6 +55,18 @@ const VIRTIO_FS: &str = "virtio-fs";
 const VIRTIO_FS_INLINE: &str = "inline-virtio-fs";
 const MAX_BRIDGE_SIZE: u32 = 5;
 
+/// Stop the guest when its watchdog expires.
+pub const WATCHDOG_ACTION_RESET: &str = "reset";
+/// Pause the guest when its watchdog expires.
+pub const WATCHDOG_ACTION_STOP: &str = "stop";
+/// Keep the guest running when its watchdog expires.
+pub const WATCHDOG_ACTION_NOTIFY: &str = "notify";
+const WATCHDOG_ACTIONS: [&str; 3] = [
+    WATCHDOG_ACTION_RESET,
+    WATCHDOG_ACTION_STOP,
+    WATCHDOG_ACTION_NOTIFY,
+];
+
 const KERNEL_PARAM_DELIMITER: &str = " ";
 
 lazy_static! {
@@ -300,6 +314,23 @@ pub struct CpuInfo {
     /// NOTICE: on arm platform with gicv2 interrupt controller, set it to 8.
     #[serde(default)]
     pub default_maxvcpus: u32,
+
+    /// Give the guest a NUMA topology mirroring the host NUMA nodes of the container cpuset, with
+    /// the guest memory of each node bound to the matching host node.
+    ///
+    /// The guest sees the nodes in the ACPI SRAT and SLIT tables built by the hypervisor, so its
+    /// kernel needs CONFIG_NUMA and CONFIG_X86_64_ACPI_NUMA. Only Dragonball on x86_64 supports
+    /// it. The vCPUs and the memory are split evenly among the nodes, so the NUMA topology is not
+    /// used, with a warning, if they can't be.
+    #[serde(default)]
+    pub enable_numa: bool,
+
+    /// The host CPUs whose NUMA nodes the guest NUMA topology mirrors, as a cpulist.
+    ///
+    /// If empty, the cpuset of the container is used for a single container. The cpusets of pod
+    /// containers are unknown when the VM boots, so pods need it set, e.g. by annotation.
+    #[serde(default)]
+    pub numa_cpus: String,
 }
 
 impl CpuInfo {
@@ -338,6 +369,10 @@ impl CpuInfo {
                 self.default_maxvcpus
             ));
         }
+        if !self.numa_cpus.is_empty() {
+            CpuSet::from_str(&self.numa_cpus)
+                .map_err(|e| eother!("Invalid numa_cpus {}: {}", self.numa_cpus, e))?;
+        }
         Ok(())
     }
 }
@@ -424,6 +459,19 @@ pub struct DeviceInfo {
     /// Enabling this will result in the VM device having iommu_platform=on set
     #[serde(default)]
     pub enable_iommu_platform: bool,
+
+    /// Action taken when the guest watchdog expires, the watchdog device is not created if empty.
+    ///
+    /// Options:
+    /// - reset: stop the hung guest.
+    /// - stop: pause the vCPUs of the hung guest, so that it could be inspected.
+    /// - notify: keep the hung guest running.
+    ///
+    /// The runtime is notified of the expiry in all cases, and reports the sandbox as dead.
+    /// The guest kernel must drive the watchdog, which is an IB700 one for Dragonball, and the
+    /// agent keeps it fed.
+    #[serde(default)]
+    pub watchdog_action: String,
 }
 
 impl DeviceInfo {
@@ -444,6 +492,15 @@ impl DeviceInfo {
                 self.default_bridges
             ));
         }
+        if !self.watchdog_action.is_empty()
+            && !WATCHDOG_ACTIONS.contains(&self.watchdog_action.as_str())
+        {
+            return Err(eother!(
+                "Invalid watchdog action `{}`, should be one of {:?}",
+                self.watchdog_action,
+                WATCHDOG_ACTIONS
+            ));
+        }
         Ok(())
     }
 }
@@ -1125,6 +1182,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_device_info_validate_watchdog_action() {
+        let mut device_info = DeviceInfo::default();
+        device_info.validate().unwrap();
+
+        for action in WATCHDOG_ACTIONS {
+            device_info.watchdog_action = action.to_string();
+            device_info.validate().unwrap();
+        }
+
+        device_info.watchdog_action = "poweroff".to_string();
+        device_info.validate().unwrap_err();
+    }
+
     #[test]
     fn test_cpu_info_adjust_config() {
         // get CPU cores of the test node
@@ -1144,11 +1215,15 @@ mod tests {
                     cpu_features: "".to_string(),
                     default_vcpus: 0,
                     default_maxvcpus: 0,
+                    enable_numa: false,
+                    numa_cpus: "".to_string(),
                 },
                 output: CpuInfo {
                     cpu_features: "".to_string(),
                     default_vcpus,
                     default_maxvcpus: node_cpus,
+                    enable_numa: false,
+                    numa_cpus: "".to_string(),
                 },
             },
             TestData {
@@ -1157,11 +1232,15 @@ mod tests {
                     cpu_features: "a,b,c".to_string(),
                     default_vcpus: 9999999,
                     default_maxvcpus: 9999999,
+                    enable_numa: false,
+                    numa_cpus: "".to_string(),
                 },
                 output: CpuInfo {
                     cpu_features: "a,b,c".to_string(),
                     default_vcpus: node_cpus as i32,
                     default_maxvcpus: node_cpus,
+                    enable_numa: false,
+                    numa_cpus: "".to_string(),
                 },
             },
             TestData {
@@ -1170,11 +1249,15 @@ mod tests {
                     cpu_features: "a, b ,c".to_string(),
                     default_vcpus: -1,
                     default_maxvcpus: 1,
+                    enable_numa: false,
+                    numa_cpus: "".to_string(),
                 },
                 output: CpuInfo {
                     cpu_features: "a,b,c".to_string(),
                     default_vcpus: 1,
                     default_maxvcpus: 1,
+                    enable_numa: false,
+                    numa_cpus: "".to_string(),
                 },
             },
         ];
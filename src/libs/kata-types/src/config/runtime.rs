@@ -32,6 +32,24 @@ pub struct Runtime {
     #[serde(default, rename = "enable_debug")]
     pub debug: bool,
 
+    /// Where the runtime writes its log records.
+    ///
+    /// Options:
+    /// - json: JSON objects written to the log file, the default.
+    /// - logfmt: `key=value` text lines written to the log file.
+    /// - journald: entries sent to the journal with a field per key.
+    /// - syslog: messages sent to the local syslog daemon.
+    #[serde(default)]
+    pub log_sink: String,
+
+    /// Directory where the event logs of the sandboxes are kept, in a sub-directory per sandbox.
+    ///
+    /// The event log of a sandbox is kept in the sandbox directory by default, and is removed
+    /// with it when the sandbox is deleted. If set, the event logs outlive their sandboxes to
+    /// be audited, and are to be cleaned up by the administrator.
+    #[serde(default)]
+    pub event_log_dir: String,
+
     /// Enabled experimental feature list, format: ["a", "b"].
     ///
     /// Experimental features are features not stable enough for production, they may break
@@ -84,6 +102,50 @@ pub struct Runtime {
     #[serde(default)]
     pub sandbox_cgroup_only: bool,
 
+    /// CPU overhead of the VMM and the other kata processes of a sandbox, in milli-CPUs.
+    ///
+    /// It's added to the summed CPU limits of the containers in the sandbox cgroup, and if
+    /// `sandbox_cgroup_only` is disabled, it's also the CPU limit of the overhead cgroup.
+    /// The overhead is not limited if it's 0.
+    #[serde(default)]
+    pub vmm_overhead_cpu: u32,
+
+    /// Memory overhead of the VMM and the other kata processes of a sandbox, in MiB.
+    ///
+    /// It's added to the summed memory limits of the containers in the sandbox cgroup, and if
+    /// `sandbox_cgroup_only` is disabled, it's also the memory limit of the overhead cgroup.
+    /// The overhead is not limited if it's 0.
+    #[serde(default)]
+    pub vmm_overhead_memory: u32,
+
+    /// If enabled, each vCPU thread is pinned to one host CPU when the number of CPUs in the
+    /// cpuset of the sandbox equals the number of vCPUs, and the sandbox memory is bound to the
+    /// host NUMA nodes of these CPUs. The vCPU threads float again on the whole cpuset when the
+    /// numbers do not match anymore.
+    #[serde(default)]
+    pub enable_vcpus_pinning: bool,
+
+    /// Interval between two health checks of the sandbox, in seconds.
+    ///
+    /// A health check pings the agent and checks that the hypervisor processes are alive.
+    #[serde(default)]
+    pub health_check_interval_secs: u32,
+
+    /// Time a health check may take before it is counted as failed, in milliseconds.
+    #[serde(default)]
+    pub health_check_timeout_ms: u32,
+
+    /// Number of consecutive failed health checks after which the sandbox is considered dead.
+    ///
+    /// The containers are then reported as exited and the sandbox is shut down.
+    #[serde(default)]
+    pub health_check_failure_threshold: u32,
+
+    /// Delay before retrying a failed health check, in milliseconds. It's doubled on each
+    /// consecutive failure, up to the health check interval.
+    #[serde(default)]
+    pub health_check_backoff_ms: u32,
+
     /// If enabled, the runtime will create opentracing.io traces and spans.
     /// See https://www.jaegertracing.io/docs/getting-started.
     #[serde(default)]
@@ -138,6 +200,19 @@ impl ConfigOps for Runtime {
         if conf.runtime.internetworking_model.is_empty() {
             conf.runtime.internetworking_model = default::DEFAULT_INTERNETWORKING_MODEL.to_owned();
         }
+        if conf.runtime.health_check_interval_secs == 0 {
+            conf.runtime.health_check_interval_secs = default::DEFAULT_HEALTH_CHECK_INTERVAL_SECS;
+        }
+        if conf.runtime.health_check_timeout_ms == 0 {
+            conf.runtime.health_check_timeout_ms = default::DEFAULT_HEALTH_CHECK_TIMEOUT_MS;
+        }
+        if conf.runtime.health_check_failure_threshold == 0 {
+            conf.runtime.health_check_failure_threshold =
+                default::DEFAULT_HEALTH_CHECK_FAILURE_THRESHOLD;
+        }
+        if conf.runtime.health_check_backoff_ms == 0 {
+            conf.runtime.health_check_backoff_ms = default::DEFAULT_HEALTH_CHECK_BACKOFF_MS;
+        }
 
         for bind in conf.runtime.sandbox_bind_mounts.iter_mut() {
             resolve_path!(*bind, "sandbox bind mount `{}` is invalid: {}")?;
@@ -169,6 +244,27 @@ impl ConfigOps for Runtime {
             ));
         }
 
+        let log_sink = &conf.runtime.log_sink;
+        if !log_sink.is_empty()
+            && log_sink != "json"
+            && log_sink != "logfmt"
+            && log_sink != "journald"
+            && log_sink != "syslog"
+        {
+            return Err(eother!(
+                "Invalid log_sink `{}` in configuration file",
+                log_sink
+            ));
+        }
+
+        let event_log_dir = &conf.runtime.event_log_dir;
+        if !event_log_dir.is_empty() && !Path::new(event_log_dir).is_absolute() {
+            return Err(eother!(
+                "Invalid event_log_dir `{}` in configuration file, must be an absolute path",
+                event_log_dir
+            ));
+        }
+
         for bind in conf.runtime.sandbox_bind_mounts.iter() {
             validate_path!(*bind, "sandbox bind mount `{}` is invalid: {}")?;
         }
@@ -249,6 +345,20 @@ vfio_mode = "vfio,guest-kernel"
 [runtime]
 enable_debug = true
 vfio_mode = "guest_kernel"
+"#;
+        let config: TomlConfig = TomlConfig::load(content).unwrap();
+        config.validate().unwrap_err();
+
+        let content = r#"
+[runtime]
+log_sink = "text"
+"#;
+        let config: TomlConfig = TomlConfig::load(content).unwrap();
+        config.validate().unwrap_err();
+
+        let content = r#"
+[runtime]
+event_log_dir = "var/log/kata"
 "#;
         let config: TomlConfig = TomlConfig::load(content).unwrap();
         config.validate().unwrap_err();
@@ -260,6 +370,8 @@ vfio_mode = "guest_kernel"
 [runtime]
 name = "virt-container"
 enable_debug = true
+log_sink = "journald"
+event_log_dir = "/var/log/kata-events"
 experimental = ["a", "b"]
 internetworking_model = "macvtap"
 disable_new_netns = true
@@ -272,12 +384,15 @@ jaeger_password = "pw"
 enable_pprof = true
 disable_guest_seccomp = true
 vfio_mode = "vfio"
+health_check_failure_threshold = 5
 field_should_be_ignored = true
 "#;
         let config: TomlConfig = TomlConfig::load(content).unwrap();
         config.validate().unwrap();
         assert_eq!(&config.runtime.name, "virt-container");
         assert!(config.runtime.debug);
+        assert_eq!(&config.runtime.log_sink, "journald");
+        assert_eq!(&config.runtime.event_log_dir, "/var/log/kata-events");
         assert_eq!(config.runtime.experimental.len(), 2);
         assert_eq!(&config.runtime.experimental[0], "a");
         assert_eq!(&config.runtime.experimental[1], "b");
@@ -289,5 +404,14 @@ field_should_be_ignored = true
         assert!(config.runtime.is_experiment_enabled("a"));
         assert!(config.runtime.is_experiment_enabled("b"));
         assert!(!config.runtime.is_experiment_enabled("c"));
+        assert_eq!(config.runtime.health_check_failure_threshold, 5);
+        assert_eq!(
+            config.runtime.health_check_interval_secs,
+            default::DEFAULT_HEALTH_CHECK_INTERVAL_SECS
+        );
+        assert_eq!(
+            config.runtime.health_check_backoff_ms,
+            default::DEFAULT_HEALTH_CHECK_BACKOFF_MS
+        );
     }
 }
@@ -7,7 +7,8 @@ mod tests {
     use kata_types::annotations::{
         Annotation, KATA_ANNO_CFG_AGENT_CONTAINER_PIPE_SIZE, KATA_ANNO_CFG_AGENT_TRACE,
         KATA_ANNO_CFG_DISABLE_GUEST_SECCOMP, KATA_ANNO_CFG_ENABLE_PPROF,
-        KATA_ANNO_CFG_EXPERIMENTAL, KATA_ANNO_CFG_HYPERVISOR_BLOCK_DEV_CACHE_NOFLUSH,
+        KATA_ANNO_CFG_ENABLE_VCPUS_PINNING, KATA_ANNO_CFG_EXPERIMENTAL,
+        KATA_ANNO_CFG_HYPERVISOR_BLOCK_DEV_CACHE_NOFLUSH,
         KATA_ANNO_CFG_HYPERVISOR_BLOCK_DEV_DRIVER, KATA_ANNO_CFG_HYPERVISOR_CTLPATH,
         KATA_ANNO_CFG_HYPERVISOR_DEFAULT_MEMORY, KATA_ANNO_CFG_HYPERVISOR_DEFAULT_VCPUS,
         KATA_ANNO_CFG_HYPERVISOR_ENABLE_GUEST_SWAP, KATA_ANNO_CFG_HYPERVISOR_ENABLE_IO_THREADS,
@@ -15,10 +16,11 @@ mod tests {
         KATA_ANNO_CFG_HYPERVISOR_GUEST_HOOK_PATH, KATA_ANNO_CFG_HYPERVISOR_HUGE_PAGES,
         KATA_ANNO_CFG_HYPERVISOR_JAILER_PATH, KATA_ANNO_CFG_HYPERVISOR_KERNEL_PATH,
         KATA_ANNO_CFG_HYPERVISOR_MEMORY_PREALLOC, KATA_ANNO_CFG_HYPERVISOR_MEMORY_SLOTS,
-        KATA_ANNO_CFG_HYPERVISOR_PATH, KATA_ANNO_CFG_HYPERVISOR_VHOSTUSER_STORE_PATH,
-        KATA_ANNO_CFG_HYPERVISOR_VIRTIO_FS_DAEMON, KATA_ANNO_CFG_HYPERVISOR_VIRTIO_FS_EXTRA_ARGS,
-        KATA_ANNO_CFG_HYPERVISOR_VIRTIO_MEM, KATA_ANNO_CFG_KERNEL_MODULES,
-        KATA_ANNO_CFG_RUNTIME_NAME,
+        KATA_ANNO_CFG_HYPERVISOR_NUMA_CPUS, KATA_ANNO_CFG_HYPERVISOR_PATH,
+        KATA_ANNO_CFG_HYPERVISOR_VHOSTUSER_STORE_PATH, KATA_ANNO_CFG_HYPERVISOR_VIRTIO_FS_DAEMON,
+        KATA_ANNO_CFG_HYPERVISOR_VIRTIO_FS_EXTRA_ARGS, KATA_ANNO_CFG_HYPERVISOR_VIRTIO_MEM,
+        KATA_ANNO_CFG_KERNEL_MODULES, KATA_ANNO_CFG_RUNTIME_NAME, KATA_ANNO_CFG_VMM_OVERHEAD_CPU,
+        KATA_ANNO_CFG_VMM_OVERHEAD_MEMORY,
     };
     use kata_types::config::KataConfig;
     use kata_types::config::{QemuConfig, TomlConfig};
@@ -112,7 +114,23 @@ mod tests {
             KATA_ANNO_CFG_HYPERVISOR_DEFAULT_VCPUS.to_string(),
             "12".to_string(),
         );
+        anno_hash.insert(
+            KATA_ANNO_CFG_HYPERVISOR_NUMA_CPUS.to_string(),
+            "0-3,8".to_string(),
+        );
         anno_hash.insert(KATA_ANNO_CFG_ENABLE_PPROF.to_string(), "false".to_string());
+        anno_hash.insert(
+            KATA_ANNO_CFG_VMM_OVERHEAD_CPU.to_string(),
+            "250".to_string(),
+        );
+        anno_hash.insert(
+            KATA_ANNO_CFG_VMM_OVERHEAD_MEMORY.to_string(),
+            "64".to_string(),
+        );
+        anno_hash.insert(
+            KATA_ANNO_CFG_ENABLE_VCPUS_PINNING.to_string(),
+            "true".to_string(),
+        );
         anno_hash.insert(
             KATA_ANNO_CFG_HYPERVISOR_ENABLE_GUEST_SWAP.to_string(),
             "false".to_string(),
@@ -197,6 +215,7 @@ mod tests {
             assert!(!hv.memory_info.enable_mem_prealloc);
             assert_eq!(hv.ctlpath, "./jvm".to_string());
             assert_eq!(hv.cpu_info.default_vcpus, 12);
+            assert_eq!(hv.cpu_info.numa_cpus, "0-3,8");
             assert!(!hv.memory_info.enable_guest_swap);
             assert_eq!(hv.memory_info.default_memory, 100);
             assert!(!hv.enable_iothreads);
@@ -230,6 +249,26 @@ mod tests {
                 .runtime
                 .enable_pprof
         );
+        assert_eq!(
+            KataConfig::get_active_config()
+                .get_config()
+                .runtime
+                .vmm_overhead_cpu,
+            250
+        );
+        assert_eq!(
+            KataConfig::get_active_config()
+                .get_config()
+                .runtime
+                .vmm_overhead_memory,
+            64
+        );
+        assert!(
+            KataConfig::get_active_config()
+                .get_config()
+                .runtime
+                .enable_vcpus_pinning
+        );
         assert_eq!(
             KataConfig::get_active_config()
                 .get_config()
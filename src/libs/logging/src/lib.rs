@@ -9,12 +9,17 @@ use std::io;
 use std::io::Write;
 use std::process;
 use std::result;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock};
 
 mod file_rotate;
+mod log_sink;
 mod log_writer;
 
 pub use file_rotate::FileRotator;
+pub use log_sink::{
+    LogSink, JOURNALD_SOCKET, LOG_SINK_JOURNALD, LOG_SINK_JSON, LOG_SINK_LOGFMT, LOG_SINK_SYSLOG,
+    SYSLOG_SOCKET,
+};
 pub use log_writer::LogWriter;
 
 #[macro_export]
@@ -39,6 +44,37 @@ const LOG_LEVELS: &[(&str, slog::Level)] = &[
 
 const DEFAULT_SUBSYSTEM: &str = "root";
 
+const SUBSYSTEM_KEY: &str = "subsystem";
+
+/// The levels of the records logged by a logger, which could be changed at runtime: the level of
+/// some subsystems, as named by `logger_with_subsystem!`, and the default level of the others.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogLevels {
+    pub default: slog::Level,
+    pub subsystems: HashMap<String, slog::Level>,
+}
+
+impl LogLevels {
+    fn new(default: slog::Level) -> Self {
+        LogLevels {
+            default,
+            subsystems: HashMap::new(),
+        }
+    }
+
+    fn level(&self, subsystem: Option<&str>) -> slog::Level {
+        subsystem
+            .and_then(|s| self.subsystems.get(s))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+type SharedLogLevels = Arc<RwLock<LogLevels>>;
+
+// The levels of the logger last created, i.e. the logger of the process.
+static PROCESS_LOG_LEVELS: Mutex<Option<SharedLogLevels>> = Mutex::new(None);
+
 // XXX: 'writer' param used to make testing possible.
 pub fn create_logger<W>(
     name: &str,
@@ -49,16 +85,28 @@ pub fn create_logger<W>(
 where
     W: Write + Send + Sync + 'static,
 {
-    let json_drain = slog_json::Json::new(writer)
-        .add_default_keys()
-        .build()
-        .fuse();
+    // A JSON sink never fails to be created.
+    create_logger_with_sink(name, source, level, LogSink::Json(Box::new(writer)))
+        .expect("failed to create JSON logger")
+}
+
+/// Create a logger writing its records to the given sink, which fails if the sink can't be used,
+/// e.g. the socket of journald doesn't exist.
+pub fn create_logger_with_sink(
+    name: &str,
+    source: &str,
+    level: slog::Level,
+    sink: LogSink,
+) -> io::Result<(slog::Logger, slog_async::AsyncGuard)> {
+    let sink_drain = sink.into_drain(name)?.fuse();
 
     // Ensure only a unique set of key/value fields is logged
-    let unique_drain = UniqueDrain::new(json_drain).fuse();
+    let unique_drain = UniqueDrain::new(sink_drain).fuse();
 
     // Allow runtime filtering of records by log level
-    let filter_drain = RuntimeLevelFilter::new(unique_drain, level).fuse();
+    let levels = Arc::new(RwLock::new(LogLevels::new(level)));
+    *PROCESS_LOG_LEVELS.lock().unwrap() = Some(levels.clone());
+    let filter_drain = RuntimeLevelFilter::new(unique_drain, levels).fuse();
 
     // Ensure the logger is thread-safe
     let (async_drain, guard) = slog_async::Async::new(filter_drain)
@@ -75,7 +123,7 @@ where
             "source" => source.to_string()),
     );
 
-    (logger, guard)
+    Ok((logger, guard))
 }
 
 pub fn get_log_levels() -> Vec<&'static str> {
@@ -84,6 +132,34 @@ pub fn get_log_levels() -> Vec<&'static str> {
     result
 }
 
+/// Set the level of the records of a subsystem logged by the process logger, or the default
+/// level of the subsystems without their own level if `subsystem` is empty.
+pub fn set_log_level(subsystem: &str, level: slog::Level) -> Result<(), &'static str> {
+    let process_levels = PROCESS_LOG_LEVELS.lock().unwrap();
+    let mut levels = process_levels
+        .as_ref()
+        .ok_or("no logger created")?
+        .write()
+        .unwrap();
+
+    if subsystem.is_empty() {
+        levels.default = level;
+    } else {
+        levels.subsystems.insert(subsystem.to_string(), level);
+    }
+
+    Ok(())
+}
+
+/// Get the levels of the records logged by the process logger.
+pub fn get_process_log_levels() -> Option<LogLevels> {
+    PROCESS_LOG_LEVELS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|levels| levels.read().unwrap().clone())
+}
+
 pub fn level_name_to_slog_level(level_name: &str) -> Result<slog::Level, String> {
     for tuple in LOG_LEVELS {
         if tuple.0 == level_name {
@@ -194,19 +270,42 @@ where
     }
 }
 
-// A RuntimeLevelFilter will discard all log records whose log level is less than the level
-// specified in the struct.
+// Used to find the subsystem of a record: the one of the record itself if any, otherwise the one
+// of the newest logger, as loggers are serialised child first.
+struct SubsystemSerializer {
+    subsystem: Option<String>,
+}
+
+impl slog::Serializer for SubsystemSerializer {
+    fn emit_arguments(&mut self, key: Key, value: &std::fmt::Arguments) -> slog::Result {
+        let key: &str = key.as_ref();
+        if self.subsystem.is_none() && key == SUBSYSTEM_KEY {
+            self.subsystem = Some(format!("{}", value));
+        }
+        Ok(())
+    }
+}
+
+fn record_subsystem(record: &Record, values: &OwnedKVList) -> Option<String> {
+    let mut serializer = SubsystemSerializer { subsystem: None };
+    let _ = record.kv().serialize(record, &mut serializer);
+    if serializer.subsystem.is_none() {
+        let _ = values.serialize(record, &mut serializer);
+    }
+
+    serializer.subsystem
+}
+
+// A RuntimeLevelFilter will discard all log records whose log level is less than the level of
+// their subsystem in the shared levels.
 struct RuntimeLevelFilter<D> {
     drain: D,
-    level: Mutex<slog::Level>,
+    levels: SharedLogLevels,
 }
 
 impl<D> RuntimeLevelFilter<D> {
-    fn new(drain: D, level: slog::Level) -> Self {
-        RuntimeLevelFilter {
-            drain,
-            level: Mutex::new(level),
-        }
+    fn new(drain: D, levels: SharedLogLevels) -> Self {
+        RuntimeLevelFilter { drain, levels }
     }
 }
 
@@ -222,9 +321,17 @@ where
         record: &slog::Record,
         values: &slog::OwnedKVList,
     ) -> result::Result<Self::Ok, Self::Err> {
-        let log_level = self.level.lock().unwrap();
+        let log_level = {
+            let levels = self.levels.read().unwrap();
+            // Only look for the subsystem when it matters.
+            if levels.subsystems.is_empty() {
+                levels.default
+            } else {
+                levels.level(record_subsystem(record, values).as_deref())
+            }
+        };
 
-        if record.level().is_at_least(*log_level) {
+        if record.level().is_at_least(log_level) {
             self.drain.log(record, values)?;
         }
 
@@ -453,6 +560,44 @@ mod tests {
         assert_eq!(field_record_value, record_value);
     }
 
+    #[test]
+    fn test_create_logger_with_logfmt_sink() {
+        let writer = NamedTempFile::new().expect("failed to create tempfile");
+        let mut writer_ref = writer.reopen().expect("failed to clone tempfile");
+
+        let sink = LogSink::from_name(LOG_SINK_LOGFMT, writer).unwrap();
+        let (logger, guard) =
+            create_logger_with_sink("name", "source", slog::Level::Info, sink).unwrap();
+        info!(&logger, "hello world"; "subsystem" => "record-subsystem");
+        debug!(&logger, "filtered out");
+
+        drop(guard);
+        drop(logger);
+
+        let mut contents = String::new();
+        writer_ref
+            .read_to_string(&mut contents)
+            .expect("failed to read tempfile contents");
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 1, "{}", contents);
+
+        let line = lines[0];
+        assert!(line.starts_with("ts="), "{}", line);
+        assert!(
+            line.contains(r#" level=info msg="hello world" subsystem=record-subsystem "#),
+            "{}",
+            line
+        );
+        // The key of the record replaces the one of the logger
+        assert_eq!(line.matches("subsystem=").count(), 1, "{}", line);
+        assert!(line.contains(" name=name "), "{}", line);
+        assert!(line.contains(" source=source "), "{}", line);
+
+        assert!(LogSink::from_name("text", io::sink()).is_err());
+        let sink = LogSink::Journald(std::path::PathBuf::from("/nonexistent/journal/socket"));
+        assert!(create_logger_with_sink("name", "source", slog::Level::Info, sink).is_err());
+    }
+
     #[test]
     fn test_logger_levels() {
         let name = "name";
@@ -617,4 +762,55 @@ mod tests {
             assert_eq!(field_subsystem, &json!(DEFAULT_SUBSYSTEM), "{}", msg);
         }
     }
+
+    #[test]
+    fn test_runtime_level_filter_subsystems() {
+        // Keeps the messages of the records reaching it.
+        struct MsgDrain(Arc<Mutex<Vec<String>>>);
+
+        impl Drain for MsgDrain {
+            type Ok = ();
+            type Err = slog::Never;
+
+            fn log(&self, record: &Record, _: &OwnedKVList) -> Result<(), slog::Never> {
+                self.0.lock().unwrap().push(format!("{}", record.msg()));
+                Ok(())
+            }
+        }
+
+        let msgs = Arc::new(Mutex::new(Vec::new()));
+        let levels = Arc::new(RwLock::new(LogLevels::new(slog::Level::Info)));
+        let filter = RuntimeLevelFilter::new(MsgDrain(msgs.clone()), levels.clone());
+        let logger = Logger::root(filter.fuse(), o!("subsystem" => DEFAULT_SUBSYSTEM));
+        let hypervisor = logger.new(o!("subsystem" => "hypervisor"));
+
+        debug!(logger, "root debug 1");
+        debug!(hypervisor, "hypervisor debug 1");
+        info!(hypervisor, "hypervisor info 1");
+
+        levels
+            .write()
+            .unwrap()
+            .subsystems
+            .insert("hypervisor".to_string(), slog::Level::Debug);
+        debug!(logger, "root debug 2");
+        debug!(hypervisor, "hypervisor debug 2");
+        // the subsystem of the record takes priority over the one of the logger
+        debug!(logger, "resource debug"; "subsystem" => "resource");
+        debug!(logger, "hypervisor debug 3"; "subsystem" => "hypervisor");
+
+        levels.write().unwrap().default = slog::Level::Error;
+        info!(logger, "root info");
+        info!(hypervisor, "hypervisor info 2");
+
+        assert_eq!(
+            *msgs.lock().unwrap(),
+            vec![
+                "hypervisor info 1",
+                "hypervisor debug 2",
+                "hypervisor debug 3",
+                "hypervisor info 2"
+            ]
+        );
+    }
 }
@@ -0,0 +1,389 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::process;
+
+use chrono::{Local, SecondsFormat, Utc};
+use slog::{Drain, Key, OwnedKVList, Record, KV};
+
+pub const LOG_SINK_JSON: &str = "json";
+pub const LOG_SINK_LOGFMT: &str = "logfmt";
+pub const LOG_SINK_JOURNALD: &str = "journald";
+pub const LOG_SINK_SYSLOG: &str = "syslog";
+
+/// The socket of the native protocol of journald.
+pub const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// The socket of the local syslog daemon.
+pub const SYSLOG_SOCKET: &str = "/dev/log";
+
+// The syslog facility of the records, the one of the system daemons.
+const SYSLOG_FACILITY_DAEMON: u8 = 3;
+
+// journald rejects the longer field names.
+const JOURNALD_FIELD_NAME_MAX_LEN: usize = 64;
+
+type BoxedDrain = Box<dyn Drain<Ok = (), Err = io::Error> + Send>;
+
+/// Where the records of a logger are written to, and in which format.
+pub enum LogSink {
+    /// JSON objects to the writer, one per line.
+    Json(Box<dyn Write + Send>),
+    /// logfmt text to the writer, one record per line.
+    Logfmt(Box<dyn Write + Send>),
+    /// Journal entries to the journald socket, with a field per key of the records.
+    Journald(PathBuf),
+    /// Messages to the syslog socket, with the keys of the records in logfmt.
+    Syslog(PathBuf),
+}
+
+impl LogSink {
+    /// The sink of the given name: "json", the default if empty, or "logfmt" writing to `writer`,
+    /// or "journald" or "syslog" sending to the default socket.
+    pub fn from_name<W>(name: &str, writer: W) -> Result<Self, String>
+    where
+        W: Write + Send + 'static,
+    {
+        match name {
+            "" | LOG_SINK_JSON => Ok(LogSink::Json(Box::new(writer))),
+            LOG_SINK_LOGFMT => Ok(LogSink::Logfmt(Box::new(writer))),
+            LOG_SINK_JOURNALD => Ok(LogSink::Journald(PathBuf::from(JOURNALD_SOCKET))),
+            LOG_SINK_SYSLOG => Ok(LogSink::Syslog(PathBuf::from(SYSLOG_SOCKET))),
+            _ => Err(format!("invalid log sink {}", name)),
+        }
+    }
+
+    // The drain writing the records of the logger `name` to the sink.
+    pub(crate) fn into_drain(self, name: &str) -> io::Result<BoxedDrain> {
+        Ok(match self {
+            LogSink::Json(writer) => {
+                Box::new(slog_json::Json::new(writer).add_default_keys().build())
+            }
+            LogSink::Logfmt(writer) => Box::new(LogfmtDrain {
+                writer: RefCell::new(writer),
+            }),
+            LogSink::Journald(path) => Box::new(JournaldDrain {
+                socket: SocketSink::new(path)?,
+                identifier: name.to_string(),
+            }),
+            LogSink::Syslog(path) => Box::new(SyslogDrain {
+                socket: SocketSink::new(path)?,
+                tag: name.to_string(),
+            }),
+        })
+    }
+}
+
+// Used to get the key/values of a record, then the ones of its logger sorted by key.
+struct KvCollector {
+    kvs: Vec<(String, String)>,
+}
+
+impl slog::Serializer for KvCollector {
+    fn emit_arguments(&mut self, key: Key, value: &fmt::Arguments) -> slog::Result {
+        self.kvs.push((format!("{}", key), format!("{}", value)));
+        Ok(())
+    }
+}
+
+fn collect_kvs(record: &Record, values: &OwnedKVList) -> io::Result<Vec<(String, String)>> {
+    let mut collector = KvCollector { kvs: vec![] };
+    record.kv().serialize(record, &mut collector)?;
+    let record_kvs = collector.kvs.len();
+    values.serialize(record, &mut collector)?;
+    collector.kvs[record_kvs..].sort();
+
+    Ok(collector.kvs)
+}
+
+fn level_name(level: slog::Level) -> &'static str {
+    crate::slog_level_to_level_name(level).unwrap_or_default()
+}
+
+// Append `key=value` to the logfmt line, with the value quoted if needed.
+fn push_logfmt(line: &mut String, key: &str, value: &str) {
+    if !line.is_empty() {
+        line.push(' ');
+    }
+    line.push_str(key);
+    line.push('=');
+
+    let quoted = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c <= ' ' || c == '=' || c == '"' || c == '\\');
+    if !quoted {
+        line.push_str(value);
+        return;
+    }
+
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+// Writes a logfmt line per record, e.g.
+// ts=2023-01-01T00:00:00.000000Z level=info msg="hello world" name=kata-runtime pid=42
+struct LogfmtDrain {
+    writer: RefCell<Box<dyn Write + Send>>,
+}
+
+impl Drain for LogfmtDrain {
+    type Ok = ();
+    type Err = io::Error;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> io::Result<()> {
+        let mut line = String::new();
+        push_logfmt(
+            &mut line,
+            "ts",
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+        );
+        push_logfmt(&mut line, "level", level_name(record.level()));
+        push_logfmt(&mut line, "msg", &format!("{}", record.msg()));
+        for (key, value) in collect_kvs(record, values)? {
+            push_logfmt(&mut line, &key, &value);
+        }
+        line.push('\n');
+
+        let mut writer = self.writer.borrow_mut();
+        writer.write_all(line.as_bytes())?;
+        writer.flush()
+    }
+}
+
+// The severity of the syslog protocol, also used as the priority of journald.
+fn syslog_severity(level: slog::Level) -> u8 {
+    match level {
+        slog::Level::Critical => 2,
+        slog::Level::Error => 3,
+        slog::Level::Warning => 4,
+        slog::Level::Info => 6,
+        slog::Level::Debug | slog::Level::Trace => 7,
+    }
+}
+
+// A datagram socket to send the records to a local daemon.
+struct SocketSink {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl SocketSink {
+    fn new(path: PathBuf) -> io::Result<Self> {
+        // fail early rather than losing all the records when the daemon is not there
+        if !path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("log socket {} not found", path.display()),
+            ));
+        }
+
+        Ok(SocketSink {
+            socket: UnixDatagram::unbound()?,
+            path,
+        })
+    }
+
+    fn send(&self, data: &[u8]) {
+        // The record is dropped if the daemon is restarting or overloaded, as syslog(3) does,
+        // rather than failing the logger.
+        let _ = self.socket.send_to(data, &self.path);
+    }
+
+    #[cfg(test)]
+    fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+// Converts a key to a journal field name, which has only uppercase letters, digits and
+// underscores, and starts with a letter.
+fn journald_field_name(key: &str) -> String {
+    let mut name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .skip_while(|c| *c == '_')
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_uppercase()) {
+        name.insert_str(0, "KEY_");
+    }
+    name.truncate(JOURNALD_FIELD_NAME_MAX_LEN);
+
+    name
+}
+
+// Append a field to the journal entry, in the binary format if the value has several lines.
+fn push_journald_field(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+// Sends a journal entry per record with the native protocol of journald, see
+// https://systemd.io/JOURNAL_NATIVE_PROTOCOL/
+struct JournaldDrain {
+    socket: SocketSink,
+    identifier: String,
+}
+
+impl Drain for JournaldDrain {
+    type Ok = ();
+    type Err = io::Error;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> io::Result<()> {
+        let mut entry = Vec::new();
+        push_journald_field(&mut entry, "MESSAGE", &format!("{}", record.msg()));
+        push_journald_field(
+            &mut entry,
+            "PRIORITY",
+            &syslog_severity(record.level()).to_string(),
+        );
+        push_journald_field(&mut entry, "SYSLOG_IDENTIFIER", &self.identifier);
+        for (key, value) in collect_kvs(record, values)? {
+            push_journald_field(&mut entry, &journald_field_name(&key), &value);
+        }
+
+        self.socket.send(&entry);
+        Ok(())
+    }
+}
+
+// Sends a message per record to the local syslog daemon in the BSD syslog format, e.g.
+// <30>Jan  1 00:00:00 kata-runtime[42]: hello world name=kata-runtime pid=42
+struct SyslogDrain {
+    socket: SocketSink,
+    tag: String,
+}
+
+impl Drain for SyslogDrain {
+    type Ok = ();
+    type Err = io::Error;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> io::Result<()> {
+        let mut kvs = String::new();
+        for (key, value) in collect_kvs(record, values)? {
+            push_logfmt(&mut kvs, &key, &value);
+        }
+        let message = format!(
+            "<{}>{} {}[{}]: {} {}",
+            SYSLOG_FACILITY_DAEMON * 8 + syslog_severity(record.level()),
+            Local::now().format("%b %e %H:%M:%S"),
+            self.tag,
+            process::id(),
+            record.msg(),
+            kvs
+        );
+
+        self.socket.send(message.trim_end().as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{info, o, Logger};
+
+    #[test]
+    fn test_push_logfmt() {
+        let mut line = String::new();
+        push_logfmt(&mut line, "a", "plain");
+        push_logfmt(&mut line, "b", "");
+        push_logfmt(&mut line, "c", "with space");
+        push_logfmt(&mut line, "d", "x=\"y\"\n\\");
+        assert_eq!(line, r#"a=plain b="" c="with space" d="x=\"y\"\n\\""#);
+    }
+
+    #[test]
+    fn test_journald_field_name() {
+        assert_eq!(journald_field_name("subsystem"), "SUBSYSTEM");
+        assert_eq!(journald_field_name("sandbox-id"), "SANDBOX_ID");
+        assert_eq!(journald_field_name("_hidden"), "HIDDEN");
+        assert_eq!(journald_field_name("9lives"), "KEY_9LIVES");
+        assert_eq!(journald_field_name("--"), "KEY_");
+        assert_eq!(journald_field_name(&"k".repeat(100)).len(), 64);
+    }
+
+    #[test]
+    fn test_push_journald_field() {
+        let mut entry = Vec::new();
+        push_journald_field(&mut entry, "MESSAGE", "hello");
+        push_journald_field(&mut entry, "STACK", "a\nb");
+
+        let mut expected = b"MESSAGE=hello\nSTACK\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(entry, expected);
+    }
+
+    #[test]
+    fn test_socket_sinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.sock");
+        assert!(SocketSink::new(path.clone()).is_err());
+        let daemon = UnixDatagram::bind(&path).unwrap();
+
+        let drain = JournaldDrain {
+            socket: SocketSink::new(path.clone()).unwrap(),
+            identifier: "kata".to_string(),
+        };
+        assert_eq!(drain.socket.path(), path.as_path());
+        let logger = Logger::root(drain.fuse(), o!("sandbox-id" => "sb1"));
+        info!(logger, "hello"; "subsystem" => "test");
+
+        let mut buf = vec![0; 4096];
+        let n = daemon.recv(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&buf[..n]),
+            "MESSAGE=hello\nPRIORITY=6\nSYSLOG_IDENTIFIER=kata\nSUBSYSTEM=test\nSANDBOX_ID=sb1\n"
+        );
+
+        let drain = SyslogDrain {
+            socket: SocketSink::new(path).unwrap(),
+            tag: "kata".to_string(),
+        };
+        let logger = Logger::root(drain.fuse(), o!("sandbox-id" => "sb1"));
+        info!(logger, "hello world");
+
+        let n = daemon.recv(&mut buf).unwrap();
+        let message = String::from_utf8_lossy(&buf[..n]).to_string();
+        assert!(message.starts_with("<30>"), "{}", message);
+        assert!(
+            message.ends_with(&format!(
+                " kata[{}]: hello world sandbox-id=sb1",
+                process::id()
+            )),
+            "{}",
+            message
+        );
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Event log of a sandbox.
+//!
+//! The shim records what it does to its sandbox, e.g. the devices hot-plugged, the resources
+//! resized, the agent RPCs issued and the errors, in an append-only log of JSON lines. The log is
+//! rotated when it grows too large, the rotated files are named `events.jsonl.1`,
+//! `events.jsonl.2`, etc. from the most recent one.
+//!
+//! The log is kept in the sandbox directory and removed with it, unless the `event_log_dir` of
+//! the runtime configuration is set, the log is then kept in a sub-directory of it named after
+//! the sandbox, and outlives the sandbox.
+//!
+//! Events are recorded once the log of the process is initialized by [`init`], which is done
+//! by the shim serving the sandbox, and are dropped otherwise.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use logging::FileRotator;
+use serde::{Deserialize, Serialize};
+
+use crate::KATA_PATH;
+
+/// Name of the event log file in the sandbox directory.
+pub const EVENT_LOG_NAME: &str = "events.jsonl";
+
+/// Size of the event log file to rotate it.
+const EVENT_LOG_ROTATE_SIZE: u64 = 10 * 1024 * 1024;
+/// Number of rotated event log files to keep.
+const EVENT_LOG_ROTATE_COUNT: usize = 5;
+
+struct EventLog {
+    sandbox_id: String,
+    writer: FileRotator,
+}
+
+static EVENT_LOG: Mutex<Option<EventLog>> = Mutex::new(None);
+
+/// An event of a sandbox, as stored in a line of the event log.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SandboxEvent {
+    /// Time of the event in RFC 3339 format.
+    pub time: String,
+    pub sandbox_id: String,
+    /// Component which did the action, e.g. "hypervisor".
+    pub subsystem: String,
+    /// What was done, e.g. "add_device".
+    pub action: String,
+    /// Parameters of the action, e.g. the ID of the device.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<String, String>,
+    /// Error of the action if it failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SandboxEvent {
+    pub fn new(subsystem: &str, action: &str) -> Self {
+        SandboxEvent {
+            subsystem: subsystem.to_string(),
+            action: action.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn detail<V: ToString>(mut self, key: &str, value: V) -> Self {
+        self.details.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn details<K: ToString, V: ToString>(mut self, details: Vec<(K, V)>) -> Self {
+        self.details.extend(
+            details
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+        self
+    }
+
+    /// Set the error of the event if the action failed.
+    pub fn result<T, E: Display>(mut self, result: &std::result::Result<T, E>) -> Self {
+        if let Err(e) = result {
+            // the alternate format of anyhow errors includes their context
+            self.error = Some(format!("{:#}", e));
+        }
+        self
+    }
+
+    /// Append the event to the event log of the sandbox.
+    ///
+    /// Errors are ignored, a sandbox operation never fails because its event can't be recorded.
+    pub fn record(mut self) {
+        let mut event_log = EVENT_LOG.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(event_log) = event_log.as_mut() {
+            self.time = chrono::Utc::now().to_rfc3339();
+            self.sandbox_id = event_log.sandbox_id.clone();
+            if let Ok(mut line) = serde_json::to_string(&self) {
+                line.push('\n');
+                let _ = event_log.writer.write_all(line.as_bytes());
+                let _ = event_log.writer.flush();
+            }
+        }
+    }
+}
+
+/// Path of the event log in its directory.
+pub fn event_log_path(dir: &Path) -> PathBuf {
+    dir.join(EVENT_LOG_NAME)
+}
+
+/// Directory of the event log of a sandbox, `event_log_dir` is the directory configured for
+/// the event logs, the sandbox directory is used if it's empty.
+pub fn sandbox_event_log_dir(sid: &str, event_log_dir: &str) -> PathBuf {
+    if event_log_dir.is_empty() {
+        Path::new(KATA_PATH).join(sid)
+    } else {
+        Path::new(event_log_dir).join(sid)
+    }
+}
+
+/// Initialize the event log of the process for the sandbox, in the directory configured for
+/// the event logs or else in the sandbox directory.
+pub fn init(sid: &str, event_log_dir: &str) -> Result<()> {
+    init_in_dir(sid, &sandbox_event_log_dir(sid, event_log_dir))
+}
+
+fn init_in_dir(sid: &str, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("create dir {}", dir.display()))?;
+    let path = event_log_path(dir);
+    let mut writer = FileRotator::new(&path).with_context(|| format!("open {}", path.display()))?;
+    writer
+        .rotate_threshold(EVENT_LOG_ROTATE_SIZE)
+        .rotate_count(EVENT_LOG_ROTATE_COUNT);
+
+    let mut event_log = EVENT_LOG.lock().unwrap_or_else(|e| e.into_inner());
+    *event_log = Some(EventLog {
+        sandbox_id: sid.to_string(),
+        writer,
+    });
+
+    Ok(())
+}
+
+/// Read the events of a sandbox from the oldest to the most recent one, including those of the
+/// rotated files. Lines which are not valid events, e.g. a line partially written when the shim
+/// was killed, are skipped.
+pub fn read_events(dir: &Path) -> Result<Vec<SandboxEvent>> {
+    let path = event_log_path(dir);
+    let mut files = vec![path.clone()];
+    for i in 1.. {
+        let mut rotated = path.clone().into_os_string();
+        rotated.push(format!(".{}", i));
+        let rotated = PathBuf::from(rotated);
+        if !rotated.exists() {
+            break;
+        }
+        files.push(rotated);
+    }
+
+    let mut events = vec![];
+    for file in files.iter().rev() {
+        let f = match fs::File::open(file) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("open {}", file.display())),
+        };
+        for line in BufReader::new(f).lines() {
+            let line = line.with_context(|| format!("read {}", file.display()))?;
+            if let Ok(event) = serde_json::from_str(&line) {
+                events.push(event);
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandbox_event_log_dir() {
+        assert_eq!(
+            sandbox_event_log_dir("sb1", ""),
+            Path::new(KATA_PATH).join("sb1")
+        );
+        assert_eq!(
+            sandbox_event_log_dir("sb1", "/var/log/kata"),
+            Path::new("/var/log/kata/sb1")
+        );
+    }
+
+    #[test]
+    fn test_event_log() {
+        // events are dropped before the log is initialized
+        SandboxEvent::new("runtime", "dropped").record();
+
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox_dir = dir.path().join("sb1");
+        assert!(read_events(&sandbox_dir).unwrap().is_empty());
+
+        init_in_dir("sb1", &sandbox_dir).unwrap();
+        SandboxEvent::new("agent", "exec_process")
+            .detail("container_id", "c1")
+            .details(vec![("args", "[\"sh\"]")])
+            .result(&Ok::<(), anyhow::Error>(()))
+            .record();
+        let err: Result<()> = Err(anyhow::anyhow!("no space")).context("add device");
+        SandboxEvent::new("hypervisor", "add_device")
+            .result(&err)
+            .record();
+
+        // a rotated file and a partially written line
+        fs::write(
+            sandbox_dir.join(format!("{}.1", EVENT_LOG_NAME)),
+            "{\"time\":\"t0\",\"sandbox_id\":\"sb1\",\"subsystem\":\"runtime\",\"action\":\"start_sandbox\"}\n{\"time\":",
+        )
+        .unwrap();
+
+        let events = read_events(&sandbox_dir).unwrap();
+        let actions: Vec<_> = events.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, vec!["start_sandbox", "exec_process", "add_device"]);
+        assert_eq!(events[1].sandbox_id, "sb1");
+        assert_eq!(events[1].details.get("container_id").unwrap(), "c1");
+        assert_eq!(events[1].details.get("args").unwrap(), "[\"sh\"]");
+        assert!(events[1].error.is_none());
+        assert_eq!(events[2].error.as_deref(), Some("add device: no space"));
+
+        *EVENT_LOG.lock().unwrap() = None;
+    }
+}
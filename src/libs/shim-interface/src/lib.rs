@@ -14,11 +14,16 @@
 //! You may construct clients by construct a MgmtClient and let is make specific
 //! HTTP request to the server. The server inside shim will multiplex the request
 //! to its corresponding handler and run certain methods.
+//!
+//! Event log:
+//! The shim records the events of its sandbox in a log under the sandbox directory, see the
+//! event_log module.
 
 use std::path::Path;
 
 use anyhow::{anyhow, Result};
 
+pub mod event_log;
 pub mod shim_mgmt;
 
 pub const KATA_PATH: &str = "/run/kata";
@@ -20,5 +20,13 @@ pub const IP_TABLE_URL: &str = "/iptables";
 pub const IP6_TABLE_URL: &str = "/ip6tables";
 /// URL for querying metrics inside shim
 pub const METRICS_URL: &str = "/metrics";
+/// URL for querying the runtime information of the sandbox
+pub const SANDBOX_INFO_URL: &str = "/sandbox-info";
+/// URL for streaming the agent log forwarded by the shim
+pub const AGENT_LOG_URL: &str = "/agent-log";
+/// URL for querying and changing the log levels of the shim
+pub const LOG_LEVEL_URL: &str = "/log-level";
+/// URL for changing the log levels of the agent
+pub const AGENT_LOG_LEVEL_URL: &str = "/agent-log-level";
 
 pub const ERR_NO_SHIM_SERVER: &str = "Failed to create shim management server";
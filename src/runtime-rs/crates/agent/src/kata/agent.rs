@@ -6,6 +6,8 @@
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use shim_interface::event_log::SandboxEvent;
+use tokio::sync::broadcast;
 use ttrpc::context as ttrpc_ctx;
 
 use kata_types::config::Agent as AgentConfig;
@@ -15,6 +17,11 @@ use crate::{kata::KataAgent, Agent, AgentManager, HealthService};
 /// millisecond to nanosecond
 const MILLISECOND_TO_NANOSECOND: i64 = 1_000_000;
 
+/// subsystem of the agent RPCs in the sandbox event log
+const EVENT_SUBSYSTEM: &str = "agent";
+
+type EventDetails = Vec<(&'static str, String)>;
+
 /// new ttrpc context with timeout
 fn new_ttrpc_ctx(timeout: i64) -> ttrpc_ctx::Context {
     ttrpc_ctx::with_timeout(timeout)
@@ -47,6 +54,10 @@ impl AgentManager for KataAgent {
     async fn agent_config(&self) -> AgentConfig {
         self.agent_config().await
     }
+
+    async fn subscribe_log(&self) -> broadcast::Receiver<String> {
+        self.subscribe_log().await
+    }
 }
 
 // implement for health service
@@ -69,51 +80,178 @@ impl_health_service!(
     version | crate::CheckRequest | crate::VersionCheckResponse
 );
 
+// The RPCs changing the sandbox are recorded in the sandbox event log with the details of their
+// requests, the others, e.g. those polling the processes or their IO, are not.
 macro_rules! impl_agent {
-    ($($name: tt | $req: ty | $resp: ty | $new_timeout: expr),*) => {
+    ($($name: tt | $req: ty | $resp: ty | $new_timeout: expr | $event_details: expr),*) => {
         #[async_trait]
         impl Agent for KataAgent {
             $(async fn $name(&self, req: $req) -> Result<$resp> {
-                let r = req.into();
-                let (mut client, mut timeout, _) = self.get_agent_client().await.context("get client")?;
+                let event_details: Option<fn(&$req) -> EventDetails> = $event_details;
+                let event = event_details
+                    .map(|details| SandboxEvent::new(EVENT_SUBSYSTEM, stringify!($name)).details(details(&req)));
 
-                // update new timeout
-                if let Some(v) = $new_timeout {
-                    timeout = v;
+                let result = async {
+                    let r = req.into();
+                    let (mut client, mut timeout, _) = self.get_agent_client().await.context("get client")?;
+
+                    // update new timeout
+                    if let Some(v) = $new_timeout {
+                        timeout = v;
+                    }
+
+                    let resp = client.$name(new_ttrpc_ctx(timeout * MILLISECOND_TO_NANOSECOND), &r).await?;
+                    Ok::<$resp, anyhow::Error>(resp.into())
                 }
+                .await;
 
-                let resp = client.$name(new_ttrpc_ctx(timeout * MILLISECOND_TO_NANOSECOND), &r).await?;
-                Ok(resp.into())
+                if let Some(event) = event {
+                    event.result(&result).record();
+                }
+                result
             })*
         }
     };
 }
 
+fn no_details<T>(_req: &T) -> EventDetails {
+    vec![]
+}
+
+fn container_details(req: &crate::ContainerID) -> EventDetails {
+    vec![("container_id", req.container_id.clone())]
+}
+
+fn process_details(process_id: &crate::ContainerProcessID) -> EventDetails {
+    vec![
+        ("container_id", process_id.container_id.container_id.clone()),
+        ("exec_id", process_id.exec_id.clone()),
+    ]
+}
+
+fn create_container_details(req: &crate::CreateContainerRequest) -> EventDetails {
+    process_details(&req.process_id)
+}
+
+fn remove_container_details(req: &crate::RemoveContainerRequest) -> EventDetails {
+    vec![("container_id", req.container_id.clone())]
+}
+
+fn exec_process_details(req: &crate::ExecProcessRequest) -> EventDetails {
+    let mut details = process_details(&req.process_id);
+    if let Some(process) = &req.process {
+        details.push(("args", format!("{:?}", process.args)));
+        details.push(("cwd", process.cwd.clone()));
+        details.push(("terminal", process.terminal.to_string()));
+        details.push(("uid", process.user.uid.to_string()));
+        details.push(("gid", process.user.gid.to_string()));
+    }
+    details
+}
+
+fn signal_process_details(req: &crate::SignalProcessRequest) -> EventDetails {
+    let mut details = process_details(&req.process_id);
+    details.push(("signal", req.signal.to_string()));
+    details
+}
+
+fn update_container_details(req: &crate::UpdateContainerRequest) -> EventDetails {
+    vec![("container_id", req.container_id.clone())]
+}
+
+fn update_interface_details(req: &crate::UpdateInterfaceRequest) -> EventDetails {
+    match &req.interface {
+        Some(interface) => vec![("name", interface.name.clone())],
+        None => vec![],
+    }
+}
+
+fn create_sandbox_details(req: &crate::CreateSandboxRequest) -> EventDetails {
+    vec![("hostname", req.hostname.clone())]
+}
+
+fn copy_file_details(req: &crate::CopyFileRequest) -> EventDetails {
+    vec![
+        ("path", req.path.clone()),
+        ("file_size", req.file_size.to_string()),
+        ("file_mode", format!("{:o}", req.file_mode)),
+        ("uid", req.uid.to_string()),
+        ("gid", req.gid.to_string()),
+        ("offset", req.offset.to_string()),
+    ]
+}
+
+fn set_ip_tables_details(req: &crate::SetIPTablesRequest) -> EventDetails {
+    vec![("is_ipv6", req.is_ipv6.to_string())]
+}
+
+fn add_swap_details(req: &crate::AddSwapRequest) -> EventDetails {
+    vec![("vm_path", req.vm_path.clone())]
+}
+
+fn set_log_level_details(req: &crate::SetLogLevelRequest) -> EventDetails {
+    vec![
+        ("subsystem", req.subsystem.clone()),
+        ("level", req.level.clone()),
+    ]
+}
+
 impl_agent!(
-    create_container | crate::CreateContainerRequest | crate::Empty | None,
-    start_container | crate::ContainerID | crate::Empty | None,
-    remove_container | crate::RemoveContainerRequest | crate::Empty | None,
-    exec_process | crate::ExecProcessRequest | crate::Empty | None,
-    signal_process | crate::SignalProcessRequest | crate::Empty | None,
-    wait_process | crate::WaitProcessRequest | crate::WaitProcessResponse | Some(0),
-    update_container | crate::UpdateContainerRequest | crate::Empty | None,
-    stats_container | crate::ContainerID | crate::StatsContainerResponse | None,
-    pause_container | crate::ContainerID | crate::Empty | None,
-    resume_container | crate::ContainerID | crate::Empty | None,
-    write_stdin | crate::WriteStreamRequest | crate::WriteStreamResponse | Some(0),
-    read_stdout | crate::ReadStreamRequest | crate::ReadStreamResponse | Some(0),
-    read_stderr | crate::ReadStreamRequest | crate::ReadStreamResponse | Some(0),
-    close_stdin | crate::CloseStdinRequest | crate::Empty | None,
-    tty_win_resize | crate::TtyWinResizeRequest | crate::Empty | None,
-    update_interface | crate::UpdateInterfaceRequest | crate::Interface | None,
-    update_routes | crate::UpdateRoutesRequest | crate::Routes | None,
-    add_arp_neighbors | crate::AddArpNeighborRequest | crate::Empty | None,
-    list_interfaces | crate::Empty | crate::Interfaces | None,
-    list_routes | crate::Empty | crate::Routes | None,
-    create_sandbox | crate::CreateSandboxRequest | crate::Empty | None,
-    destroy_sandbox | crate::Empty | crate::Empty | None,
-    copy_file | crate::CopyFileRequest | crate::Empty | None,
-    get_oom_event | crate::Empty | crate::OomEventResponse | Some(0),
-    get_ip_tables | crate::GetIPTablesRequest | crate::GetIPTablesResponse | None,
-    set_ip_tables | crate::SetIPTablesRequest | crate::SetIPTablesResponse | None
+    create_container
+        | crate::CreateContainerRequest
+        | crate::Empty
+        | None
+        | Some(create_container_details),
+    start_container | crate::ContainerID | crate::Empty | None | Some(container_details),
+    remove_container
+        | crate::RemoveContainerRequest
+        | crate::Empty
+        | None
+        | Some(remove_container_details),
+    exec_process | crate::ExecProcessRequest | crate::Empty | None | Some(exec_process_details),
+    signal_process
+        | crate::SignalProcessRequest
+        | crate::Empty
+        | None
+        | Some(signal_process_details),
+    wait_process | crate::WaitProcessRequest | crate::WaitProcessResponse | Some(0) | None,
+    update_container
+        | crate::UpdateContainerRequest
+        | crate::Empty
+        | None
+        | Some(update_container_details),
+    stats_container | crate::ContainerID | crate::StatsContainerResponse | None | None,
+    pause_container | crate::ContainerID | crate::Empty | None | Some(container_details),
+    resume_container | crate::ContainerID | crate::Empty | None | Some(container_details),
+    write_stdin | crate::WriteStreamRequest | crate::WriteStreamResponse | Some(0) | None,
+    read_stdout | crate::ReadStreamRequest | crate::ReadStreamResponse | Some(0) | None,
+    read_stderr | crate::ReadStreamRequest | crate::ReadStreamResponse | Some(0) | None,
+    close_stdin | crate::CloseStdinRequest | crate::Empty | None | None,
+    tty_win_resize | crate::TtyWinResizeRequest | crate::Empty | None | None,
+    update_interface
+        | crate::UpdateInterfaceRequest
+        | crate::Interface
+        | None
+        | Some(update_interface_details),
+    update_routes | crate::UpdateRoutesRequest | crate::Routes | None | Some(no_details),
+    add_arp_neighbors | crate::AddArpNeighborRequest | crate::Empty | None | Some(no_details),
+    list_interfaces | crate::Empty | crate::Interfaces | None | None,
+    list_routes | crate::Empty | crate::Routes | None | None,
+    create_sandbox
+        | crate::CreateSandboxRequest
+        | crate::Empty
+        | None
+        | Some(create_sandbox_details),
+    destroy_sandbox | crate::Empty | crate::Empty | None | Some(no_details),
+    copy_file | crate::CopyFileRequest | crate::Empty | None | Some(copy_file_details),
+    get_oom_event | crate::Empty | crate::OomEventResponse | Some(0) | None,
+    get_ip_tables | crate::GetIPTablesRequest | crate::GetIPTablesResponse | None | None,
+    set_ip_tables
+        | crate::SetIPTablesRequest
+        | crate::SetIPTablesResponse
+        | None
+        | Some(set_ip_tables_details),
+    add_swap | crate::AddSwapRequest | crate::Empty | None | Some(add_swap_details),
+    get_metrics | crate::Empty | crate::MetricsResponse | None | None,
+    set_log_level | crate::SetLogLevelRequest | crate::Empty | None | Some(set_log_level_details)
 );
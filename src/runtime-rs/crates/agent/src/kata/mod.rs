@@ -15,7 +15,7 @@ use std::{
 use anyhow::{Context, Result};
 use kata_types::config::Agent as AgentConfig;
 use protocols::{agent_ttrpc_async as agent_ttrpc, health_ttrpc_async as health_ttrpc};
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use ttrpc::asynchronous::Client;
 
 use crate::{log_forwarder::LogForwarder, sock};
@@ -128,6 +128,11 @@ impl KataAgent {
         inner.log_forwarder.stop();
     }
 
+    pub(crate) async fn subscribe_log(&self) -> broadcast::Receiver<String> {
+        let inner = self.inner.read().await;
+        inner.log_forwarder.subscribe()
+    }
+
     pub(crate) async fn agent_sock(&self) -> Result<String> {
         let inner = self.inner.read().await;
         Ok(format!(
@@ -6,6 +6,7 @@
 
 use anyhow::Result;
 use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::broadcast;
 
 use crate::sock;
 
@@ -17,13 +18,26 @@ const LOG_LEVEL_WARNING: &str = "WARN";
 const LOG_LEVEL_ERROR: &str = "ERRO";
 const LOG_LEVEL_CRITICAL: &str = "CRIT";
 
+// The lines kept for a slow subscriber, the older ones are dropped once it falls behind.
+const LOG_CHANNEL_CAPACITY: usize = 1024;
+
 pub(crate) struct LogForwarder {
     task_handler: Option<tokio::task::JoinHandle<()>>,
+    // the agent log is also sent to the subscribers, e.g. `kata-ctl sandbox logs`
+    sender: broadcast::Sender<String>,
 }
 
 impl LogForwarder {
     pub(crate) fn new() -> Self {
-        Self { task_handler: None }
+        let (sender, _) = broadcast::channel(LOG_CHANNEL_CAPACITY);
+        Self {
+            task_handler: None,
+            sender,
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.sender.subscribe()
     }
 
     pub(crate) fn stop(&mut self) {
@@ -43,6 +57,7 @@ impl LogForwarder {
     ) -> Result<()> {
         let logger = sl!().clone();
         let address = address.to_string();
+        let sender = self.sender.clone();
         let task_handler = tokio::spawn(async move {
             loop {
                 info!(logger, "try to connect to get agent log");
@@ -74,6 +89,8 @@ impl LogForwarder {
                                     LOG_LEVEL_CRITICAL => crit!(sl!(), "{}", l),
                                     _ => info!(sl!(), "{}", l),
                                 }
+                                // no subscriber is not an error
+                                let _ = sender.send(l);
                             }
                         }
                     }
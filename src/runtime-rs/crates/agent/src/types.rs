@@ -225,6 +225,11 @@ pub struct SetIPTablesResponse {
     pub data: Vec<u8>,
 }
 
+#[derive(PartialEq, Clone, Default, Debug)]
+pub struct MetricsResponse {
+    pub metrics: String,
+}
+
 #[derive(PartialEq, Clone, Default)]
 pub struct WriteStreamRequest {
     pub process_id: ContainerProcessID,
@@ -497,6 +502,12 @@ pub struct MemHotplugByProbeRequest {
     pub mem_hotplug_probe_addr: ::std::vec::Vec<u64>,
 }
 
+#[derive(PartialEq, Clone, Default)]
+pub struct AddSwapRequest {
+    pub pci_path: Vec<u32>,
+    pub vm_path: String,
+}
+
 #[derive(PartialEq, Clone, Default)]
 pub struct SetGuestDateTimeRequest {
     pub sec: i64,
@@ -568,6 +579,15 @@ pub struct ResizeVolumeRequest {
     pub size: u64,
 }
 
+// SetLogLevelRequest is also the common struct for serialization and deserialization with json
+// between shim-client HTTP calls to the shim-mgmt-server
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default, Debug)]
+pub struct SetLogLevelRequest {
+    #[serde(default)]
+    pub subsystem: String,
+    pub level: String,
+}
+
 #[cfg(test)]
 mod test {
     use std::convert::TryFrom;
@@ -4,12 +4,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::HypervisorState;
-use crate::{device::Device, Hypervisor, VcpuThreadIds};
+use crate::{device::Device, Hypervisor, VcpuThreadIds, EVENT_SUBSYSTEM};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use kata_types::capabilities::Capabilities;
 use kata_types::config::hypervisor::Hypervisor as HypervisorConfig;
 use persist::sandbox_persist::Persist;
+use shim_interface::event_log::SandboxEvent;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -55,12 +56,20 @@ impl Hypervisor for CloudHypervisor {
 
     async fn start_vm(&self, timeout: i32) -> Result<()> {
         let mut inner = self.inner.write().await;
-        inner.start_vm(timeout).await
+        let result = inner.start_vm(timeout).await;
+        SandboxEvent::new(EVENT_SUBSYSTEM, "start_vm")
+            .result(&result)
+            .record();
+        result
     }
 
     async fn stop_vm(&self) -> Result<()> {
         let mut inner = self.inner.write().await;
-        inner.stop_vm()
+        let result = inner.stop_vm();
+        SandboxEvent::new(EVENT_SUBSYSTEM, "stop_vm")
+            .result(&result)
+            .record();
+        result
     }
 
     async fn pause_vm(&self) -> Result<()> {
@@ -79,13 +88,21 @@ impl Hypervisor for CloudHypervisor {
     }
 
     async fn add_device(&self, device: Device) -> Result<()> {
+        let event =
+            SandboxEvent::new(EVENT_SUBSYSTEM, "add_device").details(device.event_details());
         let mut inner = self.inner.write().await;
-        inner.add_device(device).await
+        let result = inner.add_device(device).await;
+        event.result(&result).record();
+        result
     }
 
     async fn remove_device(&self, device: Device) -> Result<()> {
+        let event =
+            SandboxEvent::new(EVENT_SUBSYSTEM, "remove_device").details(device.event_details());
         let mut inner = self.inner.write().await;
-        inner.remove_device(device).await
+        let result = inner.remove_device(device).await;
+        event.result(&result).record();
+        result
     }
 
     async fn get_agent_socket(&self) -> Result<String> {
@@ -141,6 +158,10 @@ impl Hypervisor for CloudHypervisor {
         let inner = self.inner.read().await;
         inner.capabilities().await
     }
+
+    async fn get_hypervisor_metrics(&self) -> Result<String> {
+        Ok(String::new())
+    }
 }
 
 #[async_trait]
@@ -22,3 +22,30 @@ pub struct BlockConfig {
     /// device index
     pub index: u64,
 }
+
+/// Get the guest device path of the virtio block device at `index`, following the naming scheme of
+/// the guest kernel: vda, ..., vdz, vdaa, ..., vdzz, vdaaa, ...
+pub fn get_virt_drive_name(index: u64) -> String {
+    let mut name = Vec::new();
+    let mut index = index as i64;
+    while index >= 0 {
+        name.insert(0, b'a' + (index % 26) as u8);
+        index = index / 26 - 1;
+    }
+    format!("/dev/vd{}", String::from_utf8_lossy(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_virt_drive_name;
+
+    #[test]
+    fn test_get_virt_drive_name() {
+        assert_eq!(get_virt_drive_name(0), "/dev/vda");
+        assert_eq!(get_virt_drive_name(25), "/dev/vdz");
+        assert_eq!(get_virt_drive_name(26), "/dev/vdaa");
+        assert_eq!(get_virt_drive_name(27), "/dev/vdab");
+        assert_eq!(get_virt_drive_name(701), "/dev/vdzz");
+        assert_eq!(get_virt_drive_name(702), "/dev/vdaaa");
+    }
+}
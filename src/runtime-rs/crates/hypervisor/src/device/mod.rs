@@ -5,11 +5,13 @@
 //
 
 mod block;
-pub use block::BlockConfig;
+pub use block::{get_virt_drive_name, BlockConfig};
 mod network;
 pub use network::{Address, NetworkConfig};
 mod share_fs_device;
 pub use share_fs_device::ShareFsDeviceConfig;
+mod vhost_user;
+pub use vhost_user::{VhostUserConfig, VhostUserType};
 mod vfio;
 pub use vfio::{bind_device_to_host, bind_device_to_vfio, VfioBusMode, VfioConfig};
 mod share_fs_mount;
@@ -28,6 +30,56 @@ pub enum Device {
     ShareFsMount(ShareFsMountConfig),
     Vsock(VsockConfig),
     HybridVsock(HybridVsockConfig),
+    VhostUser(VhostUserConfig),
+}
+
+impl Device {
+    /// Type and identity of the device, as recorded in the sandbox event log.
+    pub fn event_details(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Device::Block(c) => vec![
+                ("type", "block".to_string()),
+                ("id", c.id.clone()),
+                ("path", c.path_on_host.clone()),
+            ],
+            Device::Network(c) => vec![
+                ("type", "network".to_string()),
+                ("id", c.id.clone()),
+                ("host_dev_name", c.host_dev_name.clone()),
+            ],
+            Device::ShareFsDevice(c) => vec![
+                ("type", "share_fs".to_string()),
+                ("mount_tag", c.mount_tag.clone()),
+                ("host_path", c.host_path.clone()),
+            ],
+            Device::Vfio(c) => vec![
+                ("type", "vfio".to_string()),
+                ("id", c.id.clone()),
+                ("sysfs_path", c.sysfs_path.clone()),
+            ],
+            Device::ShareFsMount(c) => vec![
+                ("type", "share_fs_mount".to_string()),
+                ("source", c.source.clone()),
+                ("mount_point", c.mount_point.clone()),
+                ("op", format!("{:?}", c.op)),
+            ],
+            Device::Vsock(c) => vec![
+                ("type", "vsock".to_string()),
+                ("id", c.id.clone()),
+                ("guest_cid", c.guest_cid.to_string()),
+            ],
+            Device::HybridVsock(c) => vec![
+                ("type", "hybrid_vsock".to_string()),
+                ("id", c.id.clone()),
+                ("uds_path", c.uds_path.clone()),
+            ],
+            Device::VhostUser(c) => vec![
+                ("type", "vhost_user".to_string()),
+                ("id", c.id.clone()),
+                ("socket_path", c.socket_path.clone()),
+            ],
+        }
+    }
 }
 
 impl fmt::Display for Device {
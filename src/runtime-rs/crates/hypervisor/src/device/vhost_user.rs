@@ -0,0 +1,39 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use super::Address;
+
+/// Type of the device served by a vhost-user backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VhostUserType {
+    /// Block device, e.g. served by SPDK.
+    Blk,
+    /// Network device, e.g. served by OVS-DPDK or VPP.
+    Net,
+}
+
+#[derive(Debug)]
+pub struct VhostUserConfig {
+    /// Unique identifier of the device.
+    pub id: String,
+
+    /// Path of the vhost-user socket of the backend.
+    pub socket_path: String,
+
+    /// Type of the device.
+    pub device_type: VhostUserType,
+
+    /// Guest MAC address, only for network devices.
+    pub guest_mac: Option<Address>,
+
+    /// Device index, only for block devices.
+    pub index: u64,
+
+    /// Number of virtqueues, 0 for the hypervisor default.
+    pub num_queues: usize,
+
+    /// Size of each virtqueue, 0 for the hypervisor default.
+    pub queue_size: u16,
+}
@@ -0,0 +1,152 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+use anyhow::{Context, Result};
+use shim_interface::{event_log::SandboxEvent, KATA_PATH};
+use tokio::sync::RwLock;
+use vmm_sys_util::eventfd::EventFd;
+
+use super::{inner::DragonballInner, vmm_instance::VmmRequester};
+use crate::{HypervisorConfig, VmmState, EVENT_SUBSYSTEM};
+
+const HYPERVISOR_CONFIG_FILE: &str = "hypervisor.json";
+const GUEST_KERNEL_LOG_FILE: &str = "guest-kernel.log";
+
+/// Wait for the guest kernel to panic in a background thread, then dump the guest.
+///
+/// The eventfd is also written when the VM is stopped, the VMM state tells whether the guest
+/// really panicked.
+pub(crate) fn watch_guest_panic(inner: Arc<RwLock<DragonballInner>>, panic_fd: EventFd) {
+    let watcher = thread::Builder::new()
+        .name("guest_panic_watcher".to_owned())
+        .spawn(move || {
+            if let Err(e) = panic_fd.read() {
+                error!(sl!(), "failed to wait for guest panic: {:?}", e);
+                return;
+            }
+
+            // Dumping the guest memory takes a while, don't block the other users of the
+            // hypervisor meanwhile.
+            let dump = {
+                let inner = inner.blocking_read();
+                if inner.state != VmmState::VmRunning {
+                    return;
+                }
+                inner.vmm_instance.requester().map(|requester| GuestDump {
+                    id: inner.id.clone(),
+                    config: inner.config.clone(),
+                    requester,
+                })
+            };
+            let dump = match dump {
+                Ok(dump) => dump,
+                Err(e) => {
+                    error!(sl!(), "failed to dump guest after kernel panic: {:?}", e);
+                    return;
+                }
+            };
+
+            let result = dump.dump();
+            if let Err(e) = &result {
+                error!(sl!(), "failed to dump guest after kernel panic: {:?}", e);
+            }
+            SandboxEvent::new(EVENT_SUBSYSTEM, "guest_panic_dump")
+                .detail("path", &dump.config.debug_info.guest_memory_dump_path)
+                .result(&result)
+                .record();
+            // Let the guest follow its panic policy, as if it was never paused.
+            if let Err(e) = dump.requester.resume() {
+                error!(sl!(), "failed to resume vm after guest dump: {:?}", e);
+            }
+        });
+    if let Err(e) = watcher {
+        error!(sl!(), "failed to start guest panic watcher: {:?}", e);
+    }
+}
+
+/// What is needed to dump a guest, copied out of `DragonballInner`.
+struct GuestDump {
+    id: String,
+    config: HypervisorConfig,
+    requester: VmmRequester,
+}
+
+impl GuestDump {
+    /// Save the guest memory, vCPU states, guest kernel log and sandbox configuration into
+    /// `<guest_memory_dump_path>/<sandbox id>/` after the guest kernel panicked.
+    fn dump(&self) -> Result<()> {
+        let debug_info = &self.config.debug_info;
+        let dump_dir = PathBuf::from(&debug_info.guest_memory_dump_path).join(&self.id);
+        fs::create_dir_all(&dump_dir)
+            .with_context(|| format!("failed to create dir {}", dump_dir.display()))?;
+        warn!(
+            sl!(),
+            "guest kernel panicked, dump guest to {}",
+            dump_dir.display()
+        );
+
+        // Save the small files first, they are the most useful if dumping memory fails.
+        let kernel_log = self
+            .requester
+            .get_guest_kernel_log()
+            .context("get guest kernel log")?;
+        let mut kernel_log = kernel_log.join("\n");
+        kernel_log.push('\n');
+        write_file(&dump_dir.join(GUEST_KERNEL_LOG_FILE), kernel_log.as_bytes())?;
+
+        let config = serde_json::to_vec_pretty(&self.config).context("serialize config")?;
+        write_file(&dump_dir.join(HYPERVISOR_CONFIG_FILE), &config)?;
+
+        let state_file = [KATA_PATH, self.id.as_str(), persist::PERSIST_FILE]
+            .iter()
+            .collect::<PathBuf>();
+        if state_file.exists() {
+            let target = dump_dir.join(persist::PERSIST_FILE);
+            fs::copy(&state_file, &target).with_context(|| {
+                format!(
+                    "failed to copy {} to {}",
+                    state_file.display(),
+                    target.display()
+                )
+            })?;
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::time::{SystemTime, UNIX_EPOCH};
+
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let vmcore = dump_dir.join(format!("vmcore-{}.elf", timestamp));
+            self.requester
+                .dump_guest_memory(
+                    &vmcore.to_string_lossy(),
+                    debug_info.guest_memory_dump_paging,
+                )
+                .context("dump guest memory")?;
+            info!(sl!(), "guest memory dumped to {}", vmcore.display());
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        warn!(
+            sl!(),
+            "guest memory dump is not supported on this architecture"
+        );
+
+        Ok(())
+    }
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    fs::write(path, data).with_context(|| format!("failed to write {}", path.display()))
+}
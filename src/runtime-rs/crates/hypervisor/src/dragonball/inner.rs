@@ -4,6 +4,7 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+use super::numa;
 use super::vmm_instance::VmmInstance;
 use crate::{
     device::Device, hypervisor_persist::HypervisorState, kernel_param::KernelParams, VmmState,
@@ -13,12 +14,15 @@ use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use dragonball::{
     api::v1::{BlockDeviceConfigInfo, BootSourceConfig},
-    vm::VmConfigInfo,
+    vm::{VmConfigInfo, WatchdogAction},
 };
 use kata_sys_util::mount;
 use kata_types::{
     capabilities::{Capabilities, CapabilityBits},
-    config::hypervisor::Hypervisor as HypervisorConfig,
+    config::hypervisor::{
+        Hypervisor as HypervisorConfig, WATCHDOG_ACTION_NOTIFY, WATCHDOG_ACTION_RESET,
+        WATCHDOG_ACTION_STOP,
+    },
 };
 use persist::sandbox_persist::Persist;
 use shim_interface::KATA_PATH;
@@ -63,6 +67,9 @@ pub struct DragonballInner {
 
     /// dragonball capabilities
     pub(crate) capabilities: Capabilities,
+
+    /// the guest watchdog expired
+    pub(crate) guest_hung: bool,
 }
 
 impl DragonballInner {
@@ -86,6 +93,7 @@ impl DragonballInner {
             run_dir: "".to_string(),
             cached_block_devices: Default::default(),
             capabilities,
+            guest_hung: false,
         }
     }
 
@@ -193,15 +201,38 @@ impl DragonballInner {
         } else {
             (String::from(SHMEM), String::from(""))
         };
-        let vm_config = VmConfigInfo {
+        let watchdog = match self.config.device_info.watchdog_action.as_str() {
+            "" => None,
+            WATCHDOG_ACTION_RESET => Some(WatchdogAction::Reset),
+            WATCHDOG_ACTION_STOP => Some(WatchdogAction::Stop),
+            WATCHDOG_ACTION_NOTIFY => Some(WatchdogAction::Notify),
+            action => return Err(anyhow!("invalid watchdog action {}", action)),
+        };
+        let mut vm_config = VmConfigInfo {
             serial_path: Some(serial_path),
             mem_size_mib: self.config.memory_info.default_memory as usize,
             vcpu_count: self.config.cpu_info.default_vcpus as u8,
             max_vcpu_count: self.config.cpu_info.default_maxvcpus as u8,
             mem_type,
             mem_file_path,
+            // Keep the panicked guest around until its memory is dumped.
+            pause_on_panic: !self.config.debug_info.guest_memory_dump_path.is_empty(),
+            watchdog,
             ..Default::default()
         };
+        if self.config.cpu_info.enable_numa {
+            match numa::host_numa_regions(
+                &self.config.cpu_info.numa_cpus,
+                vm_config.mem_size_mib as u64,
+                vm_config.max_vcpu_count as u32,
+            ) {
+                Ok((numa_regions, cpu_topology)) => {
+                    vm_config.numa_regions = numa_regions;
+                    vm_config.cpu_topology = cpu_topology;
+                }
+                Err(e) => warn!(sl!(), "unsupported numa topology, numa disabled: {:?}", e),
+            }
+        }
         info!(sl!(), "vm config: {:?}", vm_config);
 
         self.vmm_instance
@@ -374,6 +405,7 @@ impl Persist for DragonballInner {
             pending_devices: vec![],
             cached_block_devices: hypervisor_state.cached_block_devices,
             capabilities: Capabilities::new(),
+            guest_hung: false,
         })
     }
 }
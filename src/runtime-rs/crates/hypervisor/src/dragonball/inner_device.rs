@@ -66,6 +66,12 @@ impl DragonballInner {
             Device::Vsock(_) => {
                 todo!()
             }
+            // dbs-virtio-devices has no vhost-user frontend for block and net devices yet.
+            Device::VhostUser(config) => Err(anyhow!(
+                "vhost-user {:?} device {} is not supported by dragonball",
+                config.device_type,
+                config.id
+            )),
         }
     }
 
@@ -9,10 +9,11 @@ use std::{
     iter::FromIterator,
 };
 
-use anyhow::{Context, Ok, Result};
+use anyhow::{anyhow, Context, Ok, Result};
 use kata_types::capabilities::Capabilities;
 
 use super::inner::DragonballInner;
+use super::metrics;
 use crate::{utils, VcpuThreadIds, VmmState};
 use shim_interface::KATA_PATH;
 const DEFAULT_HYBRID_VSOCK_NAME: &str = "kata.hvsock";
@@ -60,6 +61,7 @@ impl DragonballInner {
     pub(crate) fn stop_vm(&mut self) -> Result<()> {
         info!(sl!(), "Stopping dragonball VM");
         self.vmm_instance.stop().context("stop")?;
+        self.state = VmmState::NotReady;
         Ok(())
     }
 
@@ -133,6 +135,9 @@ impl DragonballInner {
     }
 
     pub(crate) async fn check(&self) -> Result<()> {
+        if self.guest_hung {
+            return Err(anyhow!("guest watchdog expired, the guest is hung"));
+        }
         Ok(())
     }
 
@@ -143,4 +148,15 @@ impl DragonballInner {
     pub(crate) async fn capabilities(&self) -> Result<Capabilities> {
         Ok(self.capabilities.clone())
     }
+
+    pub(crate) async fn get_hypervisor_metrics(&self) -> Result<String> {
+        if self.state != VmmState::VmRunning {
+            return Ok(String::new());
+        }
+        let info = self
+            .vmm_instance
+            .get_vmm_metrics()
+            .context("get vmm metrics")?;
+        Ok(metrics::format_vmm_metrics(&self.id, &info))
+    }
 }
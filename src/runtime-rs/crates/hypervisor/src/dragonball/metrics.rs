@@ -0,0 +1,106 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+// Metrics of the Dragonball VMM and of the devices of the VM in the Prometheus text format,
+// labeled with the sandbox ID.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use dragonball::metric::VmmMetricsInfo;
+
+const NAMESPACE_KATA_HYPERVISOR: &str = "kata_hypervisor";
+
+/// Format the metrics of the VMM in the Prometheus text format.
+///
+/// The VMM counters, e.g. `vcpu_exit_io_in`, are named `kata_hypervisor_vcpu_exit_io_in`, the
+/// device counters, e.g. `read_bytes`, are named `kata_hypervisor_device_read_bytes` and labeled
+/// with the device type and ID.
+pub(crate) fn format_vmm_metrics(sandbox_id: &str, info: &VmmMetricsInfo) -> String {
+    let mut out = String::new();
+    let sandbox_label = format!("sandbox_id=\"{}\"", escape_label_value(sandbox_id));
+
+    for (name, value) in info.vmm.iter() {
+        write_metric(
+            &mut out,
+            name,
+            &format!("Dragonball VMM counter {}", name),
+            &[(sandbox_label.clone(), *value)],
+        );
+    }
+
+    let mut device_samples: BTreeMap<&str, Vec<(String, u64)>> = BTreeMap::new();
+    for device in info.devices.iter() {
+        let labels = format!(
+            "{},device_type=\"{}\",device_id=\"{}\"",
+            sandbox_label,
+            escape_label_value(&device.device_type),
+            escape_label_value(&device.device_id)
+        );
+        for (name, value) in device.counters.iter() {
+            device_samples
+                .entry(name)
+                .or_default()
+                .push((labels.clone(), *value));
+        }
+    }
+    for (name, samples) in device_samples.iter() {
+        write_metric(
+            &mut out,
+            &format!("device_{}", name),
+            &format!("Dragonball device counter {}", name),
+            samples,
+        );
+    }
+
+    out
+}
+
+fn write_metric(out: &mut String, name: &str, help: &str, samples: &[(String, u64)]) {
+    let name = format!("{}_{}", NAMESPACE_KATA_HYPERVISOR, name);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dragonball::metric::DeviceMetricsInfo;
+
+    #[test]
+    fn test_format_vmm_metrics() {
+        let mut info = VmmMetricsInfo::default();
+        info.vmm.insert("vcpu_exit_io_in".to_owned(), 3);
+        for (id, read_bytes) in [("rootfs", 4096), ("data", 512)] {
+            info.devices.push(DeviceMetricsInfo {
+                device_type: "virtio-blk".to_owned(),
+                device_id: id.to_owned(),
+                counters: BTreeMap::from([("read_bytes".to_owned(), read_bytes)]),
+            });
+        }
+
+        let out = format_vmm_metrics("sb\"1", &info);
+        assert_eq!(
+            out,
+            "# HELP kata_hypervisor_vcpu_exit_io_in Dragonball VMM counter vcpu_exit_io_in\n\
+             # TYPE kata_hypervisor_vcpu_exit_io_in counter\n\
+             kata_hypervisor_vcpu_exit_io_in{sandbox_id=\"sb\\\"1\"} 3\n\
+             # HELP kata_hypervisor_device_read_bytes Dragonball device counter read_bytes\n\
+             # TYPE kata_hypervisor_device_read_bytes counter\n\
+             kata_hypervisor_device_read_bytes{sandbox_id=\"sb\\\"1\",device_type=\"virtio-blk\",device_id=\"rootfs\"} 4096\n\
+             kata_hypervisor_device_read_bytes{sandbox_id=\"sb\\\"1\",device_type=\"virtio-blk\",device_id=\"data\"} 512\n"
+        );
+    }
+}
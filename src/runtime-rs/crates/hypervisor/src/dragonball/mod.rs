@@ -4,9 +4,13 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+mod guest_dump;
 mod inner;
 mod inner_device;
 mod inner_hypervisor;
+mod metrics;
+mod numa;
+mod watchdog;
 use super::HypervisorState;
 use inner::DragonballInner;
 use persist::sandbox_persist::Persist;
@@ -18,9 +22,10 @@ use anyhow::{Context, Result};
 use async_trait::async_trait;
 use kata_types::capabilities::Capabilities;
 use kata_types::config::hypervisor::Hypervisor as HypervisorConfig;
+use shim_interface::event_log::SandboxEvent;
 use tokio::sync::RwLock;
 
-use crate::{device::Device, Hypervisor, VcpuThreadIds};
+use crate::{device::Device, Hypervisor, VcpuThreadIds, EVENT_SUBSYSTEM};
 
 pub struct Dragonball {
     inner: Arc<RwLock<DragonballInner>>,
@@ -54,12 +59,38 @@ impl Hypervisor for Dragonball {
 
     async fn start_vm(&self, timeout: i32) -> Result<()> {
         let mut inner = self.inner.write().await;
-        inner.start_vm(timeout).await
+        let result = inner.start_vm(timeout).await;
+        SandboxEvent::new(EVENT_SUBSYSTEM, "start_vm")
+            .result(&result)
+            .record();
+        result?;
+
+        if !inner.config.debug_info.guest_memory_dump_path.is_empty() {
+            let panic_fd = inner
+                .vmm_instance
+                .get_guest_panic_eventfd()
+                .context("get guest panic eventfd")?;
+            guest_dump::watch_guest_panic(self.inner.clone(), panic_fd);
+        }
+
+        if !inner.config.device_info.watchdog_action.is_empty() {
+            let watchdog_fd = inner
+                .vmm_instance
+                .get_watchdog_eventfd()
+                .context("get watchdog eventfd")?;
+            watchdog::watch_guest_watchdog(self.inner.clone(), watchdog_fd);
+        }
+
+        Ok(())
     }
 
     async fn stop_vm(&self) -> Result<()> {
         let mut inner = self.inner.write().await;
-        inner.stop_vm()
+        let result = inner.stop_vm();
+        SandboxEvent::new(EVENT_SUBSYSTEM, "stop_vm")
+            .result(&result)
+            .record();
+        result
     }
 
     async fn pause_vm(&self) -> Result<()> {
@@ -78,13 +109,21 @@ impl Hypervisor for Dragonball {
     }
 
     async fn add_device(&self, device: Device) -> Result<()> {
+        let event =
+            SandboxEvent::new(EVENT_SUBSYSTEM, "add_device").details(device.event_details());
         let mut inner = self.inner.write().await;
-        inner.add_device(device).await
+        let result = inner.add_device(device).await;
+        event.result(&result).record();
+        result
     }
 
     async fn remove_device(&self, device: Device) -> Result<()> {
+        let event =
+            SandboxEvent::new(EVENT_SUBSYSTEM, "remove_device").details(device.event_details());
         let mut inner = self.inner.write().await;
-        inner.remove_device(device).await
+        let result = inner.remove_device(device).await;
+        event.result(&result).record();
+        result
     }
 
     async fn get_agent_socket(&self) -> Result<String> {
@@ -140,6 +179,11 @@ impl Hypervisor for Dragonball {
         let inner = self.inner.read().await;
         inner.capabilities().await
     }
+
+    async fn get_hypervisor_metrics(&self) -> Result<String> {
+        let inner = self.inner.read().await;
+        inner.get_hypervisor_metrics().await
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,119 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::{anyhow, Context, Result};
+use dragonball::vm::{CpuTopology, NumaRegionInfo};
+use kata_sys_util::numa;
+
+/// Build the guest NUMA nodes mirroring the host NUMA nodes of the given host CPUs, with the cpu
+/// topology giving one socket per node. An error is returned if the vCPUs can't be split evenly
+/// among the nodes.
+pub(crate) fn host_numa_regions(
+    cpus: &str,
+    mem_size_mib: u64,
+    max_vcpus: u32,
+) -> Result<(Vec<NumaRegionInfo>, CpuTopology)> {
+    if cpus.is_empty() {
+        return Err(anyhow!("no host cpus for the guest numa nodes"));
+    }
+
+    let node_map = numa::get_node_map(cpus).context("get numa nodes of cpus")?;
+    let mut nodes: Vec<u32> = node_map.keys().copied().collect();
+    nodes.sort_unstable();
+
+    numa_regions(&nodes, mem_size_mib, max_vcpus)
+}
+
+fn numa_regions(
+    nodes: &[u32],
+    mem_size_mib: u64,
+    max_vcpus: u32,
+) -> Result<(Vec<NumaRegionInfo>, CpuTopology)> {
+    let count = nodes.len();
+    if count == 0 {
+        return Err(anyhow!("no host numa node"));
+    }
+    if max_vcpus % count as u32 != 0 {
+        return Err(anyhow!(
+            "{} vCPUs can't be split evenly among the host numa nodes {:?}",
+            max_vcpus,
+            nodes
+        ));
+    }
+
+    let vcpus_per_node = max_vcpus / count as u32;
+    // Keep the regions 2MiB aligned for huge pages, the last one takes the remaining memory.
+    let mem_per_node = (mem_size_mib / count as u64) & !1;
+    if mem_per_node == 0 {
+        return Err(anyhow!(
+            "{} MiB of memory can't be split among the host numa nodes {:?}",
+            mem_size_mib,
+            nodes
+        ));
+    }
+
+    let regions = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| NumaRegionInfo {
+            size: if i == count - 1 {
+                mem_size_mib - mem_per_node * (count as u64 - 1)
+            } else {
+                mem_per_node
+            },
+            host_numa_node_id: Some(*node),
+            guest_numa_node_id: Some(i as u32),
+            vcpu_ids: (i as u32 * vcpus_per_node..(i as u32 + 1) * vcpus_per_node).collect(),
+        })
+        .collect();
+
+    // There are at most as many nodes as vCPUs, which fit in a u8.
+    let topology = CpuTopology {
+        threads_per_core: 1,
+        cores_per_die: vcpus_per_node as u8,
+        dies_per_socket: 1,
+        sockets: count as u8,
+    };
+
+    Ok((regions, topology))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numa_regions() {
+        let (regions, topology) = numa_regions(&[0, 1], 2050, 4).unwrap();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].size, 1024);
+        assert_eq!(regions[0].host_numa_node_id, Some(0));
+        assert_eq!(regions[0].vcpu_ids, vec![0, 1]);
+        assert_eq!(regions[1].size, 1026);
+        assert_eq!(regions[1].guest_numa_node_id, Some(1));
+        assert_eq!(regions[1].vcpu_ids, vec![2, 3]);
+        assert_eq!(topology.sockets, 2);
+        assert_eq!(topology.cores_per_die, 2);
+
+        let (regions, topology) = numa_regions(&[1], 2048, 3).unwrap();
+        assert_eq!(regions[0].size, 2048);
+        assert_eq!(regions[0].host_numa_node_id, Some(1));
+        assert_eq!(regions[0].guest_numa_node_id, Some(0));
+        assert_eq!(topology.cores_per_die, 3);
+
+        // More than 2 sockets.
+        let (regions, topology) = numa_regions(&[0, 1, 2, 3], 4096, 8).unwrap();
+        assert_eq!(regions.len(), 4);
+        assert_eq!(regions[3].host_numa_node_id, Some(3));
+        assert_eq!(regions[3].vcpu_ids, vec![6, 7]);
+        assert_eq!(topology.sockets, 4);
+
+        // The vCPUs can't be split evenly.
+        assert!(numa_regions(&[0, 1], 2048, 3).is_err());
+        assert!(numa_regions(&[0, 1, 2], 2048, 2).is_err());
+        assert!(numa_regions(&[], 2048, 2).is_err());
+        assert!(host_numa_regions("", 2048, 2).is_err());
+    }
+}
@@ -19,6 +19,7 @@ use dragonball::{
         InstanceInfo, InstanceState, VirtioNetDeviceConfigInfo, VmmAction, VmmActionError, VmmData,
         VmmRequest, VmmResponse, VmmService, VsockDeviceConfigInfo,
     },
+    metric::VmmMetricsInfo,
     vm::VmConfigInfo,
     Vmm,
 };
@@ -26,6 +27,9 @@ use nix::sched::{setns, CloneFlags};
 use seccompiler::BpfProgram;
 use vmm_sys_util::eventfd::EventFd;
 
+#[cfg(target_arch = "x86_64")]
+use dragonball::vm::GuestMemoryDumpInfo;
+
 use crate::ShareFsOperation;
 
 pub enum Request {
@@ -39,9 +43,12 @@ const KVM_DEVICE: &str = "/dev/kvm";
 pub struct VmmInstance {
     /// VMM instance info directly accessible from runtime
     vmm_shared_info: Arc<RwLock<InstanceInfo>>,
-    to_vmm: Option<Sender<VmmRequest>>,
-    from_vmm: Option<Receiver<VmmResponse>>,
+    requester: Option<VmmRequester>,
     to_vmm_fd: EventFd,
+    /// Notified by the vmm when the guest kernel panics
+    guest_panic_fd: EventFd,
+    /// Notified by the vmm when the guest watchdog expires
+    watchdog_fd: EventFd,
     seccomp: BpfProgram,
     vmm_thread: Option<thread::JoinHandle<Result<i32>>>,
 }
@@ -55,12 +62,17 @@ impl VmmInstance {
 
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK)
             .unwrap_or_else(|_| panic!("Failed to create eventfd for vmm {}", id));
+        let guest_panic_fd = EventFd::new(0)
+            .unwrap_or_else(|_| panic!("Failed to create guest panic eventfd for vmm {}", id));
+        let watchdog_fd = EventFd::new(0)
+            .unwrap_or_else(|_| panic!("Failed to create watchdog eventfd for vmm {}", id));
 
         VmmInstance {
             vmm_shared_info,
-            to_vmm: None,
-            from_vmm: None,
+            requester: None,
             to_vmm_fd,
+            guest_panic_fd,
+            watchdog_fd,
             seccomp: vec![],
             vmm_thread: None,
         }
@@ -97,11 +109,19 @@ impl VmmInstance {
 
         let vmm_service = VmmService::new(from_runtime, to_runtime);
 
-        self.to_vmm = Some(to_vmm);
-        self.from_vmm = Some(from_vmm);
+        self.requester = Some(VmmRequester {
+            to_vmm,
+            from_vmm,
+            to_vmm_fd: Arc::new(
+                self.to_vmm_fd
+                    .try_clone()
+                    .context("Failed to dup eventfd")?,
+            ),
+            request_lock: Arc::new(Mutex::new(())),
+        });
 
         let api_event_fd2 = self.to_vmm_fd.try_clone().expect("Failed to dup eventfd");
-        let vmm = Vmm::new(
+        let mut vmm = Vmm::new(
             self.vmm_shared_info.clone(),
             api_event_fd2,
             self.seccomp.clone(),
@@ -109,6 +129,18 @@ impl VmmInstance {
             Some(kvm.into_raw_fd()),
         )
         .expect("Failed to start vmm");
+        if let Some(vm) = vmm.get_vm_mut() {
+            vm.set_guest_panic_notifier(
+                self.guest_panic_fd
+                    .try_clone()
+                    .context("Failed to dup guest panic eventfd")?,
+            );
+            vm.set_watchdog_notifier(
+                self.watchdog_fd
+                    .try_clone()
+                    .context("Failed to dup watchdog eventfd")?,
+            );
+        }
         let vmm_shared_info = self.get_shared_info();
 
         self.vmm_thread = Some(
@@ -242,11 +274,36 @@ impl VmmInstance {
     }
 
     pub fn pause(&self) -> Result<()> {
-        todo!()
+        self.handle_request(Request::Sync(VmmAction::PauseMicroVm))
+            .context("Failed to pause MicroVM")?;
+        Ok(())
     }
 
     pub fn resume(&self) -> Result<()> {
-        todo!()
+        self.requester()?.resume()
+    }
+
+    /// Get an eventfd which becomes readable when the guest kernel panics.
+    pub fn get_guest_panic_eventfd(&self) -> Result<EventFd> {
+        self.guest_panic_fd
+            .try_clone()
+            .context("Failed to dup guest panic eventfd")
+    }
+
+    /// Get an eventfd which becomes readable when the guest watchdog expires.
+    pub fn get_watchdog_eventfd(&self) -> Result<EventFd> {
+        self.watchdog_fd
+            .try_clone()
+            .context("Failed to dup watchdog eventfd")
+    }
+
+    pub fn get_vmm_metrics(&self) -> Result<VmmMetricsInfo> {
+        if let Ok(VmmData::VmmMetrics(metrics)) =
+            self.handle_request(Request::Sync(VmmAction::GetVmmMetrics))
+        {
+            return Ok(*metrics);
+        }
+        Err(anyhow!("Failed to get vmm metrics"))
     }
 
     pub fn pid(&self) -> u32 {
@@ -260,6 +317,9 @@ impl VmmInstance {
                 e
             })
             .ok();
+        // wake up the guest panic and watchdog watchers, if any, so that they could exit.
+        self.guest_panic_fd.write(1).ok();
+        self.watchdog_fd.write(1).ok();
         // vmm is not running, join thread will be hang.
         if self.is_uninitialized() || self.vmm_thread.is_none() {
             debug!(sl!(), "vmm-master thread is uninitialized or has exited.");
@@ -274,39 +334,19 @@ impl VmmInstance {
         Ok(())
     }
 
-    fn send_request(&self, vmm_action: VmmAction) -> Result<VmmResponse> {
-        if let Some(ref to_vmm) = self.to_vmm {
-            to_vmm
-                .send(Box::new(vmm_action.clone()))
-                .with_context(|| format!("Failed to send  {:?} via channel ", vmm_action))?;
-        } else {
-            return Err(anyhow!("to_vmm is None"));
-        }
-
-        //notify vmm action
-        if let Err(e) = self.to_vmm_fd.write(1) {
-            return Err(anyhow!("failed to notify vmm: {}", e));
-        }
+    /// Get a requester to talk to the vmm without holding the instance.
+    pub fn requester(&self) -> Result<VmmRequester> {
+        self.requester
+            .clone()
+            .ok_or_else(|| anyhow!("vmm server is not running"))
+    }
 
-        if let Some(from_vmm) = self.from_vmm.as_ref() {
-            match from_vmm.recv() {
-                Err(e) => Err(anyhow!("vmm recv err: {}", e)),
-                Ok(vmm_outcome) => Ok(vmm_outcome),
-            }
-        } else {
-            Err(anyhow!("from_vmm is None"))
-        }
+    fn send_request(&self, vmm_action: VmmAction) -> Result<VmmResponse> {
+        self.requester()?.send_request(vmm_action)
     }
 
     fn handle_request(&self, req: Request) -> Result<VmmData> {
-        let Request::Sync(vmm_action) = req;
-        match self.send_request(vmm_action) {
-            Ok(vmm_outcome) => match *vmm_outcome {
-                Ok(vmm_data) => Ok(vmm_data),
-                Err(vmm_action_error) => Err(anyhow!("vmm action error: {:?}", vmm_action_error)),
-            },
-            Err(e) => Err(e),
-        }
+        self.requester()?.handle_request(req)
     }
 
     fn handle_request_with_retry(&self, req: Request) -> Result<VmmData> {
@@ -341,3 +381,71 @@ impl VmmInstance {
         ))
     }
 }
+
+/// Send requests to the vmm thread and wait for their responses.
+///
+/// All the clones share the same channels, a request and its response are handled under
+/// `request_lock` so that a response is never received by another requester.
+#[derive(Clone)]
+pub struct VmmRequester {
+    to_vmm: Sender<VmmRequest>,
+    from_vmm: Receiver<VmmResponse>,
+    to_vmm_fd: Arc<EventFd>,
+    request_lock: Arc<Mutex<()>>,
+}
+
+impl VmmRequester {
+    pub fn resume(&self) -> Result<()> {
+        self.handle_request(Request::Sync(VmmAction::ResumeMicroVm))
+            .context("Failed to resume MicroVM")?;
+        Ok(())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn dump_guest_memory(&self, path: &str, paging: bool) -> Result<()> {
+        let dump_cfg = GuestMemoryDumpInfo {
+            path: path.to_string(),
+            paging,
+        };
+        self.handle_request(Request::Sync(VmmAction::DumpGuestMemory(dump_cfg)))
+            .with_context(|| format!("Failed to dump guest memory to {}", path))?;
+        Ok(())
+    }
+
+    pub fn get_guest_kernel_log(&self) -> Result<Vec<String>> {
+        if let Ok(VmmData::GuestKernelLog(log)) =
+            self.handle_request(Request::Sync(VmmAction::GetGuestKernelLog))
+        {
+            return Ok(log);
+        }
+        Err(anyhow!("Failed to get guest kernel log"))
+    }
+
+    fn send_request(&self, vmm_action: VmmAction) -> Result<VmmResponse> {
+        let _guard = self.request_lock.lock().unwrap();
+        self.to_vmm
+            .send(Box::new(vmm_action.clone()))
+            .with_context(|| format!("Failed to send  {:?} via channel ", vmm_action))?;
+
+        //notify vmm action
+        if let Err(e) = self.to_vmm_fd.write(1) {
+            return Err(anyhow!("failed to notify vmm: {}", e));
+        }
+
+        match self.from_vmm.recv() {
+            Err(e) => Err(anyhow!("vmm recv err: {}", e)),
+            Ok(vmm_outcome) => Ok(vmm_outcome),
+        }
+    }
+
+    fn handle_request(&self, req: Request) -> Result<VmmData> {
+        let Request::Sync(vmm_action) = req;
+        match self.send_request(vmm_action) {
+            Ok(vmm_outcome) => match *vmm_outcome {
+                Ok(vmm_data) => Ok(vmm_data),
+                Err(vmm_action_error) => Err(anyhow!("vmm action error: {:?}", vmm_action_error)),
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{sync::Arc, thread};
+
+use shim_interface::event_log::SandboxEvent;
+use tokio::sync::RwLock;
+use vmm_sys_util::eventfd::EventFd;
+
+use super::inner::DragonballInner;
+use crate::{VmmState, EVENT_SUBSYSTEM};
+
+/// Wait for the guest watchdog to expire in a background thread, then mark the guest as hung so
+/// that the health check of the sandbox fails and the sandbox is cleaned up.
+///
+/// The eventfd is also written when the VM is stopped, the VMM state tells whether the watchdog
+/// really expired.
+pub(crate) fn watch_guest_watchdog(inner: Arc<RwLock<DragonballInner>>, watchdog_fd: EventFd) {
+    let watcher = thread::Builder::new()
+        .name("guest_watchdog_watcher".to_owned())
+        .spawn(move || {
+            if let Err(e) = watchdog_fd.read() {
+                error!(sl!(), "failed to wait for guest watchdog: {:?}", e);
+                return;
+            }
+
+            let mut inner = inner.blocking_write();
+            if inner.state != VmmState::VmRunning {
+                return;
+            }
+            error!(
+                sl!(),
+                "guest watchdog expired, action {}", inner.config.device_info.watchdog_action
+            );
+            SandboxEvent::new(EVENT_SUBSYSTEM, "guest_watchdog_expired")
+                .detail("action", &inner.config.device_info.watchdog_action)
+                .record();
+            inner.guest_hung = true;
+        });
+    if let Err(e) = watcher {
+        error!(sl!(), "failed to start guest watchdog watcher: {:?}", e);
+    }
+}
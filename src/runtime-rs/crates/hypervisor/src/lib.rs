@@ -9,6 +9,9 @@ extern crate slog;
 
 logging::logger_with_subsystem!(sl, "hypervisor");
 
+// subsystem of the hypervisor in the sandbox event log
+const EVENT_SUBSYSTEM: &str = "hypervisor";
+
 pub mod device;
 pub mod hypervisor_persist;
 pub use device::*;
@@ -93,4 +96,6 @@ pub trait Hypervisor: Send + Sync {
     async fn get_jailer_root(&self) -> Result<String>;
     async fn save_state(&self) -> Result<HypervisorState>;
     async fn capabilities(&self) -> Result<Capabilities>;
+    // metrics of the hypervisor in the Prometheus text format, empty if not supported
+    async fn get_hypervisor_metrics(&self) -> Result<String>;
 }
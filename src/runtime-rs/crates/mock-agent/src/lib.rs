@@ -0,0 +1,23 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! A mock kata agent to test runtime-rs without any VM. It serves the agent ttrpc protocol over a
+//! hybrid vsock socket, replies the requests as scripted and records them.
+
+#[macro_use]
+extern crate slog;
+
+logging::logger_with_subsystem!(sl, "mock_agent");
+
+mod mock_hypervisor;
+mod recorder;
+mod script;
+mod server;
+mod service;
+
+pub use mock_hypervisor::MockHypervisor;
+pub use recorder::{RecordedRequest, Recorder};
+pub use script::{Behavior, ErrorReply, Script};
+pub use server::MockAgent;
@@ -0,0 +1,79 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{fs, io, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use kata_types::config::default::{DEFAULT_AGENT_LOG_PORT, DEFAULT_AGENT_VSOCK_PORT};
+use mock_agent::{MockAgent, Script};
+use tokio::signal::unix::{signal, SignalKind};
+
+const WORKER_THREADS: usize = 2;
+
+#[derive(Parser, Debug)]
+#[clap(version, about = "A mock kata agent for testing runtime-rs")]
+struct Cli {
+    /// Path of the hybrid vsock socket to listen on
+    #[clap(long)]
+    socket: PathBuf,
+    /// Script file in JSON with the behaviors of the methods
+    #[clap(long)]
+    script: Option<PathBuf>,
+    /// Write the recorded requests in JSON to this file on exit
+    #[clap(long)]
+    record: Option<PathBuf>,
+    /// Port of the agent ttrpc server
+    #[clap(long, default_value_t = DEFAULT_AGENT_VSOCK_PORT)]
+    server_port: u32,
+    /// Port of the agent log forwarder
+    #[clap(long, default_value_t = DEFAULT_AGENT_LOG_PORT)]
+    log_port: u32,
+    #[clap(long, default_value = "info")]
+    log_level: String,
+}
+
+async fn real_main(cli: Cli) -> Result<()> {
+    let script = match cli.script.as_ref() {
+        Some(path) => Script::load(path)?,
+        None => Script::default(),
+    };
+
+    let mut agent = MockAgent::new(&cli.socket, script).with_ports(cli.server_port, cli.log_port);
+    agent.start().await?;
+    println!("{}", agent.address());
+
+    let mut sigterm = signal(SignalKind::terminate()).context("install SIGTERM handler")?;
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = sigterm.recv() => {},
+    }
+
+    agent.stop().await;
+
+    if let Some(path) = cli.record.as_ref() {
+        let requests = serde_json::to_string_pretty(&agent.recorder().requests())
+            .context("serialize recorded requests")?;
+        fs::write(path, requests).with_context(|| format!("write record {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let log_level = logging::level_name_to_slog_level(&cli.log_level).map_err(|e| anyhow!(e))?;
+    let (logger, _guard) =
+        logging::create_logger("mock-agent", "mock-agent", log_level, io::stderr());
+    let _scope_guard = slog_scope::set_global_logger(logger);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(WORKER_THREADS)
+        .enable_all()
+        .build()
+        .context("prepare tokio runtime")?;
+
+    runtime.block_on(real_main(cli))
+}
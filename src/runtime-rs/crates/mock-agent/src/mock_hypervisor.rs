@@ -0,0 +1,155 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use hypervisor::{
+    device::Device, hypervisor_persist::HypervisorState, Hypervisor, HypervisorConfig,
+    VcpuThreadIds,
+};
+use kata_types::capabilities::{Capabilities, CapabilityBits};
+
+const MOCK_HYPERVISOR_TYPE: &str = "mock";
+
+/// A fake hypervisor without any VM, the agent socket is the one of the mock agent. The calls
+/// and the devices are recorded for checking in tests.
+pub struct MockHypervisor {
+    agent_socket: String,
+    config: HypervisorConfig,
+    calls: Mutex<Vec<String>>,
+    devices: Mutex<Vec<Device>>,
+}
+
+impl MockHypervisor {
+    /// Create the hypervisor with the address of the mock agent, see `MockAgent::address()`.
+    pub fn new(agent_socket: &str, config: HypervisorConfig) -> Self {
+        Self {
+            agent_socket: agent_socket.to_string(),
+            config,
+            calls: Mutex::new(vec![]),
+            devices: Mutex::new(vec![]),
+        }
+    }
+
+    /// The names of the methods called, in order.
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// The devices added and not removed yet.
+    pub fn devices(&self) -> Vec<Device> {
+        self.devices.lock().unwrap().clone()
+    }
+
+    fn record(&self, call: &str) {
+        self.calls.lock().unwrap().push(call.to_string());
+    }
+}
+
+#[async_trait]
+impl Hypervisor for MockHypervisor {
+    async fn prepare_vm(&self, _id: &str, _netns: Option<String>) -> Result<()> {
+        self.record("prepare_vm");
+        Ok(())
+    }
+
+    async fn start_vm(&self, _timeout: i32) -> Result<()> {
+        self.record("start_vm");
+        Ok(())
+    }
+
+    async fn stop_vm(&self) -> Result<()> {
+        self.record("stop_vm");
+        Ok(())
+    }
+
+    async fn pause_vm(&self) -> Result<()> {
+        self.record("pause_vm");
+        Ok(())
+    }
+
+    async fn save_vm(&self) -> Result<()> {
+        self.record("save_vm");
+        Ok(())
+    }
+
+    async fn resume_vm(&self) -> Result<()> {
+        self.record("resume_vm");
+        Ok(())
+    }
+
+    async fn add_device(&self, device: Device) -> Result<()> {
+        self.record("add_device");
+        self.devices.lock().unwrap().push(device);
+        Ok(())
+    }
+
+    async fn remove_device(&self, device: Device) -> Result<()> {
+        self.record("remove_device");
+        let target = format!("{:?}", device);
+        self.devices
+            .lock()
+            .unwrap()
+            .retain(|d| format!("{:?}", d) != target);
+        Ok(())
+    }
+
+    async fn get_agent_socket(&self) -> Result<String> {
+        Ok(self.agent_socket.clone())
+    }
+
+    async fn disconnect(&self) {
+        self.record("disconnect");
+    }
+
+    async fn hypervisor_config(&self) -> HypervisorConfig {
+        self.config.clone()
+    }
+
+    async fn get_thread_ids(&self) -> Result<VcpuThreadIds> {
+        Ok(VcpuThreadIds::default())
+    }
+
+    async fn get_pids(&self) -> Result<Vec<u32>> {
+        Ok(vec![std::process::id()])
+    }
+
+    async fn get_vmm_master_tid(&self) -> Result<u32> {
+        Ok(std::process::id())
+    }
+
+    async fn cleanup(&self) -> Result<()> {
+        self.record("cleanup");
+        Ok(())
+    }
+
+    async fn check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_jailer_root(&self) -> Result<String> {
+        Ok("".to_string())
+    }
+
+    async fn save_state(&self) -> Result<HypervisorState> {
+        Ok(HypervisorState {
+            hypervisor_type: MOCK_HYPERVISOR_TYPE.to_string(),
+            config: self.config.clone(),
+            ..Default::default()
+        })
+    }
+
+    async fn capabilities(&self) -> Result<Capabilities> {
+        let mut caps = Capabilities::new();
+        caps.set(CapabilityBits::BlockDeviceSupport | CapabilityBits::FsSharingSupport);
+        Ok(caps)
+    }
+
+    async fn get_hypervisor_metrics(&self) -> Result<String> {
+        Ok(String::new())
+    }
+}
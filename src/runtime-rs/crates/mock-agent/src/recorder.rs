@@ -0,0 +1,97 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// A request received by the mock agent.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct RecordedRequest {
+    /// The method name in the protocol, e.g. "CreateContainer"
+    pub method: String,
+    /// The request in JSON
+    pub request: serde_json::Value,
+}
+
+/// The record of the requests received by the mock agent, in the order of arrival. It's cheap to
+/// clone and the clones share the same record.
+#[derive(Clone, Debug, Default)]
+pub struct Recorder {
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl Recorder {
+    /// Record the request and return how many requests of the method were received before it.
+    pub(crate) fn record(&self, method: &str, request: serde_json::Value) -> usize {
+        let mut requests = self.requests.lock().unwrap();
+        let nth = requests.iter().filter(|r| r.method == method).count();
+        requests.push(RecordedRequest {
+            method: method.to_string(),
+            request,
+        });
+        nth
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The requests of the method.
+    pub fn requests_of(&self, method: &str) -> Vec<RecordedRequest> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.method == method)
+            .cloned()
+            .collect()
+    }
+
+    /// The methods of the requests, in the order of arrival.
+    pub fn methods(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.method.clone())
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.requests.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recorder() {
+        let recorder = Recorder::default();
+        let shared = recorder.clone();
+        assert_eq!(recorder.record("CreateContainer", serde_json::json!({})), 0);
+        assert_eq!(recorder.record("StartContainer", serde_json::json!({})), 0);
+        assert_eq!(
+            shared.record(
+                "CreateContainer",
+                serde_json::json!({ "container_id": "c2" })
+            ),
+            1
+        );
+
+        assert_eq!(
+            recorder.methods(),
+            vec!["CreateContainer", "StartContainer", "CreateContainer"]
+        );
+        let created = recorder.requests_of("CreateContainer");
+        assert_eq!(created.len(), 2);
+        assert_eq!(created[1].request["container_id"], "c2");
+
+        shared.clear();
+        assert!(recorder.requests().is_empty());
+    }
+}
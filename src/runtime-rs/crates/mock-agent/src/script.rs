@@ -0,0 +1,165 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use protobuf::ProtobufEnum;
+use serde::{Deserialize, Serialize};
+use ttrpc::{error::get_rpc_status, Code};
+
+/// The error replied to the client instead of the response.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ErrorReply {
+    /// The name of the ttrpc status code, e.g. "NOT_FOUND"
+    pub code: String,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl ErrorReply {
+    pub(crate) fn to_ttrpc_error(&self) -> ttrpc::Error {
+        let code = Code::values()
+            .iter()
+            .find(|c| format!("{:?}", c) == self.code)
+            .copied()
+            .unwrap_or(Code::UNKNOWN);
+        get_rpc_status(code, self.message.clone())
+    }
+}
+
+/// How the mock agent handles a request.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Behavior {
+    /// The response in JSON, the default value of the response type is used if not set.
+    pub response: Option<serde_json::Value>,
+    /// Reply the error instead of the response.
+    pub error: Option<ErrorReply>,
+    /// Delay before replying, in milliseconds.
+    pub delay_ms: u64,
+    /// Never reply, so that the request times out on the client side.
+    pub hang: bool,
+}
+
+impl Behavior {
+    pub fn respond(response: serde_json::Value) -> Self {
+        Self {
+            response: Some(response),
+            ..Default::default()
+        }
+    }
+
+    pub fn fail(code: Code, message: &str) -> Self {
+        Self {
+            error: Some(ErrorReply {
+                code: format!("{:?}", code),
+                message: message.to_string(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    pub fn hang() -> Self {
+        Self {
+            hang: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn delay(mut self, delay_ms: u64) -> Self {
+        self.delay_ms = delay_ms;
+        self
+    }
+}
+
+/// The behaviors of the ttrpc methods, keyed by the method names in the protocol, e.g.
+/// "CreateContainer". The behaviors of a method are used in order for the requests received, and
+/// the last one is kept for the following requests.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Script {
+    #[serde(default)]
+    pub methods: HashMap<String, Vec<Behavior>>,
+}
+
+impl Script {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content =
+            fs::read_to_string(path).with_context(|| format!("read script {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("parse script {}", path.display()))
+    }
+
+    /// Set the behaviors of a method, the previous ones are replaced.
+    pub fn set(&mut self, method: &str, behaviors: Vec<Behavior>) -> Result<()> {
+        if behaviors.is_empty() {
+            return Err(anyhow!("no behavior for method {}", method));
+        }
+        self.methods.insert(method.to_string(), behaviors);
+        Ok(())
+    }
+
+    /// Get the behavior for the nth (from 0) request of the method.
+    pub(crate) fn behavior(&self, method: &str, nth: usize) -> Option<&Behavior> {
+        let behaviors = self.methods.get(method)?;
+        behaviors.get(nth).or_else(|| behaviors.last())
+    }
+}
+
+/// The behaviors used when the method is not in the script. Same as the real agent, these
+/// requests block until there is an event, which never happens in the mock agent.
+pub(crate) fn default_behavior(method: &str) -> Behavior {
+    match method {
+        "GetOOMEvent" => Behavior::hang(),
+        "Check" => Behavior::respond(serde_json::json!({ "status": "SERVING" })),
+        _ => Behavior::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        let script: Script = serde_json::from_str(
+            r#"{
+                "methods": {
+                    "WaitProcess": [
+                        { "delay_ms": 100, "response": { "status": 1 } }
+                    ],
+                    "CreateContainer": [
+                        { "error": { "code": "UNAVAILABLE", "message": "busy" } },
+                        {}
+                    ]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let wait = script.behavior("WaitProcess", 0).unwrap();
+        assert_eq!(wait.delay_ms, 100);
+        assert_eq!(wait.response, Some(serde_json::json!({ "status": 1 })));
+        assert_eq!(script.behavior("WaitProcess", 3), Some(wait));
+
+        let create = script.behavior("CreateContainer", 0).unwrap();
+        assert_eq!(create, &Behavior::fail(Code::UNAVAILABLE, "busy"));
+        match create.error.as_ref().unwrap().to_ttrpc_error() {
+            ttrpc::Error::RpcStatus(status) => {
+                assert_eq!(status.code, Code::UNAVAILABLE);
+                assert_eq!(status.message, "busy");
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(
+            script.behavior("CreateContainer", 1),
+            Some(&Behavior::default())
+        );
+        assert!(script.behavior("StartContainer", 0).is_none());
+
+        assert!(default_behavior("GetOOMEvent").hang);
+        assert!(!default_behavior("StartContainer").hang);
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{anyhow, Context, Result};
+use kata_types::config::default::{DEFAULT_AGENT_LOG_PORT, DEFAULT_AGENT_VSOCK_PORT};
+use protocols::{agent_ttrpc_async as agent_ttrpc, health_ttrpc_async as health_ttrpc};
+use tokio::{
+    io::{self, AsyncReadExt, AsyncWriteExt},
+    net::{UnixListener, UnixStream},
+    task::JoinHandle,
+};
+use ttrpc::asynchronous::Server;
+
+use crate::{
+    recorder::Recorder,
+    script::{Behavior, Script},
+    service::MockService,
+};
+
+// The ttrpc server listens on a private socket next to the hybrid vsock socket.
+const TTRPC_SOCKET_SUFFIX: &str = ".ttrpc";
+// Max length of the hybrid vsock handshake line, e.g. "CONNECT 1024\n".
+const MAX_HANDSHAKE_LEN: usize = 64;
+
+/// A mock kata agent serving the agent and health ttrpc services over a hybrid vsock socket, the
+/// same way as the agent behind Dragonball or Firecracker. The requests are replied as scripted
+/// and recorded.
+pub struct MockAgent {
+    socket_path: PathBuf,
+    server_port: u32,
+    log_port: u32,
+    script: Arc<RwLock<Script>>,
+    recorder: Recorder,
+    server: Option<Server>,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl MockAgent {
+    pub fn new<P: AsRef<Path>>(socket_path: P, script: Script) -> Self {
+        Self {
+            socket_path: socket_path.as_ref().to_path_buf(),
+            server_port: DEFAULT_AGENT_VSOCK_PORT,
+            log_port: DEFAULT_AGENT_LOG_PORT,
+            script: Arc::new(RwLock::new(script)),
+            recorder: Recorder::default(),
+            server: None,
+            listener: None,
+        }
+    }
+
+    /// Set the ports of the ttrpc server and the log forwarder, they must be the same as the ones
+    /// in the agent config of the runtime.
+    pub fn with_ports(mut self, server_port: u32, log_port: u32) -> Self {
+        self.server_port = server_port;
+        self.log_port = log_port;
+        self
+    }
+
+    /// The address of the agent returned by `Hypervisor::get_agent_socket`.
+    pub fn address(&self) -> String {
+        format!("hvsock://{}", self.socket_path.display())
+    }
+
+    pub fn recorder(&self) -> Recorder {
+        self.recorder.clone()
+    }
+
+    /// Set the behaviors of a method, it takes effect for the following requests.
+    pub fn set_behaviors(&self, method: &str, behaviors: Vec<Behavior>) -> Result<()> {
+        self.script.write().unwrap().set(method, behaviors)
+    }
+
+    pub async fn start(&mut self) -> Result<()> {
+        if self.server.is_some() {
+            return Err(anyhow!("mock agent is already started"));
+        }
+
+        let ttrpc_path = ttrpc_socket_path(&self.socket_path);
+        for path in [&self.socket_path, &ttrpc_path] {
+            if path.exists() {
+                std::fs::remove_file(path).with_context(|| format!("remove {:?}", path))?;
+            }
+        }
+
+        let service = MockService::new(self.script.clone(), self.recorder.clone());
+        let agent_service =
+            Arc::new(Box::new(service.clone()) as Box<dyn agent_ttrpc::AgentService + Send + Sync>);
+        let health_service =
+            Arc::new(Box::new(service) as Box<dyn health_ttrpc::Health + Send + Sync>);
+        let mut server = Server::new()
+            .bind(&format!("unix://{}", ttrpc_path.display()))
+            .context("bind ttrpc server")?
+            .register_service(agent_ttrpc::create_agent_service(agent_service))
+            .register_service(health_ttrpc::create_health(health_service));
+        server.start().await.context("start ttrpc server")?;
+        self.server = Some(server);
+
+        let listener = UnixListener::bind(&self.socket_path)
+            .with_context(|| format!("bind {:?}", self.socket_path))?;
+        let server_port = self.server_port;
+        let log_port = self.log_port;
+        self.listener = Some(tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        error!(sl!(), "failed to accept connection: {:?}", e);
+                        break;
+                    }
+                };
+                let ttrpc_path = ttrpc_path.clone();
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        handle_connection(stream, &ttrpc_path, server_port, log_port).await
+                    {
+                        warn!(sl!(), "connection closed: {:?}", e);
+                    }
+                });
+            }
+        }));
+
+        info!(sl!(), "mock agent started on {:?}", self.socket_path);
+        Ok(())
+    }
+
+    pub async fn stop(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            listener.abort();
+        }
+        if let Some(mut server) = self.server.take() {
+            if let Err(e) = server.shutdown().await {
+                warn!(sl!(), "failed to shutdown ttrpc server: {:?}", e);
+            }
+        }
+        for path in [
+            self.socket_path.clone(),
+            ttrpc_socket_path(&self.socket_path),
+        ] {
+            let _ = std::fs::remove_file(path);
+        }
+        info!(sl!(), "mock agent stopped");
+    }
+}
+
+fn ttrpc_socket_path(socket_path: &Path) -> PathBuf {
+    let mut path = socket_path.as_os_str().to_owned();
+    path.push(TTRPC_SOCKET_SUFFIX);
+    PathBuf::from(path)
+}
+
+// Read the handshake line byte by byte, so that nothing after it is consumed.
+async fn read_handshake(stream: &mut UnixStream) -> Result<String> {
+    let mut line = Vec::new();
+    loop {
+        let b = stream.read_u8().await.context("read handshake")?;
+        if b == b'\n' {
+            break;
+        }
+        line.push(b);
+        if line.len() > MAX_HANDSHAKE_LEN {
+            return Err(anyhow!("handshake is too long"));
+        }
+    }
+    Ok(String::from_utf8_lossy(&line).trim().to_string())
+}
+
+fn parse_handshake(line: &str) -> Result<u32> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    match fields.as_slice() {
+        [cmd, port] if cmd.eq_ignore_ascii_case("connect") => port
+            .parse::<u32>()
+            .with_context(|| format!("invalid port {:?}", port)),
+        _ => Err(anyhow!("invalid handshake {:?}", line)),
+    }
+}
+
+// Do the hybrid vsock handshake, then forward the connection to the ttrpc server for the server
+// port, or keep it open without any data for the log port.
+async fn handle_connection(
+    mut stream: UnixStream,
+    ttrpc_path: &Path,
+    server_port: u32,
+    log_port: u32,
+) -> Result<()> {
+    let port = parse_handshake(&read_handshake(&mut stream).await?)?;
+    if port != server_port && port != log_port {
+        return Err(anyhow!("unknown port {}", port));
+    }
+    stream
+        .write_all(format!("OK {}\n", port).as_bytes())
+        .await
+        .context("write handshake")?;
+
+    if port == server_port {
+        let mut server = UnixStream::connect(ttrpc_path)
+            .await
+            .context("connect ttrpc server")?;
+        io::copy_bidirectional(&mut stream, &mut server)
+            .await
+            .context("forward connection")?;
+    } else {
+        io::copy(&mut stream, &mut io::sink())
+            .await
+            .context("drain log connection")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_handshake() {
+        assert_eq!(parse_handshake("connect 1024").unwrap(), 1024);
+        assert_eq!(parse_handshake("CONNECT 1025").unwrap(), 1025);
+        assert!(parse_handshake("connect").is_err());
+        assert!(parse_handshake("connect abc").is_err());
+        assert!(parse_handshake("hello 1024").is_err());
+    }
+
+    #[test]
+    fn test_ttrpc_socket_path() {
+        assert_eq!(
+            ttrpc_socket_path(Path::new("/tmp/kata.hvsock")),
+            PathBuf::from("/tmp/kata.hvsock.ttrpc")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handshake() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("kata.hvsock");
+        let mut agent = MockAgent::new(&socket_path, Script::default());
+        agent.start().await.unwrap();
+        assert_eq!(
+            agent.address(),
+            format!("hvsock://{}", socket_path.display())
+        );
+
+        let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+        stream
+            .write_all(format!("CONNECT {}\n", DEFAULT_AGENT_VSOCK_PORT).as_bytes())
+            .await
+            .unwrap();
+        let mut reply = vec![0u8; format!("OK {}\n", DEFAULT_AGENT_VSOCK_PORT).len()];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(
+            String::from_utf8(reply).unwrap(),
+            format!("OK {}\n", DEFAULT_AGENT_VSOCK_PORT)
+        );
+
+        // the unknown port is rejected by closing the connection
+        let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+        stream.write_all(b"CONNECT 1\n").await.unwrap();
+        let mut buf = Vec::new();
+        assert_eq!(stream.read_to_end(&mut buf).await.unwrap_or(0), 0);
+
+        agent.stop().await;
+        assert!(!socket_path.exists());
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use protocols::{
+    agent, agent_ttrpc_async as agent_ttrpc, csi, empty::Empty, health,
+    health_ttrpc_async as health_ttrpc, types,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use ttrpc::{error::get_rpc_status, r#async::TtrpcContext, Code};
+
+use crate::{
+    recorder::Recorder,
+    script::{default_behavior, Script},
+};
+
+/// The ttrpc services of the mock agent, the requests are recorded and replied as scripted.
+#[derive(Clone)]
+pub(crate) struct MockService {
+    script: Arc<RwLock<Script>>,
+    recorder: Recorder,
+}
+
+impl MockService {
+    pub(crate) fn new(script: Arc<RwLock<Script>>, recorder: Recorder) -> Self {
+        Self { script, recorder }
+    }
+
+    async fn handle<Req, Resp>(&self, method: &str, req: &Req) -> ttrpc::Result<Resp>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned + Default,
+    {
+        let request = serde_json::to_value(req).unwrap_or(serde_json::Value::Null);
+        let nth = self.recorder.record(method, request);
+        let behavior = self
+            .script
+            .read()
+            .unwrap()
+            .behavior(method, nth)
+            .cloned()
+            .unwrap_or_else(|| default_behavior(method));
+        debug!(
+            sl!(),
+            "handle {} request {} with {:?}", method, nth, behavior
+        );
+
+        if behavior.delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(behavior.delay_ms)).await;
+        }
+        if behavior.hang {
+            futures::future::pending::<()>().await;
+        }
+        if let Some(error) = behavior.error.as_ref() {
+            return Err(error.to_ttrpc_error());
+        }
+
+        match behavior.response {
+            Some(response) => serde_json::from_value(response).map_err(|e| {
+                get_rpc_status(
+                    Code::INTERNAL,
+                    format!("invalid scripted response of {}: {:?}", method, e),
+                )
+            }),
+            None => Ok(Resp::default()),
+        }
+    }
+}
+
+macro_rules! impl_agent_service {
+    ($($name: tt | $method: expr | $req: ty | $resp: ty),*) => {
+        #[async_trait]
+        impl agent_ttrpc::AgentService for MockService {
+            $(async fn $name(&self, _ctx: &TtrpcContext, req: $req) -> ttrpc::Result<$resp> {
+                self.handle($method, &req).await
+            })*
+        }
+    };
+}
+
+impl_agent_service!(
+    create_container | "CreateContainer" | agent::CreateContainerRequest | Empty,
+    start_container | "StartContainer" | agent::StartContainerRequest | Empty,
+    remove_container | "RemoveContainer" | agent::RemoveContainerRequest | Empty,
+    exec_process | "ExecProcess" | agent::ExecProcessRequest | Empty,
+    signal_process | "SignalProcess" | agent::SignalProcessRequest | Empty,
+    wait_process | "WaitProcess" | agent::WaitProcessRequest | agent::WaitProcessResponse,
+    update_container | "UpdateContainer" | agent::UpdateContainerRequest | Empty,
+    stats_container
+        | "StatsContainer"
+        | agent::StatsContainerRequest
+        | agent::StatsContainerResponse,
+    pause_container | "PauseContainer" | agent::PauseContainerRequest | Empty,
+    resume_container | "ResumeContainer" | agent::ResumeContainerRequest | Empty,
+    write_stdin | "WriteStdin" | agent::WriteStreamRequest | agent::WriteStreamResponse,
+    read_stdout | "ReadStdout" | agent::ReadStreamRequest | agent::ReadStreamResponse,
+    read_stderr | "ReadStderr" | agent::ReadStreamRequest | agent::ReadStreamResponse,
+    close_stdin | "CloseStdin" | agent::CloseStdinRequest | Empty,
+    tty_win_resize | "TtyWinResize" | agent::TtyWinResizeRequest | Empty,
+    update_interface | "UpdateInterface" | agent::UpdateInterfaceRequest | types::Interface,
+    update_routes | "UpdateRoutes" | agent::UpdateRoutesRequest | agent::Routes,
+    list_interfaces | "ListInterfaces" | agent::ListInterfacesRequest | agent::Interfaces,
+    list_routes | "ListRoutes" | agent::ListRoutesRequest | agent::Routes,
+    add_arp_neighbors | "AddARPNeighbors" | agent::AddARPNeighborsRequest | Empty,
+    get_ip_tables | "GetIPTables" | agent::GetIPTablesRequest | agent::GetIPTablesResponse,
+    set_ip_tables | "SetIPTables" | agent::SetIPTablesRequest | agent::SetIPTablesResponse,
+    get_metrics | "GetMetrics" | agent::GetMetricsRequest | agent::Metrics,
+    set_log_level | "SetLogLevel" | agent::SetLogLevelRequest | Empty,
+    create_sandbox | "CreateSandbox" | agent::CreateSandboxRequest | Empty,
+    destroy_sandbox | "DestroySandbox" | agent::DestroySandboxRequest | Empty,
+    online_cpu_mem | "OnlineCPUMem" | agent::OnlineCPUMemRequest | Empty,
+    reseed_random_dev | "ReseedRandomDev" | agent::ReseedRandomDevRequest | Empty,
+    get_guest_details
+        | "GetGuestDetails"
+        | agent::GuestDetailsRequest
+        | agent::GuestDetailsResponse,
+    mem_hotplug_by_probe | "MemHotplugByProbe" | agent::MemHotplugByProbeRequest | Empty,
+    set_guest_date_time | "SetGuestDateTime" | agent::SetGuestDateTimeRequest | Empty,
+    copy_file | "CopyFile" | agent::CopyFileRequest | Empty,
+    get_oom_event | "GetOOMEvent" | agent::GetOOMEventRequest | agent::OOMEvent,
+    add_swap | "AddSwap" | agent::AddSwapRequest | Empty,
+    get_volume_stats | "GetVolumeStats" | agent::VolumeStatsRequest | csi::VolumeStatsResponse,
+    resize_volume | "ResizeVolume" | agent::ResizeVolumeRequest | Empty
+);
+
+#[async_trait]
+impl health_ttrpc::Health for MockService {
+    async fn check(
+        &self,
+        _ctx: &TtrpcContext,
+        req: health::CheckRequest,
+    ) -> ttrpc::Result<health::HealthCheckResponse> {
+        self.handle("Check", &req).await
+    }
+
+    async fn version(
+        &self,
+        _ctx: &TtrpcContext,
+        req: health::CheckRequest,
+    ) -> ttrpc::Result<health::VersionCheckResponse> {
+        self.handle("Version", &req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Behavior;
+
+    #[tokio::test]
+    async fn test_handle() {
+        let mut script = Script::default();
+        script
+            .set(
+                "WaitProcess",
+                vec![
+                    Behavior::fail(Code::NOT_FOUND, "no such process"),
+                    Behavior::respond(serde_json::json!({ "status": 137 })),
+                ],
+            )
+            .unwrap();
+        script
+            .set(
+                "ListRoutes",
+                vec![Behavior::respond(serde_json::json!({ "Routes": 1 }))],
+            )
+            .unwrap();
+        let recorder = Recorder::default();
+        let service = MockService::new(Arc::new(RwLock::new(script)), recorder.clone());
+
+        let mut req = agent::WaitProcessRequest::new();
+        req.container_id = "c1".to_string();
+        let resp: ttrpc::Result<agent::WaitProcessResponse> =
+            service.handle("WaitProcess", &req).await;
+        assert!(resp.is_err());
+        let resp: agent::WaitProcessResponse = service.handle("WaitProcess", &req).await.unwrap();
+        assert_eq!(resp.status, 137);
+
+        // the default response is used if the method is not scripted
+        let resp: agent::WaitProcessResponse = service
+            .handle("StartContainer", &agent::StartContainerRequest::new())
+            .await
+            .unwrap();
+        assert_eq!(resp.status, 0);
+        let resp: health::HealthCheckResponse = service
+            .handle("Check", &health::CheckRequest::new())
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.status,
+            health::HealthCheckResponse_ServingStatus::SERVING
+        );
+
+        // the invalid scripted response is an error
+        let resp: ttrpc::Result<agent::Routes> = service
+            .handle("ListRoutes", &agent::ListRoutesRequest::new())
+            .await;
+        assert!(resp.is_err());
+
+        let requests = recorder.requests_of("WaitProcess");
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].request["container_id"], "c1");
+    }
+}
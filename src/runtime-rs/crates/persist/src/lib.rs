@@ -15,8 +15,13 @@ use kata_sys_util::validate::verify_id;
 use safe_path::scoped_join;
 
 pub fn to_disk<T: serde::Serialize>(value: &T, sid: &str) -> Result<()> {
+    to_disk_in(value, KATA_PATH, sid)
+}
+
+/// Save the state of the sandbox in the sandbox directory under `root`.
+pub fn to_disk_in<T: serde::Serialize>(value: &T, root: &str, sid: &str) -> Result<()> {
     verify_id(sid).context("failed to verify sid")?;
-    let mut path = scoped_join(KATA_PATH, sid)?;
+    let mut path = scoped_join(root, sid)?;
     if path.exists() {
         path.push(PERSIST_FILE);
         let f = File::create(path)
@@ -30,11 +35,19 @@ pub fn to_disk<T: serde::Serialize>(value: &T, sid: &str) -> Result<()> {
 }
 
 pub fn from_disk<T>(sid: &str) -> Result<T>
+where
+    T: de::DeserializeOwned,
+{
+    from_disk_in(KATA_PATH, sid)
+}
+
+/// Load the state of the sandbox from the sandbox directory under `root`.
+pub fn from_disk_in<T>(root: &str, sid: &str) -> Result<T>
 where
     T: de::DeserializeOwned,
 {
     verify_id(sid).context("failed to verify sid")?;
-    let mut path = scoped_join(KATA_PATH, sid)?;
+    let mut path = scoped_join(root, sid)?;
     if path.exists() {
         path.push(PERSIST_FILE);
         let file = File::open(path).context("failed to open the file")?;
@@ -46,7 +59,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::{from_disk, to_disk, KATA_PATH};
+    use crate::{from_disk, from_disk_in, to_disk, to_disk_in, KATA_PATH};
     use serde::{Deserialize, Serialize};
     use std::fs::DirBuilder;
     use std::{fs, result::Result::Ok};
@@ -82,4 +95,26 @@ mod tests {
             assert!(fs::remove_dir_all(&sandbox_dir).is_ok());
         }
     }
+
+    #[test]
+    fn test_to_from_disk_in() {
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Kata {
+            name: String,
+        }
+        let data = Kata {
+            name: "kata".to_string(),
+        };
+        let root = tempfile::tempdir().unwrap();
+        let root_path = root.path().to_str().unwrap();
+        let sid = "aadede";
+        // the sandbox directory must exist
+        assert!(to_disk_in(&data, root_path, sid).is_err());
+
+        fs::create_dir(root.path().join(sid)).unwrap();
+        assert!(to_disk_in(&data, root_path, sid).is_ok());
+        assert!(root.path().join(sid).join(crate::PERSIST_FILE).exists());
+        let result = from_disk_in::<Kata>(root_path, sid).unwrap();
+        assert_eq!(result.name, data.name);
+    }
 }
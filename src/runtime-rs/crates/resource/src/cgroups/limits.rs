@@ -0,0 +1,276 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::collections::HashMap;
+
+use cgroups_rs::{CpuResources, MemoryResources, Resources};
+use kata_types::annotations::Annotation;
+
+// The CFS period used for the sandbox cgroups, in microseconds.
+const DEFAULT_CPU_PERIOD: u64 = 100_000;
+const MIB: i64 = 1 << 20;
+
+// Range of the cpu shares of cgroup v1 and of the cpu weight of cgroup v2.
+const MIN_CPU_SHARES: u64 = 2;
+const MAX_CPU_SHARES: u64 = 262_144;
+const MIN_CPU_WEIGHT: u64 = 1;
+const MAX_CPU_WEIGHT: u64 = 10_000;
+
+/// CPU and memory limits of a sandbox, a container or the VMM overhead. A limit of `None` means
+/// not limited, which is the default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Limits {
+    /// CPU quota in microseconds per `DEFAULT_CPU_PERIOD`.
+    pub cpu_quota: Option<i64>,
+    pub cpu_shares: u64,
+    /// Memory limit in bytes.
+    pub memory: Option<i64>,
+}
+
+impl From<&Resources> for Limits {
+    fn from(r: &Resources) -> Self {
+        let cpu_quota = match (r.cpu.quota, r.cpu.period) {
+            (Some(quota), Some(period)) if quota > 0 && period > 0 => {
+                Some((quota as i128 * DEFAULT_CPU_PERIOD as i128 / period as i128) as i64)
+            }
+            _ => None,
+        };
+
+        Self {
+            cpu_quota,
+            cpu_shares: r.cpu.shares.unwrap_or(0),
+            memory: r.memory.memory_hard_limit.filter(|m| *m > 0),
+        }
+    }
+}
+
+impl Limits {
+    /// The limits of nothing to run, to add the limits of the containers to.
+    pub fn empty() -> Self {
+        Self {
+            cpu_quota: Some(0),
+            cpu_shares: 0,
+            memory: Some(0),
+        }
+    }
+
+    /// The limits of the pod set by the orchestrator in the sandbox annotations, which are
+    /// known before the containers are created.
+    pub fn from_annotations(annotations: &HashMap<String, String>) -> Self {
+        let annotation = Annotation::new(annotations.clone());
+        let quota = annotation.get_sandbox_cpu_quota();
+        let period = annotation.get_sandbox_cpu_period();
+
+        let resources = Resources {
+            cpu: CpuResources {
+                quota: Some(quota),
+                period: Some(period),
+                shares: Some(annotation.get_sandbox_cpu_shares()),
+                ..Default::default()
+            },
+            memory: MemoryResources {
+                memory_hard_limit: Some(annotation.get_sandbox_mem()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        Self::from(&resources)
+    }
+
+    /// The limits of the VMM overhead or of the guest, with the CPU in milli-CPUs and the memory
+    /// in MiB.
+    pub fn from_overhead(cpu: u32, memory: u32) -> Self {
+        Self {
+            cpu_quota: Some(cpu as i64 * DEFAULT_CPU_PERIOD as i64 / 1000),
+            cpu_shares: 0,
+            memory: Some(memory as i64 * MIB),
+        }
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.cpu_quota.unwrap_or(0) > 0 || self.memory.unwrap_or(0) > 0
+    }
+
+    /// Add the limits of a container or of the overhead, the sum is not limited if any of them
+    /// is not limited.
+    pub fn add(&mut self, other: &Limits) {
+        self.cpu_quota = add_limit(self.cpu_quota, other.cpu_quota);
+        self.cpu_shares += other.cpu_shares;
+        self.memory = add_limit(self.memory, other.memory);
+    }
+
+    /// Take the larger of each limit, the limits not set in `other` are ignored.
+    pub fn max(&mut self, other: &Limits) {
+        self.cpu_quota = max_limit(self.cpu_quota, other.cpu_quota);
+        self.cpu_shares = self.cpu_shares.max(other.cpu_shares);
+        self.memory = max_limit(self.memory, other.memory);
+    }
+
+    /// Set the limits to the cgroup resources. The limits not set or of 0 are reset to
+    /// unlimited, so that the cgroup is not constrained anymore once the last limited container
+    /// is removed.
+    pub fn apply_to(&self, resources: &mut Resources, v2: bool) {
+        match self.cpu_quota.filter(|q| *q > 0) {
+            Some(quota) => {
+                resources.cpu.quota = Some(quota);
+                resources.cpu.period = Some(DEFAULT_CPU_PERIOD);
+            }
+            None => resources.cpu.quota = Some(-1),
+        }
+
+        if self.cpu_shares > 0 {
+            resources.cpu.shares = Some(if v2 {
+                shares_to_weight(self.cpu_shares)
+            } else {
+                self.cpu_shares.clamp(MIN_CPU_SHARES, MAX_CPU_SHARES)
+            });
+        }
+
+        resources.memory.memory_hard_limit = Some(match self.memory.filter(|m| *m > 0) {
+            Some(memory) => memory,
+            // memory.max takes "max" for unlimited, and clamps the larger values to it.
+            None if v2 => i64::MAX,
+            None => -1,
+        });
+    }
+}
+
+fn add_limit(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+    Some(a? + b?)
+}
+
+fn max_limit(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, _) => a,
+    }
+}
+
+// Convert the cpu shares of cgroup v1 to the cpu weight of cgroup v2, as runc does.
+pub(crate) fn shares_to_weight(shares: u64) -> u64 {
+    let shares = shares.clamp(MIN_CPU_SHARES, MAX_CPU_SHARES);
+    MIN_CPU_WEIGHT
+        + ((shares - MIN_CPU_SHARES) * (MAX_CPU_WEIGHT - MIN_CPU_WEIGHT))
+            / (MAX_CPU_SHARES - MIN_CPU_SHARES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container_resources(quota: i64, period: u64, shares: u64, memory: i64) -> Resources {
+        Resources {
+            cpu: CpuResources {
+                quota: Some(quota),
+                period: Some(period),
+                shares: Some(shares),
+                ..Default::default()
+            },
+            memory: MemoryResources {
+                memory_hard_limit: Some(memory),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        // 0.5 CPU and 1.5 CPU with different periods
+        let mut sum = Limits::empty();
+        sum.add(&Limits::from(&container_resources(
+            50_000,
+            100_000,
+            512,
+            256 * MIB,
+        )));
+        sum.add(&Limits::from(&container_resources(
+            75_000,
+            50_000,
+            1536,
+            512 * MIB,
+        )));
+        assert_eq!(
+            sum,
+            Limits {
+                cpu_quota: Some(200_000),
+                cpu_shares: 2048,
+                memory: Some(768 * MIB),
+            }
+        );
+
+        let mut annotations = HashMap::new();
+        annotations.insert(
+            "io.kubernetes.cri.sandbox-cpu-quota".to_string(),
+            "300000".to_string(),
+        );
+        annotations.insert(
+            "io.kubernetes.cri.sandbox-cpu-period".to_string(),
+            "100000".to_string(),
+        );
+        annotations.insert(
+            "io.kubernetes.cri.sandbox-memory".to_string(),
+            (512 * MIB).to_string(),
+        );
+        let sandbox_limits = Limits::from_annotations(&annotations);
+        sum.max(&sandbox_limits);
+        assert_eq!(sum.cpu_quota, Some(300_000));
+        assert_eq!(sum.memory, Some(768 * MIB));
+
+        // the guest memory at boot and the VMM overhead are on top of the containers
+        let overhead = Limits::from_overhead(250, 64);
+        assert_eq!(overhead.cpu_quota, Some(25_000));
+        assert_eq!(overhead.memory, Some(64 * MIB));
+        let mut limited = sum.clone();
+        limited.add(&overhead);
+        limited.add(&Limits::from_overhead(0, 2048));
+
+        let mut resources = Resources::default();
+        limited.apply_to(&mut resources, false);
+        assert_eq!(resources.cpu.quota, Some(325_000));
+        assert_eq!(resources.cpu.period, Some(DEFAULT_CPU_PERIOD));
+        assert_eq!(resources.cpu.shares, Some(2048));
+        assert_eq!(resources.memory.memory_hard_limit, Some(2880 * MIB));
+
+        // a container not limited makes the sandbox not limited, even with the overhead and the
+        // limits in the annotations
+        sum.add(&Limits::from(&container_resources(-1, 100_000, 2, -1)));
+        sum.max(&sandbox_limits);
+        sum.add(&overhead);
+        assert_eq!(
+            sum,
+            Limits {
+                cpu_quota: None,
+                cpu_shares: 2050,
+                memory: None,
+            }
+        );
+        assert!(!sum.is_limited());
+
+        let mut resources = Resources::default();
+        sum.apply_to(&mut resources, true);
+        assert_eq!(resources.cpu.quota, Some(-1));
+        assert_eq!(resources.cpu.shares, Some(shares_to_weight(2050)));
+        assert_eq!(resources.memory.memory_hard_limit, Some(i64::MAX));
+
+        // no CPU quota is set until a container asks for one
+        let mut resources = Resources::default();
+        Limits::empty().apply_to(&mut resources, false);
+        assert_eq!(resources.cpu.quota, Some(-1));
+        assert_eq!(resources.cpu.shares, None);
+        assert_eq!(resources.memory.memory_hard_limit, Some(-1));
+        assert!(!Limits::default().is_limited());
+        assert!(!Limits::from_annotations(&HashMap::new()).is_limited());
+    }
+
+    #[test]
+    fn test_shares_to_weight() {
+        assert_eq!(shares_to_weight(2), 1);
+        assert_eq!(shares_to_weight(1024), 39);
+        assert_eq!(shares_to_weight(262_144), 10_000);
+        assert_eq!(shares_to_weight(1_000_000), 10_000);
+    }
+}
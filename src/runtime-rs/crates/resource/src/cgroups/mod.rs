@@ -5,25 +5,36 @@
 //
 
 pub mod cgroup_persist;
+mod limits;
+mod systemd;
 mod utils;
+mod vcpu_pinning;
 
 use std::{
     collections::{HashMap, HashSet},
     error::Error,
     io,
     iter::FromIterator,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use cgroup_persist::CgroupState;
-use cgroups_rs::{cgroup_builder::CgroupBuilder, Cgroup, CgroupPid, CpuResources, Resources};
+use cgroups_rs::{
+    cgroup_builder::CgroupBuilder, Cgroup, CgroupPid, CpuResources, MemoryResources, Resources,
+};
 use hypervisor::Hypervisor;
 use kata_sys_util::spec::load_oci_spec;
+use kata_sys_util::systemd::is_systemd_cgroups_path;
 use kata_types::config::TomlConfig;
+use limits::Limits;
 use oci::LinuxResources;
 use persist::sandbox_persist::Persist;
+use systemd::SystemdCgroup;
 use tokio::sync::RwLock;
 
 const OS_ERROR_NO_SUCH_PROCESS: i32 = 3;
@@ -37,48 +48,111 @@ pub struct CgroupConfig {
     pub path: String,
     pub overhead_path: String,
     pub sandbox_cgroup_only: bool,
+    pub enable_vcpus_pinning: bool,
+    // The pod limits in the sandbox annotations.
+    sandbox_limits: Limits,
+    overhead_limits: Limits,
+    // The memory of the guest at boot, used by the guest kernel and the agent.
+    guest_limits: Limits,
+    enable_numa: bool,
+    // The host CPUs whose NUMA nodes back the guest NUMA nodes, if any.
+    numa_cpus: Vec<u32>,
 }
 
 impl CgroupConfig {
-    fn new(sid: &str, toml_config: &TomlConfig) -> Result<Self> {
-        let overhead_path = utils::gen_overhead_path(sid);
-        let spec = load_oci_spec()?;
+    fn new(sid: &str, toml_config: &TomlConfig, spec: &oci::Spec) -> Result<Self> {
+        let sandbox_limits = Limits::from_annotations(&spec.annotations);
         let path = spec
             .linux
+            .as_ref()
             // The trim of '/' is important, because cgroup_path is a relative path.
             .map(|linux| linux.cgroups_path.trim_start_matches('/').to_string())
             .unwrap_or_default();
+        let overhead_path = Self::overhead_path(sid, &path)?;
+        let hypervisor = toml_config
+            .hypervisor
+            .get(&toml_config.runtime.hypervisor_name);
+        let default_memory = hypervisor
+            .map(|h| h.memory_info.default_memory)
+            .unwrap_or_default();
+        let enable_numa = hypervisor
+            .map(|h| h.cpu_info.enable_numa)
+            .unwrap_or_default();
+        let numa_cpus = match hypervisor {
+            Some(h) if enable_numa => vcpu_pinning::parse_cpus(&h.cpu_info.numa_cpus)?,
+            _ => vec![],
+        };
 
         Ok(Self {
             path,
             overhead_path,
             sandbox_cgroup_only: toml_config.runtime.sandbox_cgroup_only,
+            enable_vcpus_pinning: toml_config.runtime.enable_vcpus_pinning,
+            sandbox_limits,
+            overhead_limits: Limits::from_overhead(
+                toml_config.runtime.vmm_overhead_cpu,
+                toml_config.runtime.vmm_overhead_memory,
+            ),
+            guest_limits: Limits::from_overhead(0, default_memory),
+            enable_numa,
+            numa_cpus,
         })
     }
+
+    fn overhead_path(sid: &str, path: &str) -> Result<String> {
+        if is_systemd_cgroups_path(path) {
+            let systemd_cgroup = SystemdCgroup::new(path)?;
+            Ok(utils::gen_systemd_overhead_path(systemd_cgroup.path()))
+        } else {
+            Ok(utils::gen_overhead_path(sid))
+        }
+    }
 }
 
 pub struct CgroupsResource {
     resources: Arc<RwLock<HashMap<String, Resources>>>,
     cgroup_manager: Cgroup,
+    // The transient unit of the sandbox cgroup, with the systemd cgroup driver.
+    systemd_cgroup: Option<SystemdCgroup>,
     overhead_cgroup_manager: Option<Cgroup>,
     cgroup_config: CgroupConfig,
+    // Whether each vCPU thread is currently pinned to one host CPU.
+    vcpus_pinned: AtomicBool,
 }
 
 impl CgroupsResource {
-    pub fn new(sid: &str, toml_config: &TomlConfig) -> Result<Self> {
-        let config = CgroupConfig::new(sid, toml_config)?;
+    pub fn new(sid: &str, toml_config: &TomlConfig, spec: &oci::Spec) -> Result<Self> {
+        let config = CgroupConfig::new(sid, toml_config, spec)?;
 
         // Create the sandbox cgroups manager (cgroups on Linux).
         // Depending on the sandbox_cgroup_only value, this cgroup
         // will either hold all the pod threads (sandbox_cgroup_only is true)
         // or only the virtual CPU ones (sandbox_cgroup_only is false).
+        // With the systemd cgroup driver, the cgroups path is in the "slice:prefix:name" format,
+        // and the sandbox cgroup is a transient unit started with the runtime in it, as systemd
+        // does not allow empty scopes.
+        let systemd_cgroup = if is_systemd_cgroups_path(&config.path) {
+            let systemd_cgroup = SystemdCgroup::new(&config.path)?;
+            systemd_cgroup
+                .apply(std::process::id())
+                .context("start sandbox unit")?;
+            Some(systemd_cgroup)
+        } else {
+            None
+        };
+
         let hier = cgroups_rs::hierarchies::auto();
-        let cgroup_manager = CgroupBuilder::new(&config.path).build(hier)?;
+        let cgroup_manager = match systemd_cgroup.as_ref() {
+            Some(systemd_cgroup) => Cgroup::load(hier, systemd_cgroup.path()),
+            None => CgroupBuilder::new(&config.path).build(hier)?,
+        };
 
         // The shim configuration is requesting that we do not put all threads
         // into the sandbox resource controller.
         // We're creating an overhead controller, with no constraints. Everything but
         // the vCPU threads will eventually make it there.
+        // With the systemd cgroup driver, the overhead controller is nested in the sandbox
+        // unit, so that the runtime keeps the unit populated once it moves there.
         let overhead_cgroup_manager = if !config.sandbox_cgroup_only {
             let hier = cgroups_rs::hierarchies::auto();
             Some(CgroupBuilder::new(&config.overhead_path).build(hier)?)
@@ -103,15 +177,25 @@ impl CgroupsResource {
 
         Ok(Self {
             cgroup_manager,
+            systemd_cgroup,
             resources: Arc::new(RwLock::new(HashMap::new())),
             overhead_cgroup_manager,
             cgroup_config: config,
+            vcpus_pinned: AtomicBool::new(false),
         })
     }
 
     /// delete will move the running processes in the cgroup_manager and
     /// overhead_cgroup_manager to the parent and then delete the cgroups.
     pub async fn delete(&self) -> Result<()> {
+        // The overhead cgroup goes first, as it may be nested in the sandbox unit.
+        if let Some(overhead) = self.overhead_cgroup_manager.as_ref() {
+            for cg_pid in overhead.tasks() {
+                overhead.remove_task(cg_pid)?;
+            }
+            overhead.delete().context("delete overhead")?;
+        }
+
         for cg_pid in self.cgroup_manager.tasks() {
             // For now, we can't guarantee that the thread in cgroup_manager does still
             // exist. Once it exit, we should ignore that error returned by remove_task
@@ -131,15 +215,12 @@ impl CgroupsResource {
             }
         }
 
-        self.cgroup_manager
-            .delete()
-            .context("delete cgroup manager")?;
-
-        if let Some(overhead) = self.overhead_cgroup_manager.as_ref() {
-            for cg_pid in overhead.tasks() {
-                overhead.remove_task(cg_pid)?;
-            }
-            overhead.delete().context("delete overhead")?;
+        match self.systemd_cgroup.as_ref() {
+            Some(systemd_cgroup) => systemd_cgroup.stop().context("stop sandbox unit")?,
+            None => self
+                .cgroup_manager
+                .delete()
+                .context("delete cgroup manager")?,
         }
 
         Ok(())
@@ -161,6 +242,17 @@ impl CgroupsResource {
         self.do_update_cgroups(h).await
     }
 
+    /// Remove the resources of the container, so that the sandbox limits are not counting it
+    /// anymore.
+    pub async fn remove_cgroups(&self, cid: &str, h: &dyn Hypervisor) -> Result<()> {
+        let removed = self.resources.write().await.remove(cid);
+        if removed.is_none() {
+            return Ok(());
+        }
+
+        self.do_update_cgroups(h).await
+    }
+
     async fn update_resources(&self, cid: &str, new_resource: Resources) -> bool {
         let mut resources = self.resources.write().await;
         let old_resource = resources.insert(cid.to_owned(), new_resource.clone());
@@ -175,10 +267,65 @@ impl CgroupsResource {
     }
 
     async fn do_update_cgroups(&self, h: &dyn Hypervisor) -> Result<()> {
-        let merged_resources = self.merge_resources().await;
-        self.cgroup_manager
-            .apply(&merged_resources)
-            .map_err(|e| anyhow!(e))?;
+        let (limits, mut cpu_resource) = self.merge_resources().await;
+
+        // The host CPUs whose NUMA nodes the sandbox memory may be allocated on, the cpusets of
+        // the containers if the guest NUMA nodes are not bound to given host CPUs.
+        let mut numa_cpus = self.cgroup_config.numa_cpus.clone();
+        if self.cgroup_config.enable_numa && numa_cpus.is_empty() {
+            numa_cpus = vcpu_pinning::parse_cpus(cpu_resource.cpus.as_deref().unwrap_or_default())?;
+        }
+
+        let vcpus_pinning = if self.cgroup_config.enable_vcpus_pinning {
+            let cpus = vcpu_pinning::parse_cpus(cpu_resource.cpus.as_deref().unwrap_or_default())?;
+            let vcpus = h
+                .get_thread_ids()
+                .await
+                .context("get vcpu thread ids")?
+                .vcpus;
+
+            if vcpu_pinning::pin_plan(&vcpus, &cpus).is_some() {
+                numa_cpus.extend(&cpus);
+            }
+
+            Some((vcpus, cpus))
+        } else {
+            None
+        };
+
+        // Bind the sandbox memory to the host NUMA nodes of the guest NUMA nodes and of the
+        // pinned vCPUs, unless the containers set the memory nodes.
+        if !numa_cpus.is_empty() && cpu_resource.mems.as_deref().unwrap_or_default().is_empty() {
+            cpu_resource.mems = Some(vcpu_pinning::numa_nodes(&numa_cpus)?);
+        }
+
+        match self.systemd_cgroup.as_ref() {
+            Some(systemd_cgroup) => systemd_cgroup.set_resources(
+                &limits,
+                cpu_resource.cpus.as_deref().unwrap_or_default(),
+                cpu_resource.mems.as_deref().unwrap_or_default(),
+            )?,
+            None => {
+                let mut merged = Resources {
+                    cpu: cpu_resource,
+                    ..Default::default()
+                };
+                limits.apply_to(&mut merged, self.cgroup_manager.v2());
+                self.cgroup_manager.apply(&merged).map_err(|e| anyhow!(e))?;
+            }
+        }
+
+        if let Some(overhead) = self.overhead_cgroup_manager.as_ref() {
+            let overhead_limits = &self.cgroup_config.overhead_limits;
+            if overhead_limits.is_limited() {
+                let mut resources = Resources::default();
+                overhead_limits.apply_to(&mut resources, overhead.v2());
+                overhead
+                    .apply(&resources)
+                    .map_err(|e| anyhow!(e))
+                    .context("apply overhead limits")?;
+            }
+        }
 
         if self.overhead_cgroup_manager.is_some() {
             // If we have an overhead controller, new vCPU threads would start there,
@@ -187,9 +334,45 @@ impl CgroupsResource {
             self.constrain_hypervisor(h).await?
         }
 
+        if let Some((vcpus, cpus)) = vcpus_pinning {
+            self.update_vcpus_pinning(&vcpus, &cpus)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pin each vCPU thread to one host CPU if there are as many CPUs in the sandbox cpuset as
+    /// vCPUs, otherwise let the vCPU threads float on the whole cpuset again.
+    fn update_vcpus_pinning(&self, vcpus: &HashMap<u32, u32>, cpus: &[u32]) -> Result<()> {
+        match vcpu_pinning::pin_plan(vcpus, cpus) {
+            Some(plan) => {
+                for (tid, cpu) in plan {
+                    if let Err(e) = vcpu_pinning::set_thread_affinity(tid, &[cpu]) {
+                        self.reset_vcpus_pinning(vcpus, cpus);
+                        return Err(e);
+                    }
+                }
+                self.vcpus_pinned.store(true, Ordering::SeqCst);
+            }
+            None => {
+                if self.vcpus_pinned.load(Ordering::SeqCst) {
+                    self.reset_vcpus_pinning(vcpus, cpus);
+                }
+            }
+        }
+
         Ok(())
     }
 
+    fn reset_vcpus_pinning(&self, vcpus: &HashMap<u32, u32>, cpus: &[u32]) {
+        for tid in vcpus.values() {
+            if let Err(e) = vcpu_pinning::set_thread_affinity(*tid, cpus) {
+                warn!(sl!(), "failed to reset vcpu thread affinity: {:?}", e);
+            }
+        }
+        self.vcpus_pinned.store(false, Ordering::SeqCst);
+    }
+
     /// constrain_hypervisor will place the VMM and vCPU threads into resource controllers (cgroups on Linux).
     async fn constrain_hypervisor(&self, h: &dyn Hypervisor) -> Result<()> {
         let tids = h.get_thread_ids().await?;
@@ -204,13 +387,17 @@ impl CgroupsResource {
         Ok(())
     }
 
-    async fn merge_resources(&self) -> Resources {
+    /// Merge the resources of all the containers, into the sandbox limits and cpusets.
+    async fn merge_resources(&self) -> (Limits, CpuResources) {
         let resources = self.resources.read().await;
 
         let mut cpu_list: HashSet<String> = HashSet::new();
         let mut mem_list: HashSet<String> = HashSet::new();
+        let mut limits = Limits::empty();
 
         resources.values().for_each(|r| {
+            limits.add(&Limits::from(r));
+
             if let Some(cpus) = &r.cpu.cpus {
                 cpu_list.insert(cpus.clone());
             }
@@ -225,10 +412,17 @@ impl CgroupsResource {
             ..Default::default()
         };
 
-        Resources {
-            cpu: cpu_resource,
-            ..Default::default()
-        }
+        // The pod limits in the annotations are the sum of the limits of all the containers,
+        // so they are still in effect while not all the containers are created.
+        limits.max(&self.cgroup_config.sandbox_limits);
+
+        // The guest memory is charged to the vCPU threads which are always in the sandbox
+        // cgroup, on top of the memory hotplugged for the containers. The overhead is added as
+        // well, whether its threads are in the sandbox cgroup or not.
+        limits.add(&self.cgroup_config.guest_limits);
+        limits.add(&self.cgroup_config.overhead_limits);
+
+        (limits, cpu_resource)
     }
 
     fn calc_cpu_resources(&self, linux_resources: Option<&LinuxResources>) -> CpuResources {
@@ -236,7 +430,21 @@ impl CgroupsResource {
 
         CpuResources {
             cpus: cpu.clone().map(|cpu| cpu.cpus),
-            mems: cpu.map(|cpu| cpu.mems),
+            mems: cpu.clone().map(|cpu| cpu.mems),
+            shares: cpu.as_ref().and_then(|cpu| cpu.shares),
+            quota: cpu.as_ref().and_then(|cpu| cpu.quota),
+            period: cpu.and_then(|cpu| cpu.period),
+            ..Default::default()
+        }
+    }
+
+    fn calc_memory_resources(&self, linux_resources: Option<&LinuxResources>) -> MemoryResources {
+        let limit = linux_resources
+            .and_then(|r| r.memory.as_ref())
+            .and_then(|m| m.limit);
+
+        MemoryResources {
+            memory_hard_limit: limit,
             ..Default::default()
         }
     }
@@ -244,6 +452,7 @@ impl CgroupsResource {
     fn calc_resource(&self, linux_resources: Option<&LinuxResources>) -> Resources {
         Resources {
             cpu: self.calc_cpu_resources(linux_resources),
+            memory: self.calc_memory_resources(linux_resources),
             ..Default::default()
         }
     }
@@ -267,14 +476,55 @@ impl Persist for CgroupsResource {
         cgroup_state: Self::State,
     ) -> Result<Self> {
         let hier = cgroups_rs::hierarchies::auto();
-        let config = CgroupConfig::new(&cgroup_args.sid, &cgroup_args.config)?;
+        // The shim is restored in the bundle of the sandbox.
+        let spec = load_oci_spec()?;
+        let config = CgroupConfig::new(&cgroup_args.sid, &cgroup_args.config, &spec)?;
         let path = cgroup_state.path.unwrap_or_default();
-        let cgroup_manager = Cgroup::load(hier, path.as_str());
+        let systemd_cgroup = if is_systemd_cgroups_path(&path) {
+            Some(SystemdCgroup::new(&path)?)
+        } else {
+            None
+        };
+        let cgroup_manager = match systemd_cgroup.as_ref() {
+            Some(systemd_cgroup) => Cgroup::load(hier, systemd_cgroup.path()),
+            None => Cgroup::load(hier, path.as_str()),
+        };
+        // The runtime lives in the overhead cgroup, which must be emptied before the sandbox
+        // unit is stopped.
+        let overhead_cgroup_manager = match cgroup_state.overhead_path {
+            Some(overhead_path) if !cgroup_state.sandbox_cgroup_only => Some(Cgroup::load(
+                cgroups_rs::hierarchies::auto(),
+                overhead_path.as_str(),
+            )),
+            _ => None,
+        };
         Ok(Self {
             cgroup_manager,
+            systemd_cgroup,
             resources: Arc::new(RwLock::new(HashMap::new())),
-            overhead_cgroup_manager: None,
+            overhead_cgroup_manager,
             cgroup_config: config,
+            vcpus_pinned: AtomicBool::new(false),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overhead_path_without_sandbox_cgroup_only() {
+        assert_eq!(
+            CgroupConfig::overhead_path("456", "/kubepods/pod123/456").unwrap(),
+            "kata_overhead/456"
+        );
+
+        // Nested in the sandbox scope, which would be garbage-collected by systemd otherwise.
+        assert_eq!(
+            CgroupConfig::overhead_path("456", "kubepods-burstable-pod123.slice:cri-containerd:456")
+                .unwrap(),
+            "kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod123.slice/cri-containerd-456.scope/kata_overhead"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::{Context, Result};
+use kata_sys_util::systemd::subsystem::transformer::Transformer;
+use kata_sys_util::systemd::subsystem::{cpu::Cpu, cpuset::CpuSet, memory::Memory};
+use kata_sys_util::systemd::{
+    CgroupHierarchy, CgroupsPath, DBusClient, Properties, SystemdInterface,
+};
+use oci::{LinuxCpu, LinuxMemory, LinuxResources};
+use zbus::zvariant::Value;
+
+use super::limits::{shares_to_weight, Limits};
+
+const UNIT_DESCRIPTION: &str = "kata sandbox";
+
+/// The sandbox cgroup created as a transient systemd unit, for the `slice:prefix:name` cgroups
+/// paths used with the systemd cgroup driver. The unit is created and constrained over D-Bus,
+/// while the threads are moved with the cgroupfs, as systemd only manages processes.
+pub(crate) struct SystemdCgroup {
+    client: DBusClient,
+    slice: String,
+    unit_name: String,
+    // Path of the unit cgroup, relative to the cgroup mount point.
+    path: String,
+    hierarchy: CgroupHierarchy,
+}
+
+impl SystemdCgroup {
+    pub fn new(cgroups_path: &str) -> Result<Self> {
+        let cgroups_path = CgroupsPath::new(cgroups_path)?;
+        let (parent_path, unit_name) = cgroups_path.parse()?;
+
+        Ok(Self {
+            client: DBusClient {},
+            slice: cgroups_path.slice,
+            path: format!("{}/{}", parent_path, unit_name)
+                .trim_start_matches('/')
+                .to_string(),
+            unit_name,
+            hierarchy: if cgroups_rs::hierarchies::is_cgroup2_unified_mode() {
+                CgroupHierarchy::Unified
+            } else {
+                CgroupHierarchy::Legacy
+            },
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Start the unit with the process in it, or add the process to the unit if it is already
+    /// running.
+    pub fn apply(&self, pid: u32) -> Result<()> {
+        if self.client.unit_exist(&self.unit_name)? {
+            return self.client.add_process(pid as i32, &self.unit_name);
+        }
+
+        self.client.start_unit(
+            pid as i32,
+            &self.slice,
+            &self.unit_name,
+            UNIT_DESCRIPTION,
+            &self.hierarchy,
+        )
+    }
+
+    /// Set the limits and the cpusets as properties of the unit.
+    pub fn set_resources(&self, limits: &Limits, cpus: &str, mems: &str) -> Result<()> {
+        let systemd_version = self.client.get_version()?;
+        let properties = self.properties(limits, cpus, mems, &systemd_version)?;
+
+        self.client
+            .set_properties(&self.unit_name, &properties)
+            .context("set sandbox unit properties")
+    }
+
+    fn properties(
+        &self,
+        limits: &Limits,
+        cpus: &str,
+        mems: &str,
+        systemd_version: &str,
+    ) -> Result<Properties<'static>> {
+        // A quota or a memory limit not set is translated to "infinity".
+        let resources = LinuxResources {
+            cpu: Some(LinuxCpu {
+                quota: Some(limits.cpu_quota.filter(|q| *q > 0).unwrap_or(-1)),
+                cpus: cpus.to_string(),
+                mems: mems.to_string(),
+                ..Default::default()
+            }),
+            memory: Some(LinuxMemory {
+                limit: Some(limits.memory.unwrap_or(0)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let mut properties: Properties = vec![];
+        Cpu::apply(
+            &resources,
+            &mut properties,
+            &self.hierarchy,
+            systemd_version,
+        )?;
+        Memory::apply(
+            &resources,
+            &mut properties,
+            &self.hierarchy,
+            systemd_version,
+        )?;
+        CpuSet::apply(
+            &resources,
+            &mut properties,
+            &self.hierarchy,
+            systemd_version,
+        )?;
+
+        // systemd converts CPUShares itself on cgroup v2, but that property is deprecated there.
+        if limits.cpu_shares > 0 {
+            properties.push(match self.hierarchy {
+                CgroupHierarchy::Legacy => ("CPUShares", Value::U64(limits.cpu_shares)),
+                CgroupHierarchy::Unified => {
+                    ("CPUWeight", Value::U64(shares_to_weight(limits.cpu_shares)))
+                }
+            });
+        }
+
+        Ok(properties)
+    }
+
+    /// Stop the unit, which also removes its cgroup. The unit is already gone if systemd
+    /// collected it once its processes were moved out.
+    pub fn stop(&self) -> Result<()> {
+        if !self.client.unit_exist(&self.unit_name)? {
+            return Ok(());
+        }
+
+        self.client.stop_unit(&self.unit_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property<'a>(properties: &'a Properties<'a>, name: &str) -> Option<&'a Value<'a>> {
+        properties.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    #[test]
+    fn test_systemd_cgroup_properties() {
+        let cgroup =
+            SystemdCgroup::new("kubepods-burstable-pod123.slice:cri-containerd:456").unwrap();
+        assert_eq!(cgroup.unit_name, "cri-containerd-456.scope");
+        assert_eq!(
+            cgroup.path(),
+            "kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod123.slice/cri-containerd-456.scope"
+        );
+
+        let limits = Limits {
+            cpu_quota: Some(150_000),
+            cpu_shares: 1024,
+            memory: Some(1 << 30),
+        };
+        let properties = cgroup.properties(&limits, "0-1", "", "249").unwrap();
+
+        assert_eq!(
+            property(&properties, "CPUQuotaPerSecUSec"),
+            Some(&Value::U64(1_500_000))
+        );
+        assert_eq!(
+            property(&properties, "AllowedCPUs"),
+            Some(&Value::Array(vec![3u8].into()))
+        );
+        assert_eq!(property(&properties, "AllowedMemoryNodes"), None);
+        match cgroup.hierarchy {
+            CgroupHierarchy::Legacy => {
+                assert_eq!(property(&properties, "CPUShares"), Some(&Value::U64(1024)));
+                assert_eq!(
+                    property(&properties, "MemoryLimit"),
+                    Some(&Value::U64(1 << 30))
+                );
+            }
+            CgroupHierarchy::Unified => {
+                assert_eq!(property(&properties, "CPUWeight"), Some(&Value::U64(39)));
+                assert_eq!(
+                    property(&properties, "MemoryMax"),
+                    Some(&Value::U64(1 << 30))
+                );
+            }
+        }
+
+        // Not limited anymore.
+        let properties = cgroup
+            .properties(&Limits::default(), "", "", "249")
+            .unwrap();
+        assert_eq!(
+            property(&properties, "CPUQuotaPerSecUSec"),
+            Some(&Value::U64(u64::MAX))
+        );
+        assert_eq!(property(&properties, "CPUShares"), None);
+        assert_eq!(property(&properties, "CPUWeight"), None);
+    }
+}
@@ -14,3 +14,11 @@
 pub(crate) fn gen_overhead_path(path: &str) -> String {
     format!("kata_overhead/{}", path.trim_start_matches('/'))
 }
+
+// With the systemd cgroup driver, the Kata overhead cgroup is a sub-cgroup of the
+// sandbox unit instead, which is delegated to us (Delegate=yes). systemd stops the
+// scope and removes its cgroup once no process is left in it, so the runtime must
+// stay in the unit subtree after leaving the sandbox cgroup.
+pub(crate) fn gen_systemd_overhead_path(unit_path: &str) -> String {
+    format!("{}/kata_overhead", unit_path)
+}
@@ -0,0 +1,97 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use kata_sys_util::numa;
+use kata_types::cpu::CpuSet;
+use nix::sched::{sched_setaffinity, CpuSet as AffinitySet};
+use nix::unistd::Pid;
+
+/// Parse the cpuset of the sandbox into a sorted list of host CPUs.
+pub(crate) fn parse_cpus(cpus: &str) -> Result<Vec<u32>> {
+    if cpus.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let cpu_set = CpuSet::from_str(cpus).with_context(|| format!("invalid cpuset {}", cpus))?;
+    Ok(cpu_set.iter().copied().collect())
+}
+
+/// Map each vCPU thread to one host CPU, in the vCPU id order, if there are exactly as many host
+/// CPUs as vCPUs.
+pub(crate) fn pin_plan(vcpus: &HashMap<u32, u32>, cpus: &[u32]) -> Option<Vec<(u32, u32)>> {
+    if vcpus.is_empty() || vcpus.len() != cpus.len() {
+        return None;
+    }
+
+    let mut ids: Vec<&u32> = vcpus.keys().collect();
+    ids.sort_unstable();
+
+    Some(
+        ids.into_iter()
+            .zip(cpus.iter())
+            .map(|(id, cpu)| (vcpus[id], *cpu))
+            .collect(),
+    )
+}
+
+/// Restrict the thread to run on the host CPUs, or allow all the CPUs if the list is empty.
+pub(crate) fn set_thread_affinity(tid: u32, cpus: &[u32]) -> Result<()> {
+    let mut cpu_set = AffinitySet::new();
+    if cpus.is_empty() {
+        for cpu in 0..AffinitySet::count() {
+            cpu_set.set(cpu)?;
+        }
+    }
+    for cpu in cpus {
+        cpu_set.set(*cpu as usize)?;
+    }
+
+    sched_setaffinity(Pid::from_raw(tid as i32), &cpu_set)
+        .with_context(|| format!("set affinity of thread {} to cpus {:?}", tid, cpus))
+}
+
+/// The host NUMA nodes of the CPUs, as a list for cpuset.mems.
+pub(crate) fn numa_nodes(cpus: &[u32]) -> Result<String> {
+    let mut cpus = cpus.to_vec();
+    cpus.sort_unstable();
+    cpus.dedup();
+    let cpus: Vec<String> = cpus.iter().map(|c| c.to_string()).collect();
+    let node_map = numa::get_node_map(&cpus.join(",")).context("get numa nodes of cpus")?;
+
+    let mut nodes: Vec<&u32> = node_map.keys().collect();
+    nodes.sort_unstable();
+    let nodes: Vec<String> = nodes.iter().map(|n| n.to_string()).collect();
+
+    Ok(nodes.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_plan() {
+        let cpus = parse_cpus("4-5,2").unwrap();
+        assert_eq!(cpus, vec![2, 4, 5]);
+        assert!(parse_cpus("").unwrap().is_empty());
+
+        let mut vcpus = HashMap::new();
+        vcpus.insert(1, 1001);
+        vcpus.insert(0, 1000);
+        vcpus.insert(2, 1002);
+        assert_eq!(
+            pin_plan(&vcpus, &cpus),
+            Some(vec![(1000, 2), (1001, 4), (1002, 5)])
+        );
+
+        // Not as many CPUs as vCPUs.
+        assert_eq!(pin_plan(&vcpus, &cpus[..2]), None);
+        assert_eq!(pin_plan(&HashMap::new(), &[]), None);
+    }
+}
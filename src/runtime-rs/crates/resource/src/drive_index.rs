@@ -0,0 +1,128 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use kata_types::config::TomlConfig;
+use persist::sandbox_persist::Persist;
+use serde::{Deserialize, Serialize};
+
+/// Allocator of the indexes of block devices plugged into the guest.
+///
+/// The index decides the device name in the guest, e.g. index 1 is `/dev/vdb`. The guest kernel
+/// names a new virtio block device after the lowest free index, and so does the allocator.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DriveIndexAllocator {
+    /// The first index available, indexes below it are taken by the guest rootfs image.
+    base: u64,
+    used: Arc<Mutex<BTreeSet<u64>>>,
+}
+
+impl DriveIndexAllocator {
+    pub(crate) fn new(toml_config: &TomlConfig) -> Self {
+        let base = toml_config
+            .hypervisor
+            .get(&toml_config.runtime.hypervisor_name)
+            .map(|h| u64::from(!h.boot_info.image.is_empty()))
+            .unwrap_or_default();
+        Self::with_base(base)
+    }
+
+    fn with_base(base: u64) -> Self {
+        Self {
+            base,
+            used: Arc::new(Mutex::new(BTreeSet::new())),
+        }
+    }
+
+    /// Allocate the lowest free index.
+    pub(crate) fn allocate(&self) -> u64 {
+        let mut used = self.used.lock().unwrap();
+        let mut index = self.base;
+        for i in used.range(self.base..) {
+            if *i != index {
+                break;
+            }
+            index += 1;
+        }
+        used.insert(index);
+        index
+    }
+
+    /// Release the index after the device is removed from the guest.
+    pub(crate) fn release(&self, index: u64) {
+        self.used.lock().unwrap().remove(&index);
+    }
+}
+
+/// Indexes of the block devices plugged into the guest, saved across runtime restarts.
+#[derive(Serialize, Deserialize, Default)]
+pub struct DriveIndexState {
+    pub base: u64,
+    pub used: Vec<u64>,
+}
+
+#[async_trait]
+impl Persist for DriveIndexAllocator {
+    type State = DriveIndexState;
+    type ConstructorArgs = ();
+
+    async fn save(&self) -> Result<Self::State> {
+        Ok(DriveIndexState {
+            base: self.base,
+            used: self.used.lock().unwrap().iter().copied().collect(),
+        })
+    }
+
+    async fn restore(_: Self::ConstructorArgs, state: Self::State) -> Result<Self> {
+        Ok(Self {
+            base: state.base,
+            used: Arc::new(Mutex::new(state.used.into_iter().collect())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use persist::sandbox_persist::Persist;
+
+    use super::DriveIndexAllocator;
+
+    #[test]
+    fn test_drive_index_allocator() {
+        let allocator = DriveIndexAllocator::with_base(1);
+        assert_eq!(allocator.allocate(), 1);
+        assert_eq!(allocator.allocate(), 2);
+        assert_eq!(allocator.allocate(), 3);
+
+        allocator.release(2);
+        assert_eq!(allocator.allocate(), 2);
+        assert_eq!(allocator.allocate(), 4);
+
+        allocator.release(1);
+        allocator.release(3);
+        assert_eq!(allocator.allocate(), 1);
+        assert_eq!(allocator.allocate(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_drive_index_allocator_persist() {
+        let allocator = DriveIndexAllocator::with_base(1);
+        assert_eq!(allocator.allocate(), 1);
+        assert_eq!(allocator.allocate(), 2);
+        assert_eq!(allocator.allocate(), 3);
+        allocator.release(2);
+
+        let state = allocator.save().await.unwrap();
+        let restored = DriveIndexAllocator::restore((), state).await.unwrap();
+        assert_eq!(restored.allocate(), 2);
+        assert_eq!(restored.allocate(), 4);
+    }
+}
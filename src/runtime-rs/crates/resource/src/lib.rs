@@ -12,7 +12,11 @@ extern crate slog;
 
 logging::logger_with_subsystem!(sl, "resource");
 
+// subsystem of the resources in the sandbox event log
+const EVENT_SUBSYSTEM: &str = "resource";
+
 pub mod cgroups;
+mod drive_index;
 pub mod manager;
 mod manager_inner;
 pub mod network;
@@ -20,6 +24,7 @@ pub mod resource_persist;
 use network::NetworkConfig;
 pub mod rootfs;
 pub mod share_fs;
+pub mod swap;
 pub mod volume;
 pub use manager::ResourceManager;
 
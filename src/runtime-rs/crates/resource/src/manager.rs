@@ -34,6 +34,7 @@ impl ResourceManager {
         agent: Arc<dyn Agent>,
         hypervisor: Arc<dyn Hypervisor>,
         toml_config: Arc<TomlConfig>,
+        spec: &oci::Spec,
     ) -> Result<Self> {
         Ok(Self {
             inner: Arc::new(RwLock::new(ResourceManagerInner::new(
@@ -41,6 +42,7 @@ impl ResourceManager {
                 agent,
                 hypervisor,
                 toml_config,
+                spec,
             )?)),
         })
     }
@@ -101,6 +103,25 @@ impl ResourceManager {
         inner.update_cgroups(cid, linux_resources).await
     }
 
+    pub async fn remove_cgroups(&self, cid: &str) -> Result<()> {
+        let inner = self.inner.read().await;
+        inner.remove_cgroups(cid).await
+    }
+
+    pub async fn update_swap(
+        &self,
+        cid: &str,
+        linux_resources: Option<&LinuxResources>,
+    ) -> Result<()> {
+        let inner = self.inner.read().await;
+        inner.update_swap(cid, linux_resources).await
+    }
+
+    pub async fn remove_swap(&self, cid: &str) {
+        let inner = self.inner.read().await;
+        inner.remove_swap(cid).await
+    }
+
     pub async fn cleanup(&self) -> Result<()> {
         let inner = self.inner.read().await;
         inner.cleanup().await
@@ -15,16 +15,19 @@ use kata_types::config::TomlConfig;
 use kata_types::mount::Mount;
 use oci::LinuxResources;
 use persist::sandbox_persist::Persist;
+use shim_interface::event_log::SandboxEvent;
 use tokio::runtime;
 
 use crate::{
     cgroups::{CgroupArgs, CgroupsResource},
+    drive_index::DriveIndexAllocator,
     manager::ManagerArgs,
     network::{self, Network},
     rootfs::{RootFsResource, Rootfs},
     share_fs::{self, ShareFs},
+    swap::{SwapArgs, SwapResource},
     volume::{Volume, VolumeResource},
-    ResourceConfig,
+    ResourceConfig, EVENT_SUBSYSTEM,
 };
 
 pub(crate) struct ResourceManagerInner {
@@ -34,10 +37,12 @@ pub(crate) struct ResourceManagerInner {
     hypervisor: Arc<dyn Hypervisor>,
     network: Option<Arc<dyn Network>>,
     share_fs: Option<Arc<dyn ShareFs>>,
+    drive_index: DriveIndexAllocator,
 
     pub rootfs_resource: RootFsResource,
     pub volume_resource: VolumeResource,
     pub cgroups_resource: CgroupsResource,
+    pub swap_resource: SwapResource,
 }
 
 impl ResourceManagerInner {
@@ -46,8 +51,12 @@ impl ResourceManagerInner {
         agent: Arc<dyn Agent>,
         hypervisor: Arc<dyn Hypervisor>,
         toml_config: Arc<TomlConfig>,
+        spec: &oci::Spec,
     ) -> Result<Self> {
-        let cgroups_resource = CgroupsResource::new(sid, &toml_config)?;
+        let cgroups_resource = CgroupsResource::new(sid, &toml_config, spec)?;
+        let drive_index = DriveIndexAllocator::new(&toml_config);
+        let swap_resource =
+            SwapResource::new(SwapArgs::new(sid, &toml_config, drive_index.clone()));
         Ok(Self {
             sid: sid.to_string(),
             toml_config,
@@ -55,9 +64,11 @@ impl ResourceManagerInner {
             hypervisor,
             network: None,
             share_fs: None,
+            drive_index,
             rootfs_resource: RootFsResource::new(),
             volume_resource: VolumeResource::new(),
             cgroups_resource,
+            swap_resource,
         })
     }
 
@@ -218,8 +229,30 @@ impl ResourceManagerInner {
         cid: &str,
         spec: &oci::Spec,
     ) -> Result<Vec<Arc<dyn Volume>>> {
+        // An empty store path keeps the volumes away from vhost-user-blk.
+        let vhost_user_store_path = if self
+            .hypervisor
+            .capabilities()
+            .await?
+            .is_vhost_user_supported()
+        {
+            self.toml_config
+                .hypervisor
+                .get(&self.toml_config.runtime.hypervisor_name)
+                .map(|h| h.blockdev_info.vhost_user_store_path.as_str())
+                .unwrap_or_default()
+        } else {
+            ""
+        };
         self.volume_resource
-            .handler_volumes(&self.share_fs, cid, spec)
+            .handler_volumes(
+                &self.share_fs,
+                &self.hypervisor,
+                &self.drive_index,
+                vhost_user_store_path,
+                cid,
+                spec,
+            )
             .await
     }
 
@@ -228,11 +261,48 @@ impl ResourceManagerInner {
         cid: &str,
         linux_resources: Option<&LinuxResources>,
     ) -> Result<()> {
-        self.cgroups_resource
+        let result = self
+            .cgroups_resource
             .update_cgroups(cid, linux_resources, self.hypervisor.as_ref())
+            .await;
+        SandboxEvent::new(EVENT_SUBSYSTEM, "update_cgroups")
+            .details(resources_event_details(cid, linux_resources))
+            .result(&result)
+            .record();
+        result
+    }
+
+    pub async fn remove_cgroups(&self, cid: &str) -> Result<()> {
+        self.cgroups_resource
+            .remove_cgroups(cid, self.hypervisor.as_ref())
             .await
     }
 
+    pub async fn update_swap(
+        &self,
+        cid: &str,
+        linux_resources: Option<&LinuxResources>,
+    ) -> Result<()> {
+        let result = self
+            .swap_resource
+            .update_swap(
+                cid,
+                linux_resources,
+                self.hypervisor.as_ref(),
+                self.agent.as_ref(),
+            )
+            .await;
+        SandboxEvent::new(EVENT_SUBSYSTEM, "update_swap")
+            .details(resources_event_details(cid, linux_resources))
+            .result(&result)
+            .record();
+        result
+    }
+
+    pub async fn remove_swap(&self, cid: &str) {
+        self.swap_resource.remove_swap(cid).await
+    }
+
     pub async fn cleanup(&self) -> Result<()> {
         // clean up cgroup
         self.cgroups_resource
@@ -247,6 +317,8 @@ impl ResourceManagerInner {
                 .await
                 .context("failed to cleanup host path")?;
         }
+        // clean up swap files
+        self.swap_resource.cleanup().await;
         // TODO cleanup other resources
         Ok(())
     }
@@ -257,6 +329,34 @@ impl ResourceManagerInner {
     }
 }
 
+// The cpu and memory resources of a container, as recorded in the sandbox event log.
+fn resources_event_details(
+    cid: &str,
+    linux_resources: Option<&LinuxResources>,
+) -> Vec<(&'static str, String)> {
+    let mut details = vec![("container_id", cid.to_string())];
+    if let Some(cpu) = linux_resources.and_then(|r| r.cpu.as_ref()) {
+        if let Some(quota) = cpu.quota {
+            details.push(("cpu_quota", quota.to_string()));
+        }
+        if let Some(period) = cpu.period {
+            details.push(("cpu_period", period.to_string()));
+        }
+        if !cpu.cpus.is_empty() {
+            details.push(("cpus", cpu.cpus.clone()));
+        }
+    }
+    if let Some(memory) = linux_resources.and_then(|r| r.memory.as_ref()) {
+        if let Some(limit) = memory.limit {
+            details.push(("memory_limit", limit.to_string()));
+        }
+        if let Some(swap) = memory.swap {
+            details.push(("memory_swap", swap.to_string()));
+        }
+    }
+    details
+}
+
 #[async_trait]
 impl Persist for ResourceManagerInner {
     type State = ResourceState;
@@ -271,9 +371,13 @@ impl Persist for ResourceManagerInner {
             }
         }
         let cgroup_state = self.cgroups_resource.save().await?;
+        let drive_index_state = self.drive_index.save().await?;
+        let swap_state = self.swap_resource.save().await?;
         Ok(ResourceState {
             endpoint: endpoint_state,
             cgroup_state: Some(cgroup_state),
+            drive_index_state: Some(drive_index_state),
+            swap_state: Some(swap_state),
         })
     }
 
@@ -282,6 +386,18 @@ impl Persist for ResourceManagerInner {
         resource_args: Self::ConstructorArgs,
         resource_state: Self::State,
     ) -> Result<Self> {
+        // The devices plugged into the guest before the restart still hold their indexes.
+        let drive_index = match resource_state.drive_index_state {
+            Some(state) => DriveIndexAllocator::restore((), state).await?,
+            None => DriveIndexAllocator::new(&resource_args.config),
+        };
+        let swap_args = SwapArgs::new(
+            &resource_args.sid,
+            &resource_args.config,
+            drive_index.clone(),
+        );
+        let swap_resource =
+            SwapResource::restore(swap_args, resource_state.swap_state.unwrap_or_default()).await?;
         let args = CgroupArgs {
             sid: resource_args.sid.clone(),
             config: resource_args.config,
@@ -292,6 +408,7 @@ impl Persist for ResourceManagerInner {
             hypervisor: resource_args.hypervisor,
             network: None,
             share_fs: None,
+            drive_index,
             rootfs_resource: RootFsResource::new(),
             volume_resource: VolumeResource::new(),
             cgroups_resource: CgroupsResource::restore(
@@ -299,6 +416,7 @@ impl Persist for ResourceManagerInner {
                 resource_state.cgroup_state.unwrap_or_default(),
             )
             .await?,
+            swap_resource,
             toml_config: Arc::new(TomlConfig::default()),
         })
     }
@@ -14,6 +14,9 @@ mod vlan_endpoint;
 pub use vlan_endpoint::VlanEndpoint;
 mod macvlan_endpoint;
 pub use macvlan_endpoint::MacVlanEndpoint;
+mod vhost_user_endpoint;
+pub(crate) use vhost_user_endpoint::find_vhost_user_socket;
+pub use vhost_user_endpoint::VhostUserEndpoint;
 pub mod endpoint_persist;
 mod endpoints_test;
 
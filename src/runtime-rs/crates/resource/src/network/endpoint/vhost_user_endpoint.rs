@@ -0,0 +1,137 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    io::{self, Error},
+    path::Path,
+};
+
+use agent::IPAddress;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hypervisor::{
+    device::{VhostUserConfig, VhostUserType},
+    Device, Hypervisor,
+};
+
+use super::endpoint_persist::EndpointState;
+use super::Endpoint;
+use crate::network::utils;
+
+// The vhost-user socket of an interface is looked up by the IP addresses of the interface.
+const VHOST_USER_SOCKET_DIR_PREFIX: &str = "/tmp/vhostuser_";
+const VHOST_USER_SOCKET_NAME: &str = "vhu.sock";
+
+// VhostUserEndpoint is a network interface served by a vhost-user backend, e.g. OVS-DPDK or
+// VPP. The interface in the network namespace is a placeholder carrying the network
+// configuration, the packets go through the backend.
+#[derive(Debug)]
+pub struct VhostUserEndpoint {
+    iface_name: String,
+    hard_addr: String,
+    socket_path: String,
+}
+
+impl VhostUserEndpoint {
+    pub fn new(name: &str, hardware_addr: &[u8], socket_path: &str) -> Result<Self> {
+        Ok(Self {
+            iface_name: name.to_string(),
+            hard_addr: utils::get_mac_addr(hardware_addr).context("get mac addr")?,
+            socket_path: socket_path.to_string(),
+        })
+    }
+
+    fn get_vhost_user_config(&self) -> Result<VhostUserConfig> {
+        let guest_mac = utils::parse_mac(&self.hard_addr).ok_or_else(|| {
+            Error::new(
+                io::ErrorKind::InvalidData,
+                format!("hard_addr {}", &self.hard_addr),
+            )
+        })?;
+        Ok(VhostUserConfig {
+            id: self.iface_name.clone(),
+            socket_path: self.socket_path.clone(),
+            device_type: VhostUserType::Net,
+            guest_mac: Some(guest_mac),
+            index: 0,
+            num_queues: 0,
+            queue_size: 0,
+        })
+    }
+}
+
+#[async_trait]
+impl Endpoint for VhostUserEndpoint {
+    async fn name(&self) -> String {
+        self.iface_name.clone()
+    }
+
+    async fn hardware_addr(&self) -> String {
+        self.hard_addr.clone()
+    }
+
+    async fn attach(&self, h: &dyn Hypervisor) -> Result<()> {
+        let config = self
+            .get_vhost_user_config()
+            .context("get vhost-user config")?;
+        h.add_device(Device::VhostUser(config))
+            .await
+            .with_context(|| {
+                format!(
+                    "add vhost-user-net device {} with socket {}",
+                    &self.iface_name, &self.socket_path
+                )
+            })?;
+        Ok(())
+    }
+
+    // Nothing is created on the host for a vhost-user endpoint, the backend owns the socket.
+    async fn detach(&self, _h: &dyn Hypervisor) -> Result<()> {
+        Ok(())
+    }
+
+    async fn save(&self) -> Option<EndpointState> {
+        None
+    }
+}
+
+/// Find the vhost-user socket of the interface at `/tmp/vhostuser_<ip address>/vhu.sock`.
+pub(crate) fn find_vhost_user_socket(addresses: &[IPAddress]) -> Option<String> {
+    addresses
+        .iter()
+        .map(|addr| {
+            format!(
+                "{}{}/{}",
+                VHOST_USER_SOCKET_DIR_PREFIX, addr.address, VHOST_USER_SOCKET_NAME
+            )
+        })
+        .find(|path| Path::new(path).exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use agent::IPFamily;
+
+    use super::*;
+
+    #[test]
+    fn test_find_vhost_user_socket() {
+        let address = |a: &str| IPAddress {
+            family: IPFamily::V4,
+            address: a.to_string(),
+            mask: "24".to_string(),
+        };
+        assert_eq!(find_vhost_user_socket(&[]), None);
+        assert_eq!(find_vhost_user_socket(&[address("192.0.2.254")]), None);
+
+        let dir = format!("{}{}", VHOST_USER_SOCKET_DIR_PREFIX, "192.0.2.253");
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = format!("{}/{}", dir, VHOST_USER_SOCKET_NAME);
+        std::fs::write(&socket, b"").unwrap();
+        let found = find_vhost_user_socket(&[address("192.0.2.254"), address("192.0.2.253")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found, Some(socket));
+    }
+}
@@ -59,7 +59,10 @@ impl NetworkInfoFromLink {
     }
 }
 
-async fn handle_addresses(handle: &rtnetlink::Handle, attrs: &LinkAttrs) -> Result<Vec<IPAddress>> {
+pub(crate) async fn handle_addresses(
+    handle: &rtnetlink::Handle,
+    attrs: &LinkAttrs,
+) -> Result<Vec<IPAddress>> {
     let mut addr_msg_list = handle
         .address()
         .get()
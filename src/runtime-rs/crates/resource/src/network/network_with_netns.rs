@@ -19,10 +19,11 @@ use tokio::sync::RwLock;
 
 use super::{
     endpoint::{
-        Endpoint, IPVlanEndpoint, MacVlanEndpoint, PhysicalEndpoint, VethEndpoint, VlanEndpoint,
+        find_vhost_user_socket, Endpoint, IPVlanEndpoint, MacVlanEndpoint, PhysicalEndpoint,
+        VethEndpoint, VhostUserEndpoint, VlanEndpoint,
     },
     network_entity::NetworkEntity,
-    network_info::network_info_from_link::NetworkInfoFromLink,
+    network_info::network_info_from_link::{handle_addresses, NetworkInfoFromLink},
     utils::{link, netns},
     Network,
 };
@@ -33,6 +34,9 @@ pub struct NetworkWithNetNsConfig {
     pub network_model: String,
     pub netns_path: String,
     pub queues: usize,
+    // Interfaces are only handed to a vhost-user backend when the hypervisor can plug
+    // vhost-user devices.
+    pub vhost_user_supported: bool,
 }
 
 struct NetworkWithNetnsInner {
@@ -172,6 +176,15 @@ async fn create_endpoint(
         .unwrap();
     let attrs = link.attrs();
     let link_type = link.r#type();
+    let vhost_user_socket = if config.vhost_user_supported {
+        find_vhost_user_socket(
+            &handle_addresses(handle, attrs)
+                .await
+                .context("handle addresses")?,
+        )
+    } else {
+        None
+    };
     let endpoint: Arc<dyn Endpoint> = if is_physical_iface(&attrs.name)? {
         info!(
             sl!(),
@@ -182,6 +195,14 @@ async fn create_endpoint(
         let t = PhysicalEndpoint::new(&attrs.name, &attrs.hardware_addr)
             .context("new physical endpoint")?;
         Arc::new(t)
+    } else if let Some(socket_path) = vhost_user_socket {
+        info!(
+            sl!(),
+            "vhost-user network interface found: {} {}", &attrs.name, &socket_path
+        );
+        let t = VhostUserEndpoint::new(&attrs.name, &attrs.hardware_addr, &socket_path)
+            .context("new vhost-user endpoint")?;
+        Arc::new(t)
     } else {
         info!(
             sl!(),
@@ -8,8 +8,12 @@ use crate::network::EndpointState;
 use serde::{Deserialize, Serialize};
 
 use crate::cgroups::cgroup_persist::CgroupState;
+use crate::drive_index::DriveIndexState;
+use crate::swap::SwapState;
 #[derive(Serialize, Deserialize, Default)]
 pub struct ResourceState {
     pub endpoint: Vec<EndpointState>,
     pub cgroup_state: Option<CgroupState>,
+    pub drive_index_state: Option<DriveIndexState>,
+    pub swap_state: Option<SwapState>,
 }
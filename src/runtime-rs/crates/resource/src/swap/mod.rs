@@ -0,0 +1,499 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    process::Command,
+    sync::Arc,
+};
+
+use agent::{AddSwapRequest, Agent};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use hypervisor::{device::Device, get_virt_drive_name, BlockConfig, Hypervisor};
+use kata_types::{annotations::Annotation, config::TomlConfig};
+use oci::LinuxResources;
+use persist::sandbox_persist::Persist;
+use serde::{Deserialize, Serialize};
+use shim_interface::KATA_PATH;
+use tokio::sync::RwLock;
+
+use crate::drive_index::DriveIndexAllocator;
+
+const MKSWAP_PATH: &str = "/sbin/mkswap";
+const MIB_TO_BYTES_SHIFT: u64 = 20;
+// mkswap refuses areas smaller than 10 pages.
+const MIN_SWAP_PAGES: u64 = 10;
+
+/// Swap needed by a container.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct ContainerSwap {
+    /// Swap size in bytes.
+    size: u64,
+    /// The container has no memory limit, so swap as large as the sandbox memory is needed.
+    need_pod_swap: bool,
+}
+
+impl ContainerSwap {
+    fn new(linux_resources: Option<&LinuxResources>) -> Self {
+        let memory = match linux_resources.and_then(|r| r.memory.as_ref()) {
+            Some(memory) if memory.swappiness.unwrap_or_default() > 0 => memory,
+            _ => return Self::default(),
+        };
+
+        let limit = memory.limit.filter(|l| *l > 0).unwrap_or_default();
+        match memory.swap.unwrap_or_default() {
+            // swap is not limited, but no more than the memory limit is needed.
+            0 | -1 if limit == 0 => Self {
+                size: 0,
+                need_pod_swap: true,
+            },
+            0 | -1 => Self {
+                size: limit as u64,
+                need_pod_swap: false,
+            },
+            // memory.swap is the limit of memory plus swap.
+            swap if swap > limit => Self {
+                size: (swap - limit) as u64,
+                need_pod_swap: false,
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+/// A swap file plugged into the guest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SwapDevice {
+    path: PathBuf,
+    size: u64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct SwapInner {
+    containers: HashMap<String, ContainerSwap>,
+    devices: Vec<SwapDevice>,
+    /// Total size of the swap devices plugged into the guest.
+    size: u64,
+}
+
+impl SwapInner {
+    /// Swap needed by all the containers, in bytes.
+    fn needed_size(&self, default_memory: u64) -> u64 {
+        let mut size: u64 = self.containers.values().map(|s| s.size).sum();
+        if self.containers.values().any(|s| s.need_pod_swap) {
+            size += default_memory;
+        }
+        size
+    }
+}
+
+/// Guest swap, backed by swap files hot-plugged into the guest as block devices.
+///
+/// Swap is only added when a container asks for it with a positive swappiness, and it is never
+/// removed from the guest until the sandbox stops.
+pub struct SwapResource {
+    sid: String,
+    enable_guest_swap: bool,
+    /// Default memory of the sandbox in bytes.
+    default_memory: u64,
+    drive_index: DriveIndexAllocator,
+    inner: Arc<RwLock<SwapInner>>,
+}
+
+pub struct SwapArgs {
+    sid: String,
+    enable_guest_swap: bool,
+    default_memory: u64,
+    drive_index: DriveIndexAllocator,
+}
+
+impl SwapArgs {
+    pub(crate) fn new(
+        sid: &str,
+        toml_config: &TomlConfig,
+        drive_index: DriveIndexAllocator,
+    ) -> Self {
+        let (enable_guest_swap, default_memory) = toml_config
+            .hypervisor
+            .get(&toml_config.runtime.hypervisor_name)
+            .map(|h| {
+                (
+                    h.memory_info.enable_guest_swap,
+                    (h.memory_info.default_memory as u64) << MIB_TO_BYTES_SHIFT,
+                )
+            })
+            .unwrap_or_default();
+
+        Self {
+            sid: sid.to_string(),
+            enable_guest_swap,
+            default_memory,
+            drive_index,
+        }
+    }
+}
+
+impl SwapResource {
+    pub(crate) fn new(args: SwapArgs) -> Self {
+        Self {
+            sid: args.sid,
+            enable_guest_swap: args.enable_guest_swap,
+            default_memory: args.default_memory,
+            drive_index: args.drive_index,
+            inner: Arc::new(RwLock::new(SwapInner::default())),
+        }
+    }
+
+    /// Update the swap needed by the container, plug more swap into the guest if needed.
+    pub async fn update_swap(
+        &self,
+        cid: &str,
+        linux_resources: Option<&LinuxResources>,
+        h: &dyn Hypervisor,
+        agent: &dyn Agent,
+    ) -> Result<()> {
+        if !self.enable_guest_swap {
+            return Ok(());
+        }
+
+        let mut inner = self.inner.write().await;
+        inner
+            .containers
+            .insert(cid.to_string(), ContainerSwap::new(linux_resources));
+
+        let size = inner.needed_size(self.default_memory);
+        if size <= inner.size {
+            return Ok(());
+        }
+
+        let swap_size = size - inner.size;
+        self.add_swap(&mut inner, swap_size, h, agent)
+            .await
+            .context("add swap")?;
+        inner.size = size;
+
+        Ok(())
+    }
+
+    /// Forget the swap needed by the deleted container, the swap plugged for it is left to the
+    /// other containers.
+    pub async fn remove_swap(&self, cid: &str) {
+        self.inner.write().await.containers.remove(cid);
+    }
+
+    async fn add_swap(
+        &self,
+        inner: &mut SwapInner,
+        size: u64,
+        h: &dyn Hypervisor,
+        agent: &dyn Agent,
+    ) -> Result<()> {
+        let index = inner.devices.len();
+        let swap_id = format!("swap{}", index);
+        let path = [KATA_PATH, self.sid.as_str(), swap_id.as_str()]
+            .iter()
+            .collect::<PathBuf>();
+        let size = create_swap_file(&path, size).context("create swap file")?;
+
+        let path_on_host = path.to_string_lossy().to_string();
+        let device = SwapDevice { path, size };
+        let drive_index = self.drive_index.allocate();
+        let block = BlockConfig {
+            id: swap_id,
+            path_on_host,
+            is_readonly: false,
+            no_drop: false,
+            index: drive_index,
+        };
+        if let Err(e) = h.add_device(Device::Block(block)).await {
+            self.drive_index.release(drive_index);
+            remove_swap_file(&device);
+            return Err(e).context("hotplug swap device");
+        }
+
+        // The swap file can't be removed until the guest stops once it's plugged.
+        info!(
+            sl!(),
+            "add swap {} size {} to guest",
+            device.path.display(),
+            device.size
+        );
+        inner.devices.push(device);
+
+        let vm_path = get_virt_drive_name(drive_index);
+        agent
+            .add_swap(AddSwapRequest {
+                pci_path: vec![],
+                vm_path: vm_path.clone(),
+            })
+            .await
+            .with_context(|| format!("agent add swap {}", vm_path))?;
+
+        Ok(())
+    }
+
+    /// Remove the swap files, the guest must have been stopped.
+    pub async fn cleanup(&self) {
+        let mut inner = self.inner.write().await;
+        for device in inner.devices.drain(..) {
+            remove_swap_file(&device);
+        }
+        inner.size = 0;
+    }
+}
+
+/// Guest swap plugged into the guest, saved across runtime restarts.
+#[derive(Default, Serialize, Deserialize)]
+pub struct SwapState {
+    inner: SwapInner,
+}
+
+#[async_trait]
+impl Persist for SwapResource {
+    type State = SwapState;
+    type ConstructorArgs = SwapArgs;
+
+    async fn save(&self) -> Result<Self::State> {
+        Ok(SwapState {
+            inner: self.inner.read().await.clone(),
+        })
+    }
+
+    async fn restore(args: Self::ConstructorArgs, state: Self::State) -> Result<Self> {
+        let swap = Self::new(args);
+        *swap.inner.write().await = state.inner;
+        Ok(swap)
+    }
+}
+
+/// Apply the swap annotations of the container to its resources, as what the agent applies to
+/// the container cgroup must match the swap plugged into the guest.
+pub fn apply_swap_annotations(spec: &mut oci::Spec) -> Result<()> {
+    let annotation = Annotation::new(spec.annotations.clone());
+    let swappiness = annotation
+        .get_container_resource_swappiness()
+        .context("get swappiness annotation")?;
+    let swap = annotation
+        .get_container_resource_swap_in_bytes()
+        .map(|s| {
+            s.parse::<u64>()
+                .map_err(|e| anyhow!("invalid swap_in_bytes annotation {}: {}", s, e))
+        })
+        .transpose()?;
+    if swappiness.is_none() && swap.is_none() {
+        return Ok(());
+    }
+
+    let memory = spec
+        .linux
+        .get_or_insert_with(Default::default)
+        .resources
+        .get_or_insert_with(Default::default)
+        .memory
+        .get_or_insert_with(Default::default);
+    if let Some(swappiness) = swappiness {
+        memory.swappiness = Some(swappiness as u64);
+    }
+    if let Some(swap) = swap {
+        memory.swap = Some(swap as i64);
+    }
+
+    Ok(())
+}
+
+/// Create a swap file of at least `size` bytes, return the size of the swap file.
+fn create_swap_file(path: &PathBuf, size: u64) -> Result<u64> {
+    let page_size = nix::unistd::sysconf(nix::unistd::SysconfVar::PAGE_SIZE)?
+        .map(|s| s as u64)
+        .unwrap_or(4096);
+    // The first page of the swap file keeps the swap header.
+    let size = size.max(page_size * MIN_SWAP_PAGES) + page_size;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("create {}", path.display()))?;
+    let result = file
+        .set_len(size)
+        .with_context(|| format!("truncate {}", path.display()))
+        .and_then(|_| {
+            let output = Command::new(MKSWAP_PATH)
+                .arg(path)
+                .output()
+                .context("run mkswap")?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "mkswap {} failed: {}",
+                    path.display(),
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
+            Ok(size)
+        });
+    if result.is_err() {
+        fs::remove_file(path).ok();
+    }
+
+    result
+}
+
+fn remove_swap_file(device: &SwapDevice) {
+    if let Err(e) = fs::remove_file(&device.path) {
+        warn!(
+            sl!(),
+            "failed to remove swap file {}: {}",
+            device.path.display(),
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci::LinuxMemory;
+
+    fn resources(limit: Option<i64>, swap: Option<i64>, swappiness: u64) -> LinuxResources {
+        LinuxResources {
+            memory: Some(LinuxMemory {
+                limit,
+                swap,
+                swappiness: Some(swappiness),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_container_swap() {
+        let tests = [
+            (None, None, 0, ContainerSwap::default()),
+            (Some(1024), Some(4096), 0, ContainerSwap::default()),
+            (
+                None,
+                None,
+                60,
+                ContainerSwap {
+                    size: 0,
+                    need_pod_swap: true,
+                },
+            ),
+            (
+                Some(1024),
+                None,
+                60,
+                ContainerSwap {
+                    size: 1024,
+                    need_pod_swap: false,
+                },
+            ),
+            (
+                Some(1024),
+                Some(4096),
+                60,
+                ContainerSwap {
+                    size: 3072,
+                    need_pod_swap: false,
+                },
+            ),
+            (Some(1024), Some(1024), 60, ContainerSwap::default()),
+            (
+                None,
+                Some(-1),
+                60,
+                ContainerSwap {
+                    size: 0,
+                    need_pod_swap: true,
+                },
+            ),
+            (
+                Some(1024),
+                Some(-1),
+                60,
+                ContainerSwap {
+                    size: 1024,
+                    need_pod_swap: false,
+                },
+            ),
+        ];
+
+        assert_eq!(ContainerSwap::new(None), ContainerSwap::default());
+        for (limit, swap, swappiness, expected) in tests {
+            let r = resources(limit, swap, swappiness);
+            assert_eq!(ContainerSwap::new(Some(&r)), expected);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_remove_swap() {
+        let swap = SwapResource::new(SwapArgs {
+            sid: "test".to_string(),
+            enable_guest_swap: true,
+            default_memory: 4096,
+            drive_index: DriveIndexAllocator::default(),
+        });
+        {
+            let mut inner = swap.inner.write().await;
+            inner.containers.insert(
+                "c1".to_string(),
+                ContainerSwap {
+                    size: 0,
+                    need_pod_swap: true,
+                },
+            );
+            inner.containers.insert(
+                "c2".to_string(),
+                ContainerSwap {
+                    size: 1024,
+                    need_pod_swap: false,
+                },
+            );
+            assert_eq!(inner.needed_size(swap.default_memory), 5120);
+        }
+
+        swap.remove_swap("c1").await;
+        let inner = swap.inner.read().await;
+        assert!(!inner.containers.contains_key("c1"));
+        assert_eq!(inner.needed_size(swap.default_memory), 1024);
+    }
+
+    #[test]
+    fn test_apply_swap_annotations() {
+        let mut spec = oci::Spec::default();
+        apply_swap_annotations(&mut spec).unwrap();
+        assert!(spec.linux.is_none());
+
+        spec.annotations.insert(
+            kata_types::annotations::KATA_ANNO_CONTAINER_RES_SWAPPINESS.to_string(),
+            "60".to_string(),
+        );
+        spec.annotations.insert(
+            kata_types::annotations::KATA_ANNO_CONTAINER_RES_SWAP_IN_BYTES.to_string(),
+            "1048576".to_string(),
+        );
+        apply_swap_annotations(&mut spec).unwrap();
+        let memory = spec
+            .linux
+            .and_then(|l| l.resources)
+            .and_then(|r| r.memory)
+            .unwrap();
+        assert_eq!(memory.swappiness, Some(60));
+        assert_eq!(memory.swap, Some(1048576));
+
+        let mut spec = oci::Spec::default();
+        spec.annotations.insert(
+            kata_types::annotations::KATA_ANNO_CONTAINER_RES_SWAP_IN_BYTES.to_string(),
+            "invalid".to_string(),
+        );
+        assert!(apply_swap_annotations(&mut spec).is_err());
+    }
+}
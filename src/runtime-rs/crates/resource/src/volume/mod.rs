@@ -9,13 +9,15 @@ mod default_volume;
 pub mod hugepage;
 mod share_fs_volume;
 mod shm_volume;
+mod vhost_user_blk_volume;
 use async_trait::async_trait;
 
 use anyhow::{Context, Result};
+use hypervisor::Hypervisor;
 use std::{sync::Arc, vec::Vec};
 use tokio::sync::RwLock;
 
-use crate::share_fs::ShareFs;
+use crate::{drive_index::DriveIndexAllocator, share_fs::ShareFs};
 
 use self::hugepage::{get_huge_page_limits_map, get_huge_page_option};
 
@@ -43,9 +45,12 @@ impl VolumeResource {
         Self::default()
     }
 
-    pub async fn handler_volumes(
+    pub(crate) async fn handler_volumes(
         &self,
         share_fs: &Option<Arc<dyn ShareFs>>,
+        hypervisor: &Arc<dyn Hypervisor>,
+        drive_index: &DriveIndexAllocator,
+        vhost_user_store_path: &str,
         cid: &str,
         spec: &oci::Spec,
     ) -> Result<Vec<Arc<dyn Volume>>> {
@@ -59,6 +64,21 @@ impl VolumeResource {
                     shm_volume::ShmVolume::new(m, shm_size)
                         .with_context(|| format!("new shm volume {:?}", m))?,
                 )
+            } else if let Some(socket_path) =
+                vhost_user_blk_volume::get_vhost_user_blk_socket(m, vhost_user_store_path)
+                    .context("check vhost-user-blk volume")?
+            {
+                Arc::new(
+                    vhost_user_blk_volume::VhostUserBlkVolume::new(
+                        m,
+                        cid,
+                        socket_path,
+                        hypervisor,
+                        drive_index,
+                    )
+                    .await
+                    .with_context(|| format!("new vhost-user-blk volume {:?}", m))?,
+                )
             } else if share_fs_volume::is_share_fs_volume(m) {
                 Arc::new(
                     share_fs_volume::ShareFsVolume::new(share_fs, m, cid)
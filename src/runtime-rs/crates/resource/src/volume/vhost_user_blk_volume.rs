@@ -0,0 +1,209 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    fs,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use hypervisor::{
+    device::{Device, VhostUserConfig, VhostUserType},
+    get_virt_drive_name, Hypervisor,
+};
+
+use super::Volume;
+use crate::{
+    drive_index::DriveIndexAllocator, share_fs::DEFAULT_KATA_GUEST_SANDBOX_DIR,
+    volume::share_fs_volume::generate_mount_path,
+};
+
+/// Major number of the simulated block device nodes of vhost-user-blk devices, it's in the
+/// range reserved for local/experimental use by Linux.
+const VHOST_USER_BLK_MAJOR: u64 = 241;
+/// Sub-path of the vhost-user store where vhost-user-blk sockets live, the socket of a device
+/// has the same name as its simulated block device node.
+const VHOST_USER_BLK_SOCKETS_DIR: &str = "block/sockets";
+const DRIVER_MMIO_BLK_TYPE: &str = "mmioblk";
+const GUEST_STORAGE_DIR: &str = "storage";
+
+/// A block device volume served by a vhost-user-blk backend, e.g. SPDK.
+///
+/// The CSI plugin creates a simulated block device node with major number 241 under
+/// `<vhost_user_store_path>/block/devices/` as the source of the volume, and the backend listens
+/// on `<vhost_user_store_path>/block/sockets/<device node name>`.
+pub(crate) struct VhostUserBlkVolume {
+    hypervisor: Arc<dyn Hypervisor>,
+    drive_index: DriveIndexAllocator,
+    id: String,
+    socket_path: String,
+    index: u64,
+    storage: agent::Storage,
+    mount: oci::Mount,
+}
+
+impl VhostUserBlkVolume {
+    pub(crate) async fn new(
+        m: &oci::Mount,
+        cid: &str,
+        socket_path: PathBuf,
+        hypervisor: &Arc<dyn Hypervisor>,
+        drive_index: &DriveIndexAllocator,
+    ) -> Result<Self> {
+        let file_name = Path::new(&m.source)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow!("invalid volume source {}", m.source))?;
+        let id = format!("vhost-user-blk-{}", file_name);
+        let socket_path = socket_path.to_string_lossy().to_string();
+
+        let index = drive_index.allocate();
+        let config = vhost_user_blk_config(&id, &socket_path, index);
+        if let Err(e) = hypervisor.add_device(Device::VhostUser(config)).await {
+            drive_index.release(index);
+            return Err(e).with_context(|| format!("add vhost-user-blk device {}", socket_path));
+        }
+        info!(
+            sl!(),
+            "add vhost-user-blk device {} for volume {} of container {}",
+            socket_path,
+            m.destination,
+            cid
+        );
+
+        let mount_point = Path::new(DEFAULT_KATA_GUEST_SANDBOX_DIR)
+            .join(GUEST_STORAGE_DIR)
+            .join(generate_mount_path(cid, file_name))
+            .to_string_lossy()
+            .to_string();
+        // The device node is bind mounted into the container, as with other block device volumes.
+        let storage = agent::Storage {
+            driver: DRIVER_MMIO_BLK_TYPE.to_string(),
+            source: get_virt_drive_name(index),
+            fs_type: m.r#type.clone(),
+            options: m.options.clone(),
+            mount_point: mount_point.clone(),
+            ..Default::default()
+        };
+        let mount = oci::Mount {
+            destination: m.destination.clone(),
+            r#type: "bind".to_string(),
+            source: mount_point,
+            options: m.options.clone(),
+        };
+
+        Ok(Self {
+            hypervisor: hypervisor.clone(),
+            drive_index: drive_index.clone(),
+            id,
+            socket_path,
+            index,
+            storage,
+            mount,
+        })
+    }
+}
+
+#[async_trait]
+impl Volume for VhostUserBlkVolume {
+    fn get_volume_mount(&self) -> Result<Vec<oci::Mount>> {
+        Ok(vec![self.mount.clone()])
+    }
+
+    fn get_storage(&self) -> Result<Vec<agent::Storage>> {
+        Ok(vec![self.storage.clone()])
+    }
+
+    async fn cleanup(&self) -> Result<()> {
+        let config = vhost_user_blk_config(&self.id, &self.socket_path, self.index);
+        self.hypervisor
+            .remove_device(Device::VhostUser(config))
+            .await
+            .with_context(|| format!("remove vhost-user-blk device {}", self.socket_path))?;
+        self.drive_index.release(self.index);
+        Ok(())
+    }
+}
+
+fn vhost_user_blk_config(id: &str, socket_path: &str, index: u64) -> VhostUserConfig {
+    VhostUserConfig {
+        id: id.to_string(),
+        socket_path: socket_path.to_string(),
+        device_type: VhostUserType::Blk,
+        guest_mac: None,
+        index,
+        num_queues: 0,
+        queue_size: 0,
+    }
+}
+
+/// Get the vhost-user socket of the volume if its source is a simulated block device node of a
+/// vhost-user-blk device, `vhost_user_store_path` is empty if the vhost-user store is disabled.
+pub(crate) fn get_vhost_user_blk_socket(
+    m: &oci::Mount,
+    vhost_user_store_path: &str,
+) -> Result<Option<PathBuf>> {
+    if vhost_user_store_path.is_empty() {
+        return Ok(None);
+    }
+    let metadata = match fs::metadata(&m.source) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(None),
+    };
+    if !metadata.file_type().is_block_device()
+        || nix::sys::stat::major(metadata.rdev()) != VHOST_USER_BLK_MAJOR
+    {
+        return Ok(None);
+    }
+
+    let file_name = Path::new(&m.source)
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid volume source {}", m.source))?;
+    let socket_path = Path::new(vhost_user_store_path)
+        .join(VHOST_USER_BLK_SOCKETS_DIR)
+        .join(file_name);
+    if !socket_path.exists() {
+        return Err(anyhow!(
+            "vhost-user socket {} of volume {} not found",
+            socket_path.display(),
+            m.source
+        ));
+    }
+
+    Ok(Some(socket_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_vhost_user_blk_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("vhost-blk-0");
+        fs::write(&file, b"").unwrap();
+        let m = oci::Mount {
+            destination: "/data".to_string(),
+            r#type: "bind".to_string(),
+            source: file.to_string_lossy().to_string(),
+            options: vec!["rbind".to_string()],
+        };
+
+        // vhost-user store is disabled
+        assert!(get_vhost_user_blk_socket(&m, "").unwrap().is_none());
+        // not a block device
+        let store = dir.path().to_string_lossy().to_string();
+        assert!(get_vhost_user_blk_socket(&m, &store).unwrap().is_none());
+        // source doesn't exist
+        let mut missing = m.clone();
+        missing.source = dir.path().join("missing").to_string_lossy().to_string();
+        assert!(get_vhost_user_blk_socket(&missing, &store)
+            .unwrap()
+            .is_none());
+    }
+}
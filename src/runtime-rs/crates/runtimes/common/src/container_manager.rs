@@ -8,7 +8,7 @@ use anyhow::Result;
 use async_trait::async_trait;
 
 use crate::types::{
-    ContainerConfig, ContainerID, ContainerProcess, ExecProcessRequest, KillRequest,
+    ContainerConfig, ContainerID, ContainerInfo, ContainerProcess, ExecProcessRequest, KillRequest,
     ProcessExitStatus, ProcessStateInfo, ResizePTYRequest, ShutdownRequest, StatsInfo,
     UpdateRequest, PID,
 };
@@ -22,6 +22,7 @@ pub trait ContainerManager: Send + Sync {
     async fn stats_container(&self, container_id: &ContainerID) -> Result<StatsInfo>;
     async fn update_container(&self, req: UpdateRequest) -> Result<()>;
     async fn connect_container(&self, container_id: &ContainerID) -> Result<PID>;
+    async fn containers_info(&self) -> Result<Vec<ContainerInfo>>;
 
     // process lifecycle
     async fn close_process_io(&self, process_id: &ContainerProcess) -> Result<()>;
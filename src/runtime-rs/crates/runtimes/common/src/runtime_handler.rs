@@ -37,6 +37,7 @@ pub trait RuntimeHandler: Send + Sync {
         sid: &str,
         msg_sender: Sender<Message>,
         config: Arc<TomlConfig>,
+        spec: &oci::Spec,
     ) -> Result<RuntimeInstance>;
 
     fn cleanup(&self, id: &str) -> Result<()>;
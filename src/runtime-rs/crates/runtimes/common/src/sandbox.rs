@@ -6,6 +6,9 @@
 
 use anyhow::Result;
 use async_trait::async_trait;
+use tokio::sync::broadcast;
+
+use crate::types::SandboxInfo;
 
 #[async_trait]
 pub trait Sandbox: Send + Sync {
@@ -22,8 +25,16 @@ pub trait Sandbox: Send + Sync {
 
     // agent function
     async fn agent_sock(&self) -> Result<String>;
+    async fn agent_log(&self) -> Result<broadcast::Receiver<String>>;
+    // set the log level of a subsystem of the agent, or its default level if subsystem is empty
+    async fn set_agent_log_level(&self, subsystem: &str, level: &str) -> Result<()>;
+
+    // the runtime information of the sandbox, the containers are filled by the container manager
+    async fn info(&self) -> Result<SandboxInfo>;
 
     // utils
     async fn set_iptables(&self, is_ipv6: bool, data: Vec<u8>) -> Result<Vec<u8>>;
     async fn get_iptables(&self, is_ipv6: bool) -> Result<Vec<u8>>;
+    async fn agent_metrics(&self) -> Result<String>;
+    async fn hypervisor_metrics(&self) -> Result<String>;
 }
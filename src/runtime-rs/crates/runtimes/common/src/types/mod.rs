@@ -11,9 +11,12 @@ mod trans_into_shim;
 
 use std::fmt;
 
+use std::collections::BTreeMap;
+
 use anyhow::{Context, Result};
 use kata_sys_util::validate;
 use kata_types::mount::Mount;
+use serde::{Deserialize, Serialize};
 use strum::Display;
 
 /// Request: request from shim
@@ -235,3 +238,24 @@ pub struct UpdateRequest {
     pub container_id: String,
     pub value: Vec<u8>,
 }
+
+/// Runtime information of a running sandbox, served by the shim management server.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SandboxInfo {
+    pub sandbox_id: String,
+    pub shim_pid: u32,
+    pub hypervisor_pid: u32,
+    /// vCPU index to the thread ID on the host.
+    pub vcpu_threads: BTreeMap<u32, u32>,
+    pub agent_url: String,
+    pub containers: Vec<ContainerInfo>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContainerInfo {
+    pub container_id: String,
+    pub bundle: String,
+    pub status: String,
+    /// The IDs of the exec processes of the container.
+    pub exec_processes: Vec<String>,
+}
@@ -11,7 +11,7 @@ use std::{
 };
 
 use anyhow::{anyhow, Result};
-use containerd_shim_protos::api;
+use containerd_shim_protos::{api, events::task::TaskExit};
 
 use super::{ProcessExitStatus, ProcessStateInfo, ProcessStatus, Response};
 use crate::error::Error;
@@ -90,6 +90,25 @@ impl From<ProcessStateInfo> for api::DeleteResponse {
     }
 }
 
+impl From<ProcessStateInfo> for TaskExit {
+    fn from(from: ProcessStateInfo) -> Self {
+        // the init process of a container has no exec id
+        let id = if from.exec_id.is_empty() {
+            from.container_id.clone()
+        } else {
+            from.exec_id
+        };
+        Self {
+            container_id: from.container_id,
+            id,
+            pid: from.pid.pid,
+            exit_status: from.exit_status as u32,
+            exited_at: option_system_time_into(from.exited_at),
+            ..Default::default()
+        }
+    }
+}
+
 impl TryFrom<Response> for api::CreateTaskResponse {
     type Error = anyhow::Error;
     fn try_from(from: Response) -> Result<Self> {
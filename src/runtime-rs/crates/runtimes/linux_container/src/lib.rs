@@ -32,6 +32,7 @@ impl RuntimeHandler for LinuxContainer {
         _sid: &str,
         _msg_sender: Sender<Message>,
         _config: Arc<TomlConfig>,
+        _spec: &oci::Spec,
     ) -> Result<RuntimeInstance> {
         todo!()
     }
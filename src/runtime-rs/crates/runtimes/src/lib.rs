@@ -9,6 +9,9 @@ extern crate slog;
 
 logging::logger_with_subsystem!(sl, "runtimes");
 
+// subsystem of the runtime in the sandbox event log
+const EVENT_SUBSYSTEM: &str = "runtime";
+
 pub mod manager;
 pub use manager::RuntimeHandlerManager;
 pub use shim_interface;
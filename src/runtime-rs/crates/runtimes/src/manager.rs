@@ -8,21 +8,24 @@ use std::{str::from_utf8, sync::Arc};
 
 use anyhow::{anyhow, Context, Result};
 
-use crate::{shim_mgmt::server::MgmtServer, static_resource::StaticResourceManager};
+use crate::{
+    shim_mgmt::server::MgmtServer, static_resource::StaticResourceManager, EVENT_SUBSYSTEM,
+};
 use common::{
     message::Message,
-    types::{Request, Response},
+    types::{ContainerProcess, Request, Response},
     RuntimeHandler, RuntimeInstance, Sandbox,
 };
 use hypervisor::Param;
 use kata_types::{
     annotations::Annotation, config::default::DEFAULT_GUEST_DNS_FILE, config::TomlConfig,
+    container::ContainerType, k8s,
 };
 
 #[cfg(feature = "linux")]
 use linux_container::LinuxContainer;
 use persist::sandbox_persist::Persist;
-use shim_interface::shim_mgmt::ERR_NO_SHIM_SERVER;
+use shim_interface::{event_log::SandboxEvent, shim_mgmt::ERR_NO_SHIM_SERVER};
 use tokio::fs;
 use tokio::sync::{mpsc::Sender, RwLock};
 #[cfg(feature = "virt")]
@@ -70,16 +73,21 @@ impl RuntimeHandlerManagerInner {
             _ => return Err(anyhow!("Unsupported runtime: {}", &config.runtime.name)),
         };
         let runtime_instance = runtime_handler
-            .new_instance(&self.id, self.msg_sender.clone(), config)
+            .new_instance(&self.id, self.msg_sender.clone(), config.clone(), spec)
             .await
             .context("new runtime instance")?;
 
         // start sandbox
-        runtime_instance
+        let result = runtime_instance
             .sandbox
             .start(netns, dns, spec, state)
             .await
-            .context("start sandbox")?;
+            .context("start sandbox");
+        SandboxEvent::new(EVENT_SUBSYSTEM, "start_sandbox")
+            .detail("runtime", &config.runtime.name)
+            .result(&result)
+            .record();
+        result?;
         self.runtime_instance = Some(Arc::new(runtime_instance));
         Ok(())
     }
@@ -129,6 +137,12 @@ impl RuntimeHandlerManagerInner {
         }
 
         let config = load_config(spec, options).context("load config")?;
+
+        // the sandbox can run without its event log
+        if let Err(e) = shim_interface::event_log::init(&self.id, &config.runtime.event_log_dir) {
+            warn!(sl!(), "failed to init the event log: {:?}", e);
+        }
+
         self.init_runtime_handler(spec, state, netns, dns, Arc::new(config))
             .await
             .context("init runtime handler")?;
@@ -136,9 +150,11 @@ impl RuntimeHandlerManagerInner {
         // the sandbox creation can reach here only once and the sandbox is created
         // so we can safely create the shim management socket right now
         // the unwrap here is safe because the runtime handler is correctly created
+        let runtime_instance = self.runtime_instance.as_ref().unwrap();
         let shim_mgmt_svr = MgmtServer::new(
             &self.id,
-            self.runtime_instance.as_ref().unwrap().sandbox.clone(),
+            runtime_instance.sandbox.clone(),
+            runtime_instance.container_manager.clone(),
         )
         .context(ERR_NO_SHIM_SERVER)?;
 
@@ -223,6 +239,15 @@ impl RuntimeHandlerManager {
     }
 
     pub async fn handler_message(&self, req: Request) -> Result<Response> {
+        let (event, audited) = request_event(&req);
+        let resp = self.do_handler_message(req).await;
+        if audited || resp.is_err() {
+            event.result(&resp).record();
+        }
+        resp
+    }
+
+    async fn do_handler_message(&self, req: Request) -> Result<Response> {
         if let Request::CreateContainer(container_config) = req {
             // get oci spec
             let bundler_path = format!(
@@ -351,6 +376,63 @@ impl RuntimeHandlerManager {
     }
 }
 
+/// Event of a request in the sandbox event log, and whether the request is recorded when it
+/// succeeds: the requests changing the containers are always recorded, the others only when
+/// they fail.
+fn request_event(req: &Request) -> (SandboxEvent, bool) {
+    let container = |action: &str, container_id: &str| {
+        SandboxEvent::new(EVENT_SUBSYSTEM, action).detail("container_id", container_id)
+    };
+    let process = |action: &str, process: &ContainerProcess| {
+        container(action, &process.container_id.container_id).detail("exec_id", &process.exec_id)
+    };
+
+    match req {
+        Request::CreateContainer(req) => (
+            container("create_container", &req.container_id).detail("bundle", &req.bundle),
+            true,
+        ),
+        Request::CloseProcessIO(process_id) => (process("close_process_io", process_id), true),
+        Request::DeleteProcess(process_id) => (process("delete_process", process_id), true),
+        Request::ExecProcess(req) => (
+            process("exec_process", &req.process).detail("terminal", req.terminal),
+            true,
+        ),
+        Request::KillProcess(req) => (
+            process("kill_process", &req.process)
+                .detail("signal", req.signal)
+                .detail("all", req.all),
+            true,
+        ),
+        Request::WaitProcess(process_id) => (process("wait_process", process_id), false),
+        Request::StartProcess(process_id) => (process("start_process", process_id), true),
+        Request::StateProcess(process_id) => (process("state_process", process_id), false),
+        Request::ShutdownContainer(req) => (
+            container("shutdown_container", &req.container_id).detail("is_now", req.is_now),
+            true,
+        ),
+        Request::PauseContainer(container_id) => (
+            container("pause_container", &container_id.container_id),
+            true,
+        ),
+        Request::ResumeContainer(container_id) => (
+            container("resume_container", &container_id.container_id),
+            true,
+        ),
+        Request::ResizeProcessPTY(req) => (process("resize_process_pty", &req.process), false),
+        Request::StatsContainer(container_id) => (
+            container("stats_container", &container_id.container_id),
+            false,
+        ),
+        Request::UpdateContainer(req) => (container("update_container", &req.container_id), true),
+        Request::Pid => (SandboxEvent::new(EVENT_SUBSYSTEM, "pid"), false),
+        Request::ConnectContainer(container_id) => (
+            container("connect_container", &container_id.container_id),
+            false,
+        ),
+    }
+}
+
 /// Config override ordering(high to low):
 /// 1. podsandbox annotation
 /// 2. shimv2 create task option
@@ -379,6 +461,7 @@ fn load_config(spec: &oci::Spec, option: &Option<Vec<u8>>) -> Result<TomlConfig>
         TomlConfig::load_from_file(&config_path).context("load toml config")?;
     annotation.update_config_by_annotation(&mut toml_config)?;
     update_agent_kernel_params(&mut toml_config)?;
+    update_numa_cpus(spec, &mut toml_config);
 
     // validate configuration and return the error
     toml_config.validate()?;
@@ -404,6 +487,28 @@ fn load_config(spec: &oci::Spec, option: &Option<Vec<u8>>) -> Result<TomlConfig>
     Ok(toml_config)
 }
 
+// The guest NUMA topology mirrors the cpuset of the container, unless the host CPUs are set in
+// the annotations. The spec of a pod sandbox only holds the cpuset of the shared pool, and the
+// cpusets of its containers are unknown when the VM boots.
+fn update_numa_cpus(spec: &oci::Spec, config: &mut TomlConfig) {
+    if k8s::container_type(spec) != ContainerType::SingleContainer {
+        return;
+    }
+
+    let cpus = spec
+        .linux
+        .as_ref()
+        .and_then(|linux| linux.resources.as_ref())
+        .and_then(|resources| resources.cpu.as_ref())
+        .map(|cpu| cpu.cpus.clone())
+        .unwrap_or_default();
+    if let Some(h) = config.hypervisor.get_mut(&config.runtime.hypervisor_name) {
+        if h.cpu_info.enable_numa && h.cpu_info.numa_cpus.is_empty() {
+            h.cpu_info.numa_cpus = cpus;
+        }
+    }
+}
+
 // this update the agent-specfic kernel parameters into hypervisor's bootinfo
 // the agent inside the VM will read from file cmdline to get the params and function
 fn update_agent_kernel_params(config: &mut TomlConfig) -> Result<()> {
@@ -420,3 +525,58 @@ fn update_agent_kernel_params(config: &mut TomlConfig) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::types::{ContainerConfig, ContainerID, KillRequest};
+
+    #[test]
+    fn test_request_event() {
+        let (event, audited) = request_event(&Request::CreateContainer(ContainerConfig {
+            container_id: "c1".to_string(),
+            bundle: "/run/bundle".to_string(),
+            rootfs_mounts: vec![],
+            terminal: false,
+            options: None,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+        }));
+        assert!(audited);
+        assert_eq!(event.subsystem, EVENT_SUBSYSTEM);
+        assert_eq!(event.action, "create_container");
+        assert_eq!(event.details.get("container_id").unwrap(), "c1");
+        assert_eq!(event.details.get("bundle").unwrap(), "/run/bundle");
+
+        let (event, audited) = request_event(&Request::KillProcess(KillRequest {
+            process: ContainerProcess::new("c1", "e1").unwrap(),
+            signal: 9,
+            all: true,
+        }));
+        assert!(audited);
+        assert_eq!(event.action, "kill_process");
+        assert_eq!(event.details.get("container_id").unwrap(), "c1");
+        assert_eq!(event.details.get("exec_id").unwrap(), "e1");
+        assert_eq!(event.details.get("signal").unwrap(), "9");
+        assert_eq!(event.details.get("all").unwrap(), "true");
+
+        // the requests not changing the containers are recorded only when they fail
+        let (event, audited) = request_event(&Request::StateProcess(
+            ContainerProcess::new("c1", "").unwrap(),
+        ));
+        assert!(!audited);
+        assert_eq!(event.action, "state_process");
+        assert_eq!(event.details.get("exec_id").unwrap(), "");
+
+        let (event, audited) =
+            request_event(&Request::StatsContainer(ContainerID::new("c1").unwrap()));
+        assert!(!audited);
+        assert_eq!(event.action, "stats_container");
+
+        let (event, audited) = request_event(&Request::Pid);
+        assert!(!audited);
+        assert!(event.details.is_empty());
+        assert!(event.error.is_none());
+    }
+}
@@ -7,17 +7,25 @@
 // This defines the handlers corresponding to the url when a request is sent to destined url,
 // the handler function should be invoked, and the corresponding data will be in the response
 
-use anyhow::{anyhow, Result};
-use common::Sandbox;
-use hyper::{Body, Method, Request, Response, StatusCode};
-use std::sync::Arc;
+use agent::SetLogLevelRequest;
+use anyhow::{anyhow, Context, Result};
+use common::{ContainerManager, Sandbox};
+use hyper::{body::Bytes, Body, Method, Request, Response, StatusCode};
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::broadcast::error::RecvError;
 
-use shim_interface::shim_mgmt::{AGENT_URL, IP6_TABLE_URL, IP_TABLE_URL};
+use shim_interface::shim_mgmt::{
+    AGENT_LOG_LEVEL_URL, AGENT_LOG_URL, AGENT_URL, IP6_TABLE_URL, IP_TABLE_URL, LOG_LEVEL_URL,
+    METRICS_URL, SANDBOX_INFO_URL,
+};
+
+use super::metrics::shim_metrics;
 
 // main router for response, this works as a multiplexer on
 // http arrival which invokes the corresponding handler function
 pub(crate) async fn handler_mux(
     sandbox: Arc<dyn Sandbox>,
+    container_manager: Arc<dyn ContainerManager>,
     req: Request<Body>,
 ) -> Result<Response<Body>> {
     info!(
@@ -34,6 +42,15 @@ pub(crate) async fn handler_mux(
         (&Method::PUT, IP6_TABLE_URL) | (&Method::GET, IP6_TABLE_URL) => {
             ipv6_table_handler(sandbox, req).await
         }
+        (&Method::GET, METRICS_URL) => metrics_handler(sandbox, req).await,
+        (&Method::GET, SANDBOX_INFO_URL) => {
+            sandbox_info_handler(sandbox, container_manager, req).await
+        }
+        (&Method::GET, AGENT_LOG_URL) => agent_log_handler(sandbox, req).await,
+        (&Method::PUT, LOG_LEVEL_URL) | (&Method::GET, LOG_LEVEL_URL) => {
+            log_level_handler(req).await
+        }
+        (&Method::PUT, AGENT_LOG_LEVEL_URL) => agent_log_level_handler(sandbox, req).await,
         _ => Ok(not_found(req).await),
     }
 }
@@ -46,6 +63,25 @@ async fn not_found(_req: Request<Body>) -> Response<Body> {
         .unwrap()
 }
 
+// the error is returned to the client instead of dropping the connection, so that the
+// client can tell what's wrong, e.g. the error from the agent
+fn internal_error(msg: String) -> Response<Body> {
+    error!(sl!(), "{}", msg);
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::from(msg))
+        .unwrap()
+}
+
+// the request can't be served as is, e.g. its body is invalid
+fn bad_request(msg: String) -> Response<Body> {
+    warn!(sl!(), "{}", msg);
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from(msg))
+        .unwrap()
+}
+
 // returns the url for agent
 async fn agent_url_handler(
     sandbox: Arc<dyn Sandbox>,
@@ -58,6 +94,183 @@ async fn agent_url_handler(
     Ok(Response::new(Body::from(agent_sock)))
 }
 
+// returns the metrics of the shim, followed by those of the agent and the guest, and those of
+// the hypervisor
+async fn metrics_handler(sandbox: Arc<dyn Sandbox>, _req: Request<Body>) -> Result<Response<Body>> {
+    let mut metrics = match shim_metrics() {
+        Ok(metrics) => metrics,
+        Err(e) => {
+            return Ok(internal_error(format!(
+                "Failed to get shim metrics: {:?}",
+                e
+            )))
+        }
+    };
+    // the shim metrics are still useful when the agent is not responding
+    match sandbox.agent_metrics().await {
+        Ok(agent_metrics) => metrics.push_str(&agent_metrics),
+        Err(e) => warn!(sl!(), "failed to get agent metrics: {:?}", e),
+    }
+    match sandbox.hypervisor_metrics().await {
+        Ok(hypervisor_metrics) => metrics.push_str(&hypervisor_metrics),
+        Err(e) => warn!(sl!(), "failed to get hypervisor metrics: {:?}", e),
+    }
+
+    Response::builder()
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(Body::from(metrics))
+        .map_err(|e| anyhow!(e))
+}
+
+// returns the runtime information of the sandbox and its containers in json
+async fn sandbox_info_handler(
+    sandbox: Arc<dyn Sandbox>,
+    container_manager: Arc<dyn ContainerManager>,
+    _req: Request<Body>,
+) -> Result<Response<Body>> {
+    let mut info = match sandbox.info().await {
+        Ok(info) => info,
+        Err(e) => {
+            return Ok(internal_error(format!(
+                "Failed to get sandbox info: {:?}",
+                e
+            )))
+        }
+    };
+    info.containers = match container_manager.containers_info().await {
+        Ok(containers) => containers,
+        Err(e) => {
+            return Ok(internal_error(format!(
+                "Failed to get containers info: {:?}",
+                e
+            )))
+        }
+    };
+
+    let body = serde_json::to_vec(&info)?;
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .map_err(|e| anyhow!(e))
+}
+
+// streams the agent log lines from now on, until the client goes away
+async fn agent_log_handler(
+    sandbox: Arc<dyn Sandbox>,
+    _req: Request<Body>,
+) -> Result<Response<Body>> {
+    let mut receiver = match sandbox.agent_log().await {
+        Ok(receiver) => receiver,
+        Err(e) => return Ok(internal_error(format!("Failed to get agent log: {:?}", e))),
+    };
+
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        loop {
+            let line = match receiver.recv().await {
+                Ok(line) => line,
+                Err(RecvError::Lagged(n)) => format!("... {} lines of agent log dropped", n),
+                Err(RecvError::Closed) => break,
+            };
+            if sender.send_data(Bytes::from(line + "\n")).await.is_err() {
+                // the client is gone
+                break;
+            }
+        }
+    });
+
+    Ok(Response::new(body))
+}
+
+// parses the log level change in the body of the request, the level must be a valid one
+async fn parse_log_level_request(req: Request<Body>) -> Result<(SetLogLevelRequest, slog::Level)> {
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .context("read body")?;
+    let req: SetLogLevelRequest = serde_json::from_slice(&body).context("parse body")?;
+    let level = logging::level_name_to_slog_level(&req.level).map_err(|e| anyhow!(e))?;
+    Ok((req, level))
+}
+
+// the log crate filters the records of the dependencies, e.g. the built-in hypervisor, before
+// they reach the shim logger, let the most verbose level through
+fn update_stdlog_level(levels: &logging::LogLevels) {
+    let max_level = levels
+        .subsystems
+        .values()
+        .chain(std::iter::once(&levels.default))
+        .max_by_key(|level| level.as_usize())
+        .copied()
+        .unwrap_or(levels.default);
+    let filter = match max_level {
+        slog::Level::Trace => log::LevelFilter::Trace,
+        slog::Level::Debug => log::LevelFilter::Debug,
+        slog::Level::Info => log::LevelFilter::Info,
+        slog::Level::Warning => log::LevelFilter::Warn,
+        slog::Level::Error | slog::Level::Critical => log::LevelFilter::Error,
+    };
+    log::set_max_level(filter);
+}
+
+// returns the log levels of the shim in json, or changes the level of one of its subsystems
+async fn log_level_handler(req: Request<Body>) -> Result<Response<Body>> {
+    let is_put = req.method() == Method::PUT;
+    if is_put {
+        let (log_req, level) = match parse_log_level_request(req).await {
+            Ok(r) => r,
+            Err(e) => return Ok(bad_request(format!("Invalid log level request: {:?}", e))),
+        };
+        if let Err(e) = logging::set_log_level(&log_req.subsystem, level) {
+            return Ok(internal_error(format!("Failed to set log level: {}", e)));
+        }
+        info!(sl!(), "log level changed"; "log-subsystem" => &log_req.subsystem, "log-level" => &log_req.level);
+    }
+
+    let levels = match logging::get_process_log_levels() {
+        Some(levels) => levels,
+        None => return Ok(internal_error("No logger created".to_string())),
+    };
+    if is_put {
+        update_stdlog_level(&levels);
+    }
+
+    let level_name = |level: slog::Level| logging::slog_level_to_level_name(level).unwrap_or("");
+    let subsystems: BTreeMap<&str, &str> = levels
+        .subsystems
+        .iter()
+        .map(|(subsystem, level)| (subsystem.as_str(), level_name(*level)))
+        .collect();
+    let body = serde_json::to_vec(&serde_json::json!({
+        "default": level_name(levels.default),
+        "subsystems": subsystems,
+    }))?;
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .map_err(|e| anyhow!(e))
+}
+
+// changes the level of a subsystem of the agent
+async fn agent_log_level_handler(
+    sandbox: Arc<dyn Sandbox>,
+    req: Request<Body>,
+) -> Result<Response<Body>> {
+    let (log_req, _) = match parse_log_level_request(req).await {
+        Ok(r) => r,
+        Err(e) => return Ok(bad_request(format!("Invalid log level request: {:?}", e))),
+    };
+    match sandbox
+        .set_agent_log_level(&log_req.subsystem, &log_req.level)
+        .await
+    {
+        Ok(()) => Ok(Response::new(Body::empty())),
+        Err(e) => Ok(internal_error(format!(
+            "Failed to set agent log level: {:?}",
+            e
+        ))),
+    }
+}
+
 /// the ipv4 handler of iptable operation
 async fn ip_table_handler(sandbox: Arc<dyn Sandbox>, req: Request<Body>) -> Result<Response<Body>> {
     generic_ip_table_handler(sandbox, req, false).await
@@ -85,7 +298,7 @@ async fn generic_ip_table_handler(
                 let body = Body::from(data);
                 Response::builder().body(body).map_err(|e| anyhow!(e))
             }
-            _ => Err(anyhow!("Failed to get iptable")),
+            Err(e) => Ok(internal_error(format!("Failed to get iptable: {:?}", e))),
         },
 
         Method::PUT => {
@@ -94,7 +307,7 @@ async fn generic_ip_table_handler(
                 Ok(resp_data) => Response::builder()
                     .body(Body::from(resp_data))
                     .map_err(|e| anyhow!(e)),
-                _ => Err(anyhow!("Failed to set iptable")),
+                Err(e) => Ok(internal_error(format!("Failed to set iptable: {:?}", e))),
             }
         }
 
@@ -0,0 +1,186 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+// Metrics of the shim process in the Prometheus text format. As the hypervisor may run inside
+// the shim process, e.g. Dragonball, the memory and cpu usage include those of the VM.
+
+use std::fmt::Write;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{anyhow, Context, Result};
+
+const NAMESPACE_KATA_SHIM: &str = "kata_shim";
+
+const PROC_SELF_STAT: &str = "/proc/self/stat";
+const PROC_SELF_STATUS: &str = "/proc/self/status";
+const PROC_SELF_FD: &str = "/proc/self/fd";
+
+// Fields of /proc/self/stat reported, counted from the one after the command name, see proc(5).
+const PROC_STAT_ITEMS: &[(&str, usize)] = &[
+    ("utime", 11),
+    ("stime", 12),
+    ("cutime", 13),
+    ("cstime", 14),
+    ("num_threads", 17),
+    ("vsize", 20),
+    ("rss", 21),
+];
+
+static SCRAPE_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Gather the metrics of the shim process.
+pub(crate) fn shim_metrics() -> Result<String> {
+    let scrape_count = SCRAPE_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+    let mut out = String::new();
+
+    write_metric(
+        &mut out,
+        "scrape_count",
+        "Metrics scrape count",
+        "counter",
+        &[(None, scrape_count)],
+    );
+
+    let status = fs::read_to_string(PROC_SELF_STATUS)
+        .with_context(|| format!("read {}", PROC_SELF_STATUS))?;
+    let status = parse_proc_status(&status);
+    if let Some((_, threads)) = status.iter().find(|(k, _)| k == "threads") {
+        write_metric(
+            &mut out,
+            "threads",
+            "Shim process threads",
+            "gauge",
+            &[(None, *threads)],
+        );
+    }
+    let samples: Vec<_> = status
+        .iter()
+        .filter(|(k, _)| k != "threads")
+        .map(|(k, v)| (Some(("item", k.as_str())), *v))
+        .collect();
+    write_metric(
+        &mut out,
+        "proc_status",
+        "Shim process status, the sizes are in KiB",
+        "gauge",
+        &samples,
+    );
+
+    let stat =
+        fs::read_to_string(PROC_SELF_STAT).with_context(|| format!("read {}", PROC_SELF_STAT))?;
+    let stat = parse_proc_stat(&stat)?;
+    let samples: Vec<_> = stat.iter().map(|(k, v)| (Some(("item", *k)), *v)).collect();
+    write_metric(
+        &mut out,
+        "proc_stat",
+        "Shim process statistics",
+        "gauge",
+        &samples,
+    );
+
+    let fds = fs::read_dir(PROC_SELF_FD)
+        .with_context(|| format!("read dir {}", PROC_SELF_FD))?
+        .count() as u64;
+    write_metric(
+        &mut out,
+        "fds",
+        "Shim process open file descriptors",
+        "gauge",
+        &[(None, fds)],
+    );
+
+    Ok(out)
+}
+
+fn write_metric(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    metric_type: &str,
+    samples: &[(Option<(&str, &str)>, u64)],
+) {
+    let name = format!("{}_{}", NAMESPACE_KATA_SHIM, name);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+    for (label, value) in samples {
+        match label {
+            Some((k, v)) => {
+                let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, k, v, value);
+            }
+            None => {
+                let _ = writeln!(out, "{} {}", name, value);
+            }
+        }
+    }
+}
+
+// Get the numeric items of /proc/self/status, e.g. "VmRSS:  1024 kB" is ("vmrss", 1024).
+fn parse_proc_status(status: &str) -> Vec<(String, u64)> {
+    status
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            let value = value.split_whitespace().next()?.parse::<u64>().ok()?;
+            Some((key.trim().to_lowercase(), value))
+        })
+        .collect()
+}
+
+fn parse_proc_stat(stat: &str) -> Result<Vec<(&'static str, u64)>> {
+    // the command name may contain spaces and parentheses, skip to the last ')'
+    let fields: Vec<&str> = stat
+        .rfind(')')
+        .map(|pos| stat[pos + 1..].split_whitespace().collect())
+        .ok_or_else(|| anyhow!("invalid proc stat {:?}", stat))?;
+
+    PROC_STAT_ITEMS
+        .iter()
+        .map(|(name, index)| {
+            let value = fields
+                .get(*index)
+                .and_then(|v| v.parse::<u64>().ok())
+                .ok_or_else(|| anyhow!("invalid {} in proc stat", name))?;
+            Ok((*name, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc() {
+        let status = "Name:\tcontainerd-shim\nState:\tS (sleeping)\nVmRSS:\t    2048 kB\nThreads:\t12\nCpus_allowed:\tff\n";
+        assert_eq!(
+            parse_proc_status(status),
+            vec![("vmrss".to_string(), 2048), ("threads".to_string(), 12)]
+        );
+
+        let stat = "4242 (shim (v2)) S 1 4242 4242 0 -1 4194560 100 0 0 0 7 3 0 0 20 0 12 0 123 4096000 250 18446744073709551615";
+        assert_eq!(
+            parse_proc_stat(stat).unwrap(),
+            vec![
+                ("utime", 7),
+                ("stime", 3),
+                ("cutime", 0),
+                ("cstime", 0),
+                ("num_threads", 12),
+                ("vsize", 4096000),
+                ("rss", 250),
+            ]
+        );
+        assert!(parse_proc_stat("4242 (shim) S 1").is_err());
+    }
+
+    #[test]
+    fn test_shim_metrics() {
+        let metrics = shim_metrics().unwrap();
+        assert!(metrics.contains("# TYPE kata_shim_scrape_count counter"));
+        assert!(metrics.contains("kata_shim_proc_stat{item=\"utime\"}"));
+        assert!(metrics.contains("kata_shim_threads "));
+    }
+}
@@ -14,7 +14,7 @@
 use std::{fs, path::Path, sync::Arc};
 
 use anyhow::{Context, Result};
-use common::Sandbox;
+use common::{ContainerManager, Sandbox};
 use hyper::{server::conn::Http, service::service_fn};
 use shim_interface::{mgmt_socket_addr, shim_mgmt::ERR_NO_SHIM_SERVER};
 use tokio::net::UnixListener;
@@ -28,20 +28,26 @@ pub struct MgmtServer {
 
     /// The sandbox instance
     pub sandbox: Arc<dyn Sandbox>,
+
+    /// The container manager of the sandbox
+    pub container_manager: Arc<dyn ContainerManager>,
 }
 
 impl MgmtServer {
     /// construct a new management server
-    pub fn new(sid: &str, sandbox: Arc<dyn Sandbox>) -> Result<Self> {
+    pub fn new(
+        sid: &str,
+        sandbox: Arc<dyn Sandbox>,
+        container_manager: Arc<dyn ContainerManager>,
+    ) -> Result<Self> {
         Ok(Self {
             s_addr: mgmt_socket_addr(sid).context(ERR_NO_SHIM_SERVER)?,
             sandbox,
+            container_manager,
         })
     }
 
     // TODO(when metrics is supported): write metric addresses to fs
-    // TODO(when metrics is supported): register shim metrics
-    // TODO(when metrics is supported): register sandbox metrics
     // running management http server in an infinite loop, able to serve concurrent requests
     pub async fn run(self: Arc<Self>) {
         let listener = listener_from_path(self.s_addr.clone()).await.unwrap();
@@ -54,7 +60,9 @@ impl MgmtServer {
                 if let Err(err) = Http::new()
                     .serve_connection(
                         stream,
-                        service_fn(|request| handler_mux(me.sandbox.clone(), request)),
+                        service_fn(|request| {
+                            handler_mux(me.sandbox.clone(), me.container_manager.clone(), request)
+                        }),
                     )
                     .await
                 {
@@ -12,14 +12,15 @@ use anyhow::{anyhow, Context, Result};
 use common::{
     error::Error,
     types::{
-        ContainerConfig, ContainerID, ContainerProcess, ProcessStateInfo, ProcessStatus,
-        ProcessType,
+        ContainerConfig, ContainerID, ContainerInfo, ContainerProcess, ProcessStateInfo,
+        ProcessStatus, ProcessType,
     },
 };
 use kata_sys_util::k8s::update_ephemeral_storage_type;
+use kata_types::k8s::container_type;
 
 use oci::{LinuxResources, Process as OCIProcess};
-use resource::ResourceManager;
+use resource::{swap::apply_swap_annotations, ResourceManager};
 use tokio::sync::RwLock;
 
 use super::{
@@ -87,7 +88,9 @@ impl Container {
         let toml_config = self.resource_manager.config().await;
         let config = &self.config;
         let sandbox_pidns = is_pid_namespace_enabled(&spec);
+        let is_pod_sandbox = container_type(&spec).is_pod_sandbox();
         amend_spec(&mut spec, toml_config.runtime.disable_guest_seccomp).context("amend spec")?;
+        apply_swap_annotations(&mut spec).context("apply swap annotations")?;
 
         // get mutable root from oci spec
         let mut root = match spec.root.as_mut() {
@@ -142,15 +145,23 @@ impl Container {
 
         // TODO: handler device
 
-        // update cgroups
+        // update cgroups, the pod sandbox container only runs the pause process and never sets
+        // limits, it's not counted so that it doesn't make the sandbox limits unlimited.
+        let linux_resources = spec
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.resources.as_ref());
+        if !is_pod_sandbox {
+            self.resource_manager
+                .update_cgroups(&config.container_id, linux_resources)
+                .await?;
+        }
+
+        // update guest swap
         self.resource_manager
-            .update_cgroups(
-                &config.container_id,
-                spec.linux
-                    .as_ref()
-                    .and_then(|linux| linux.resources.as_ref()),
-            )
-            .await?;
+            .update_swap(&config.container_id, linux_resources)
+            .await
+            .context("update swap")?;
 
         // create container
         let r = agent::CreateContainerRequest {
@@ -373,6 +384,10 @@ impl Container {
         self.resource_manager
             .update_cgroups(&self.config.container_id, Some(resources))
             .await?;
+        self.resource_manager
+            .update_swap(&self.config.container_id, Some(resources))
+            .await
+            .context("update swap")?;
 
         let req = agent::UpdateContainerRequest {
             container_id: self.container_id.container_id.clone(),
@@ -393,6 +408,19 @@ impl Container {
     pub async fn spec(&self) -> oci::Spec {
         self.spec.clone()
     }
+
+    pub async fn info(&self) -> Result<ContainerInfo> {
+        let inner = self.inner.read().await;
+        let state = inner.init_process.state().await?;
+        let mut exec_processes: Vec<String> = inner.exec_processes.keys().cloned().collect();
+        exec_processes.sort();
+        Ok(ContainerInfo {
+            container_id: self.container_id.container_id.clone(),
+            bundle: state.bundle,
+            status: format!("{:?}", state.status),
+            exec_processes,
+        })
+    }
 }
 
 fn amend_spec(spec: &mut oci::Spec, disable_guest_seccomp: bool) -> Result<()> {
@@ -12,9 +12,9 @@ use agent::Agent;
 use common::{
     error::Error,
     types::{
-        ContainerConfig, ContainerID, ContainerProcess, ExecProcessRequest, KillRequest,
-        ProcessExitStatus, ProcessStateInfo, ProcessType, ResizePTYRequest, ShutdownRequest,
-        StatsInfo, UpdateRequest, PID,
+        ContainerConfig, ContainerID, ContainerInfo, ContainerProcess, ExecProcessRequest,
+        KillRequest, ProcessExitStatus, ProcessStateInfo, ProcessType, ResizePTYRequest,
+        ShutdownRequest, StatsInfo, UpdateRequest, PID,
     },
     ContainerManager,
 };
@@ -119,6 +119,16 @@ impl ContainerManager for VirtContainerManager {
                     .remove(container_id)
                     .ok_or_else(|| Error::ContainerNotFound(container_id.to_string()))?;
 
+                // The sandbox cgroups limits are shrunk once the container is gone, failing to do
+                // so only leaves the sandbox less constrained.
+                if let Err(e) = self.resource_manager.remove_cgroups(container_id).await {
+                    warn!(
+                        sl!(),
+                        "failed to remove cgroups of container {}: {:?}", container_id, e
+                    );
+                }
+                self.resource_manager.remove_swap(container_id).await;
+
                 // Poststop Hooks:
                 // * should be run in runtime namespace
                 // * should be run after the container is deleted but before delete operation returns
@@ -333,6 +343,20 @@ impl ContainerManager for VirtContainerManager {
         Ok(PID { pid: self.pid })
     }
 
+    async fn containers_info(&self) -> Result<Vec<ContainerInfo>> {
+        let containers = self.containers.read().await;
+        let mut infos = Vec::with_capacity(containers.len());
+        for (id, c) in containers.iter() {
+            infos.push(
+                c.info()
+                    .await
+                    .with_context(|| format!("info of container {}", id))?,
+            );
+        }
+        infos.sort_by(|a, b| a.container_id.cmp(&b.container_id));
+        Ok(infos)
+    }
+
     async fn need_shutdown_sandbox(&self, req: &ShutdownRequest) -> bool {
         req.is_now || self.containers.read().await.is_empty() || self.sid == req.container_id
     }
@@ -4,55 +4,127 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 use agent::Agent;
-use anyhow::Context;
+use anyhow::{anyhow, Context, Result};
+use hypervisor::Hypervisor;
+use kata_types::config::{default, Runtime};
+use nix::sys::signal::kill;
+use nix::unistd::Pid;
 use tokio::sync::{mpsc, Mutex};
 
-/// monitor check interval 30s
-const HEALTH_CHECK_TIMER_INTERVAL: u64 = 30;
-
 /// version check threshold 5min
-const VERSION_CHECK_THRESHOLD: u64 = 5 * 60 / HEALTH_CHECK_TIMER_INTERVAL;
+const VERSION_CHECK_INTERVAL_SECS: u64 = 5 * 60;
 
 /// health check stop channel buffer size
 const HEALTH_CHECK_STOP_CHANNEL_BUFFER_SIZE: usize = 1;
 
+/// When and how often the sandbox is checked, and how many failures it takes to consider it dead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthCheckPolicy {
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub failure_threshold: u32,
+    pub backoff: Duration,
+}
+
+impl Default for HealthCheckPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(default::DEFAULT_HEALTH_CHECK_INTERVAL_SECS as u64),
+            timeout: Duration::from_millis(default::DEFAULT_HEALTH_CHECK_TIMEOUT_MS as u64),
+            failure_threshold: default::DEFAULT_HEALTH_CHECK_FAILURE_THRESHOLD,
+            backoff: Duration::from_millis(default::DEFAULT_HEALTH_CHECK_BACKOFF_MS as u64),
+        }
+    }
+}
+
+impl HealthCheckPolicy {
+    /// The policy of the runtime configuration, with the defaults for the unset values.
+    pub fn new(config: &Runtime) -> Self {
+        let default = Self::default();
+        let non_zero = |v: u32| if v == 0 { None } else { Some(v as u64) };
+
+        Self {
+            interval: non_zero(config.health_check_interval_secs)
+                .map_or(default.interval, Duration::from_secs),
+            timeout: non_zero(config.health_check_timeout_ms)
+                .map_or(default.timeout, Duration::from_millis),
+            failure_threshold: non_zero(config.health_check_failure_threshold)
+                .map_or(default.failure_threshold, |v| v as u32),
+            backoff: non_zero(config.health_check_backoff_ms)
+                .map_or(default.backoff, Duration::from_millis),
+        }
+    }
+
+    /// Delay before the next check after the given number of consecutive failures: the backoff
+    /// doubled on each failure, up to the interval.
+    fn retry_delay(&self, failures: u32) -> Duration {
+        if failures == 0 {
+            return self.interval;
+        }
+
+        let factor = 1u32.checked_shl(failures - 1).unwrap_or(u32::MAX);
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.interval, |d| d.min(self.interval))
+    }
+
+    fn version_check_threshold(&self) -> u64 {
+        (VERSION_CHECK_INTERVAL_SECS / self.interval.as_secs().max(1)).max(1)
+    }
+}
+
 pub struct HealthCheck {
     pub keep_alive: bool,
     keep_vm: bool,
+    policy: HealthCheckPolicy,
     stop_tx: mpsc::Sender<()>,
     stop_rx: Arc<Mutex<mpsc::Receiver<()>>>,
 }
 
 impl HealthCheck {
-    pub fn new(keep_alive: bool, keep_vm: bool) -> HealthCheck {
+    pub fn new(keep_alive: bool, keep_vm: bool, policy: HealthCheckPolicy) -> HealthCheck {
         let (tx, rx) = mpsc::channel(HEALTH_CHECK_STOP_CHANNEL_BUFFER_SIZE);
         HealthCheck {
             keep_alive,
             keep_vm,
+            policy,
             stop_tx: tx,
             stop_rx: Arc::new(Mutex::new(rx)),
         }
     }
 
-    pub fn start(&self, id: &str, agent: Arc<dyn Agent>) {
+    /// Check the agent and the hypervisor processes periodically. `on_unhealthy` is run once the
+    /// failure threshold is reached, unless the VM is kept alive.
+    pub fn start<F>(
+        &self,
+        id: &str,
+        agent: Arc<dyn Agent>,
+        hypervisor: Arc<dyn Hypervisor>,
+        on_unhealthy: F,
+    ) where
+        F: Future<Output = ()> + Send + 'static,
+    {
         if !self.keep_alive {
             return;
         }
         let id = id.to_string();
 
-        info!(sl!(), "start runtime keep alive");
+        info!(sl!(), "start runtime keep alive"; "policy" => format!("{:?}", self.policy));
 
         let stop_rx = self.stop_rx.clone();
         let keep_vm = self.keep_vm;
+        let policy = self.policy.clone();
         let _ = tokio::spawn(async move {
             let mut version_check_threshold_count = 0;
+            let mut failures = 0;
 
             loop {
-                tokio::time::sleep(std::time::Duration::from_secs(HEALTH_CHECK_TIMER_INTERVAL))
-                    .await;
+                tokio::time::sleep(policy.retry_delay(failures)).await;
                 let mut stop_rx = stop_rx.lock().await;
                 match stop_rx.try_recv() {
                     Ok(_) => {
@@ -61,16 +133,20 @@ impl HealthCheck {
                     }
 
                     Err(mpsc::error::TryRecvError::Empty) => {
-                        // check agent
-                        match agent
-                            .check(agent::CheckRequest::new(""))
-                            .await
-                            .context("check health")
-                        {
+                        let result = tokio::time::timeout(
+                            policy.timeout,
+                            check(agent.as_ref(), hypervisor.as_ref()),
+                        )
+                        .await
+                        .unwrap_or_else(|_| Err(anyhow!("timed out after {:?}", policy.timeout)));
+
+                        match result {
                             Ok(_) => {
                                 debug!(sl!(), "check {} agent health successfully", id);
+                                failures = 0;
                                 version_check_threshold_count += 1;
-                                if version_check_threshold_count >= VERSION_CHECK_THRESHOLD {
+                                if version_check_threshold_count >= policy.version_check_threshold()
+                                {
                                     // need to check version
                                     version_check_threshold_count = 0;
                                     if let Ok(v) = agent
@@ -84,16 +160,32 @@ impl HealthCheck {
                                 continue;
                             }
                             Err(e) => {
-                                error!(sl!(), "failed to do {} agent health check: {}", id, e);
+                                failures += 1;
+                                error!(
+                                    sl!(),
+                                    "failed to do {} health check ({}/{}): {:?}",
+                                    id,
+                                    failures,
+                                    policy.failure_threshold,
+                                    e
+                                );
+                                if failures < policy.failure_threshold {
+                                    continue;
+                                }
+
                                 if let Err(mpsc::error::TryRecvError::Empty) = stop_rx.try_recv() {
-                                    error!(sl!(), "failed to receive stop monitor signal");
-                                    if !keep_vm {
-                                        ::std::process::exit(1);
+                                    if keep_vm {
+                                        warn!(sl!(), "sandbox {} is unhealthy, keep the vm", id);
+                                        failures = 0;
+                                        continue;
                                     }
+
+                                    error!(sl!(), "sandbox {} is unhealthy, shut it down", id);
+                                    on_unhealthy.await;
                                 } else {
                                     info!(sl!(), "wait to exit {}", id);
-                                    break;
                                 }
+                                break;
                             }
                         }
                     }
@@ -121,3 +213,59 @@ impl HealthCheck {
             .ok();
     }
 }
+
+// The hypervisor processes must be alive, the hypervisor must find the guest healthy, and the
+// agent must answer.
+async fn check(agent: &dyn Agent, hypervisor: &dyn Hypervisor) -> Result<()> {
+    let pids = hypervisor.get_pids().await.context("get hypervisor pids")?;
+    // Threads of the hypervisor may come and go, it's alive as long as one of them is.
+    if !pids
+        .iter()
+        .any(|pid| kill(Pid::from_raw(*pid as i32), None).is_ok())
+    {
+        return Err(anyhow!("hypervisor processes {:?} are gone", pids));
+    }
+    // e.g. the guest watchdog expired
+    hypervisor.check().await.context("check hypervisor")?;
+
+    agent
+        .check(agent::CheckRequest::new(""))
+        .await
+        .context("check agent health")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_check_policy() {
+        let config = Runtime {
+            health_check_interval_secs: 10,
+            health_check_failure_threshold: 4,
+            health_check_backoff_ms: 3_000,
+            ..Default::default()
+        };
+        let policy = HealthCheckPolicy::new(&config);
+        assert_eq!(policy.interval, Duration::from_secs(10));
+        assert_eq!(
+            policy.timeout,
+            Duration::from_millis(default::DEFAULT_HEALTH_CHECK_TIMEOUT_MS as u64)
+        );
+        assert_eq!(policy.failure_threshold, 4);
+        assert_eq!(policy.version_check_threshold(), 30);
+
+        assert_eq!(policy.retry_delay(0), Duration::from_secs(10));
+        assert_eq!(policy.retry_delay(1), Duration::from_secs(3));
+        assert_eq!(policy.retry_delay(2), Duration::from_secs(6));
+        // capped at the interval
+        assert_eq!(policy.retry_delay(3), Duration::from_secs(10));
+        assert_eq!(policy.retry_delay(64), Duration::from_secs(10));
+
+        assert_eq!(
+            HealthCheckPolicy::new(&Runtime::default()),
+            HealthCheckPolicy::default()
+        );
+    }
+}
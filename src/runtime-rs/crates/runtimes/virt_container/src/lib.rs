@@ -69,6 +69,7 @@ impl RuntimeHandler for VirtContainer {
         sid: &str,
         msg_sender: Sender<Message>,
         config: Arc<TomlConfig>,
+        spec: &oci::Spec,
     ) -> Result<RuntimeInstance> {
         let hypervisor = new_hypervisor(&config).await.context("new hypervisor")?;
 
@@ -79,28 +80,30 @@ impl RuntimeHandler for VirtContainer {
             agent.clone(),
             hypervisor.clone(),
             config,
+            spec,
         )?);
         let pid = std::process::id();
 
-        let sandbox = sandbox::VirtSandbox::new(
+        let container_manager = Arc::new(container_manager::VirtContainerManager::new(
             sid,
-            msg_sender,
+            pid,
             agent.clone(),
             hypervisor.clone(),
             resource_manager.clone(),
-        )
-        .await
-        .context("new virt sandbox")?;
-        let container_manager = container_manager::VirtContainerManager::new(
+        ));
+        let sandbox = sandbox::VirtSandbox::new(
             sid,
-            pid,
+            msg_sender,
             agent,
             hypervisor,
             resource_manager,
-        );
+            container_manager.clone(),
+        )
+        .await
+        .context("new virt sandbox")?;
         Ok(RuntimeInstance {
             sandbox: Arc::new(sandbox),
-            container_manager: Arc::new(container_manager),
+            container_manager,
         })
     }
 
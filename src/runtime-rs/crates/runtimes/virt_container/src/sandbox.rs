@@ -8,14 +8,16 @@ use std::sync::Arc;
 
 use agent::{
     self, kata::KataAgent, types::KernelModule, Agent, GetIPTablesRequest, SetIPTablesRequest,
+    SetLogLevelRequest,
 };
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use common::{
     message::{Action, Message},
-    Sandbox,
+    types::{ContainerProcess, SandboxInfo},
+    ContainerManager, Sandbox,
 };
-use containerd_shim_protos::events::task::TaskOOM;
+use containerd_shim_protos::events::task::{TaskExit, TaskOOM};
 use hypervisor::{dragonball::Dragonball, Hypervisor, HYPERVISOR_DRAGONBALL};
 use kata_sys_util::hooks::HookStates;
 use kata_types::config::{
@@ -27,12 +29,18 @@ use resource::{
     network::{NetworkConfig, NetworkWithNetNsConfig},
     ResourceConfig, ResourceManager,
 };
-use tokio::sync::{mpsc::Sender, Mutex, RwLock};
+use shim_interface::KATA_PATH;
+use tokio::sync::{broadcast, mpsc::Sender, Mutex, RwLock};
 
-use crate::health_check::HealthCheck;
+use crate::container_manager::VirtContainerManager;
+use crate::health_check::{HealthCheck, HealthCheckPolicy};
 use persist::{self, sandbox_persist::Persist};
 
 pub(crate) const VIRTCONTAINER: &str = "virt_container";
+
+/// Exit status reported for the containers of a sandbox which died.
+const UNHEALTHY_SANDBOX_EXIT_STATUS: i32 = 255;
+
 pub struct SandboxRestoreArgs {
     pub sid: String,
     pub toml_config: TomlConfig,
@@ -67,6 +75,9 @@ pub struct VirtSandbox {
     agent: Arc<dyn Agent>,
     hypervisor: Arc<dyn Hypervisor>,
     monitor: Arc<HealthCheck>,
+    container_manager: Option<Arc<dyn ContainerManager>>,
+    // The directory the state of the sandbox is saved under.
+    state_root: String,
 }
 
 impl VirtSandbox {
@@ -76,7 +87,10 @@ impl VirtSandbox {
         agent: Arc<dyn Agent>,
         hypervisor: Arc<dyn Hypervisor>,
         resource_manager: Arc<ResourceManager>,
+        container_manager: Arc<dyn ContainerManager>,
     ) -> Result<Self> {
+        let config = resource_manager.config().await;
+        let policy = HealthCheckPolicy::new(&config.runtime);
         Ok(Self {
             sid: sid.to_string(),
             msg_sender: Arc::new(Mutex::new(msg_sender)),
@@ -84,10 +98,67 @@ impl VirtSandbox {
             agent,
             hypervisor,
             resource_manager,
-            monitor: Arc::new(HealthCheck::new(true, false)),
+            monitor: Arc::new(HealthCheck::new(true, false, policy)),
+            container_manager: Some(container_manager),
+            state_root: KATA_PATH.to_string(),
         })
     }
 
+    /// Report all the processes as exited, as they are gone with the VM, then shut the sandbox
+    /// down so that containerd doesn't keep stale tasks around.
+    async fn handle_unhealthy(&self) {
+        if let Some(container_manager) = self.container_manager.as_ref() {
+            if let Err(err) = self.send_exit_events(container_manager.as_ref()).await {
+                error!(sl!(), "failed to send exit events: {:?}", err);
+            }
+        }
+
+        if let Err(err) = self.shutdown().await {
+            error!(sl!(), "failed to shutdown unhealthy sandbox: {:?}", err);
+        }
+    }
+
+    async fn send_exit_events(&self, container_manager: &dyn ContainerManager) -> Result<()> {
+        let containers = container_manager
+            .containers_info()
+            .await
+            .context("get containers")?;
+        for c in containers {
+            // The exec processes exit before the init process, as they would in the guest.
+            for exec_id in c.exec_processes.iter() {
+                let process =
+                    ContainerProcess::new(&c.container_id, exec_id).context("new exec process")?;
+                self.send_exit_event(container_manager, &process).await?;
+            }
+
+            let process =
+                ContainerProcess::new(&c.container_id, "").context("new container process")?;
+            self.send_exit_event(container_manager, &process).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_exit_event(
+        &self,
+        container_manager: &dyn ContainerManager,
+        process: &ContainerProcess,
+    ) -> Result<()> {
+        let mut state = container_manager
+            .state_process(process)
+            .await
+            .with_context(|| format!("state of process {:?}", process))?;
+        if state.exited_at.is_none() {
+            state.exit_status = UNHEALTHY_SANDBOX_EXIT_STATUS;
+            state.exited_at = Some(std::time::SystemTime::now());
+        }
+
+        warn!(sl!(), "send exit event for process {:?}", process);
+        let event: TaskExit = state.into();
+        let msg = Message::new(Action::Event(Arc::new(event)));
+        let sender = self.msg_sender.lock().await;
+        sender.send(msg).await.context("send event")
+    }
+
     async fn prepare_for_start_sandbox(
         &self,
         _id: &str,
@@ -107,6 +178,12 @@ impl VirtSandbox {
                         .await
                         .network_info
                         .network_queues as usize,
+                    vhost_user_supported: self
+                        .hypervisor
+                        .capabilities()
+                        .await
+                        .context("get hypervisor capabilities")?
+                        .is_vhost_user_supported(),
                 },
             ));
             resource_configs.push(network_config);
@@ -275,7 +352,13 @@ impl Sandbox for VirtSandbox {
                 }
             }
         });
-        self.monitor.start(id, self.agent.clone());
+        let sandbox = self.clone();
+        self.monitor.start(
+            id,
+            self.agent.clone(),
+            self.hypervisor.clone(),
+            async move { sandbox.handle_unhealthy().await },
+        );
         self.save().await.context("save state")?;
         Ok(())
     }
@@ -329,6 +412,50 @@ impl Sandbox for VirtSandbox {
         self.agent.agent_sock().await
     }
 
+    async fn agent_log(&self) -> Result<broadcast::Receiver<String>> {
+        Ok(self.agent.subscribe_log().await)
+    }
+
+    async fn set_agent_log_level(&self, subsystem: &str, level: &str) -> Result<()> {
+        info!(sl!(), "sb: set agent log level"; "log-subsystem" => subsystem, "log-level" => level);
+        let req = SetLogLevelRequest {
+            subsystem: subsystem.to_string(),
+            level: level.to_string(),
+        };
+        self.agent
+            .set_log_level(req)
+            .await
+            .context("sandbox: failed to set agent log level")?;
+        Ok(())
+    }
+
+    async fn info(&self) -> Result<SandboxInfo> {
+        let shim_pid = std::process::id();
+        // the built-in hypervisor, e.g. Dragonball, runs inside the shim process
+        let hypervisor_pid = self
+            .hypervisor
+            .save_state()
+            .await
+            .context("save hypervisor state")?
+            .pid
+            .map(|pid| pid as u32)
+            .unwrap_or(shim_pid);
+        let vcpu_threads = self
+            .hypervisor
+            .get_thread_ids()
+            .await
+            .context("get vcpu thread ids")?;
+
+        Ok(SandboxInfo {
+            sandbox_id: self.sid.clone(),
+            shim_pid,
+            hypervisor_pid,
+            vcpu_threads: vcpu_threads.vcpus.into_iter().collect(),
+            agent_url: self.agent.agent_sock().await.context("get agent sock")?,
+            containers: vec![],
+        })
+    }
+
     async fn set_iptables(&self, is_ipv6: bool, data: Vec<u8>) -> Result<Vec<u8>> {
         info!(sl!(), "sb: set_iptables invoked");
         let req = SetIPTablesRequest { is_ipv6, data };
@@ -350,6 +477,22 @@ impl Sandbox for VirtSandbox {
             .context("sandbox: failed to get iptables")?;
         Ok(resp.data)
     }
+
+    async fn agent_metrics(&self) -> Result<String> {
+        let resp = self
+            .agent
+            .get_metrics(agent::Empty::new())
+            .await
+            .context("sandbox: failed to get agent metrics")?;
+        Ok(resp.metrics)
+    }
+
+    async fn hypervisor_metrics(&self) -> Result<String> {
+        self.hypervisor
+            .get_hypervisor_metrics()
+            .await
+            .context("sandbox: failed to get hypervisor metrics")
+    }
 }
 
 #[async_trait]
@@ -364,7 +507,7 @@ impl Persist for VirtSandbox {
             resource: Some(self.resource_manager.save().await?),
             hypervisor: Some(self.hypervisor.save_state().await?),
         };
-        persist::to_disk(&sandbox_state, &self.sid)?;
+        persist::to_disk_in(&sandbox_state, &self.state_root, &self.sid)?;
         Ok(sandbox_state)
     }
     /// Restore Sandbox
@@ -373,6 +516,7 @@ impl Persist for VirtSandbox {
         sandbox_state: Self::State,
     ) -> Result<Self> {
         let config = sandbox_args.toml_config;
+        let policy = HealthCheckPolicy::new(&config.runtime);
         let r = sandbox_state.resource.unwrap_or_default();
         let h = sandbox_state.hypervisor.unwrap_or_default();
         let hypervisor = match h.hypervisor_type.as_str() {
@@ -401,6 +545,13 @@ impl Persist for VirtSandbox {
             config,
         };
         let resource_manager = Arc::new(ResourceManager::restore(args, r).await?);
+        let container_manager = Arc::new(VirtContainerManager::new(
+            &sid,
+            std::process::id(),
+            agent.clone(),
+            hypervisor.clone(),
+            resource_manager.clone(),
+        ));
         Ok(Self {
             sid: sid.to_string(),
             msg_sender: Arc::new(Mutex::new(sandbox_args.sender)),
@@ -408,7 +559,277 @@ impl Persist for VirtSandbox {
             agent,
             hypervisor,
             resource_manager,
-            monitor: Arc::new(HealthCheck::new(true, false)),
+            monitor: Arc::new(HealthCheck::new(true, false, policy)),
+            container_manager: Some(container_manager),
+            state_root: KATA_PATH.to_string(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, time::Duration};
+
+    use common::types::{
+        ContainerConfig, ExecProcessRequest, KillRequest, ProcessType, ShutdownRequest,
+    };
+    use containerd_shim_protos::protobuf::Message as _;
+    use hypervisor::HypervisorConfig;
+    use kata_types::config::Agent as AgentConfig;
+    use mock_agent::{Behavior, MockAgent, MockHypervisor, Script};
+    use test_utils::skip_if_not_root;
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+
+    const SANDBOX_ID: &str = "mock-agent-sandbox";
+    const EXEC_ID: &str = "mock-agent-exec";
+
+    fn sandbox_spec(bundle: &Path) -> oci::Spec {
+        let rootfs = bundle.join("rootfs");
+        fs::create_dir_all(&rootfs).unwrap();
+        oci::Spec {
+            root: Some(oci::Root {
+                path: rootfs.display().to_string(),
+                readonly: false,
+            }),
+            linux: Some(oci::Linux {
+                cgroups_path: format!("/kata-{}", SANDBOX_ID),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    // Wait until the mock agent received the nth request of the method, the request may be sent
+    // from a task spawned by the runtime.
+    async fn wait_for_request(agent: &MockAgent, method: &str, nth: usize) {
+        for _ in 0..100 {
+            if agent.recorder().requests_of(method).len() >= nth {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("no {} request {} received", method, nth);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sandbox_with_mock_agent() {
+        // The sandbox cgroups are created from the spec.
+        skip_if_not_root!();
+
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("bundle");
+        let spec = sandbox_spec(&bundle);
+        let state_root = dir.path().join("run");
+        fs::create_dir_all(state_root.join(SANDBOX_ID)).unwrap();
+
+        // The init process is killed after the exec process exits with 3.
+        let mut script = Script::default();
+        script
+            .set(
+                "WaitProcess",
+                vec![
+                    Behavior::respond(serde_json::json!({ "status": 137 })).delay(1000),
+                    Behavior::respond(serde_json::json!({ "status": 3 })),
+                ],
+            )
+            .unwrap();
+        let mut mock_agent = MockAgent::new(dir.path().join("kata.hvsock"), script);
+        mock_agent.start().await.unwrap();
+
+        let mut hypervisor_config = HypervisorConfig::default();
+        hypervisor_config.shared_fs.shared_fs = Some("inline-virtio-fs".to_string());
+        let hypervisor = Arc::new(MockHypervisor::new(
+            &mock_agent.address(),
+            hypervisor_config,
+        ));
+        let agent = Arc::new(KataAgent::new(AgentConfig {
+            server_port: DEFAULT_AGENT_VSOCK_PORT,
+            log_port: DEFAULT_AGENT_LOG_PORT,
+            dial_timeout_ms: 10,
+            reconnect_timeout_ms: 3_000,
+            request_timeout_ms: 30_000,
+            health_check_request_timeout_ms: 90_000,
+            ..Default::default()
+        }));
+        let resource_manager = Arc::new(
+            ResourceManager::new(
+                SANDBOX_ID,
+                agent.clone(),
+                hypervisor.clone(),
+                Arc::new(TomlConfig::default()),
+                &spec,
+            )
+            .unwrap(),
+        );
+        let container_manager = Arc::new(VirtContainerManager::new(
+            SANDBOX_ID,
+            std::process::id(),
+            agent.clone(),
+            hypervisor.clone(),
+            resource_manager.clone(),
+        ));
+        let (sender, mut receiver) = channel(1);
+        let mut sandbox = VirtSandbox::new(
+            SANDBOX_ID,
+            sender,
+            agent,
+            hypervisor.clone(),
+            resource_manager,
+            container_manager.clone(),
+        )
+        .await
+        .unwrap();
+        sandbox.state_root = state_root.display().to_string();
+
+        // start the sandbox and its container
+        let state = oci::State {
+            version: spec.version.clone(),
+            id: SANDBOX_ID.to_string(),
+            status: oci::ContainerState::Creating,
+            pid: 0,
+            bundle: bundle.display().to_string(),
+            annotations: spec.annotations.clone(),
+        };
+        sandbox.start(None, vec![], &spec, &state).await.unwrap();
+        let config = ContainerConfig {
+            container_id: SANDBOX_ID.to_string(),
+            bundle: bundle.display().to_string(),
+            rootfs_mounts: vec![],
+            terminal: false,
+            options: None,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+        };
+        container_manager
+            .create_container(config, spec.clone())
+            .await
+            .unwrap();
+        let init = ContainerProcess::new(SANDBOX_ID, "").unwrap();
+        container_manager.start_process(&init).await.unwrap();
+        wait_for_request(&mock_agent, "WaitProcess", 1).await;
+
+        // exec a process in the container and wait for it
+        let exec = ContainerProcess::new(SANDBOX_ID, EXEC_ID).unwrap();
+        assert_eq!(exec.process_type, ProcessType::Exec);
+        let process = oci::Process {
+            args: vec!["true".to_string()],
+            cwd: "/".to_string(),
+            ..Default::default()
+        };
+        container_manager
+            .exec_process(ExecProcessRequest {
+                process: exec.clone(),
+                terminal: false,
+                stdin: None,
+                stdout: None,
+                stderr: None,
+                spec_type_url: "types.containerd.io/opencontainers/runtime-spec/1/Process"
+                    .to_string(),
+                spec_value: serde_json::to_vec(&process).unwrap(),
+            })
+            .await
+            .unwrap();
+        container_manager.start_process(&exec).await.unwrap();
+
+        // all the processes are reported as exited when the sandbox is unhealthy
+        let (events, sent) = tokio::join!(
+            async {
+                let mut events = vec![];
+                while events.len() < 2 {
+                    if let Action::Event(event) = receiver.recv().await.unwrap().action {
+                        events.push(TaskExit::parse_from_bytes(&event.value().unwrap()).unwrap());
+                    }
+                }
+                events
+            },
+            sandbox.send_exit_events(container_manager.as_ref())
+        );
+        sent.unwrap();
+        let ids: Vec<(&str, &str)> = events
+            .iter()
+            .map(|e| (e.container_id.as_str(), e.id.as_str()))
+            .collect();
+        assert_eq!(ids, vec![(SANDBOX_ID, EXEC_ID), (SANDBOX_ID, SANDBOX_ID)]);
+
+        let status = container_manager.wait_process(&exec).await.unwrap();
+        assert_eq!(status.exit_code, 3);
+        container_manager.delete_process(&exec).await.unwrap();
+
+        // stop the container and the sandbox
+        container_manager
+            .kill_process(&KillRequest {
+                process: init.clone(),
+                signal: libc::SIGKILL as u32,
+                all: false,
+            })
+            .await
+            .unwrap();
+        let status = container_manager.wait_process(&init).await.unwrap();
+        assert_eq!(status.exit_code, 137);
+        let state = container_manager.delete_process(&init).await.unwrap();
+        assert_eq!(state.exit_status, 137);
+        assert!(
+            container_manager
+                .need_shutdown_sandbox(&ShutdownRequest {
+                    container_id: SANDBOX_ID.to_string(),
+                    is_now: false,
+                })
+                .await
+        );
+        sandbox.shutdown().await.unwrap();
+        let msg = receiver.recv().await.unwrap();
+        assert!(matches!(msg.action, Action::Shutdown));
+
+        let calls: Vec<String> = hypervisor
+            .calls()
+            .into_iter()
+            .filter(|c| c != "add_device" && c != "remove_device")
+            .collect();
+        assert_eq!(calls, vec!["prepare_vm", "start_vm", "stop_vm", "cleanup"]);
+
+        // The other requests are sent by the tasks watching the sandbox and the processes.
+        let recorder = mock_agent.recorder();
+        let methods: Vec<String> = recorder
+            .methods()
+            .into_iter()
+            .filter(|m| {
+                [
+                    "CreateSandbox",
+                    "CreateContainer",
+                    "StartContainer",
+                    "ExecProcess",
+                    "RemoveContainer",
+                ]
+                .contains(&m.as_str())
+            })
+            .collect();
+        assert_eq!(
+            methods,
+            vec![
+                "CreateSandbox",
+                "CreateContainer",
+                "StartContainer",
+                "ExecProcess",
+                "RemoveContainer"
+            ]
+        );
+        let exec_requests = recorder.requests_of("ExecProcess");
+        assert_eq!(exec_requests[0].request["exec_id"], EXEC_ID);
+        assert!(recorder
+            .requests_of("SignalProcess")
+            .iter()
+            .any(|r| r.request["container_id"] == SANDBOX_ID
+                && r.request["exec_id"] == ""
+                && r.request["signal"] == libc::SIGKILL));
+
+        assert!(state_root
+            .join(SANDBOX_ID)
+            .join(persist::PERSIST_FILE)
+            .exists());
+
+        mock_agent.stop().await;
+    }
+}
@@ -31,6 +31,7 @@ impl RuntimeHandler for WasmContainer {
         _sid: &str,
         _msg_sender: Sender<Message>,
         _config: Arc<TomlConfig>,
+        _spec: &oci::Spec,
     ) -> Result<RuntimeInstance> {
         todo!()
     }
@@ -6,11 +6,29 @@
 
 use std::os::unix::fs::OpenOptionsExt;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use kata_types::config::TomlConfig;
+use logging::LogSink;
 
 use crate::Error;
 
-pub(crate) fn set_logger(path: &str, sid: &str, is_debug: bool) -> Result<slog_async::AsyncGuard> {
+const KATA_CONF_FILE: &str = "KATA_CONF_FILE";
+
+// Get the log sink of the configuration. The logger is set before the sandbox is created, so
+// only the configuration of the environment or the default one is used.
+pub(crate) fn load_log_sink() -> String {
+    let config_path = std::env::var(KATA_CONF_FILE).unwrap_or_default();
+    TomlConfig::load_raw_from_file(config_path)
+        .map(|(config, _)| config.runtime.log_sink)
+        .unwrap_or_default()
+}
+
+pub(crate) fn set_logger(
+    path: &str,
+    sid: &str,
+    is_debug: bool,
+    log_sink: &str,
+) -> Result<slog_async::AsyncGuard> {
     let fifo = std::fs::OpenOptions::new()
         .custom_flags(libc::O_NONBLOCK)
         .create(true)
@@ -25,7 +43,24 @@ pub(crate) fn set_logger(path: &str, sid: &str, is_debug: bool) -> Result<slog_a
         slog::Level::Info
     };
 
-    let (logger, async_guard) = logging::create_logger("kata-runtime", sid, level, fifo);
+    let sink_writer = fifo.try_clone().context("clone log fifo")?;
+    let (logger, async_guard) = match LogSink::from_name(log_sink, sink_writer)
+        .map_err(|e| anyhow!(e))
+        .and_then(|sink| {
+            logging::create_logger_with_sink("kata-runtime", sid, level, sink)
+                .context(format!("create logger with sink {}", log_sink))
+        }) {
+        Ok(result) => result,
+        Err(err) => {
+            // still log to the fifo rather than losing all the records of the shim
+            let (logger, async_guard) = logging::create_logger("kata-runtime", sid, level, fifo);
+            warn!(
+                logger,
+                "failed to use log sink, fall back to json: {:?}", err
+            );
+            (logger, async_guard)
+        }
+    };
 
     // not reset global logger when drop
     slog_scope::set_global_logger(logger).cancel_reset();
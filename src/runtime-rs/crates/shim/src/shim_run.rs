@@ -21,8 +21,10 @@ impl ShimExecutor {
         let sid = self.args.id.clone();
         let bundle_path = get_bundle_path().context("get bundle")?;
         let path = bundle_path.join("log");
+        let log_sink = logger::load_log_sink();
         let _logger_guard =
-            logger::set_logger(path.to_str().unwrap(), &sid, self.args.debug).context("set logger");
+            logger::set_logger(path.to_str().unwrap(), &sid, self.args.debug, &log_sink)
+                .context("set logger");
         if try_core_sched().is_err() {
             warn!(
                 sl!(),
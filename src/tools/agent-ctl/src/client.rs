@@ -42,7 +42,7 @@ macro_rules! run_if_auto_values {
 }
 
 // Hack until the actual Context type supports this.
-fn clone_context(ctx: &Context) -> Context {
+pub(crate) fn clone_context(ctx: &Context) -> Context {
     Context {
         metadata: ctx.metadata.clone(),
         timeout_nano: ctx.timeout_nano,
@@ -226,6 +226,11 @@ static AGENT_CMDS: &[AgentCmd] = &[
         st: ServiceType::Agent,
         fp: agent_cmd_container_remove,
     },
+    AgentCmd {
+        name: "ResizeVolume",
+        st: ServiceType::Agent,
+        fp: agent_cmd_sandbox_resize_volume,
+    },
     AgentCmd {
         name: "ResumeContainer",
         st: ServiceType::Agent,
@@ -241,6 +246,11 @@ static AGENT_CMDS: &[AgentCmd] = &[
         st: ServiceType::Agent,
         fp: agent_cmd_sandbox_set_ip_tables,
     },
+    AgentCmd {
+        name: "SetLogLevel",
+        st: ServiceType::Agent,
+        fp: agent_cmd_sandbox_set_log_level,
+    },
     AgentCmd {
         name: "SignalProcess",
         st: ServiceType::Agent,
@@ -564,7 +574,7 @@ fn create_ttrpc_client(
     Ok(ttrpc::Client::new(fd))
 }
 
-fn kata_service_agent(
+pub(crate) fn kata_service_agent(
     server_address: String,
     hybrid_vsock_port: u64,
     hybrid_vsock: bool,
@@ -574,7 +584,7 @@ fn kata_service_agent(
     Ok(AgentServiceClient::new(ttrpc_client))
 }
 
-fn kata_service_health(
+pub(crate) fn kata_service_health(
     server_address: String,
     hybrid_vsock_port: u64,
     hybrid_vsock: bool,
@@ -1700,6 +1710,48 @@ fn agent_cmd_sandbox_get_volume_stats(
     Ok(())
 }
 
+fn agent_cmd_sandbox_resize_volume(
+    ctx: &Context,
+    client: &AgentServiceClient,
+    _health: &HealthClient,
+    options: &mut Options,
+    args: &str,
+) -> Result<()> {
+    let mut req: ResizeVolumeRequest = utils::make_request(args)?;
+
+    let ctx = clone_context(ctx);
+
+    run_if_auto_values!(ctx, || -> Result<()> {
+        let path = utils::get_option("volume_guest_path", options, args)?;
+        if !path.is_empty() {
+            req.set_volume_guest_path(path);
+        }
+
+        let size_str = utils::get_option("size", options, args)?;
+
+        if !size_str.is_empty() {
+            let size = size_str
+                .parse::<u64>()
+                .map_err(|e| anyhow!(e).context("invalid size"))?;
+
+            req.set_size(size);
+        }
+
+        Ok(())
+    });
+
+    debug!(sl!(), "sending request"; "request" => format!("{:?}", req));
+
+    let reply = client
+        .resize_volume(ctx, &req)
+        .map_err(|e| anyhow!(e).context(ERR_API_FAILED))?;
+
+    info!(sl!(), "response received";
+        "response" => format!("{:?}", reply));
+
+    Ok(())
+}
+
 fn agent_cmd_sandbox_copy_file(
     ctx: &Context,
     client: &AgentServiceClient,
@@ -1955,6 +2007,29 @@ fn agent_cmd_sandbox_set_ip_tables(
     Ok(())
 }
 
+fn agent_cmd_sandbox_set_log_level(
+    ctx: &Context,
+    client: &AgentServiceClient,
+    _health: &HealthClient,
+    _options: &mut Options,
+    args: &str,
+) -> Result<()> {
+    let req: SetLogLevelRequest = utils::make_request(args)?;
+
+    let ctx = clone_context(ctx);
+
+    debug!(sl!(), "sending request"; "request" => format!("{:?}", req));
+
+    let reply = client
+        .set_log_level(ctx, &req)
+        .map_err(|e| anyhow!("{:?}", e).context(ERR_API_FAILED))?;
+
+    info!(sl!(), "response received";
+        "response" => format!("{:?}", reply));
+
+    Ok(())
+}
+
 fn agent_cmd_sandbox_add_arp_neighbors(
     ctx: &Context,
     client: &AgentServiceClient,
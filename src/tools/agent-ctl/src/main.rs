@@ -9,7 +9,9 @@ extern crate lazy_static;
 use crate::types::Config;
 use anyhow::{anyhow, Result};
 use clap::{crate_name, crate_version, App, Arg, SubCommand};
+use slog::o;
 use std::io;
+use std::path::Path;
 use std::process::exit;
 
 // Convenience macro to obtain the scope logger
@@ -21,7 +23,9 @@ macro_rules! sl {
 }
 
 mod client;
+mod raw;
 mod rpc;
+mod scenario;
 mod types;
 mod utils;
 
@@ -118,6 +122,15 @@ fn make_examples_text(program_name: &str) -> String {
 - Create a Container using a custom configuration file:
 
   $ {program} connect --server-address "{vsock_server_address}" --bundle-dir {bundle:?} --cmd 'CreateContainer spec={config_file_uri}'
+
+- Send a complete API request in JSON format read from stdin, and display the response in JSON format:
+
+  $ echo '{{"volume_guest_path": "/run/kata-containers/shared/vol", "size": 1073741824}}' | {program} call --server-address "{vsock_server_address}" --method ResizeVolume --request -
+
+- Run a scenario file, recording the session so it can be replayed later:
+
+  $ {program} scenario --server-address "{vsock_server_address}" --record /tmp/session.yaml /tmp/scenario.yaml
+  $ {program} scenario --server-address "{vsock_server_address}" /tmp/session.yaml
 	"#,
         abstract_server_address = abstract_server_address,
         bundle = bundle,
@@ -141,7 +154,7 @@ fn connect(name: &str, global_args: clap::ArgMatches) -> Result<()> {
 
     let server_address = args
         .value_of("server-address")
-        .ok_or_else(|| anyhow!("need server adddress"))?
+        .ok_or_else(|| anyhow!("need server address"))?
         .to_string();
 
     let mut commands: Vec<&str> = Vec::new();
@@ -194,6 +207,131 @@ fn connect(name: &str, global_args: clap::ArgMatches) -> Result<()> {
     result.map_err(|e| anyhow!(e))
 }
 
+// Arguments specifying how to connect to the agent, shared by the
+// sub-commands sending raw API requests.
+fn connection_args(hybrid_vsock_port_help: &str) -> Vec<Arg> {
+    vec![
+        Arg::with_name("server-address")
+            .long("server-address")
+            .help("server URI (vsock:// or unix://)")
+            .takes_value(true)
+            .value_name("URI")
+            .required(true),
+        Arg::with_name("hybrid-vsock")
+            .long("hybrid-vsock")
+            .help("Treat a unix:// server address as a Hybrid VSOCK one"),
+        Arg::with_name("hybrid-vsock-port")
+            .long("hybrid-vsock-port")
+            .help(hybrid_vsock_port_help)
+            .default_value(DEFAULT_KATA_AGENT_API_VSOCK_PORT)
+            .takes_value(true)
+            .value_name("PORT"),
+        Arg::with_name("timeout")
+            .long("timeout")
+            .help("timeout value as nanoseconds or using human-readable suffixes (0 [forever], 99ns, 30us, 2ms, 5s, 7m, etc)")
+            .takes_value(true)
+            .value_name("human-time"),
+        Arg::with_name("record")
+            .long("record")
+            .help("Record the session to a scenario file (YAML if the extension is .yaml or .yml, JSON otherwise)")
+            .takes_value(true)
+            .value_name("FILE"),
+    ]
+}
+
+fn connection_config(args: &clap::ArgMatches) -> Result<Config> {
+    let server_address = args
+        .value_of("server-address")
+        .ok_or_else(|| anyhow!("need server address"))?
+        .to_string();
+
+    let timeout_nano: i64 = match args.value_of("timeout") {
+        Some(t) => utils::human_time_to_ns(t)?,
+        None => 0,
+    };
+
+    let hybrid_vsock_port = args
+        .value_of("hybrid-vsock-port")
+        .ok_or_else(|| anyhow!("Need Hybrid VSOCK port number"))?
+        .parse::<u64>()
+        .map_err(|e| anyhow!("VSOCK port number must be an integer: {:?}", e))?;
+
+    Ok(Config {
+        server_address,
+        bundle_dir: String::new(),
+        timeout_nano,
+        hybrid_vsock_port,
+        interactive: false,
+        hybrid_vsock: args.is_present("hybrid-vsock"),
+        ignore_errors: false,
+        no_auto_values: true,
+    })
+}
+
+// Handle the sub-commands sending raw API requests. Logs are written to
+// stderr so that stdout only contains the results.
+fn raw_request(name: &str, global_args: &clap::ArgMatches, subcmd: &str) -> Result<()> {
+    let args = global_args
+        .subcommand_matches(subcmd)
+        .ok_or_else(|| anyhow!("BUG: missing sub-command arguments"))?;
+
+    let log_level_name = global_args
+        .value_of("log-level")
+        .ok_or_else(|| anyhow!("cannot get log level"))?;
+
+    let log_level = logging::level_name_to_slog_level(log_level_name).map_err(|e| anyhow!(e))?;
+
+    let (logger, _guard) = logging::create_logger(name, crate_name!(), log_level, io::stderr());
+    let _scope_guard = slog_scope::set_global_logger(logger.new(o!("subsystem" => "raw")));
+
+    let cfg = connection_config(args)?;
+    let client = raw::RawClient::new(&cfg)?;
+
+    let mut recorder = args
+        .value_of("record")
+        .map(|_| scenario::Recorder::default());
+
+    let result = match subcmd {
+        "call" => {
+            let method = args
+                .value_of("method")
+                .ok_or_else(|| anyhow!("need method"))?;
+
+            let request = match args.value_of("request") {
+                Some(file) => raw::read_request(file)?,
+                None => serde_json::json!({}),
+            };
+
+            let result = client.call(method, request.clone());
+
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(method, &request, &result);
+            }
+
+            result.and_then(|response| {
+                println!("{}", serde_json::to_string_pretty(&response)?);
+                Ok(())
+            })
+        }
+        "scenario" => {
+            let file = args
+                .value_of("file")
+                .ok_or_else(|| anyhow!("need scenario file"))?;
+
+            scenario::run(&client, Path::new(file), recorder.as_mut())
+        }
+        _ => Err(anyhow!("invalid sub-command: {:?}", subcmd)),
+    };
+
+    // Save the session even if a request failed, since that is often the
+    // interesting part.
+    if let (Some(recorder), Some(file)) = (recorder, args.value_of("record")) {
+        recorder.save(Path::new(file))?;
+    }
+
+    result
+}
+
 fn real_main() -> Result<()> {
     let name = crate_name!();
 
@@ -281,6 +419,41 @@ fn real_main() -> Result<()> {
                     .value_name("human-time"),
                     )
                 )
+                .subcommand(
+                    SubCommand::with_name("call")
+                    .about("Send a complete API request in JSON format and display the response in JSON format")
+                    .after_help(WARNING_TEXT)
+                    .args(&connection_args(&hybrid_vsock_port_help))
+                    .arg(
+                        Arg::with_name("method")
+                        .long("method")
+                        .short("m")
+                        .help("API method name, as used in the protocol files")
+                        .takes_value(true)
+                        .possible_values(raw::METHODS)
+                        .required(true),
+                        )
+                    .arg(
+                        Arg::with_name("request")
+                        .long("request")
+                        .short("r")
+                        .help("File containing the request in JSON format ('-' for stdin) [default: empty request]")
+                        .takes_value(true)
+                        .value_name("FILE"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("scenario")
+                    .about("Run the API requests of a scenario file (YAML or JSON) and check the responses")
+                    .after_help(WARNING_TEXT)
+                    .args(&connection_args(&hybrid_vsock_port_help))
+                    .arg(
+                        Arg::with_name("file")
+                        .help("Scenario file")
+                        .required(true)
+                        .value_name("FILE"),
+                        )
+                )
                 .subcommand(
                     SubCommand::with_name("generate-cid")
                     .about("Create a random container ID")
@@ -314,6 +487,7 @@ fn real_main() -> Result<()> {
             Ok(())
         }
         "connect" => connect(name, args),
+        "call" | "scenario" => raw_request(name, &args, subcmd),
         _ => return Err(anyhow!(format!("invalid sub-command: {:?}", subcmd))),
     }
 }
@@ -0,0 +1,212 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+// Description: Send complete API requests in JSON format and return the
+// responses in JSON format, without any automatic values.
+
+use crate::client::{clone_context, kata_service_agent, kata_service_health};
+use crate::types::Config;
+use anyhow::{anyhow, Context as _, Result};
+use protocols::agent_ttrpc::AgentServiceClient;
+use protocols::health_ttrpc::HealthClient;
+use serde_json::Value;
+use slog::debug;
+use ttrpc::context::Context;
+
+const ERR_API_FAILED: &str = "API failed";
+
+// Generate the list of the API method names and the dispatcher calling
+// them. Each entry is the client field, the method name used in the
+// protocol, and the client function.
+macro_rules! raw_methods {
+    ($($service:ident $name:literal => $func:ident),* $(,)?) => {
+        // API method names, as used in the protocol files.
+        pub const METHODS: &[&str] = &[$($name),*];
+
+        impl RawClient {
+            // Send the request of the method and return the response.
+            //
+            // Errors returned by the agent are reported as `ttrpc::Error` so
+            // callers can check the status code.
+            pub fn call(&self, method: &str, request: Value) -> Result<Value> {
+                debug!(sl!(), "sending request";
+                    "method" => method,
+                    "request" => request.to_string());
+
+                let response = match method {
+                    $($name => {
+                        let req = serde_json::from_value(request)
+                            .with_context(|| format!("invalid {} request", method))?;
+
+                        let reply = self
+                            .$service
+                            .$func(clone_context(&self.ctx), &req)
+                            .map_err(|e| anyhow!(e).context(ERR_API_FAILED))?;
+
+                        serde_json::to_value(reply).context("failed to encode response")?
+                    })*
+                    _ => return Err(anyhow!("invalid method: {:?}", method)),
+                };
+
+                debug!(sl!(), "response received";
+                    "method" => method,
+                    "response" => response.to_string());
+
+                Ok(response)
+            }
+        }
+    };
+}
+
+// Client sending raw requests to both the agent and health services.
+pub struct RawClient {
+    agent: AgentServiceClient,
+    health: HealthClient,
+    ctx: Context,
+}
+
+impl RawClient {
+    pub fn new(cfg: &Config) -> Result<Self> {
+        let agent = kata_service_agent(
+            cfg.server_address.clone(),
+            cfg.hybrid_vsock_port,
+            cfg.hybrid_vsock,
+        )?;
+
+        let health = kata_service_health(
+            cfg.server_address.clone(),
+            cfg.hybrid_vsock_port,
+            cfg.hybrid_vsock,
+        )?;
+
+        Ok(RawClient {
+            agent,
+            health,
+            ctx: ttrpc::context::with_timeout(cfg.timeout_nano),
+        })
+    }
+}
+
+raw_methods!(
+    health "Check" => check,
+    health "Version" => version,
+    agent "CreateContainer" => create_container,
+    agent "StartContainer" => start_container,
+    agent "RemoveContainer" => remove_container,
+    agent "ExecProcess" => exec_process,
+    agent "SignalProcess" => signal_process,
+    agent "WaitProcess" => wait_process,
+    agent "UpdateContainer" => update_container,
+    agent "StatsContainer" => stats_container,
+    agent "PauseContainer" => pause_container,
+    agent "ResumeContainer" => resume_container,
+    agent "WriteStdin" => write_stdin,
+    agent "ReadStdout" => read_stdout,
+    agent "ReadStderr" => read_stderr,
+    agent "CloseStdin" => close_stdin,
+    agent "TtyWinResize" => tty_win_resize,
+    agent "UpdateInterface" => update_interface,
+    agent "UpdateRoutes" => update_routes,
+    agent "ListInterfaces" => list_interfaces,
+    agent "ListRoutes" => list_routes,
+    agent "AddARPNeighbors" => add_arp_neighbors,
+    agent "GetIPTables" => get_ip_tables,
+    agent "SetIPTables" => set_ip_tables,
+    agent "GetMetrics" => get_metrics,
+    agent "SetLogLevel" => set_log_level,
+    agent "CreateSandbox" => create_sandbox,
+    agent "DestroySandbox" => destroy_sandbox,
+    agent "OnlineCPUMem" => online_cpu_mem,
+    agent "ReseedRandomDev" => reseed_random_dev,
+    agent "GetGuestDetails" => get_guest_details,
+    agent "MemHotplugByProbe" => mem_hotplug_by_probe,
+    agent "SetGuestDateTime" => set_guest_date_time,
+    agent "CopyFile" => copy_file,
+    agent "GetOOMEvent" => get_oom_event,
+    agent "AddSwap" => add_swap,
+    agent "GetVolumeStats" => get_volume_stats,
+    agent "ResizeVolume" => resize_volume,
+);
+
+// Read a JSON request from the specified file, or from stdin if the file
+// is "-". An empty input is the default (empty) request.
+pub fn read_request(file: &str) -> Result<Value> {
+    let data = if file == "-" {
+        let mut data = String::new();
+
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut data)
+            .context("failed to read request from stdin")?;
+
+        data
+    } else {
+        std::fs::read_to_string(file)
+            .with_context(|| format!("failed to read request file {:?}", file))?
+    };
+
+    if data.trim().is_empty() {
+        return Ok(Value::Object(Default::default()));
+    }
+
+    serde_json::from_str(&data).context("invalid JSON request")
+}
+
+// Return the ttRPC status of the error returned by the agent, if any.
+pub fn rpc_status(err: &anyhow::Error) -> Option<&ttrpc::Status> {
+    match err.downcast_ref::<ttrpc::Error>() {
+        Some(ttrpc::Error::RpcStatus(status)) => Some(status),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_read_request() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let file = dir.path().join("request.json");
+        std::fs::write(&file, r#"{"container_id": "foo"}"#).unwrap();
+        assert_eq!(
+            read_request(file.to_str().unwrap()).unwrap(),
+            json!({"container_id": "foo"})
+        );
+
+        let file = dir.path().join("empty.json");
+        std::fs::write(&file, " \n").unwrap();
+        assert_eq!(read_request(file.to_str().unwrap()).unwrap(), json!({}));
+
+        let file = dir.path().join("invalid.json");
+        std::fs::write(&file, "container_id: foo").unwrap();
+        assert!(read_request(file.to_str().unwrap()).is_err());
+
+        let file = dir.path().join("missing.json");
+        assert!(read_request(file.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_rpc_status() {
+        let status = ttrpc::get_status(ttrpc::Code::NOT_FOUND, "no such container");
+        let err = anyhow!(ttrpc::Error::RpcStatus(status.clone())).context(ERR_API_FAILED);
+        assert_eq!(rpc_status(&err), Some(&status));
+
+        let err = anyhow!(ttrpc::Error::Others("connection reset".to_string()));
+        assert!(rpc_status(&err).is_none());
+        assert!(rpc_status(&anyhow!("invalid method")).is_none());
+    }
+
+    #[test]
+    fn test_methods() {
+        assert!(METHODS.contains(&"Check"));
+        assert!(METHODS.contains(&"CreateContainer"));
+
+        let mut methods = METHODS.to_vec();
+        methods.sort_unstable();
+        methods.dedup();
+        assert_eq!(methods.len(), METHODS.len());
+    }
+}
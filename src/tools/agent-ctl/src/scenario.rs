@@ -0,0 +1,512 @@
+// Copyright (c) 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+// Description: Run a sequence of API requests described in a YAML or JSON
+// file, checking the responses, and record sessions in the same format so
+// they can be replayed.
+
+use crate::raw::{rpc_status, RawClient};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use slog::info;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::Duration;
+
+// Expected failure of a request.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExpectedError {
+    // ttRPC status code name (for example "NOT_FOUND"). Any code matches if
+    // not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+
+    // Text the error message must contain.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+// Expected result of a request. The request must succeed if neither field
+// is set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Expect {
+    // Fields the response must contain. Only the specified fields are
+    // checked; arrays must match element by element.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ExpectedError>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Step {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+
+    // API method name, as used in the protocol files (for example
+    // "CreateContainer").
+    pub method: String,
+
+    // Request in JSON format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<Value>,
+
+    // File containing the request in JSON format, relative to the scenario
+    // file. Ignored if "request" is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_file: Option<PathBuf>,
+
+    // Time to wait before sending the request (human-readable format).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect: Option<Expect>,
+}
+
+impl Step {
+    fn description(&self, index: usize) -> String {
+        if self.name.is_empty() {
+            format!("step {} ({})", index + 1, self.method)
+        } else {
+            format!("step {} ({}: {})", index + 1, self.name, self.method)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+
+    pub steps: Vec<Step>,
+}
+
+fn is_yaml(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("yaml") | Some("yml")
+    )
+}
+
+impl Scenario {
+    // Load a scenario from a YAML (".yaml" or ".yml" extension) or JSON file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("failed to read scenario file {:?}", path))?;
+
+        if is_yaml(path) {
+            serde_yaml::from_str(&data).with_context(|| format!("invalid scenario {:?}", path))
+        } else {
+            serde_json::from_str(&data).with_context(|| format!("invalid scenario {:?}", path))
+        }
+    }
+
+    // Save the scenario, in YAML or JSON format depending on the file
+    // extension.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let data = if is_yaml(path) {
+            serde_yaml::to_string(self)?
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+
+        fs::write(path, data).with_context(|| format!("failed to write session file {:?}", path))
+    }
+}
+
+// Records the requests sent and the results received, as a scenario which
+// expects the same results when replayed.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    session: Scenario,
+}
+
+impl Recorder {
+    pub fn record(&mut self, method: &str, request: &Value, result: &Result<Value>) {
+        let expect = match result {
+            Ok(response) => Expect {
+                response: Some(response.clone()),
+                error: None,
+            },
+            Err(e) => Expect {
+                response: None,
+                error: Some(ExpectedError {
+                    code: rpc_status(e).map(|s| format!("{:?}", s.get_code())),
+                    message: None,
+                }),
+            },
+        };
+
+        self.session.steps.push(Step {
+            method: method.to_string(),
+            request: Some(request.clone()),
+            expect: Some(expect),
+            ..Default::default()
+        });
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        self.session.save(path)
+    }
+}
+
+// Check the actual value contains all the fields of the expected one.
+fn value_matches(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Object(e), Value::Object(a)) => e
+            .iter()
+            .all(|(k, v)| a.get(k).map_or(false, |av| value_matches(v, av))),
+        (Value::Array(e), Value::Array(a)) => {
+            e.len() == a.len() && e.iter().zip(a).all(|(ev, av)| value_matches(ev, av))
+        }
+        _ => expected == actual,
+    }
+}
+
+fn check_result(expect: &Expect, result: &Result<Value>) -> Result<()> {
+    match (result, &expect.error) {
+        (Ok(response), None) => {
+            if let Some(expected) = &expect.response {
+                if !value_matches(expected, response) {
+                    return Err(anyhow!(
+                        "unexpected response: expected {}, got {}",
+                        expected,
+                        response
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+        (Ok(response), Some(_)) => Err(anyhow!("expected an error, got response {}", response)),
+        (Err(e), None) => Err(anyhow!("unexpected error: {:#}", e)),
+        (Err(e), Some(expected)) => {
+            let status = rpc_status(e);
+
+            if let Some(code) = &expected.code {
+                let actual = status.map(|s| format!("{:?}", s.get_code()));
+
+                if actual.as_deref() != Some(code.as_str()) {
+                    return Err(anyhow!("expected error code {}, got error: {:#}", code, e));
+                }
+            }
+
+            if let Some(message) = &expected.message {
+                let actual = status.map_or_else(|| format!("{:#}", e), |s| s.get_message().into());
+
+                if !actual.contains(message.as_str()) {
+                    return Err(anyhow!(
+                        "expected error message containing {:?}, got error: {:#}",
+                        message,
+                        e
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+fn step_request(step: &Step, base_dir: &Path) -> Result<Value> {
+    if let Some(request) = &step.request {
+        return Ok(request.clone());
+    }
+
+    match &step.request_file {
+        Some(file) => {
+            let path = base_dir.join(file);
+
+            let data = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read request file {:?}", path))?;
+
+            serde_json::from_str(&data)
+                .with_context(|| format!("invalid JSON request file {:?}", path))
+        }
+        None => Ok(Value::Object(Default::default())),
+    }
+}
+
+// Run all the steps of the scenario file, stopping at the first step whose
+// result is not the expected one.
+pub fn run(client: &RawClient, path: &Path, mut recorder: Option<&mut Recorder>) -> Result<()> {
+    let scenario = Scenario::load(path)?;
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    info!(sl!(), "running scenario";
+        "name" => &scenario.name,
+        "steps" => scenario.steps.len());
+
+    for (index, step) in scenario.steps.iter().enumerate() {
+        let descr = step.description(index);
+
+        if let Some(delay) = &step.delay {
+            let ns = crate::utils::human_time_to_ns(delay)
+                .with_context(|| format!("{}: invalid delay", descr))?;
+
+            sleep(Duration::from_nanos(ns as u64));
+        }
+
+        let request = step_request(step, base_dir).with_context(|| descr.clone())?;
+
+        let result = client.call(&step.method, request.clone());
+
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(&step.method, &request, &result);
+        }
+
+        check_result(&step.expect.clone().unwrap_or_default(), &result)
+            .with_context(|| format!("{} failed", descr))?;
+
+        println!("PASS: {}", descr);
+    }
+
+    println!(
+        "Scenario {:?} passed ({} steps)",
+        scenario.name,
+        scenario.steps.len()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rpc_error(code: ttrpc::Code, message: &str) -> anyhow::Error {
+        anyhow!(ttrpc::Error::RpcStatus(ttrpc::get_status(code, message))).context("API failed")
+    }
+
+    #[test]
+    fn test_value_matches() {
+        let actual = json!({
+            "container_id": "foo",
+            "process": {"args": ["sh", "-c", "true"], "terminal": false},
+            "pids": [1, 2],
+        });
+
+        // Only the expected fields are checked.
+        assert!(value_matches(&json!({}), &actual));
+        assert!(value_matches(&json!({"container_id": "foo"}), &actual));
+        assert!(value_matches(
+            &json!({"process": {"terminal": false}}),
+            &actual
+        ));
+        assert!(!value_matches(&json!({"container_id": "bar"}), &actual));
+        assert!(!value_matches(&json!({"exec_id": "foo"}), &actual));
+        assert!(!value_matches(
+            &json!({"process": {"terminal": true}}),
+            &actual
+        ));
+
+        // Arrays must match element by element.
+        assert!(value_matches(&json!({"pids": [1, 2]}), &actual));
+        assert!(!value_matches(&json!({"pids": [1]}), &actual));
+        assert!(!value_matches(&json!({"pids": [1, 2, 3]}), &actual));
+        assert!(!value_matches(&json!({"pids": [2, 1]}), &actual));
+        assert!(value_matches(
+            &json!([{"a": 1}, {}]),
+            &json!([{"a": 1, "b": 2}, {"c": 3}])
+        ));
+
+        assert!(!value_matches(&json!({"pids": [1, 2]}), &json!([1, 2])));
+    }
+
+    #[test]
+    fn test_check_result() {
+        let response = json!({"status": 0, "pid": 3});
+
+        assert!(check_result(&Expect::default(), &Ok(response.clone())).is_ok());
+
+        let expect = Expect {
+            response: Some(json!({"status": 0})),
+            error: None,
+        };
+        assert!(check_result(&expect, &Ok(response.clone())).is_ok());
+
+        let expect = Expect {
+            response: Some(json!({"status": 1})),
+            error: None,
+        };
+        assert!(check_result(&expect, &Ok(response.clone())).is_err());
+
+        // Any error is unexpected unless an error is expected.
+        let result = Err(rpc_error(ttrpc::Code::NOT_FOUND, "no such container"));
+        assert!(check_result(&Expect::default(), &result).is_err());
+
+        let expect = Expect {
+            response: None,
+            error: Some(ExpectedError::default()),
+        };
+        assert!(check_result(&expect, &result).is_ok());
+        assert!(check_result(&expect, &Ok(response)).is_err());
+    }
+
+    #[test]
+    fn test_check_result_expected_error() {
+        let result = Err(rpc_error(ttrpc::Code::NOT_FOUND, "no such container"));
+        let expect_error = |code: Option<&str>, message: Option<&str>| Expect {
+            response: None,
+            error: Some(ExpectedError {
+                code: code.map(|c| c.to_string()),
+                message: message.map(|m| m.to_string()),
+            }),
+        };
+
+        assert!(check_result(&expect_error(Some("NOT_FOUND"), None), &result).is_ok());
+        assert!(check_result(&expect_error(Some("INTERNAL"), None), &result).is_err());
+        assert!(check_result(&expect_error(None, Some("no such")), &result).is_ok());
+        assert!(check_result(&expect_error(None, Some("exists")), &result).is_err());
+        assert!(check_result(&expect_error(Some("NOT_FOUND"), Some("container")), &result).is_ok());
+
+        // Errors not returned by the agent have no status code, the message
+        // is checked against the whole error.
+        let result = Err(anyhow!("invalid method: \"Foo\""));
+        assert!(check_result(&expect_error(Some("NOT_FOUND"), None), &result).is_err());
+        assert!(check_result(&expect_error(None, Some("invalid method")), &result).is_ok());
+    }
+
+    #[test]
+    fn test_scenario_load_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let scenario = Scenario {
+            name: "test".to_string(),
+            steps: vec![
+                Step {
+                    name: "create".to_string(),
+                    method: "CreateContainer".to_string(),
+                    request: Some(json!({"container_id": "foo"})),
+                    delay: Some("1ms".to_string()),
+                    ..Default::default()
+                },
+                Step {
+                    method: "RemoveContainer".to_string(),
+                    request_file: Some(PathBuf::from("remove.json")),
+                    expect: Some(Expect {
+                        response: None,
+                        error: Some(ExpectedError {
+                            code: Some("NOT_FOUND".to_string()),
+                            message: None,
+                        }),
+                    }),
+                    ..Default::default()
+                },
+            ],
+        };
+
+        for file in ["scenario.yaml", "scenario.yml", "scenario.json"] {
+            let path = dir.path().join(file);
+            scenario.save(&path).unwrap();
+            assert_eq!(Scenario::load(&path).unwrap(), scenario);
+        }
+
+        // The format follows the file extension.
+        let data = fs::read_to_string(dir.path().join("scenario.json")).unwrap();
+        assert!(serde_json::from_str::<Value>(&data).is_ok());
+        assert!(Scenario::load(&dir.path().join("missing.json")).is_err());
+
+        fs::write(dir.path().join("invalid.json"), "steps: []").unwrap();
+        assert!(Scenario::load(&dir.path().join("invalid.json")).is_err());
+    }
+
+    #[test]
+    fn test_step_request() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("remove.json"), r#"{"container_id": "foo"}"#).unwrap();
+
+        let step = Step {
+            request_file: Some(PathBuf::from("remove.json")),
+            ..Default::default()
+        };
+        assert_eq!(
+            step_request(&step, dir.path()).unwrap(),
+            json!({"container_id": "foo"})
+        );
+
+        let step = Step {
+            request: Some(json!({"container_id": "bar"})),
+            request_file: Some(PathBuf::from("remove.json")),
+            ..Default::default()
+        };
+        assert_eq!(
+            step_request(&step, dir.path()).unwrap(),
+            json!({"container_id": "bar"})
+        );
+
+        assert_eq!(
+            step_request(&Step::default(), dir.path()).unwrap(),
+            json!({})
+        );
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.yaml");
+
+        let requests = vec![
+            ("Check", json!({}), Ok(json!({"status": "SERVING"}))),
+            (
+                "StartContainer",
+                json!({"container_id": "foo"}),
+                Err(rpc_error(ttrpc::Code::NOT_FOUND, "no such container")),
+            ),
+            (
+                "Foo",
+                json!({}),
+                Err(anyhow!("invalid method: {:?}", "Foo")),
+            ),
+        ];
+
+        let mut recorder = Recorder::default();
+        for (method, request, result) in &requests {
+            recorder.record(method, request, result);
+        }
+        recorder.save(&path).unwrap();
+
+        // The recorded session expects the results it was recorded with.
+        let session = Scenario::load(&path).unwrap();
+        assert_eq!(session.steps.len(), requests.len());
+        for (step, (method, request, result)) in session.steps.iter().zip(&requests) {
+            assert_eq!(&step.method, method);
+            assert_eq!(step.request.as_ref(), Some(request));
+            check_result(step.expect.as_ref().unwrap(), result).unwrap();
+        }
+
+        let expect = session.steps[1].expect.as_ref().unwrap();
+        assert_eq!(
+            expect.error.as_ref().unwrap().code.as_deref(),
+            Some("NOT_FOUND")
+        );
+        assert!(rpc_status(requests[1].2.as_ref().unwrap_err()).is_some());
+        assert_eq!(
+            session.steps[2].expect.as_ref().unwrap().error,
+            Some(ExpectedError::default())
+        );
+
+        // A different result fails the replay.
+        assert!(check_result(
+            session.steps[0].expect.as_ref().unwrap(),
+            &Ok(json!({"status": "NOT_SERVING"}))
+        )
+        .is_err());
+        assert!(check_result(
+            session.steps[1].expect.as_ref().unwrap(),
+            &Err(rpc_error(ttrpc::Code::INTERNAL, "failed"))
+        )
+        .is_err());
+    }
+}
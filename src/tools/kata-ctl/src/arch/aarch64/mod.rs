@@ -7,33 +7,56 @@
 pub use arch_specific::*;
 
 mod arch_specific {
+    use crate::check;
     use crate::types::*;
     use anyhow::Result;
-    use std::path::Path;
 
-    const KVM_DEV: &str = "/dev/kvm";
     #[allow(dead_code)]
     pub const ARCH_CPU_VENDOR_FIELD: &str = "CPU implementer";
     #[allow(dead_code)]
     pub const ARCH_CPU_MODEL_FIELD: &str = "CPU architecture";
 
-    // List of check functions
+    const KERNEL_MODULES: &[KernelModule] = &[
+        KernelModule {
+            name: "kvm",
+            descr: "Kernel-based Virtual Machine",
+            params: &[],
+            required: true,
+        },
+        KernelModule {
+            name: "vhost",
+            descr: "Host kernel accelerator for virtio",
+            params: &[],
+            required: true,
+        },
+        KernelModule {
+            name: "vhost_net",
+            descr: "Host kernel accelerator for virtio network",
+            params: &[],
+            required: true,
+        },
+        KernelModule {
+            name: "vhost_vsock",
+            descr: "Host Support for Virtio VSock",
+            params: &[],
+            required: true,
+        },
+    ];
+
+    // List of check functions, the virtualization support is covered by the generic check of
+    // the KVM device.
     static CHECK_LIST: &[CheckItem] = &[CheckItem {
-        name: CheckType::CheckCpu,
-        descr: "This parameter performs the host check",
-        fp: check,
+        name: CheckType::CheckKernelModules,
+        descr: "This parameter performs the kernel modules check",
+        fp: check_kernel_modules,
         perm: PermissionType::NonPrivileged,
     }];
 
-    pub fn check(_args: &str) -> Result<()> {
-        println!("INFO: check: aarch64");
-        if Path::new(KVM_DEV).exists() {
-            println!("Kata Containers can run on this host\n");
-        } else {
-            eprintln!("WARNING: Kata Containers can't run on this host as lack of virtulization support\n");
-        }
-
-        Ok(())
+    fn check_kernel_modules(_args: &str) -> Result<Vec<CheckResult>> {
+        Ok(check::check_kernel_modules(
+            check::SYS_MODULE_DIR,
+            KERNEL_MODULES,
+        ))
     }
 
     pub fn get_checks() -> Option<&'static [CheckItem<'static>]> {
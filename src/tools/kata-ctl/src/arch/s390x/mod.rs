@@ -21,10 +21,35 @@ mod arch_specific {
     #[allow(dead_code)]
     pub const ARCH_CPU_MODEL_FIELD: &str = "machine";
 
-    // check cpu
-    fn check_cpu() -> Result<()> {
-        println!("INFO: check CPU: s390x");
+    const KERNEL_MODULES: &[KernelModule] = &[
+        KernelModule {
+            name: "kvm",
+            descr: "Kernel-based Virtual Machine",
+            params: &[],
+            required: true,
+        },
+        KernelModule {
+            name: "vhost",
+            descr: "Host kernel accelerator for virtio",
+            params: &[],
+            required: true,
+        },
+        KernelModule {
+            name: "vhost_net",
+            descr: "Host kernel accelerator for virtio network",
+            params: &[],
+            required: true,
+        },
+        KernelModule {
+            name: "vhost_vsock",
+            descr: "Host Support for Virtio VSock",
+            params: &[],
+            required: true,
+        },
+    ];
 
+    // check cpu
+    fn check_cpu(_args: &str) -> Result<Vec<CheckResult>> {
         let cpu_info = check::get_single_cpu_info(check::PROC_CPUINFO, CPUINFO_DELIMITER)?;
 
         let cpu_features = check::get_cpu_flags(&cpu_info, CPUINFO_FEATURES_TAG).map_err(|e| {
@@ -36,31 +61,45 @@ mod arch_specific {
         })?;
 
         let missing_cpu_features = check::check_cpu_flags(&cpu_features, CPU_FEATURES_REQ)?;
-        if !missing_cpu_features.is_empty() {
-            eprintln!("WARNING: Missing CPU flags {:?}", missing_cpu_features);
-        }
+        let result = if missing_cpu_features.is_empty() {
+            CheckResult::new(
+                "cpu",
+                CheckStatus::Pass,
+                format!("CPU with required features {:?}", CPU_FEATURES_REQ),
+            )
+        } else {
+            CheckResult::new(
+                "cpu",
+                CheckStatus::Fail,
+                format!("CPU missing features {:?}", missing_cpu_features),
+            )
+        };
 
-        Ok(())
+        Ok(vec![result])
     }
 
-    pub fn check(_args: &str) -> Result<()> {
-        println!("INFO: check: s390x");
-
-        let _cpu_result = check_cpu();
-
-        // TODO: add additional checks, e.g, kernel modules as in go runtime
-        // TODO: collect outcome of tests to determine if checks pass or not
-
-        Ok(())
+    fn check_kernel_modules(_args: &str) -> Result<Vec<CheckResult>> {
+        Ok(check::check_kernel_modules(
+            check::SYS_MODULE_DIR,
+            KERNEL_MODULES,
+        ))
     }
 
     // List of check functions
-    static CHECK_LIST: &[CheckItem] = &[CheckItem {
-        name: CheckType::CheckCpu,
-        descr: "This parameter performs the cpu check",
-        fp: check,
-        perm: PermissionType::NonPrivileged,
-    }];
+    static CHECK_LIST: &[CheckItem] = &[
+        CheckItem {
+            name: CheckType::CheckCpu,
+            descr: "This parameter performs the cpu check",
+            fp: check_cpu,
+            perm: PermissionType::NonPrivileged,
+        },
+        CheckItem {
+            name: CheckType::CheckKernelModules,
+            descr: "This parameter performs the kernel modules check",
+            fp: check_kernel_modules,
+            perm: PermissionType::NonPrivileged,
+        },
+    ];
 
     pub fn get_checks() -> Option<&'static [CheckItem<'static>]> {
         Some(CHECK_LIST)
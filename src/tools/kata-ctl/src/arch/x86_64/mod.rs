@@ -14,26 +14,101 @@ mod arch_specific {
     const CPUINFO_DELIMITER: &str = "\nprocessor";
     const CPUINFO_FLAGS_TAG: &str = "flags";
     const CPU_FLAGS_INTEL: &[&str] = &["lm", "sse4_1", "vmx"];
-    const CPU_ATTRIBS_INTEL: &[&str] = &["GenuineIntel"];
+    const CPU_FLAGS_AMD: &[&str] = &["lm", "sse4_1", "svm"];
+    const CPU_VENDOR_INTEL: &str = "GenuineIntel";
+    const CPU_VENDOR_AMD: &str = "AuthenticAMD";
+    // set when running in a virtual machine, nested virtualization is then required
+    const CPU_FLAG_HYPERVISOR: &str = "hypervisor";
     pub const ARCH_CPU_VENDOR_FIELD: &str = check::GENERIC_CPU_VENDOR_FIELD;
     pub const ARCH_CPU_MODEL_FIELD: &str = check::GENERIC_CPU_MODEL_FIELD;
 
+    const KERNEL_MODULES_COMMON: &[KernelModule] = &[
+        KernelModule {
+            name: "kvm",
+            descr: "Kernel-based Virtual Machine",
+            params: &[],
+            required: true,
+        },
+        KernelModule {
+            name: "vhost",
+            descr: "Host kernel accelerator for virtio",
+            params: &[],
+            required: true,
+        },
+        KernelModule {
+            name: "vhost_net",
+            descr: "Host kernel accelerator for virtio network",
+            params: &[],
+            required: true,
+        },
+        KernelModule {
+            name: "vhost_vsock",
+            descr: "Host Support for Virtio VSock",
+            params: &[],
+            required: true,
+        },
+    ];
+
+    const KERNEL_MODULE_INTEL: KernelModule = KernelModule {
+        name: "kvm_intel",
+        descr: "Intel KVM",
+        params: &[
+            KernelParam {
+                name: "nested",
+                value: "Y",
+                required: false,
+            },
+            // required for running guests in real mode
+            KernelParam {
+                name: "unrestricted_guest",
+                value: "Y",
+                required: true,
+            },
+        ],
+        required: true,
+    };
+
+    const KERNEL_MODULE_AMD: KernelModule = KernelModule {
+        name: "kvm_amd",
+        descr: "AMD KVM",
+        params: &[KernelParam {
+            name: "nested",
+            value: "1",
+            required: false,
+        }],
+        required: true,
+    };
+
     // List of check functions
-    static CHECK_LIST: &[CheckItem] = &[CheckItem {
-        name: CheckType::CheckCpu,
-        descr: "This parameter performs the cpu check",
-        fp: check_cpu,
-        perm: PermissionType::NonPrivileged,
-    }];
+    static CHECK_LIST: &[CheckItem] = &[
+        CheckItem {
+            name: CheckType::CheckCpu,
+            descr: "This parameter performs the cpu check",
+            fp: check_cpu,
+            perm: PermissionType::NonPrivileged,
+        },
+        CheckItem {
+            name: CheckType::CheckKernelModules,
+            descr: "This parameter performs the kernel modules check",
+            fp: check_kernel_modules,
+            perm: PermissionType::NonPrivileged,
+        },
+    ];
 
     pub fn get_checks() -> Option<&'static [CheckItem<'static>]> {
         Some(CHECK_LIST)
     }
 
-    // check cpu
-    fn check_cpu(_args: &str) -> Result<()> {
-        println!("INFO: check CPU: x86_64");
+    fn get_cpu_vendor(cpu_info: &str) -> Option<&'static str> {
+        [CPU_VENDOR_INTEL, CPU_VENDOR_AMD]
+            .iter()
+            .copied()
+            .find(|vendor| cpu_info.split_whitespace().any(|x| x == *vendor))
+    }
 
+    // check cpu
+    fn check_cpu(_args: &str) -> Result<Vec<CheckResult>> {
+        let name = "cpu";
         let cpu_info = check::get_single_cpu_info(check::PROC_CPUINFO, CPUINFO_DELIMITER)?;
 
         let cpu_flags = check::get_cpu_flags(&cpu_info, CPUINFO_FLAGS_TAG).map_err(|e| {
@@ -44,21 +119,61 @@ mod arch_specific {
             )
         })?;
 
-        // perform checks
-        // TODO: Perform checks based on hypervisor type
-        // TODO: Add more information to output (see kata-check in go tool); adjust formatting
-        let missing_cpu_attributes = check::check_cpu_attribs(&cpu_info, CPU_ATTRIBS_INTEL)?;
-        if !missing_cpu_attributes.is_empty() {
-            eprintln!(
-                "WARNING: Missing CPU attributes {:?}",
-                missing_cpu_attributes
-            );
+        let (vendor, required_flags) = match get_cpu_vendor(&cpu_info) {
+            Some(CPU_VENDOR_AMD) => (CPU_VENDOR_AMD, CPU_FLAGS_AMD),
+            Some(vendor) => (vendor, CPU_FLAGS_INTEL),
+            None => {
+                return Ok(vec![CheckResult::new(
+                    name,
+                    CheckStatus::Fail,
+                    format!(
+                        "unsupported CPU vendor, expected {} or {}",
+                        CPU_VENDOR_INTEL, CPU_VENDOR_AMD
+                    ),
+                )])
+            }
+        };
+
+        let mut results = Vec::new();
+        let missing_cpu_flags = check::check_cpu_flags(&cpu_flags, required_flags)?;
+        if missing_cpu_flags.is_empty() {
+            results.push(CheckResult::new(
+                name,
+                CheckStatus::Pass,
+                format!("{} CPU with required flags {:?}", vendor, required_flags),
+            ));
+        } else {
+            results.push(CheckResult::new(
+                name,
+                CheckStatus::Fail,
+                format!("{} CPU missing flags {:?}", vendor, missing_cpu_flags),
+            ));
         }
-        let missing_cpu_flags = check::check_cpu_flags(&cpu_flags, CPU_FLAGS_INTEL)?;
-        if !missing_cpu_flags.is_empty() {
-            eprintln!("WARNING: Missing CPU flags {:?}", missing_cpu_flags);
+
+        if cpu_flags
+            .split_whitespace()
+            .any(|f| f == CPU_FLAG_HYPERVISOR)
+        {
+            results.push(CheckResult::new(
+                "nested-virtualization",
+                CheckStatus::Warn,
+                "running in a virtual machine, nested virtualization must be enabled on the host"
+                    .to_string(),
+            ));
+        }
+
+        Ok(results)
+    }
+
+    fn check_kernel_modules(_args: &str) -> Result<Vec<CheckResult>> {
+        let cpu_info = check::get_single_cpu_info(check::PROC_CPUINFO, CPUINFO_DELIMITER)?;
+
+        let mut modules = KERNEL_MODULES_COMMON.to_vec();
+        match get_cpu_vendor(&cpu_info) {
+            Some(CPU_VENDOR_AMD) => modules.push(KERNEL_MODULE_AMD),
+            _ => modules.push(KERNEL_MODULE_INTEL),
         }
 
-        Ok(())
+        Ok(check::check_kernel_modules(check::SYS_MODULE_DIR, &modules))
     }
 }
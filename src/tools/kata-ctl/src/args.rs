@@ -12,6 +12,14 @@ use thiserror::Error;
 pub struct KataCtlCli {
     #[clap(subcommand)]
     pub command: Commands,
+
+    /// Log to a sink: json or logfmt on stderr, journald or syslog; nothing is logged if not specified
+    #[clap(long, global = true)]
+    pub log_sink: Option<String>,
+
+    /// Level of the records logged to the sink
+    #[clap(long, global = true, default_value = "info")]
+    pub log_level: String,
 }
 
 #[derive(Debug, Subcommand)]
@@ -23,7 +31,7 @@ pub enum Commands {
     DirectVolume(DirectVolumeCommand),
 
     /// Display settings
-    Env,
+    Env(EnvArgument),
 
     /// Enter into guest VM by debug console
     Exec(ExecArguments),
@@ -34,9 +42,15 @@ pub enum Commands {
     /// Manage guest VM iptables
     Iptables(IptablesCommand),
 
+    /// Show or change the log levels of the shim or the agent of a sandbox
+    LogLevel(LogLevelArgs),
+
     /// Gather metrics associated with infrastructure used to run a sandbox
     Metrics(MetricsCommand),
 
+    /// Inspect the sandboxes on the host
+    Sandbox(SandboxCommand),
+
     /// Display version details
     Version,
 }
@@ -44,6 +58,14 @@ pub enum Commands {
 #[derive(Debug, Args, Error)]
 #[error("Argument is not valid")]
 pub struct CheckArgument {
+    /// Kata configuration file, the default configuration file is used if not specified
+    #[clap(short, long, global = true)]
+    pub config: Option<String>,
+
+    /// Format the results of the host checks as JSON
+    #[clap(long, global = true)]
+    pub json: bool,
+
     #[clap(subcommand)]
     pub command: CheckSubCommand,
 }
@@ -56,6 +78,9 @@ pub enum CheckSubCommand {
     /// Run all checks but excluding network checks.
     NoNetworkChecks,
 
+    /// Only run the host capability checks, e.g. KVM, kernel modules and cgroups
+    Host,
+
     /// Only compare the current and latest available versions
     CheckVersionOnly,
 
@@ -69,19 +94,102 @@ pub enum CheckSubCommand {
     List,
 }
 
+#[derive(Debug, Args)]
+pub struct EnvArgument {
+    /// Kata configuration file, the default configuration file is used if not specified
+    #[clap(short, long)]
+    pub config: Option<String>,
+
+    /// Format output as JSON, the default format is TOML
+    #[clap(long)]
+    pub json: bool,
+}
+
 #[derive(Debug, Args)]
 pub struct MetricsCommand {
+    /// Serve the metrics on the address, e.g. 0.0.0.0:8090, instead of printing them once
+    #[clap(short, long)]
+    pub listen: Option<String>,
+
+    /// Timeout in milliseconds to get the metrics of a sandbox
+    #[clap(short, long, default_value_t = 3000)]
+    pub timeout: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct LogLevelArgs {
+    /// pod sandbox ID.
+    pub sandbox_id: String,
+
+    /// The new level: trace, debug, info, warn, error or critical. The levels of the shim are
+    /// shown if not specified
+    pub level: Option<String>,
+
+    /// The subsystem to change the level of, e.g. hypervisor, resource or agent. The default level
+    /// of the subsystems without their own level is changed if not specified
+    #[clap(short, long)]
+    pub subsystem: Option<String>,
+
+    /// Change the level of the agent in the guest VM rather than the one of the shim
+    #[clap(long)]
+    pub guest: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SandboxCommand {
     #[clap(subcommand)]
-    pub metrics_cmd: MetricsSubCommand,
+    pub sandbox_cmd: SandboxSubCommand,
 }
 
 #[derive(Debug, Subcommand)]
-pub enum MetricsSubCommand {
-    /// Arguments for metrics
-    MetricsArgs,
+pub enum SandboxSubCommand {
+    /// List the sandboxes on the host
+    List(SandboxListArgs),
+
+    /// Show the details of a sandbox in JSON
+    Describe(SandboxDescribeArgs),
+
+    /// Stream the log of the agent in the guest VM of a sandbox
+    Logs(SandboxLogsArgs),
+
+    /// Show the event log of a sandbox
+    Events(SandboxEventsArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct SandboxListArgs {
+    /// Format output as JSON
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SandboxDescribeArgs {
+    /// pod sandbox ID.
+    pub sandbox_id: String,
+}
+
+#[derive(Debug, Args)]
+pub struct SandboxLogsArgs {
+    /// pod sandbox ID.
+    pub sandbox_id: String,
+}
+
+#[derive(Debug, Args)]
+pub struct SandboxEventsArgs {
+    /// pod sandbox ID.
+    pub sandbox_id: String,
+
+    /// Kata configuration file to get the directory of the event logs, the default
+    /// configuration file is used if not specified
+    #[clap(short, long)]
+    pub config: Option<String>,
+
+    /// Output the events as JSON lines
+    #[clap(long)]
+    pub json: bool,
 }
 
-// #[derive(Parser, Debug)]
 #[derive(Debug, Args)]
 pub struct IptablesCommand {
     #[clap(subcommand)]
@@ -90,8 +198,37 @@ pub struct IptablesCommand {
 
 #[derive(Debug, Subcommand)]
 pub enum IpTablesArguments {
-    /// Configure iptables
-    Metrics,
+    /// Get iptables from the guest VM of a sandbox
+    Get(IptablesGetArgs),
+
+    /// Set iptables in the guest VM of a sandbox
+    Set(IptablesSetArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct IptablesGetArgs {
+    /// pod sandbox ID.
+    #[clap(long)]
+    pub sandbox_id: String,
+
+    /// Get ip6tables instead of iptables
+    #[clap(long)]
+    pub v6: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct IptablesSetArgs {
+    /// pod sandbox ID.
+    #[clap(long)]
+    pub sandbox_id: String,
+
+    /// Set ip6tables instead of iptables
+    #[clap(long)]
+    pub v6: bool,
+
+    /// File of the rules in the iptables-save format
+    #[clap(short, long)]
+    pub file: String,
 }
 
 #[derive(Debug, Args)]
@@ -5,9 +5,15 @@
 
 // Contains checks that are not architecture-specific
 
+use crate::types::{CheckResult, CheckStatus, KernelModule};
+
 use anyhow::{anyhow, Result};
+use kata_types::config::{hypervisor::HYPERVISOR_NAME_DRAGONBALL, TomlConfig};
 use reqwest::header::{CONTENT_TYPE, USER_AGENT};
 use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 struct Release {
     tag_name: String,
@@ -125,6 +131,310 @@ pub fn check_cpu_attribs(
     Ok(missing_attribs)
 }
 
+pub const SYS_MODULE_DIR: &str = "/sys/module";
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+pub const KVM_DEVICE: &str = "/dev/kvm";
+pub const VHOST_VSOCK_DEVICE: &str = "/dev/vhost-vsock";
+pub const PROC_MEMINFO: &str = "/proc/meminfo";
+
+// cgroup controllers used by the runtime to limit the sandboxes
+const CGROUP_CONTROLLERS: &[&str] = &["cpu", "cpuset", "memory", "pids"];
+const CGROUP_V2_CONTROLLERS_FILE: &str = "cgroup.controllers";
+const VIRTIO_FS: &str = "virtio-fs";
+
+// check_kvm_device checks the KVM device exists and is accessible by the current user
+pub fn check_kvm_device(kvm_device: &str) -> CheckResult {
+    let name = "kvm";
+    match fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(kvm_device)
+    {
+        Ok(_) => CheckResult::new(
+            name,
+            CheckStatus::Pass,
+            format!("{} is accessible", kvm_device),
+        ),
+        Err(e) if e.kind() == ErrorKind::NotFound => CheckResult::new(
+            name,
+            CheckStatus::Fail,
+            format!(
+                "{} not found, virtualization is disabled or the kvm module is not loaded",
+                kvm_device
+            ),
+        ),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => CheckResult::new(
+            name,
+            CheckStatus::Fail,
+            format!("no permission to access {}", kvm_device),
+        ),
+        Err(e) => CheckResult::new(
+            name,
+            CheckStatus::Fail,
+            format!("failed to open {}: {}", kvm_device, e),
+        ),
+    }
+}
+
+// check_kernel_modules checks the kernel modules are loaded (or built in) and that their
+// parameters have the expected values
+pub fn check_kernel_modules(sys_module_dir: &str, modules: &[KernelModule]) -> Vec<CheckResult> {
+    let mut results = Vec::new();
+
+    for module in modules {
+        let name = format!("kernel-module {}", module.name);
+        let module_dir = Path::new(sys_module_dir).join(module.name);
+        if !module_dir.exists() {
+            let status = if module.required {
+                CheckStatus::Fail
+            } else {
+                CheckStatus::Warn
+            };
+            results.push(CheckResult::new(
+                &name,
+                status,
+                format!("{} ({}) is not loaded", module.name, module.descr),
+            ));
+            continue;
+        }
+        results.push(CheckResult::new(
+            &name,
+            CheckStatus::Pass,
+            format!("{} ({}) is loaded", module.name, module.descr),
+        ));
+
+        for param in module.params {
+            let name = format!("kernel-module {}.{}", module.name, param.name);
+            let path = module_dir.join("parameters").join(param.name);
+            let (status, message) = match fs::read_to_string(&path) {
+                Ok(value) if value.trim() == param.value => (
+                    CheckStatus::Pass,
+                    format!("{} is {}", param.name, param.value),
+                ),
+                Ok(value) => (
+                    CheckStatus::Fail,
+                    format!(
+                        "{} is {}, expected {}",
+                        param.name,
+                        value.trim(),
+                        param.value
+                    ),
+                ),
+                Err(e) => (
+                    CheckStatus::Fail,
+                    format!("failed to read {}: {}", path.display(), e),
+                ),
+            };
+            let status = if status == CheckStatus::Fail && !param.required {
+                CheckStatus::Warn
+            } else {
+                status
+            };
+            results.push(CheckResult::new(&name, status, message));
+        }
+    }
+
+    results
+}
+
+// check_cgroups checks the cgroup layout of the host and the controllers used by the runtime
+pub fn check_cgroups(cgroup_root: &str) -> CheckResult {
+    let name = "cgroups";
+    let root = Path::new(cgroup_root);
+
+    let (layout, missing) = match fs::read_to_string(root.join(CGROUP_V2_CONTROLLERS_FILE)) {
+        Ok(controllers) => {
+            let missing: Vec<&str> = CGROUP_CONTROLLERS
+                .iter()
+                .filter(|c| !controllers.split_whitespace().any(|x| x == **c))
+                .copied()
+                .collect();
+            ("v2 (unified)", missing)
+        }
+        Err(_) => {
+            if !root.exists() {
+                return CheckResult::new(
+                    name,
+                    CheckStatus::Fail,
+                    format!("{} not found, cgroups are not mounted", cgroup_root),
+                );
+            }
+            let layout = if root.join("unified").exists() {
+                "v1 (hybrid)"
+            } else {
+                "v1 (legacy)"
+            };
+            let missing: Vec<&str> = CGROUP_CONTROLLERS
+                .iter()
+                .filter(|c| !root.join(c).exists())
+                .copied()
+                .collect();
+            (layout, missing)
+        }
+    };
+
+    if missing.is_empty() {
+        CheckResult::new(
+            name,
+            CheckStatus::Pass,
+            format!(
+                "cgroup {}, controllers {:?} available",
+                layout, CGROUP_CONTROLLERS
+            ),
+        )
+    } else {
+        CheckResult::new(
+            name,
+            CheckStatus::Fail,
+            format!("cgroup {}, controllers {:?} not available", layout, missing),
+        )
+    }
+}
+
+// check_hugepages checks the huge pages reserved on the host, `required_mb` is the size of the
+// guest memory to back with huge pages, if any.
+pub fn check_hugepages(meminfo_file: &str, required_mb: Option<u64>) -> CheckResult {
+    let name = "hugepages";
+    let meminfo = match fs::read_to_string(meminfo_file) {
+        Ok(meminfo) => meminfo,
+        Err(e) => {
+            return CheckResult::new(
+                name,
+                CheckStatus::Fail,
+                format!("failed to read {}: {}", meminfo_file, e),
+            )
+        }
+    };
+
+    let get_field = |field: &str| -> u64 {
+        meminfo
+            .lines()
+            .find(|l| l.starts_with(field))
+            .and_then(|l| l.split_whitespace().nth(1))
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or_default()
+    };
+    let total = get_field("HugePages_Total:");
+    let free = get_field("HugePages_Free:");
+    let size_kb = get_field("Hugepagesize:");
+    let free_mb = free * size_kb / 1024;
+    let message = format!(
+        "{} of {} huge pages of {} kB free ({} MiB)",
+        free, total, size_kb, free_mb
+    );
+
+    match required_mb {
+        Some(required_mb) if free_mb < required_mb => CheckResult::new(
+            name,
+            CheckStatus::Fail,
+            format!(
+                "{}, {} MiB required by enable_hugepages",
+                message, required_mb
+            ),
+        ),
+        Some(_) => CheckResult::new(name, CheckStatus::Pass, message),
+        None if total == 0 => CheckResult::new(
+            name,
+            CheckStatus::Warn,
+            "no huge pages reserved, they are only needed when enable_hugepages is set".to_string(),
+        ),
+        None => CheckResult::new(name, CheckStatus::Pass, message),
+    }
+}
+
+// check_vsock checks the host supports the vsock used to talk to the agent
+pub fn check_vsock(vhost_vsock_device: &str) -> CheckResult {
+    let name = "vsock";
+    if Path::new(vhost_vsock_device).exists() {
+        CheckResult::new(
+            name,
+            CheckStatus::Pass,
+            format!("{} is available", vhost_vsock_device),
+        )
+    } else {
+        CheckResult::new(
+            name,
+            CheckStatus::Fail,
+            format!(
+                "{} not found, load the vhost_vsock module",
+                vhost_vsock_device
+            ),
+        )
+    }
+}
+
+fn check_file(name: &str, path: &str) -> CheckResult {
+    if path.is_empty() {
+        CheckResult::new(name, CheckStatus::Fail, "not configured".to_string())
+    } else if Path::new(path).is_file() {
+        CheckResult::new(name, CheckStatus::Pass, format!("{} exists", path))
+    } else {
+        CheckResult::new(name, CheckStatus::Fail, format!("{} not found", path))
+    }
+}
+
+// check_config_files checks the files configured for the hypervisor exist
+pub fn check_config_files(config: &TomlConfig) -> Vec<CheckResult> {
+    let hypervisor_name = &config.runtime.hypervisor_name;
+    let hypervisor = match config.hypervisor.get(hypervisor_name) {
+        Some(hypervisor) => hypervisor,
+        None => {
+            return vec![CheckResult::new(
+                "config hypervisor",
+                CheckStatus::Fail,
+                format!("hypervisor {} not found in configuration", hypervisor_name),
+            )]
+        }
+    };
+
+    let mut results = Vec::new();
+    // Dragonball is built into the runtime, there is no separate executable.
+    if hypervisor_name != HYPERVISOR_NAME_DRAGONBALL {
+        results.push(check_file("config hypervisor", &hypervisor.path));
+    }
+    results.push(check_file("config kernel", &hypervisor.boot_info.kernel));
+    if !hypervisor.boot_info.image.is_empty() || hypervisor.boot_info.initrd.is_empty() {
+        results.push(check_file("config image", &hypervisor.boot_info.image));
+    }
+    if !hypervisor.boot_info.initrd.is_empty() {
+        results.push(check_file("config initrd", &hypervisor.boot_info.initrd));
+    }
+    if hypervisor.shared_fs.shared_fs.as_deref() == Some(VIRTIO_FS) {
+        results.push(check_file(
+            "config virtiofsd",
+            &hypervisor.shared_fs.virtio_fs_daemon,
+        ));
+    }
+
+    results
+}
+
+// run_generic_host_checks runs the checks which are not architecture-specific, the
+// configuration related checks are skipped if no configuration is available
+pub fn run_generic_host_checks(config: Option<&TomlConfig>) -> Vec<CheckResult> {
+    let mut results = vec![check_kvm_device(KVM_DEVICE), check_cgroups(CGROUP_ROOT)];
+
+    let required_mb = config.and_then(|c| {
+        c.hypervisor
+            .get(&c.runtime.hypervisor_name)
+            .filter(|h| h.memory_info.enable_hugepages)
+            .map(|h| h.memory_info.default_memory as u64)
+    });
+    results.push(check_hugepages(PROC_MEMINFO, required_mb));
+    results.push(check_vsock(VHOST_VSOCK_DEVICE));
+
+    match config {
+        Some(config) => results.extend(check_config_files(config)),
+        None => results.push(CheckResult::new(
+            "config",
+            CheckStatus::Warn,
+            "no Kata configuration found, configuration checks skipped".to_string(),
+        )),
+    }
+
+    results
+}
+
 pub fn run_network_checks() -> Result<()> {
     Ok(())
 }
@@ -201,6 +511,7 @@ pub fn check_official_releases() -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::KernelParam;
     use semver::Version;
     use std::fs;
     use std::io::Write;
@@ -332,6 +643,164 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_check_kernel_modules() {
+        let dir = tempdir().unwrap();
+        let params = dir.path().join("kvm_intel").join("parameters");
+        fs::create_dir_all(&params).unwrap();
+        fs::write(params.join("nested"), "N\n").unwrap();
+        fs::write(params.join("unrestricted_guest"), "Y\n").unwrap();
+
+        let modules = &[
+            KernelModule {
+                name: "kvm_intel",
+                descr: "Intel KVM",
+                params: &[
+                    KernelParam {
+                        name: "nested",
+                        value: "Y",
+                        required: false,
+                    },
+                    KernelParam {
+                        name: "unrestricted_guest",
+                        value: "Y",
+                        required: true,
+                    },
+                    KernelParam {
+                        name: "enable_apicv",
+                        value: "Y",
+                        required: true,
+                    },
+                ],
+                required: true,
+            },
+            KernelModule {
+                name: "vhost_vsock",
+                descr: "vsock",
+                params: &[],
+                required: true,
+            },
+            KernelModule {
+                name: "vhost_net",
+                descr: "vhost net",
+                params: &[],
+                required: false,
+            },
+        ];
+
+        let statuses: Vec<(String, CheckStatus)> =
+            check_kernel_modules(dir.path().to_str().unwrap(), modules)
+                .into_iter()
+                .map(|r| (r.name, r.status))
+                .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("kernel-module kvm_intel".to_string(), CheckStatus::Pass),
+                (
+                    "kernel-module kvm_intel.nested".to_string(),
+                    CheckStatus::Warn
+                ),
+                (
+                    "kernel-module kvm_intel.unrestricted_guest".to_string(),
+                    CheckStatus::Pass
+                ),
+                (
+                    "kernel-module kvm_intel.enable_apicv".to_string(),
+                    CheckStatus::Fail
+                ),
+                ("kernel-module vhost_vsock".to_string(), CheckStatus::Fail),
+                ("kernel-module vhost_net".to_string(), CheckStatus::Warn),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_cgroups() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        // cgroup v1
+        for c in ["cpu", "cpuset", "memory"] {
+            fs::create_dir(dir.path().join(c)).unwrap();
+        }
+        assert_eq!(check_cgroups(root).status, CheckStatus::Fail);
+        fs::create_dir(dir.path().join("pids")).unwrap();
+        let result = check_cgroups(root);
+        assert_eq!(result.status, CheckStatus::Pass);
+        assert!(result.message.contains("v1 (legacy)"));
+
+        // cgroup v2
+        let controllers = dir.path().join(CGROUP_V2_CONTROLLERS_FILE);
+        fs::write(&controllers, "cpuset cpu io memory hugetlb\n").unwrap();
+        assert_eq!(check_cgroups(root).status, CheckStatus::Fail);
+        fs::write(&controllers, "cpuset cpu io memory hugetlb pids\n").unwrap();
+        let result = check_cgroups(root);
+        assert_eq!(result.status, CheckStatus::Pass);
+        assert!(result.message.contains("v2 (unified)"));
+
+        let missing = dir.path().join("missing");
+        assert_eq!(
+            check_cgroups(missing.to_str().unwrap()).status,
+            CheckStatus::Fail
+        );
+    }
+
+    #[test]
+    fn test_check_hugepages() {
+        let dir = tempdir().unwrap();
+        let meminfo = dir.path().join("meminfo");
+        let meminfo_file = meminfo.to_str().unwrap();
+
+        assert_eq!(
+            check_hugepages(meminfo_file, None).status,
+            CheckStatus::Fail
+        );
+
+        fs::write(
+            &meminfo,
+            "MemTotal: 16314920 kB\nHugePages_Total: 0\nHugePages_Free: 0\nHugepagesize: 2048 kB\n",
+        )
+        .unwrap();
+        assert_eq!(
+            check_hugepages(meminfo_file, None).status,
+            CheckStatus::Warn
+        );
+        assert_eq!(
+            check_hugepages(meminfo_file, Some(2048)).status,
+            CheckStatus::Fail
+        );
+
+        fs::write(&meminfo, "MemTotal: 16314920 kB\nHugePages_Total: 1024\nHugePages_Free: 1000\nHugepagesize: 2048 kB\n").unwrap();
+        assert_eq!(
+            check_hugepages(meminfo_file, None).status,
+            CheckStatus::Pass
+        );
+        assert_eq!(
+            check_hugepages(meminfo_file, Some(2000)).status,
+            CheckStatus::Pass
+        );
+        assert_eq!(
+            check_hugepages(meminfo_file, Some(2001)).status,
+            CheckStatus::Fail
+        );
+    }
+
+    #[test]
+    fn test_check_devices() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let missing = missing.to_str().unwrap();
+        assert_eq!(check_kvm_device(missing).status, CheckStatus::Fail);
+        assert_eq!(check_vsock(missing).status, CheckStatus::Fail);
+
+        let device = dir.path().join("device");
+        fs::write(&device, b"").unwrap();
+        let device = device.to_str().unwrap();
+        assert_eq!(check_kvm_device(device).status, CheckStatus::Pass);
+        assert_eq!(check_vsock(device).status, CheckStatus::Pass);
+    }
+
     #[test]
     fn test_get_kata_all_releases_by_url() {
         #[derive(Debug)]
@@ -10,29 +10,54 @@ mod ops;
 mod types;
 mod utils;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::Parser;
+use std::io;
 use std::process::exit;
 
 use args::{Commands, KataCtlCli};
 
-use ops::check_ops::{
-    handle_check, handle_env, handle_factory, handle_iptables, handle_metrics, handle_version,
-};
+use ops::check_ops::{handle_check, handle_factory, handle_version};
+use ops::env_ops::handle_env;
 use ops::exec_ops::handle_exec;
+use ops::iptables_ops::handle_iptables;
+use ops::log_level_ops::handle_log_level;
+use ops::metrics_ops::handle_metrics;
+use ops::sandbox_ops::handle_sandbox;
 use ops::volume_ops::handle_direct_volume;
 
+// Set the global logger used by the subcommands, which discards the records by default.
+fn setup_logger(
+    log_sink: Option<&str>,
+    log_level: &str,
+) -> Result<Option<(slog_scope::GlobalLoggerGuard, slog_async::AsyncGuard)>> {
+    let log_sink = match log_sink {
+        Some(log_sink) => log_sink,
+        None => return Ok(None),
+    };
+
+    let level = logging::level_name_to_slog_level(log_level).map_err(|e| anyhow!(e))?;
+    let sink = logging::LogSink::from_name(log_sink, io::stderr()).map_err(|e| anyhow!(e))?;
+    let name = env!("CARGO_PKG_NAME");
+    let (logger, async_guard) = logging::create_logger_with_sink(name, name, level, sink)?;
+
+    Ok(Some((slog_scope::set_global_logger(logger), async_guard)))
+}
+
 fn real_main() -> Result<()> {
     let args = KataCtlCli::parse();
+    let _logger_guards = setup_logger(args.log_sink.as_deref(), &args.log_level)?;
 
     match args.command {
         Commands::Check(args) => handle_check(args),
         Commands::DirectVolume(args) => handle_direct_volume(args),
         Commands::Exec(args) => handle_exec(args),
-        Commands::Env => handle_env(),
+        Commands::Env(args) => handle_env(args),
         Commands::Factory => handle_factory(),
         Commands::Iptables(args) => handle_iptables(args),
+        Commands::LogLevel(args) => handle_log_level(args),
         Commands::Metrics(args) => handle_metrics(args),
+        Commands::Sandbox(args) => handle_sandbox(args),
         Commands::Version => handle_version(),
     }
 }
@@ -4,6 +4,11 @@
 //
 
 pub mod check_ops;
+pub mod env_ops;
 pub mod exec_ops;
+pub mod iptables_ops;
+pub mod log_level_ops;
+pub mod metrics_ops;
+pub mod sandbox_ops;
 pub mod version;
 pub mod volume_ops;
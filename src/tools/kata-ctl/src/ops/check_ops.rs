@@ -5,7 +5,7 @@
 
 use crate::arch::arch_specific::get_checks;
 
-use crate::args::{CheckArgument, CheckSubCommand, IptablesCommand, MetricsCommand};
+use crate::args::{CheckArgument, CheckSubCommand};
 
 use crate::check;
 
@@ -13,31 +13,61 @@ use crate::ops::version;
 
 use crate::types::*;
 
+use crate::utils;
+
 use anyhow::{anyhow, Result};
+use serde::Serialize;
 
 const NAME: &str = "kata-ctl";
 
-// This function retrieves the cmd function passes as argument
-fn get_builtin_check_func(name: CheckType) -> Result<BuiltinCmdFp> {
+#[derive(Serialize)]
+struct HostCheckReport {
+    passed: bool,
+    results: Vec<CheckResult>,
+}
+
+// This function runs the architecture-specific checks followed by the generic ones
+fn run_host_checks(config_file: Option<&str>) -> Result<Vec<CheckResult>> {
+    let config = match utils::load_kata_config(config_file) {
+        Ok((config, _)) => Some(config),
+        // a configuration file specified explicitly must be valid
+        Err(e) if config_file.is_some() => return Err(e),
+        Err(_) => None,
+    };
+
+    let mut results = Vec::new();
     if let Some(check_list) = get_checks() {
         for check in check_list {
-            if check.name.eq(&name) {
-                return Ok(check.fp);
-            }
+            results.extend((check.fp)("")?);
         }
     }
+    results.extend(check::run_generic_host_checks(config.as_ref()));
 
-    Err(anyhow!("Invalid command: {:?}", name))
+    Ok(results)
 }
 
-// This function is called from each 'kata-ctl check' argument section
-fn handle_builtin_check(check: CheckType, args: &str) -> Result<()> {
-    let f = match get_builtin_check_func(check) {
-        Ok(fp) => fp,
-        Err(e) => return Err(e),
-    };
+fn handle_host_checks(checkcmd: &CheckArgument) -> Result<()> {
+    let results = run_host_checks(checkcmd.config.as_deref())?;
+    let passed = !results.iter().any(|r| r.status == CheckStatus::Fail);
+
+    if checkcmd.json {
+        let report = HostCheckReport { passed, results };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        results
+            .iter()
+            .for_each(|r| println!("[{}] {}: {}", r.status, r.name, r.message));
+        println!();
+    }
 
-    f(args)
+    if !passed {
+        return Err(anyhow!("System is not capable of running Kata Containers"));
+    }
+    if !checkcmd.json {
+        println!("System is capable of running Kata Containers");
+    }
+
+    Ok(())
 }
 
 fn get_client_cmd_details() -> Vec<String> {
@@ -68,20 +98,18 @@ fn print_check_list() -> Result<()> {
 }
 
 pub fn handle_check(checkcmd: CheckArgument) -> Result<()> {
-    let command = checkcmd.command;
-
-    match command {
+    match checkcmd.command {
         CheckSubCommand::All => {
-            // run architecture-specific tests
-            handle_builtin_check(CheckType::CheckCpu, "")?;
+            // run host capability tests
+            handle_host_checks(&checkcmd)?;
 
             // run code that uses network checks
             check::run_network_checks()?;
         }
 
-        CheckSubCommand::NoNetworkChecks => {
-            // run architecture-specific tests
-            handle_builtin_check(CheckType::CheckCpu, "")?;
+        CheckSubCommand::NoNetworkChecks | CheckSubCommand::Host => {
+            // run host capability tests
+            handle_host_checks(&checkcmd)?;
         }
 
         CheckSubCommand::CheckVersionOnly => {
@@ -104,20 +132,8 @@ pub fn handle_check(checkcmd: CheckArgument) -> Result<()> {
     Ok(())
 }
 
-pub fn handle_env() -> Result<()> {
-    Ok(())
-}
-
 pub fn handle_factory() -> Result<()> {
-    Ok(())
-}
-
-pub fn handle_iptables(_args: IptablesCommand) -> Result<()> {
-    Ok(())
-}
-
-pub fn handle_metrics(_args: MetricsCommand) -> Result<()> {
-    Ok(())
+    Err(anyhow!("VM factory is not supported by runtime-rs yet"))
 }
 
 pub fn handle_version() -> Result<()> {
@@ -0,0 +1,435 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use crate::args::EnvArgument;
+use crate::check::{KVM_DEVICE, PROC_MEMINFO, VHOST_VSOCK_DEVICE};
+use crate::ops::version;
+use crate::utils;
+
+use anyhow::{anyhow, Context, Result};
+use kata_types::config::{hypervisor::HYPERVISOR_NAME_DRAGONBALL, TomlConfig};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Version of the format of the output, bump it when the layout changes.
+const FORMAT_VERSION: &str = "1.0.0";
+
+const RUNTIME_NAME: &str = "containerd-shim-kata-v2";
+
+// _IO(KVMIO, 0x00) and _IO(KVMIO, 0x03)
+const KVM_GET_API_VERSION: u64 = 0xAE00;
+const KVM_CHECK_EXTENSION: u64 = 0xAE03;
+
+// KVM capabilities required by all the supported hypervisors.
+const KVM_CAPS: &[(&str, u64)] = &[
+    ("KVM_CAP_IRQCHIP", 0),
+    ("KVM_CAP_USER_MEMORY", 3),
+    ("KVM_CAP_IRQ_ROUTING", 25),
+    ("KVM_CAP_IRQFD", 32),
+    ("KVM_CAP_IOEVENTFD", 36),
+];
+
+// The layout of the output follows the `kata-runtime env` command of the Go runtime. TOML
+// requires plain values to be placed before tables, so nested structures come last.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EnvInfo {
+    kernel: KernelInfo,
+    meta: MetaInfo,
+    image: ImageInfo,
+    initrd: InitrdInfo,
+    hypervisor: HypervisorInfo,
+    runtime: RuntimeInfo,
+    host: HostInfo,
+    agent: AgentInfo,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetaInfo {
+    version: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct KernelInfo {
+    path: String,
+    parameters: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ImageInfo {
+    path: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct InitrdInfo {
+    path: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct HypervisorInfo {
+    name: String,
+    path: String,
+    version: Option<String>,
+    machine_type: String,
+    block_device_driver: String,
+    entropy_source: String,
+    shared_fs: Option<String>,
+    virtio_fs_daemon: String,
+    virtio_fs_daemon_version: Option<String>,
+    memory_slots: u32,
+    pcie_root_port: u32,
+    hotplug_vfio_on_root_bus: bool,
+    debug: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct RuntimeInfo {
+    name: String,
+    path: Option<String>,
+    experimental: Vec<String>,
+    debug: bool,
+    trace: bool,
+    disable_guest_seccomp: bool,
+    disable_new_netns: bool,
+    sandbox_cgroup_only: bool,
+    config: RuntimeConfigInfo,
+    version: RuntimeVersionInfo,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct RuntimeConfigInfo {
+    path: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct RuntimeVersionInfo {
+    version: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct HostInfo {
+    kernel: String,
+    architecture: String,
+    support_vsocks: bool,
+    distro: DistroInfo,
+    cpu: CpuInfo,
+    memory: MemoryInfo,
+    kvm: KvmInfo,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct DistroInfo {
+    name: String,
+    version: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct CpuInfo {
+    vendor: String,
+    model: String,
+    cpus: usize,
+}
+
+// All the sizes are in KiB, as reported by /proc/meminfo.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MemoryInfo {
+    total: u64,
+    free: u64,
+    available: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct KvmInfo {
+    // Whether the host is able to run VM based containers.
+    vm_container_capable: bool,
+    api_version: Option<i32>,
+    capabilities: BTreeMap<String, bool>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct AgentInfo {
+    debug: bool,
+    trace: bool,
+}
+
+pub fn handle_env(args: EnvArgument) -> Result<()> {
+    let (config, config_path) = utils::load_kata_config(args.config.as_deref())?;
+    let env = get_env_info(&config, &config_path)?;
+
+    let output = if args.json {
+        serde_json::to_string_pretty(&env).context("serialize to json")?
+    } else {
+        toml::to_string(&env).context("serialize to toml")?
+    };
+    println!("{}", output);
+
+    Ok(())
+}
+
+fn get_env_info(config: &TomlConfig, config_path: &Path) -> Result<EnvInfo> {
+    let hypervisor_name = &config.runtime.hypervisor_name;
+    let hypervisor = config
+        .hypervisor
+        .get(hypervisor_name)
+        .ok_or_else(|| anyhow!("hypervisor {} not found in configuration", hypervisor_name))?;
+    let agent = config.agent.get(&config.runtime.agent_name);
+    let runtime_version = version::get().unwrap_or_default();
+
+    let boot_info = &hypervisor.boot_info;
+    let shared_fs = &hypervisor.shared_fs;
+    // Dragonball is built into the runtime, there is no separate executable.
+    let hypervisor_version = if hypervisor_name == HYPERVISOR_NAME_DRAGONBALL {
+        Some(runtime_version.clone())
+    } else {
+        get_command_version(&hypervisor.path)
+    };
+
+    Ok(EnvInfo {
+        kernel: KernelInfo {
+            path: resolve_path(&boot_info.kernel),
+            parameters: boot_info.kernel_params.clone(),
+        },
+        meta: MetaInfo {
+            version: FORMAT_VERSION.to_string(),
+        },
+        image: ImageInfo {
+            path: resolve_path(&boot_info.image),
+        },
+        initrd: InitrdInfo {
+            path: resolve_path(&boot_info.initrd),
+        },
+        hypervisor: HypervisorInfo {
+            name: hypervisor_name.clone(),
+            path: resolve_path(&hypervisor.path),
+            version: hypervisor_version,
+            machine_type: hypervisor.machine_info.machine_type.clone(),
+            block_device_driver: hypervisor.blockdev_info.block_device_driver.clone(),
+            entropy_source: hypervisor.machine_info.entropy_source.clone(),
+            shared_fs: shared_fs.shared_fs.clone(),
+            virtio_fs_daemon: resolve_path(&shared_fs.virtio_fs_daemon),
+            virtio_fs_daemon_version: get_command_version(&shared_fs.virtio_fs_daemon),
+            memory_slots: hypervisor.memory_info.memory_slots,
+            pcie_root_port: hypervisor.device_info.pcie_root_port,
+            hotplug_vfio_on_root_bus: hypervisor.device_info.hotplug_vfio_on_root_bus,
+            debug: hypervisor.debug_info.enable_debug,
+        },
+        runtime: RuntimeInfo {
+            name: config.runtime.name.clone(),
+            path: find_in_path(RUNTIME_NAME).map(|p| p.display().to_string()),
+            experimental: config.runtime.experimental.clone(),
+            debug: config.runtime.debug,
+            trace: config.runtime.enable_tracing,
+            disable_guest_seccomp: config.runtime.disable_guest_seccomp,
+            disable_new_netns: config.runtime.disable_new_netns,
+            sandbox_cgroup_only: config.runtime.sandbox_cgroup_only,
+            config: RuntimeConfigInfo {
+                path: config_path.display().to_string(),
+            },
+            version: RuntimeVersionInfo {
+                version: runtime_version,
+            },
+        },
+        host: get_host_info()?,
+        agent: AgentInfo {
+            debug: agent.map(|a| a.debug).unwrap_or_default(),
+            trace: agent.map(|a| a.enable_tracing).unwrap_or_default(),
+        },
+    })
+}
+
+fn get_host_info() -> Result<HostInfo> {
+    let kernel = utils::get_kernel_version(utils::PROC_VERSION_FILE)?;
+    let (distro_name, distro_version) =
+        utils::get_distro_details(utils::OS_RELEASE, utils::OS_RELEASE_CLR)?;
+    let meminfo =
+        fs::read_to_string(PROC_MEMINFO).with_context(|| format!("read {}", PROC_MEMINFO))?;
+
+    Ok(HostInfo {
+        kernel,
+        architecture: std::env::consts::ARCH.to_string(),
+        support_vsocks: Path::new(VHOST_VSOCK_DEVICE).exists(),
+        distro: DistroInfo {
+            name: distro_name,
+            version: distro_version,
+        },
+        cpu: get_cpu_info(),
+        memory: parse_meminfo(&meminfo),
+        kvm: get_kvm_info(),
+    })
+}
+
+fn get_cpu_info() -> CpuInfo {
+    #[cfg(any(target_arch = "s390x", target_arch = "x86_64"))]
+    let (vendor, model) =
+        utils::get_generic_cpu_details(crate::check::PROC_CPUINFO).unwrap_or_default();
+    #[cfg(not(any(target_arch = "s390x", target_arch = "x86_64")))]
+    let (vendor, model) = (String::new(), String::new());
+
+    CpuInfo {
+        vendor,
+        model,
+        cpus: std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or_default(),
+    }
+}
+
+fn parse_meminfo(meminfo: &str) -> MemoryInfo {
+    let mut info = MemoryInfo::default();
+
+    for line in meminfo.lines() {
+        let mut fields = line.split_whitespace();
+        let (key, value) = match (fields.next(), fields.next()) {
+            (Some(key), Some(value)) => (key, value.parse::<u64>().unwrap_or_default()),
+            _ => continue,
+        };
+        match key {
+            "MemTotal:" => info.total = value,
+            "MemFree:" => info.free = value,
+            "MemAvailable:" => info.available = value,
+            _ => {}
+        }
+    }
+
+    info
+}
+
+fn get_kvm_info() -> KvmInfo {
+    let mut info = KvmInfo::default();
+
+    let kvm = match fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(KVM_DEVICE)
+    {
+        Ok(f) => f,
+        Err(_) => return info,
+    };
+    let fd = kvm.as_raw_fd();
+
+    // SAFETY: both ioctls take no argument and don't touch any memory of the process.
+    let api_version = unsafe { libc::ioctl(fd, KVM_GET_API_VERSION as _, 0) };
+    if api_version < 0 {
+        return info;
+    }
+    info.vm_container_capable = true;
+    info.api_version = Some(api_version);
+
+    for (name, cap) in KVM_CAPS {
+        let ret = unsafe { libc::ioctl(fd, KVM_CHECK_EXTENSION as _, *cap) };
+        info.capabilities.insert(name.to_string(), ret > 0);
+    }
+
+    info
+}
+
+// Get the version of an executable from the first line of its `--version` output.
+fn get_command_version(path: &str) -> Option<String> {
+    if path.is_empty() {
+        return None;
+    }
+
+    let output = Command::new(path).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|l| l.trim())
+        .find(|l| !l.is_empty())
+        .map(|l| l.to_string())
+}
+
+// Resolve symbolic links so that the report shows which files are really used.
+fn resolve_path(path: &str) -> String {
+    if path.is_empty() {
+        return String::new();
+    }
+
+    fs::canonicalize(path)
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meminfo() {
+        let meminfo = "MemTotal:       16314920 kB\nMemFree:         1009644 kB\nMemAvailable:   10263472 kB\nBuffers:          612316 kB\nbad line\n";
+        assert_eq!(
+            parse_meminfo(meminfo),
+            MemoryInfo {
+                total: 16314920,
+                free: 1009644,
+                available: 10263472,
+            }
+        );
+        assert_eq!(parse_meminfo(""), MemoryInfo::default());
+    }
+
+    #[test]
+    fn test_get_command_version() {
+        assert_eq!(get_command_version(""), None);
+        assert_eq!(get_command_version("/not/exist/hypervisor"), None);
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("vmlinux-5.19");
+        let link = dir.path().join("vmlinux.container");
+        fs::write(&file, b"").unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        assert_eq!(resolve_path(""), "");
+        assert_eq!(resolve_path("/not/exist/vmlinux"), "/not/exist/vmlinux");
+        assert_eq!(
+            resolve_path(link.to_str().unwrap()),
+            fs::canonicalize(&file).unwrap().display().to_string()
+        );
+    }
+
+    #[test]
+    fn test_env_info_format() {
+        let mut env = EnvInfo::default();
+        env.host
+            .kvm
+            .capabilities
+            .insert("KVM_CAP_IRQFD".to_string(), true);
+
+        let output = toml::to_string(&env).unwrap();
+        assert!(output.contains("[Host.Kvm.Capabilities]"));
+        let output = serde_json::to_string(&env).unwrap();
+        assert!(output.contains("\"VmContainerCapable\":false"));
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use crate::args::{IpTablesArguments, IptablesCommand};
+use crate::utils::verify_sandbox_id;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::StatusCode;
+use std::fs;
+use std::time::Duration;
+
+use shim_interface::shim_mgmt::client::MgmtClient;
+use shim_interface::shim_mgmt::{IP6_TABLE_URL, IP_TABLE_URL};
+
+// Setting iptables runs iptables-restore in the guest, give it more time than a plain query.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// The agent passes the rules to iptables-restore, reject anything it would choke on early.
+const MAX_RULES_SIZE: u64 = 1 << 20;
+
+pub fn handle_iptables(args: IptablesCommand) -> Result<()> {
+    if !nix::unistd::Uid::effective().is_root() {
+        return Err(anyhow!(
+            "super-user privileges are required for the iptables subcommand"
+        ));
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    match args.iptables {
+        IpTablesArguments::Get(args) => {
+            verify_sandbox_id(&args.sandbox_id)?;
+            let rules = rt
+                .block_on(get_iptables(&args.sandbox_id, args.v6))
+                .context("get iptables")?;
+            print!("{}", rules);
+        }
+        IpTablesArguments::Set(args) => {
+            verify_sandbox_id(&args.sandbox_id)?;
+            let rules = read_rules(&args.file)?;
+            rt.block_on(set_iptables(&args.sandbox_id, args.v6, rules))
+                .context("set iptables")?;
+            println!("iptables of sandbox {} updated", args.sandbox_id);
+        }
+    }
+
+    Ok(())
+}
+
+fn iptables_url(is_ipv6: bool) -> &'static str {
+    if is_ipv6 {
+        IP6_TABLE_URL
+    } else {
+        IP_TABLE_URL
+    }
+}
+
+async fn get_iptables(sandbox_id: &str, is_ipv6: bool) -> Result<String> {
+    let shim_client = MgmtClient::new(sandbox_id, Some(TIMEOUT))?;
+    let response = shim_client.get(iptables_url(is_ipv6)).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    let body = String::from_utf8_lossy(&body).to_string();
+    if status != StatusCode::OK {
+        return Err(anyhow!("shim returned {:?}: {}", status, body));
+    }
+
+    Ok(body)
+}
+
+async fn set_iptables(sandbox_id: &str, is_ipv6: bool, rules: Vec<u8>) -> Result<()> {
+    let shim_client = MgmtClient::new(sandbox_id, Some(TIMEOUT))?;
+    let response = shim_client.put(iptables_url(is_ipv6), rules).await?;
+    let status = response.status();
+    if status != StatusCode::OK {
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        return Err(anyhow!(
+            "shim returned {:?}: {}",
+            status,
+            String::from_utf8_lossy(&body)
+        ));
+    }
+
+    Ok(())
+}
+
+fn read_rules(file: &str) -> Result<Vec<u8>> {
+    let metadata = fs::metadata(file).with_context(|| format!("stat rules file {}", file))?;
+    if !metadata.is_file() {
+        return Err(anyhow!("rules file {} is not a regular file", file));
+    }
+    if metadata.len() > MAX_RULES_SIZE {
+        return Err(anyhow!(
+            "rules file {} is too large ({} bytes, limit {} bytes)",
+            file,
+            metadata.len(),
+            MAX_RULES_SIZE
+        ));
+    }
+
+    let rules = fs::read_to_string(file).with_context(|| format!("read rules file {}", file))?;
+    validate_rules(&rules).with_context(|| format!("invalid rules file {}", file))?;
+
+    Ok(rules.into_bytes())
+}
+
+// Check the rules are in the iptables-save format: each table starts with a `*<table>` line and
+// ends with a `COMMIT` line.
+fn validate_rules(rules: &str) -> Result<()> {
+    let mut table: Option<&str> = None;
+    let mut tables = 0;
+
+    for (i, line) in rules.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('*') {
+            if let Some(current) = table {
+                return Err(anyhow!(
+                    "line {}: table {} starts before table {} is committed",
+                    i + 1,
+                    name,
+                    current
+                ));
+            }
+            table = Some(name);
+        } else if line == "COMMIT" {
+            if table.take().is_none() {
+                return Err(anyhow!("line {}: COMMIT outside of a table", i + 1));
+            }
+            tables += 1;
+        } else if table.is_none() {
+            return Err(anyhow!("line {}: rule outside of a table", i + 1));
+        }
+    }
+
+    if let Some(current) = table {
+        return Err(anyhow!("table {} is not committed", current));
+    }
+    if tables == 0 {
+        return Err(anyhow!("no table found"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_rules() {
+        let valid = "# Generated by iptables-save\n*filter\n:INPUT ACCEPT [0:0]\n-A INPUT -p tcp --dport 22 -j DROP\nCOMMIT\n\n*nat\n:PREROUTING ACCEPT [0:0]\nCOMMIT\n";
+        assert!(validate_rules(valid).is_ok());
+
+        let invalid = [
+            "",
+            "# comment only\n",
+            "-A INPUT -j DROP\n",
+            "*filter\n-A INPUT -j DROP\n",
+            "*filter\n*nat\nCOMMIT\n",
+            "COMMIT\n",
+        ];
+        for rules in invalid {
+            assert!(validate_rules(rules).is_err(), "rules: {:?}", rules);
+        }
+    }
+
+    #[test]
+    fn test_read_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(read_rules(dir.path().to_str().unwrap()).is_err());
+        assert!(read_rules(dir.path().join("missing").to_str().unwrap()).is_err());
+
+        let file = dir.path().join("rules");
+        fs::write(&file, "*filter\nCOMMIT\n").unwrap();
+        assert_eq!(
+            read_rules(file.to_str().unwrap()).unwrap(),
+            b"*filter\nCOMMIT\n".to_vec()
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use crate::args::LogLevelArgs;
+use crate::utils::verify_sandbox_id;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+use agent::SetLogLevelRequest;
+use shim_interface::shim_mgmt::client::MgmtClient;
+use shim_interface::shim_mgmt::{AGENT_LOG_LEVEL_URL, LOG_LEVEL_URL};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn handle_log_level(args: LogLevelArgs) -> Result<()> {
+    if !nix::unistd::Uid::effective().is_root() {
+        return Err(anyhow!(
+            "super-user privileges are required for the log-level subcommand"
+        ));
+    }
+    verify_sandbox_id(&args.sandbox_id)?;
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let level = match args.level {
+        Some(level) => level,
+        None => {
+            if args.guest {
+                return Err(anyhow!(
+                    "the levels of the agent can't be shown, only changed"
+                ));
+            }
+            let levels = rt
+                .block_on(get_log_levels(&args.sandbox_id))
+                .context("get log levels")?;
+            println!("{}", serde_json::to_string_pretty(&levels)?);
+            return Ok(());
+        }
+    };
+
+    let req = new_request(args.subsystem, level)?;
+    let (url, target) = if args.guest {
+        (AGENT_LOG_LEVEL_URL, "agent")
+    } else {
+        (LOG_LEVEL_URL, "shim")
+    };
+    rt.block_on(set_log_level(&args.sandbox_id, url, &req))
+        .context("set log level")?;
+    println!(
+        "log level of {} of sandbox {} set to {}",
+        if req.subsystem.is_empty() {
+            target.to_string()
+        } else {
+            format!("{} subsystem {}", target, req.subsystem)
+        },
+        args.sandbox_id,
+        req.level
+    );
+
+    Ok(())
+}
+
+fn new_request(subsystem: Option<String>, level: String) -> Result<SetLogLevelRequest> {
+    if logging::level_name_to_slog_level(&level).is_err() {
+        return Err(anyhow!(
+            "invalid level {}, expected one of {}",
+            level,
+            logging::get_log_levels().join(", ")
+        ));
+    }
+
+    Ok(SetLogLevelRequest {
+        subsystem: subsystem.unwrap_or_default(),
+        level,
+    })
+}
+
+async fn get_log_levels(sandbox_id: &str) -> Result<serde_json::Value> {
+    let shim_client = MgmtClient::new(sandbox_id, Some(TIMEOUT))?;
+    let response = shim_client.get(LOG_LEVEL_URL).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    if status != StatusCode::OK {
+        return Err(anyhow!(
+            "shim returned {:?}: {}",
+            status,
+            String::from_utf8_lossy(&body)
+        ));
+    }
+
+    serde_json::from_slice(&body).context("parse log levels")
+}
+
+async fn set_log_level(sandbox_id: &str, url: &str, req: &SetLogLevelRequest) -> Result<()> {
+    let shim_client = MgmtClient::new(sandbox_id, Some(TIMEOUT))?;
+    let response = shim_client.put(url, serde_json::to_vec(req)?).await?;
+    let status = response.status();
+    if status != StatusCode::OK {
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        return Err(anyhow!(
+            "shim returned {:?}: {}",
+            status,
+            String::from_utf8_lossy(&body)
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_request() {
+        let req = new_request(Some("hypervisor".to_string()), "debug".to_string()).unwrap();
+        assert_eq!(req.subsystem, "hypervisor");
+        assert_eq!(req.level, "debug");
+
+        let req = new_request(None, "warn".to_string()).unwrap();
+        assert!(req.subsystem.is_empty());
+
+        assert!(new_request(None, "verbose".to_string()).is_err());
+        assert!(new_request(None, "".to_string()).is_err());
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use crate::args::MetricsCommand;
+use crate::sl;
+
+use anyhow::{anyhow, Context, Result};
+use futures::future::join_all;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use slog::warn;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::fs;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use shim_interface::shim_mgmt::client::MgmtClient;
+use shim_interface::shim_mgmt::METRICS_URL;
+use shim_interface::{KATA_PATH, SHIM_MGMT_SOCK_NAME};
+
+// The metrics of kata-ctl itself share the namespace with kata-monitor of the Go runtime, so
+// that the dashboards work with both.
+const NAMESPACE_KATA_MONITOR: &str = "kata_monitor";
+const SANDBOX_ID_LABEL: &str = "sandbox_id";
+const CONTENT_TYPE_TEXT: &str = "text/plain; version=0.0.4";
+
+pub fn handle_metrics(args: MetricsCommand) -> Result<()> {
+    if !nix::unistd::Uid::effective().is_root() {
+        return Err(anyhow!(
+            "super-user privileges are required for the metrics subcommand"
+        ));
+    }
+
+    let timeout = Duration::from_millis(args.timeout);
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    match args.listen {
+        Some(addr) => {
+            let addr: SocketAddr = addr
+                .parse()
+                .with_context(|| format!("invalid listen address {}", addr))?;
+            rt.block_on(serve_metrics(addr, timeout))
+        }
+        None => {
+            let metrics = rt.block_on(gather_metrics(KATA_PATH, timeout))?;
+            print!("{}", metrics);
+            Ok(())
+        }
+    }
+}
+
+async fn serve_metrics(addr: SocketAddr, timeout: Duration) -> Result<()> {
+    let make_svc = make_service_fn(move |_| async move {
+        Ok::<_, Infallible>(service_fn(move |req| metrics_handler(req, timeout)))
+    });
+
+    let server = Server::try_bind(&addr)
+        .with_context(|| format!("bind {}", addr))?
+        .serve(make_svc);
+    println!(
+        "serving metrics of all sandboxes on http://{}/metrics",
+        addr
+    );
+
+    server.await.context("serve metrics")
+}
+
+async fn metrics_handler(
+    req: Request<Body>,
+    timeout: Duration,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let resp = match (req.method(), req.uri().path()) {
+        (&Method::GET, METRICS_URL) => match gather_metrics(KATA_PATH, timeout).await {
+            Ok(metrics) => Response::builder()
+                .header("Content-Type", CONTENT_TYPE_TEXT)
+                .body(Body::from(metrics)),
+            Err(e) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(format!("{:?}", e))),
+        },
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("URL NOT FOUND")),
+    };
+
+    Ok(resp.unwrap_or_else(|_| Response::new(Body::empty())))
+}
+
+// Get the IDs of the sandboxes whose shim management server is running.
+fn list_sandboxes(kata_path: &str) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(kata_path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).with_context(|| format!("read dir {}", kata_path)),
+    };
+
+    let mut sandboxes: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().join(SHIM_MGMT_SOCK_NAME).exists())
+        .filter_map(|e| e.file_name().to_str().map(|s| s.to_string()))
+        .collect();
+    sandboxes.sort();
+
+    Ok(sandboxes)
+}
+
+async fn scrape_sandbox(sandbox_id: &str, timeout: Duration) -> Result<String> {
+    let shim_client = MgmtClient::new(sandbox_id, Some(timeout))?;
+    let response = shim_client.get(METRICS_URL).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    let body = String::from_utf8_lossy(&body).to_string();
+    if status != StatusCode::OK {
+        return Err(anyhow!("shim returned {:?}: {}", status, body));
+    }
+
+    Ok(body)
+}
+
+async fn gather_metrics(kata_path: &str, timeout: Duration) -> Result<String> {
+    let sandboxes = list_sandboxes(kata_path)?;
+    let results = join_all(sandboxes.iter().map(|sid| scrape_sandbox(sid, timeout))).await;
+
+    let mut families = MetricFamilies::default();
+    let mut failed = 0;
+    for (sid, result) in sandboxes.iter().zip(results) {
+        match result {
+            Ok(metrics) => families.add(sid, &metrics),
+            Err(e) => {
+                warn!(sl!(), "failed to get metrics of sandbox {}: {:?}", sid, e);
+                failed += 1;
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for (name, help, value) in [
+        (
+            "running_shim_count",
+            "Running shim count (running sandboxes).",
+            sandboxes.len(),
+        ),
+        (
+            "scrape_failed_count",
+            "Failed scrape count in the last scrape.",
+            failed,
+        ),
+    ] {
+        let name = format!("{}_{}", NAMESPACE_KATA_MONITOR, name);
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+    out.push_str(&families.encode());
+
+    Ok(out)
+}
+
+#[derive(Debug, Default)]
+struct MetricFamily {
+    help: Option<String>,
+    metric_type: Option<String>,
+    samples: Vec<String>,
+}
+
+// Metrics of all the sandboxes grouped by family, as the text format requires the samples of a
+// family to be contiguous and the HELP and TYPE lines to show up only once.
+#[derive(Debug, Default)]
+struct MetricFamilies {
+    names: Vec<String>,
+    families: HashMap<String, MetricFamily>,
+}
+
+impl MetricFamilies {
+    fn family(&mut self, name: &str) -> &mut MetricFamily {
+        if !self.families.contains_key(name) {
+            self.names.push(name.to_string());
+        }
+        self.families.entry(name.to_string()).or_default()
+    }
+
+    // Add the metrics of a sandbox, each sample is labeled with the sandbox ID.
+    fn add(&mut self, sandbox_id: &str, metrics: &str) {
+        // the family of the last HELP or TYPE line, the samples of a histogram or a summary
+        // have suffixes appended to the family name
+        let mut current: Option<String> = None;
+
+        for line in metrics.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(comment) = line.strip_prefix('#') {
+                let mut parts = comment.trim_start().splitn(3, ' ');
+                let (kind, name, rest) = match (parts.next(), parts.next()) {
+                    (Some(kind), Some(name)) => (kind, name, parts.next().unwrap_or_default()),
+                    _ => continue,
+                };
+                // the first sandbox wins if the descriptions differ
+                let family = match kind {
+                    "HELP" => &mut self.family(name).help,
+                    "TYPE" => &mut self.family(name).metric_type,
+                    _ => continue,
+                };
+                if family.is_none() {
+                    *family = Some(rest.to_string());
+                }
+                current = Some(name.to_string());
+                continue;
+            }
+
+            let (name, sample) = match relabel(line, sandbox_id) {
+                Some(v) => v,
+                None => continue,
+            };
+            let family_name = match &current {
+                Some(family) if name.starts_with(family.as_str()) => family.clone(),
+                _ => name.to_string(),
+            };
+            self.family(&family_name).samples.push(sample);
+        }
+    }
+
+    fn encode(&self) -> String {
+        let mut out = String::new();
+        for name in &self.names {
+            let family = &self.families[name];
+            if let Some(help) = &family.help {
+                let _ = writeln!(out, "# HELP {} {}", name, help);
+            }
+            if let Some(metric_type) = &family.metric_type {
+                let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+            }
+            for sample in &family.samples {
+                let _ = writeln!(out, "{}", sample);
+            }
+        }
+
+        out
+    }
+}
+
+// Add the sandbox ID label to a sample, returns the metric name and the new sample.
+fn relabel<'a>(sample: &'a str, sandbox_id: &str) -> Option<(&'a str, String)> {
+    let end = sample.find(|c: char| c == '{' || c.is_whitespace())?;
+    let (name, rest) = sample.split_at(end);
+    if name.is_empty() {
+        return None;
+    }
+
+    let label = format!("{}=\"{}\"", SANDBOX_ID_LABEL, sandbox_id);
+    let sample = match rest.strip_prefix('{') {
+        Some(labels) if labels.trim_start().starts_with('}') => {
+            format!("{}{{{}{}", name, label, labels.trim_start())
+        }
+        Some(labels) => format!("{}{{{},{}", name, label, labels),
+        None => format!("{}{{{}}}{}", name, label, rest),
+    };
+
+    Some((name, sample))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relabel() {
+        assert_eq!(
+            relabel("kata_shim_threads 12", "sb1"),
+            Some((
+                "kata_shim_threads",
+                "kata_shim_threads{sandbox_id=\"sb1\"} 12".to_string()
+            ))
+        );
+        assert_eq!(
+            relabel("kata_guest_load{item=\"load1\"} 0.5", "sb1"),
+            Some((
+                "kata_guest_load",
+                "kata_guest_load{sandbox_id=\"sb1\",item=\"load1\"} 0.5".to_string()
+            ))
+        );
+        assert_eq!(
+            relabel("kata_agent_total_time{} 3", "sb1"),
+            Some((
+                "kata_agent_total_time",
+                "kata_agent_total_time{sandbox_id=\"sb1\"} 3".to_string()
+            ))
+        );
+        assert_eq!(relabel("no_value", "sb1"), None);
+    }
+
+    #[test]
+    fn test_metric_families() {
+        let metrics = "# HELP kata_shim_threads Shim process threads\n# TYPE kata_shim_threads gauge\nkata_shim_threads 12\n# HELP rpc_seconds RPC latency\n# TYPE rpc_seconds histogram\nrpc_seconds_bucket{le=\"1\"} 2\nrpc_seconds_sum 0.3\nrpc_seconds_count 2\n";
+        let mut families = MetricFamilies::default();
+        families.add("sb1", metrics);
+        families.add("sb2", metrics);
+
+        let expected = "# HELP kata_shim_threads Shim process threads\n# TYPE kata_shim_threads gauge\nkata_shim_threads{sandbox_id=\"sb1\"} 12\nkata_shim_threads{sandbox_id=\"sb2\"} 12\n# HELP rpc_seconds RPC latency\n# TYPE rpc_seconds histogram\nrpc_seconds_bucket{sandbox_id=\"sb1\",le=\"1\"} 2\nrpc_seconds_sum{sandbox_id=\"sb1\"} 0.3\nrpc_seconds_count{sandbox_id=\"sb1\"} 2\nrpc_seconds_bucket{sandbox_id=\"sb2\",le=\"1\"} 2\nrpc_seconds_sum{sandbox_id=\"sb2\"} 0.3\nrpc_seconds_count{sandbox_id=\"sb2\"} 2\n";
+        assert_eq!(families.encode(), expected);
+    }
+
+    #[test]
+    fn test_list_sandboxes() {
+        let dir = tempfile::tempdir().unwrap();
+        let kata_path = dir.path().to_str().unwrap();
+        assert!(list_sandboxes(kata_path).unwrap().is_empty());
+        assert!(list_sandboxes(&format!("{}/missing", kata_path))
+            .unwrap()
+            .is_empty());
+
+        for sid in ["sb2", "sb1", "stopped"] {
+            fs::create_dir(dir.path().join(sid)).unwrap();
+        }
+        fs::write(dir.path().join("sb1").join(SHIM_MGMT_SOCK_NAME), b"").unwrap();
+        fs::write(dir.path().join("sb2").join(SHIM_MGMT_SOCK_NAME), b"").unwrap();
+        assert_eq!(list_sandboxes(kata_path).unwrap(), vec!["sb1", "sb2"]);
+    }
+}
@@ -0,0 +1,492 @@
+// Copyright (c) 2023 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use crate::args::{SandboxCommand, SandboxSubCommand};
+use crate::utils::{load_kata_config, verify_sandbox_id};
+
+use anyhow::{anyhow, Context, Result};
+use hyper::body::HttpBody;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use common::types::{ContainerInfo, SandboxInfo};
+use persist::PERSIST_FILE;
+use shim_interface::event_log::{read_events, sandbox_event_log_dir, SandboxEvent};
+use shim_interface::shim_mgmt::client::MgmtClient;
+use shim_interface::shim_mgmt::{AGENT_LOG_URL, SANDBOX_INFO_URL};
+use shim_interface::{KATA_PATH, SHIM_MGMT_SOCK_NAME};
+use virt_container::sandbox_persist::SandboxState;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const STATUS_RUNNING: &str = "running";
+const STATUS_STOPPED: &str = "stopped";
+
+// A sandbox found under the kata path, the state is only there after the VM is started.
+#[derive(Debug, Serialize)]
+struct SandboxEntry {
+    sandbox_id: String,
+    status: String,
+    hypervisor: String,
+    vcpus: Option<i32>,
+    memory_mb: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct SandboxDescription {
+    sandbox_id: String,
+    status: String,
+    sandbox_type: String,
+    hypervisor: String,
+    shim_pid: Option<u32>,
+    hypervisor_pid: Option<u32>,
+    vcpu_threads: BTreeMap<u32, u32>,
+    agent_url: Option<String>,
+    netns: Option<String>,
+    containers: Vec<ContainerInfo>,
+    devices: SandboxDevices,
+    resources: SandboxResources,
+}
+
+#[derive(Serialize, Default)]
+struct SandboxDevices {
+    block: Vec<String>,
+    network: serde_json::Value,
+}
+
+#[derive(Serialize, Default)]
+struct SandboxResources {
+    vcpus: Option<i32>,
+    max_vcpus: Option<u32>,
+    memory_mb: Option<u32>,
+    cgroup: serde_json::Value,
+}
+
+pub fn handle_sandbox(args: SandboxCommand) -> Result<()> {
+    if !nix::unistd::Uid::effective().is_root() {
+        return Err(anyhow!(
+            "super-user privileges are required for the sandbox subcommand"
+        ));
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    match args.sandbox_cmd {
+        SandboxSubCommand::List(args) => {
+            let sandboxes = list_sandboxes(KATA_PATH)?;
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&sandboxes)?);
+            } else {
+                print!("{}", format_sandboxes(&sandboxes));
+            }
+        }
+        SandboxSubCommand::Describe(args) => {
+            verify_sandbox_id(&args.sandbox_id)?;
+            let desc = rt
+                .block_on(describe_sandbox(KATA_PATH, &args.sandbox_id))
+                .with_context(|| format!("describe sandbox {}", args.sandbox_id))?;
+            println!("{}", serde_json::to_string_pretty(&desc)?);
+        }
+        SandboxSubCommand::Logs(args) => {
+            verify_sandbox_id(&args.sandbox_id)?;
+            rt.block_on(stream_agent_log(&args.sandbox_id))
+                .with_context(|| format!("agent log of sandbox {}", args.sandbox_id))?;
+        }
+        SandboxSubCommand::Events(args) => {
+            verify_sandbox_id(&args.sandbox_id)?;
+            let event_log_dir = match load_kata_config(args.config.as_deref()) {
+                Ok((config, _)) => config.runtime.event_log_dir,
+                // a configuration file specified explicitly must be valid
+                Err(e) if args.config.is_some() => return Err(e),
+                Err(_) => String::new(),
+            };
+            let events = read_events(&sandbox_event_log_dir(&args.sandbox_id, &event_log_dir))
+                .with_context(|| format!("events of sandbox {}", args.sandbox_id))?;
+            if args.json {
+                for event in events.iter() {
+                    println!("{}", serde_json::to_string(event)?);
+                }
+            } else {
+                print!("{}", format_events(&events));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn sandbox_status(sandbox_dir: &Path) -> &'static str {
+    if sandbox_dir.join(SHIM_MGMT_SOCK_NAME).exists() {
+        STATUS_RUNNING
+    } else {
+        STATUS_STOPPED
+    }
+}
+
+fn load_state(sandbox_dir: &Path) -> Result<Option<SandboxState>> {
+    let path = sandbox_dir.join(PERSIST_FILE);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("read {}", path.display())),
+    };
+
+    serde_json::from_str(&content)
+        .map(Some)
+        .with_context(|| format!("parse {}", path.display()))
+}
+
+fn list_sandboxes(kata_path: &str) -> Result<Vec<SandboxEntry>> {
+    let entries = match fs::read_dir(kata_path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).with_context(|| format!("read dir {}", kata_path)),
+    };
+
+    let mut sandboxes = vec![];
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let sandbox_id = match entry.file_name().to_str() {
+            Some(id) if path.is_dir() && verify_sandbox_id(id).is_ok() => id.to_string(),
+            _ => continue,
+        };
+        let status = sandbox_status(&path);
+        let state = match load_state(&path) {
+            Ok(state) => state,
+            Err(e) => {
+                eprintln!("WARNING: {:?}", e);
+                None
+            }
+        };
+        // skip the directories not created by the runtime
+        if state.is_none() && status == STATUS_STOPPED {
+            continue;
+        }
+
+        let hypervisor = state.and_then(|s| s.hypervisor);
+        sandboxes.push(SandboxEntry {
+            sandbox_id,
+            status: status.to_string(),
+            hypervisor: hypervisor
+                .as_ref()
+                .map(|h| h.hypervisor_type.clone())
+                .unwrap_or_default(),
+            vcpus: hypervisor.as_ref().map(|h| h.config.cpu_info.default_vcpus),
+            memory_mb: hypervisor
+                .as_ref()
+                .map(|h| h.config.memory_info.default_memory),
+        });
+    }
+    sandboxes.sort_by(|a, b| a.sandbox_id.cmp(&b.sandbox_id));
+
+    Ok(sandboxes)
+}
+
+fn format_sandboxes(sandboxes: &[SandboxEntry]) -> String {
+    let id_width = sandboxes
+        .iter()
+        .map(|s| s.sandbox_id.len())
+        .chain(std::iter::once("SANDBOX ID".len()))
+        .max()
+        .unwrap_or_default();
+    let optional = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+
+    let mut out = format!(
+        "{:<id_width$}  {:<8}  {:<12}  {:>5}  {:>10}\n",
+        "SANDBOX ID",
+        "STATUS",
+        "HYPERVISOR",
+        "VCPUS",
+        "MEMORY(MB)",
+        id_width = id_width
+    );
+    for s in sandboxes {
+        out.push_str(&format!(
+            "{:<id_width$}  {:<8}  {:<12}  {:>5}  {:>10}\n",
+            s.sandbox_id,
+            s.status,
+            if s.hypervisor.is_empty() {
+                "-"
+            } else {
+                s.hypervisor.as_str()
+            },
+            optional(s.vcpus.map(|v| v.to_string())),
+            optional(s.memory_mb.map(|v| v.to_string())),
+            id_width = id_width
+        ));
+    }
+
+    out
+}
+
+fn format_events(events: &[SandboxEvent]) -> String {
+    let mut out = format!(
+        "{:<35}  {:<10}  {:<24}  {}\n",
+        "TIME", "SUBSYSTEM", "ACTION", "DETAILS"
+    );
+    for e in events {
+        let mut details: Vec<_> = e
+            .details
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        if let Some(error) = &e.error {
+            details.push(format!("error={:?}", error));
+        }
+        out.push_str(&format!(
+            "{:<35}  {:<10}  {:<24}  {}\n",
+            e.time,
+            e.subsystem,
+            e.action,
+            details.join(" ")
+        ));
+    }
+
+    out
+}
+
+async fn get_sandbox_info(sandbox_id: &str) -> Result<SandboxInfo> {
+    let shim_client = MgmtClient::new(sandbox_id, Some(TIMEOUT))?;
+    let response = shim_client.get(SANDBOX_INFO_URL).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    if status != hyper::StatusCode::OK {
+        return Err(anyhow!(
+            "shim returned {:?}: {}",
+            status,
+            String::from_utf8_lossy(&body)
+        ));
+    }
+
+    serde_json::from_slice(&body).context("parse sandbox info")
+}
+
+// Describe a sandbox from the persisted state, together with the runtime information from the
+// shim if the sandbox is running.
+async fn describe_sandbox(kata_path: &str, sandbox_id: &str) -> Result<SandboxDescription> {
+    let sandbox_dir = Path::new(kata_path).join(sandbox_id);
+    if !sandbox_dir.is_dir() {
+        return Err(anyhow!("sandbox {} not found", sandbox_id));
+    }
+    let status = sandbox_status(&sandbox_dir);
+    let state = load_state(&sandbox_dir)?;
+
+    let info = if status == STATUS_RUNNING {
+        match get_sandbox_info(sandbox_id).await {
+            Ok(info) => Some(info),
+            Err(e) => {
+                eprintln!("WARNING: failed to get info from the shim: {:?}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    Ok(build_description(sandbox_id, status, state, info))
+}
+
+fn build_description(
+    sandbox_id: &str,
+    status: &str,
+    state: Option<SandboxState>,
+    info: Option<SandboxInfo>,
+) -> SandboxDescription {
+    let (sandbox_type, resource, hypervisor) = match state {
+        Some(s) => (s.sandbox_type, s.resource, s.hypervisor),
+        None => (String::new(), None, None),
+    };
+
+    let mut devices = SandboxDevices::default();
+    let mut resources = SandboxResources::default();
+    if let Some(r) = &resource {
+        devices.network = serde_json::to_value(&r.endpoint).unwrap_or_default();
+        resources.cgroup = serde_json::to_value(&r.cgroup_state).unwrap_or_default();
+    }
+    if let Some(h) = &hypervisor {
+        devices.block = h.cached_block_devices.iter().cloned().collect();
+        devices.block.sort();
+        resources.vcpus = Some(h.config.cpu_info.default_vcpus);
+        resources.max_vcpus = Some(h.config.cpu_info.default_maxvcpus);
+        resources.memory_mb = Some(h.config.memory_info.default_memory);
+    }
+
+    let persisted_pid = hypervisor
+        .as_ref()
+        .and_then(|h| h.pid)
+        .map(|pid| pid as u32);
+    let (shim_pid, hypervisor_pid, vcpu_threads, agent_url, containers) = match info {
+        Some(i) => (
+            Some(i.shim_pid),
+            Some(i.hypervisor_pid),
+            i.vcpu_threads,
+            Some(i.agent_url),
+            i.containers,
+        ),
+        None => (None, persisted_pid, BTreeMap::new(), None, vec![]),
+    };
+
+    SandboxDescription {
+        sandbox_id: sandbox_id.to_string(),
+        status: status.to_string(),
+        sandbox_type,
+        hypervisor: hypervisor
+            .as_ref()
+            .map(|h| h.hypervisor_type.clone())
+            .unwrap_or_default(),
+        shim_pid,
+        hypervisor_pid,
+        vcpu_threads,
+        agent_url,
+        netns: hypervisor.and_then(|h| h.netns),
+        containers,
+        devices,
+        resources,
+    }
+}
+
+// Stream the agent log lines forwarded by the shim until the sandbox or the user stops it.
+async fn stream_agent_log(sandbox_id: &str) -> Result<()> {
+    let shim_client = MgmtClient::new(sandbox_id, Some(TIMEOUT))?;
+    let response = shim_client.get(AGENT_LOG_URL).await?;
+    let status = response.status();
+    let mut body = response.into_body();
+    if status != hyper::StatusCode::OK {
+        let body = hyper::body::to_bytes(body).await?;
+        return Err(anyhow!(
+            "shim returned {:?}: {}",
+            status,
+            String::from_utf8_lossy(&body)
+        ));
+    }
+
+    let mut stdout = std::io::stdout();
+    while let Some(chunk) = body.data().await {
+        stdout.write_all(&chunk.context("read agent log")?)?;
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hypervisor::hypervisor_persist::HypervisorState;
+
+    #[test]
+    fn test_list_sandboxes() {
+        let dir = tempfile::tempdir().unwrap();
+        let kata_path = dir.path().to_str().unwrap();
+        assert!(list_sandboxes(kata_path).unwrap().is_empty());
+        assert!(list_sandboxes(&format!("{}/missing", kata_path))
+            .unwrap()
+            .is_empty());
+
+        for sid in ["sb2", "sb1", "other"] {
+            fs::create_dir(dir.path().join(sid)).unwrap();
+        }
+        fs::write(dir.path().join("sb1").join(SHIM_MGMT_SOCK_NAME), b"").unwrap();
+        fs::write(
+            dir.path().join("sb2").join(PERSIST_FILE),
+            r#"{"sandbox_type":"virt_container","resource":null,"hypervisor":null}"#,
+        )
+        .unwrap();
+
+        let sandboxes = list_sandboxes(kata_path).unwrap();
+        let summary: Vec<_> = sandboxes
+            .iter()
+            .map(|s| (s.sandbox_id.as_str(), s.status.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("sb1", STATUS_RUNNING), ("sb2", STATUS_STOPPED)]
+        );
+
+        let table = format_sandboxes(&sandboxes);
+        assert!(table.starts_with("SANDBOX ID  STATUS"));
+        assert!(table.contains("sb1         running   -"));
+    }
+
+    #[test]
+    fn test_build_description() {
+        let mut hypervisor = HypervisorState {
+            hypervisor_type: "dragonball".to_string(),
+            netns: Some("/var/run/netns/test".to_string()),
+            ..Default::default()
+        };
+        hypervisor.cached_block_devices.insert("vdb".to_string());
+        hypervisor.cached_block_devices.insert("vda".to_string());
+        let state = || SandboxState {
+            sandbox_type: "virt_container".to_string(),
+            resource: None,
+            hypervisor: Some(hypervisor.clone()),
+        };
+
+        let desc = build_description("sb1", STATUS_STOPPED, Some(state()), None);
+        assert_eq!(desc.hypervisor, "dragonball");
+        assert_eq!(desc.devices.block, vec!["vda", "vdb"]);
+        assert_eq!(desc.netns.as_deref(), Some("/var/run/netns/test"));
+        assert!(desc.agent_url.is_none());
+        assert!(desc.containers.is_empty());
+
+        let mut info = SandboxInfo {
+            sandbox_id: "sb1".to_string(),
+            shim_pid: 42,
+            hypervisor_pid: 42,
+            agent_url: "vsock://3:1024".to_string(),
+            containers: vec![ContainerInfo {
+                container_id: "c1".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        info.vcpu_threads.insert(0, 100);
+        let desc = build_description("sb1", STATUS_RUNNING, Some(state()), Some(info));
+        assert_eq!(desc.hypervisor_pid, Some(42));
+        assert_eq!(desc.vcpu_threads.get(&0), Some(&100));
+        assert_eq!(desc.agent_url.as_deref(), Some("vsock://3:1024"));
+        assert_eq!(desc.containers.len(), 1);
+    }
+
+    #[test]
+    fn test_format_events() {
+        let events = vec![
+            SandboxEvent {
+                time: "2023-05-04T08:00:00.000000000+00:00".to_string(),
+                sandbox_id: "sb1".to_string(),
+                subsystem: "agent".to_string(),
+                action: "exec_process".to_string(),
+                details: BTreeMap::from([
+                    ("container_id".to_string(), "c1".to_string()),
+                    ("exec_id".to_string(), "e1".to_string()),
+                ]),
+                error: None,
+            },
+            SandboxEvent {
+                time: "2023-05-04T08:00:01.000000000+00:00".to_string(),
+                sandbox_id: "sb1".to_string(),
+                subsystem: "hypervisor".to_string(),
+                action: "add_device".to_string(),
+                error: Some("no space".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let table = format_events(&events);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("TIME"));
+        assert!(lines[1].contains("agent       exec_process"));
+        assert!(lines[1].ends_with("container_id=c1 exec_id=e1"));
+        assert!(lines[2].ends_with("add_device                error=\"no space\""));
+    }
+}
@@ -4,18 +4,68 @@
 //
 
 use anyhow::Result;
+use serde::Serialize;
 use strum_macros::EnumString;
 
 // Builtin check command handler type.
-pub type BuiltinCmdFp = fn(args: &str) -> Result<()>;
+pub type BuiltinCmdFp = fn(args: &str) -> Result<Vec<CheckResult>>;
 
 // CheckType encodes the name of each check provided by kata-ctl.
 #[derive(Debug, strum_macros::Display, EnumString, PartialEq)]
 pub enum CheckType {
     CheckCpu,
+    CheckKernelModules,
     CheckNetwork,
 }
 
+// CheckStatus is the outcome of a check, a warning doesn't prevent Kata Containers from running
+// but some features may not work.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "UPPERCASE")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+// CheckResult is the outcome of a check along with a human readable explanation.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl CheckResult {
+    pub fn new(name: &str, status: CheckStatus, message: String) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            message,
+        }
+    }
+}
+
+// KernelParam is a kernel module parameter and the value it should have.
+#[derive(Clone, Copy)]
+pub struct KernelParam<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+    // a mismatch of an optional parameter is reported as a warning
+    pub required: bool,
+}
+
+// KernelModule is a kernel module used by Kata Containers, it may be built into the kernel.
+#[derive(Clone, Copy)]
+pub struct KernelModule<'a> {
+    pub name: &'a str,
+    pub descr: &'a str,
+    pub params: &'a [KernelParam<'a>],
+    // a missing optional module is reported as a warning
+    pub required: bool,
+}
+
 // PermissionType is used to show whether a check needs to run with elevated (super-user)
 // privileges, or whether it can run as normal user.
 #[derive(strum_macros::Display, EnumString, PartialEq)]
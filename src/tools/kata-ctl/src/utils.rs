@@ -11,7 +11,12 @@ use crate::arch::arch_specific;
 use crate::check::get_single_cpu_info;
 
 use anyhow::{anyhow, Context, Result};
+use kata_types::config::{
+    hypervisor::register_hypervisor_plugin, DragonballConfig, QemuConfig, TomlConfig,
+};
 use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 const NON_PRIV_USER: &str = "nobody";
 
@@ -27,7 +32,20 @@ pub fn drop_privs() -> Result<()> {
     Ok(())
 }
 
-const PROC_VERSION_FILE: &str = "/proc/version";
+// Load the Kata configuration, `config_file` or the default configuration file is used.
+pub fn load_kata_config(config_file: Option<&str>) -> Result<(TomlConfig, PathBuf)> {
+    // hypervisor plugins must be registered before loading the configuration
+    register_hypervisor_plugin("dragonball", Arc::new(DragonballConfig::new()));
+    register_hypervisor_plugin("qemu", Arc::new(QemuConfig::new()));
+
+    let (config, path) = TomlConfig::load_from_file(config_file.unwrap_or_default())
+        .context("load kata configuration")?;
+    config.validate().context("validate kata configuration")?;
+
+    Ok((config, path))
+}
+
+pub const PROC_VERSION_FILE: &str = "/proc/version";
 
 pub fn get_kernel_version(proc_version_file: &str) -> Result<String> {
     let contents = fs::read_to_string(proc_version_file)
@@ -43,10 +61,10 @@ pub fn get_kernel_version(proc_version_file: &str) -> Result<String> {
     Ok(kernel_version)
 }
 
-const OS_RELEASE: &str = "/etc/os-release";
+pub const OS_RELEASE: &str = "/etc/os-release";
 
 // Clear Linux has a different path (for stateless support)
-const OS_RELEASE_CLR: &str = "/usr/lib/os-release";
+pub const OS_RELEASE_CLR: &str = "/usr/lib/os-release";
 
 const UNKNOWN: &str = "unknown";
 
@@ -110,7 +128,6 @@ pub fn get_distro_details(os_release: &str, os_release_clr: &str) -> Result<(Str
 pub fn get_generic_cpu_details(cpu_info_file: &str) -> Result<(String, String)> {
     let cpu_info = get_single_cpu_info(cpu_info_file, "\n\n")?;
     let lines = cpu_info.lines();
-    println!("Single cpu info: {}", cpu_info);
     let mut vendor = String::new();
     let mut model = String::new();
 
@@ -144,6 +161,19 @@ pub fn get_generic_cpu_details(cpu_info_file: &str) -> Result<(String, String)>
     Ok((vendor, model))
 }
 
+// A sandbox ID must match ^[a-zA-Z0-9][a-zA-Z0-9_.-]+$, the same as the runtime requires.
+pub fn verify_sandbox_id(id: &str) -> Result<()> {
+    let mut chars = id.chars();
+    let valid = matches!(chars.next(), Some(first) if first.is_ascii_alphanumeric()
+                && id.len() > 1
+                && chars.all(|c| c.is_ascii_alphanumeric() || ['.', '-', '_'].contains(&c)));
+    if !valid {
+        return Err(anyhow!("invalid sandbox ID {:?}", id));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -287,4 +317,15 @@ mod tests {
         );
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_verify_sandbox_id() {
+        assert!(verify_sandbox_id("a1b2c3").is_ok());
+        assert!(verify_sandbox_id("sandbox_1.test-2").is_ok());
+        assert!(verify_sandbox_id("").is_err());
+        assert!(verify_sandbox_id("a").is_err());
+        assert!(verify_sandbox_id("-abc").is_err());
+        assert!(verify_sandbox_id("../abc").is_err());
+        assert!(verify_sandbox_id("abc/def").is_err());
+    }
 }
@@ -9,9 +9,23 @@ use cgroups;
 use cgroups::freezer::{FreezerController, FreezerState};
 use std::{thread, time};
 
+// Whether the cgroup exists in any of its hierarchies, the rootless container may run without
+// cgroups.
+pub fn cgroup_exists(cgroup: &cgroups::Cgroup) -> bool {
+    cgroup
+        .subsystems()
+        .iter()
+        .any(|s| s.to_controller().exists())
+}
+
 // Try to remove the provided cgroups path five times with increasing delay between tries.
 // If after all there are not removed cgroups, an appropriate error will be returned.
 pub fn remove_cgroup_dir(cgroup: &cgroups::Cgroup) -> Result<()> {
+    // The rootless container may run without cgroups.
+    if !cgroup_exists(cgroup) {
+        return Ok(());
+    }
+
     let mut retries = 5;
     let mut delay = time::Duration::from_millis(10);
     while retries != 0 {
@@ -50,6 +64,9 @@ pub fn get_freezer_state(freezer: &FreezerController) -> Result<FreezerState> {
 
 // check whether freezer state is frozen
 pub fn is_paused(cgroup: &cgroups::Cgroup) -> Result<bool> {
+    if !cgroup_exists(cgroup) {
+        return Ok(false);
+    }
     let freezer_controller: &FreezerController = cgroup
         .controller_of()
         .ok_or_else(|| anyhow!("failed to get freezer controller"))?;
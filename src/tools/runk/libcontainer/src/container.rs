@@ -3,9 +3,12 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use crate::cgroup::{freeze, remove_cgroup_dir};
+use crate::cgroup::{cgroup_exists, freeze, remove_cgroup_dir};
+use crate::criu::{self, CriuOpts};
+use crate::events::Stats;
 use crate::status::{self, get_current_container_state, Status};
-use anyhow::{anyhow, Result};
+use crate::utils::canonicalize_spec_root;
+use anyhow::{anyhow, Context, Result};
 use cgroups;
 use cgroups::freezer::FreezerState;
 use cgroups::hierarchies::is_cgroup2_unified_mode;
@@ -15,9 +18,11 @@ use nix::{
     sys::signal::SIGKILL,
     unistd::{chdir, unlink, Pid},
 };
-use oci::{ContainerState, State as OCIState};
+use oci::{ContainerState, Linux, LinuxResources, Spec, State as OCIState};
 use procfs;
 use rustjail::cgroups::fs::Manager as CgroupManager;
+use rustjail::cgroups::systemd::manager::Manager as SystemdManager;
+use rustjail::cgroups::Manager;
 use rustjail::{
     container::{BaseContainer, LinuxContainer, EXEC_FIFO_FILENAME},
     process::{Process, ProcessOperations},
@@ -29,6 +34,7 @@ use std::{
     env::current_dir,
     fs,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 use kata_sys_util::hooks::HookStates;
@@ -53,25 +59,10 @@ pub struct Container {
 impl Container {
     pub fn load(state_root: &Path, id: &str) -> Result<Self> {
         let status = Status::load(state_root, id)?;
-        let spec = status
-            .config
-            .spec
-            .as_ref()
-            .ok_or_else(|| anyhow!("spec config was not present"))?;
-        let linux = spec
-            .linux
-            .as_ref()
-            .ok_or_else(|| anyhow!("linux config was not present"))?;
-        let cpath = if linux.cgroups_path.is_empty() {
-            id.to_string()
-        } else {
-            linux
-                .cgroups_path
-                .clone()
-                .trim_start_matches('/')
-                .to_string()
-        };
-        let cgroup = cgroups::Cgroup::load(cgroups::hierarchies::auto(), cpath);
+        let cgroup = cgroups::Cgroup::load(
+            cgroups::hierarchies::auto(),
+            status.cgroup_manager.cpath.trim_start_matches('/'),
+        );
         let state = get_current_container_state(&status, &cgroup)?;
         Ok(Self {
             status,
@@ -81,6 +72,13 @@ impl Container {
     }
 
     pub fn processes(&self) -> Result<Vec<Pid>> {
+        // Only the init process is known if the rootless container runs without cgroups.
+        if !cgroup_exists(&self.cgroup) {
+            if self.state == ContainerState::Stopped {
+                return Ok(vec![]);
+            }
+            return Ok(vec![Pid::from_raw(self.status.pid)]);
+        }
         let pids = self.cgroup.tasks();
         let result = pids.iter().map(|x| Pid::from_raw(x.pid as i32)).collect();
         Ok(result)
@@ -179,6 +177,11 @@ impl Container {
                 self.state
             ));
         }
+        if !cgroup_exists(&self.cgroup) {
+            return Err(anyhow!(
+                "failed to pause container: container is running without cgroups"
+            ));
+        }
         freeze(&self.cgroup, FreezerState::Frozen)?;
         Ok(())
     }
@@ -198,6 +201,183 @@ impl Container {
         remove_cgroup_dir(&self.cgroup)?;
         self.status.remove_dir()
     }
+
+    // The cgroup manager in the status is deserialized without the cgroup, so create a new one
+    // from the cgroup path.
+    fn cgroup_manager(&self) -> Result<Box<dyn Manager>> {
+        if self.state == ContainerState::Stopped {
+            return Err(anyhow!("container {} is not running", self.status.id));
+        }
+        if !cgroup_exists(&self.cgroup) {
+            return Err(anyhow!(
+                "container {} is running without cgroups",
+                self.status.id
+            ));
+        }
+        if self.status.config.use_systemd_cgroup {
+            let cgroups_path = self
+                .status
+                .config
+                .spec
+                .as_ref()
+                .and_then(|spec| spec.linux.as_ref())
+                .map(|linux| linux.cgroups_path.as_str())
+                .unwrap_or_default();
+            return Ok(Box::new(SystemdManager::new(cgroups_path)?));
+        }
+        Ok(Box::new(CgroupManager::new(
+            &self.status.cgroup_manager.cpath,
+        )?))
+    }
+
+    /// Get the cgroup path of the subsystem, e.g. "memory".
+    pub fn cgroup_path(&self, subsystem: &str) -> Result<String> {
+        self.cgroup_manager()?.get_cgroup_path(subsystem)
+    }
+
+    /// The resources of the container, the ones in the spec unless updated.
+    pub fn resources(&self) -> LinuxResources {
+        self.status
+            .config
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.linux.as_ref())
+            .and_then(|linux| linux.resources.clone())
+            .unwrap_or_default()
+    }
+
+    /// Update the cgroup resources of a running container, the new resources are saved in the
+    /// status so that the following updates are based on them.
+    pub fn update(&mut self, resources: &LinuxResources) -> Result<()> {
+        self.cgroup_manager()?.set(resources, true)?;
+
+        if let Some(linux) = self
+            .status
+            .config
+            .spec
+            .as_mut()
+            .and_then(|spec| spec.linux.as_mut())
+        {
+            linux.resources = Some(resources.clone());
+        }
+        self.status.save()
+    }
+
+    pub fn stats(&self) -> Result<Stats> {
+        let stats = self.cgroup_manager()?.get_stats()?;
+        Ok(Stats::from(&stats))
+    }
+
+    /// Checkpoint the container into the image path of the options. Unless leave_running is set,
+    /// the processes are killed by CRIU and the container is destroyed after the checkpoint.
+    pub fn checkpoint(&self, opts: &CriuOpts, leave_running: bool, logger: &Logger) -> Result<()> {
+        if self.state != ContainerState::Running && self.state != ContainerState::Paused {
+            return Err(anyhow!(
+                "failed to checkpoint container: current status is: {:?}",
+                self.state
+            ));
+        }
+        criu::checkpoint(&self.status, opts, leave_running, logger)?;
+
+        if !leave_running {
+            self.destroy()?;
+        }
+        Ok(())
+    }
+
+    /// Restore a container from the image path of the options with the given id. The spec is
+    /// loaded from the bundle, and the cgroup of the container is created before CRIU restores
+    /// the processes into it.
+    pub fn restore(
+        state_root: &Path,
+        id: &str,
+        bundle: &Path,
+        opts: &CriuOpts,
+        logger: &Logger,
+    ) -> Result<Self> {
+        let mut status = criu::import_status(&opts.image_path)?;
+        if status.config.use_systemd_cgroup {
+            return Err(anyhow!(
+                "restore is not supported with the systemd cgroup driver"
+            ));
+        }
+
+        let bundle_canon = bundle.canonicalize()?;
+        let config_path = get_config_path(&bundle_canon);
+        let mut spec = Spec::load(
+            config_path
+                .to_str()
+                .ok_or_else(|| anyhow!("invalid config path"))?,
+        )?;
+        canonicalize_spec_root(&mut spec, &bundle_canon)?;
+        let linux = spec
+            .linux
+            .as_ref()
+            .ok_or_else(|| anyhow!("linux config was not present"))?;
+        let cpath = cgroup_cpath(linux, id);
+        let resources = linux.resources.clone().unwrap_or_default();
+
+        status.id = id.to_string();
+        status.root = state_root.to_path_buf();
+        status.bundle = bundle_canon;
+        status.rootfs = spec
+            .root
+            .as_ref()
+            .ok_or_else(|| anyhow!("root config was not present in the spec"))?
+            .path
+            .clone();
+        status.config.spec = Some(spec);
+
+        Status::create_dir(state_root, id)?;
+        let cgroup_manager = match CgroupManager::new(&cpath) {
+            Ok(cgm) => cgm,
+            Err(e) => {
+                let _ = status.remove_dir();
+                return Err(e);
+            }
+        };
+        status.cgroup_manager = cgroup_manager;
+
+        if let Err(e) = restore_process(&mut status, &resources, opts, logger) {
+            let _ = remove_cgroup_dir(&cgroups::Cgroup::load(cgroups::hierarchies::auto(), cpath));
+            let _ = status.remove_dir();
+            return Err(e);
+        }
+        debug!(logger, "saved status is {:?}", status);
+
+        Self::load(state_root, id)
+    }
+}
+
+fn restore_process(
+    status: &mut Status,
+    resources: &LinuxResources,
+    opts: &CriuOpts,
+    logger: &Logger,
+) -> Result<()> {
+    status
+        .cgroup_manager
+        .set(resources, false)
+        .context("failed to set cgroup resources")?;
+
+    status.pid = criu::restore(status, opts, logger)?;
+    let proc = procfs::process::Process::new(status.pid)?;
+    status.process_start_time = proc.stat()?.starttime;
+    status.created = SystemTime::now().into();
+    status.save()
+}
+
+// The cgroup path of the container relative to the cgroup mount points.
+fn cgroup_cpath(linux: &Linux, id: &str) -> String {
+    if linux.cgroups_path.is_empty() {
+        id.to_string()
+    } else {
+        linux
+            .cgroups_path
+            .clone()
+            .trim_start_matches('/')
+            .to_string()
+    }
 }
 
 /// Used to run a process. If init is set, it will create a container and run the process in it.
@@ -332,16 +512,23 @@ impl ContainerLauncher {
             oci_state,
             process_start_time,
             self.runner.created,
-            self.runner
-                .cgroup_manager
-                .as_ref()
-                .as_any()?
-                .downcast_ref::<CgroupManager>()
-                .unwrap()
-                .clone(),
+            self.fs_cgroup_manager()?,
             self.runner.config.clone(),
         )
     }
+
+    // The status keeps the cgroupfs manager, the systemd manager is also backed by the cgroupfs
+    // in the same path.
+    fn fs_cgroup_manager(&self) -> Result<CgroupManager> {
+        let cgroup_manager = self.runner.cgroup_manager.as_ref().as_any()?;
+        if let Some(cgm) = cgroup_manager.downcast_ref::<CgroupManager>() {
+            return Ok(cgm.clone());
+        }
+        let cgm = cgroup_manager
+            .downcast_ref::<SystemdManager>()
+            .ok_or_else(|| anyhow!("unknown cgroup manager"))?;
+        CgroupManager::new(&cgm.cpath)
+    }
 }
 
 pub fn create_linux_container(
@@ -0,0 +1,427 @@
+// Copyright 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+// Checkpoint and restore a container with CRIU.
+// Ref: https://criu.org/CLI
+
+use crate::status::Status;
+use anyhow::{anyhow, Context, Result};
+use nix::{
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    mount::{mount, umount2, MntFlags, MsFlags},
+};
+use oci::Spec;
+use slog::{info, warn, Logger};
+use std::{
+    fs::{self, File},
+    os::unix::{fs::MetadataExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+pub const CRIU_BINARY: &str = "criu";
+const DUMP_LOG_FILE: &str = "dump.log";
+const RESTORE_LOG_FILE: &str = "restore.log";
+const RESTORE_PID_FILE: &str = "restore.pid";
+// The status of the container is exported along with the images, so that the container can be
+// restored on another host.
+const EXPORTED_STATUS_FILE: &str = "runk-status.json";
+// The key of the network namespace which is not created by CRIU, same as runc.
+const EXTERNAL_NETNS_KEY: &str = "extRootNetNS";
+// The masked files are bind mounts of /dev/null.
+const MASKED_PATH_SOURCE: &str = "/dev/null";
+
+/// Options shared by checkpoint and restore.
+#[derive(Debug, Clone, Default)]
+pub struct CriuOpts {
+    pub image_path: PathBuf,
+    /// Path for the CRIU work files and logs, the image path is used if not set.
+    pub work_path: Option<PathBuf>,
+    pub tcp_established: bool,
+    pub file_locks: bool,
+    pub ext_unix_sk: bool,
+    pub shell_job: bool,
+}
+
+impl CriuOpts {
+    fn work_path(&self) -> &Path {
+        self.work_path.as_deref().unwrap_or(&self.image_path)
+    }
+
+    fn common_args(&self, log_file: &str) -> Vec<String> {
+        let mut args = vec![
+            "--images-dir".to_string(),
+            self.image_path.display().to_string(),
+            "--work-dir".to_string(),
+            self.work_path().display().to_string(),
+            "--log-file".to_string(),
+            log_file.to_string(),
+            "-v4".to_string(),
+            "--manage-cgroups=soft".to_string(),
+        ];
+        for (enabled, arg) in [
+            (self.tcp_established, "--tcp-established"),
+            (self.file_locks, "--file-locks"),
+            (self.ext_unix_sk, "--ext-unix-sk"),
+            (self.shell_job, "--shell-job"),
+        ] {
+            if enabled {
+                args.push(arg.to_string());
+            }
+        }
+        args
+    }
+}
+
+// The bind mounts are not dumped by CRIU, they are mapped by the destinations instead.
+fn bind_mounts(spec: &Spec) -> impl Iterator<Item = &oci::Mount> {
+    spec.mounts
+        .iter()
+        .filter(|m| m.r#type == "bind" || m.options.iter().any(|o| o == "bind" || o == "rbind"))
+}
+
+// The network namespace joined by path, e.g. the one of the pod, is not created by CRIU.
+fn external_netns(spec: &Spec) -> Option<&str> {
+    spec.linux
+        .as_ref()?
+        .namespaces
+        .iter()
+        .find(|ns| ns.r#type == "network" && !ns.path.is_empty())
+        .map(|ns| ns.path.as_str())
+}
+
+// The masked paths which are files are hidden by bind mounts of /dev/null, they are not dumped
+// by CRIU either. The masked directories are left alone when the container is created.
+fn masked_files<'a>(spec: &'a Spec, root: &Path) -> Vec<&'a str> {
+    spec.linux
+        .as_ref()
+        .map(|linux| {
+            linux
+                .masked_paths
+                .iter()
+                .filter(|p| {
+                    fs::metadata(root.join(p.trim_start_matches('/')))
+                        .map(|m| !m.is_dir())
+                        .unwrap_or(false)
+                })
+                .map(|p| p.as_str())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn masked_paths(spec: &Spec) -> impl Iterator<Item = &str> {
+    spec.linux
+        .iter()
+        .flat_map(|linux| linux.masked_paths.iter().map(|p| p.as_str()))
+}
+
+fn dump_args(status: &Status, opts: &CriuOpts, leave_running: bool) -> Result<Vec<String>> {
+    let spec = status
+        .config
+        .spec
+        .as_ref()
+        .ok_or_else(|| anyhow!("spec config was not present in the status"))?;
+
+    let mut args = vec![
+        "dump".to_string(),
+        "--tree".to_string(),
+        status.pid.to_string(),
+        "--root".to_string(),
+        status.rootfs.clone(),
+    ];
+    args.extend(opts.common_args(DUMP_LOG_FILE));
+    if leave_running {
+        args.push("--leave-running".to_string());
+    }
+    for m in bind_mounts(spec) {
+        args.push("--ext-mount-map".to_string());
+        args.push(format!("{}:{}", m.destination, m.destination));
+    }
+    let container_root = PathBuf::from(format!("/proc/{}/root", status.pid));
+    for path in masked_files(spec, &container_root) {
+        args.push("--external".to_string());
+        args.push(format!("mnt[{}]:{}", path, path));
+    }
+    if let Some(path) = external_netns(spec) {
+        let ino = fs::metadata(path)
+            .with_context(|| format!("failed to stat network namespace {}", path))?
+            .ino();
+        args.push("--external".to_string());
+        args.push(format!("net[{}]:{}", ino, EXTERNAL_NETNS_KEY));
+    }
+
+    Ok(args)
+}
+
+fn restore_args(status: &Status, opts: &CriuOpts, netns_fd: Option<i32>) -> Result<Vec<String>> {
+    let spec = status
+        .config
+        .spec
+        .as_ref()
+        .ok_or_else(|| anyhow!("spec config was not present in the status"))?;
+
+    let mut args = vec![
+        "restore".to_string(),
+        "--root".to_string(),
+        status.rootfs.clone(),
+        "--restore-detached".to_string(),
+        "--pidfile".to_string(),
+        opts.work_path()
+            .join(RESTORE_PID_FILE)
+            .display()
+            .to_string(),
+        "--cgroup-root".to_string(),
+        format!("/{}", status.cgroup_manager.cpath.trim_start_matches('/')),
+    ];
+    args.extend(opts.common_args(RESTORE_LOG_FILE));
+    for m in bind_mounts(spec) {
+        args.push("--ext-mount-map".to_string());
+        args.push(format!("{}:{}", m.destination, m.source));
+    }
+    // The masked paths which were not dumped as external mounts are ignored by CRIU.
+    for path in masked_paths(spec) {
+        args.push("--external".to_string());
+        args.push(format!("mnt[{}]:{}", path, MASKED_PATH_SOURCE));
+    }
+    if let Some(fd) = netns_fd {
+        args.push("--inherit-fd".to_string());
+        args.push(format!("fd[{}]:{}", fd, EXTERNAL_NETNS_KEY));
+    }
+
+    Ok(args)
+}
+
+fn run_criu(args: &[String], opts: &CriuOpts, log_file: &str, logger: &Logger) -> Result<()> {
+    info!(logger, "run criu"; "args" => format!("{:?}", args));
+    let status = Command::new(CRIU_BINARY)
+        .args(args)
+        .status()
+        .with_context(|| format!("failed to run {}", CRIU_BINARY))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "criu {} failed with {}, see {} for details",
+            args[0],
+            status,
+            opts.work_path().join(log_file).display()
+        ));
+    }
+
+    Ok(())
+}
+
+fn prepare_dirs(opts: &CriuOpts) -> Result<()> {
+    fs::create_dir_all(&opts.image_path).with_context(|| {
+        format!(
+            "failed to create image directory {}",
+            opts.image_path.display()
+        )
+    })?;
+    fs::create_dir_all(opts.work_path()).with_context(|| {
+        format!(
+            "failed to create work directory {}",
+            opts.work_path().display()
+        )
+    })
+}
+
+/// Dump the processes of the container into the image path, and export the status of the
+/// container along with the images.
+pub fn checkpoint(
+    status: &Status,
+    opts: &CriuOpts,
+    leave_running: bool,
+    logger: &Logger,
+) -> Result<()> {
+    prepare_dirs(opts)?;
+    let args = dump_args(status, opts, leave_running)?;
+    run_criu(&args, opts, DUMP_LOG_FILE, logger)?;
+    export_status(status, &opts.image_path)
+}
+
+/// Restore the processes of the container from the image path, returns the pid of the restored
+/// init process. The cgroup of the container must have been created.
+pub fn restore(status: &Status, opts: &CriuOpts, logger: &Logger) -> Result<i32> {
+    prepare_dirs(opts)?;
+    let spec = status
+        .config
+        .spec
+        .as_ref()
+        .ok_or_else(|| anyhow!("spec config was not present in the status"))?;
+
+    // CRIU joins the network namespace through the inherited fd, keep it open until it's done.
+    let netns = match external_netns(spec) {
+        Some(path) => {
+            let file = File::open(path)
+                .with_context(|| format!("failed to open network namespace {}", path))?;
+            fcntl(file.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty()))?;
+            Some(file)
+        }
+        None => None,
+    };
+    let args = restore_args(status, opts, netns.as_ref().map(|f| f.as_raw_fd()))?;
+    let pid_file = opts.work_path().join(RESTORE_PID_FILE);
+    let _ = fs::remove_file(&pid_file);
+
+    // CRIU pivots into the root of the container, which must be a mount point. The restored
+    // processes keep their own copy of the mount in the new mount namespace.
+    let rootfs = status.rootfs.as_str();
+    mount(
+        Some(rootfs),
+        rootfs,
+        None::<&str>,
+        MsFlags::MS_BIND | MsFlags::MS_REC,
+        None::<&str>,
+    )
+    .with_context(|| format!("failed to bind mount rootfs {}", rootfs))?;
+    let result = run_criu(&args, opts, RESTORE_LOG_FILE, logger);
+    if let Err(e) = umount2(rootfs, MntFlags::MNT_DETACH) {
+        warn!(logger, "failed to umount rootfs {}: {}", rootfs, e);
+    }
+    result?;
+
+    let pid = fs::read_to_string(&pid_file)
+        .with_context(|| format!("failed to read {}", pid_file.display()))?;
+    pid.trim()
+        .parse::<i32>()
+        .with_context(|| format!("invalid pid {:?} of the restored container", pid))
+}
+
+pub fn export_status(status: &Status, image_path: &Path) -> Result<()> {
+    let file = File::create(image_path.join(EXPORTED_STATUS_FILE))?;
+    serde_json::to_writer(&file, status)?;
+    Ok(())
+}
+
+pub fn import_status(image_path: &Path) -> Result<Status> {
+    let path = image_path.join(EXPORTED_STATUS_FILE);
+    let file = File::open(&path).with_context(|| {
+        format!(
+            "failed to open {}, is it a checkpoint of runk?",
+            path.display()
+        )
+    })?;
+    Ok(serde_json::from_reader(&file)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::*;
+    use rustjail::cgroups::fs::Manager as CgroupManager;
+    use std::time::SystemTime;
+
+    fn create_status(spec: Spec) -> Status {
+        let cgm: CgroupManager = serde_json::from_str(TEST_CGM_DATA).unwrap();
+        let mut config = create_dummy_opts();
+        config.spec = Some(spec);
+        Status::new(
+            Path::new(TEST_STATE_ROOT_PATH),
+            Path::new(TEST_BUNDLE_PATH),
+            create_dummy_oci_state(),
+            1,
+            SystemTime::now(),
+            cgm,
+            config,
+        )
+        .unwrap()
+    }
+
+    fn test_spec() -> Spec {
+        let mut spec = create_dummy_opts().spec.unwrap();
+        spec.mounts = vec![
+            oci::Mount {
+                destination: "/proc".to_string(),
+                r#type: "proc".to_string(),
+                source: "proc".to_string(),
+                options: vec![],
+            },
+            oci::Mount {
+                destination: "/data".to_string(),
+                r#type: "none".to_string(),
+                source: "/host/data".to_string(),
+                options: vec!["rbind".to_string(), "ro".to_string()],
+            },
+        ];
+        spec
+    }
+
+    #[test]
+    fn test_dump_and_restore_args() {
+        let status = create_status(test_spec());
+        let opts = CriuOpts {
+            image_path: PathBuf::from("/tmp/image"),
+            work_path: Some(PathBuf::from("/tmp/work")),
+            tcp_established: true,
+            ..Default::default()
+        };
+
+        let args = dump_args(&status, &opts, true).unwrap();
+        assert_eq!(args[0], "dump");
+        assert!(args
+            .windows(2)
+            .any(|w| w[0] == "--tree" && w[1] == status.pid.to_string()));
+        assert!(args
+            .windows(2)
+            .any(|w| w[0] == "--work-dir" && w[1] == "/tmp/work"));
+        assert!(args.contains(&"--leave-running".to_string()));
+        assert!(args.contains(&"--tcp-established".to_string()));
+        assert!(!args.contains(&"--file-locks".to_string()));
+        assert!(args
+            .windows(2)
+            .any(|w| w[0] == "--ext-mount-map" && w[1] == "/data:/data"));
+        assert!(!args.iter().any(|a| a.starts_with("/proc")));
+
+        let args = restore_args(&status, &opts, Some(5)).unwrap();
+        assert_eq!(args[0], "restore");
+        assert!(args
+            .windows(2)
+            .any(|w| w[0] == "--pidfile" && w[1] == "/tmp/work/restore.pid"));
+        assert!(args
+            .windows(2)
+            .any(|w| w[0] == "--ext-mount-map" && w[1] == "/data:/host/data"));
+        assert!(args
+            .windows(2)
+            .any(|w| w[0] == "--inherit-fd" && w[1] == "fd[5]:extRootNetNS"));
+    }
+
+    #[test]
+    fn test_masked_paths() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("proc/acpi")).unwrap();
+        File::create(root.path().join("proc/kcore")).unwrap();
+        let mut spec = test_spec();
+        spec.linux.get_or_insert_with(Default::default).masked_paths = vec![
+            "/proc/kcore".to_string(),
+            "/proc/acpi".to_string(),
+            "/proc/missing".to_string(),
+        ];
+
+        assert_eq!(masked_files(&spec, root.path()), vec!["/proc/kcore"]);
+
+        let status = create_status(spec);
+        let opts = CriuOpts {
+            image_path: PathBuf::from("/tmp/image"),
+            ..Default::default()
+        };
+        let args = restore_args(&status, &opts, None).unwrap();
+        assert!(args
+            .windows(2)
+            .any(|w| w[0] == "--external" && w[1] == "mnt[/proc/kcore]:/dev/null"));
+    }
+
+    #[test]
+    fn test_export_import_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let status = create_status(test_spec());
+        export_status(&status, dir.path()).unwrap();
+
+        let imported = import_status(dir.path()).unwrap();
+        assert_eq!(imported.id, status.id);
+        assert_eq!(imported.pid, status.pid);
+        assert_eq!(imported.rootfs, status.rootfs);
+        assert!(import_status(&dir.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+// Copyright 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+// The events emitted by `runk events`, in the same JSON format as runc.
+// Ref: https://github.com/opencontainers/runc/blob/main/types/events.go
+
+use protocols::agent::{BlkioStatsEntry, CgroupStats, MemoryData};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const EVENT_TYPE_STATS: &str = "stats";
+pub const EVENT_TYPE_OOM: &str = "oom";
+
+fn is_zero(v: &u64) -> bool {
+    *v == 0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Event {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Stats>,
+}
+
+impl Event {
+    pub fn stats(id: &str, stats: Stats) -> Self {
+        Self {
+            event_type: EVENT_TYPE_STATS.to_string(),
+            id: id.to_string(),
+            data: Some(stats),
+        }
+    }
+
+    pub fn oom(id: &str) -> Self {
+        Self {
+            event_type: EVENT_TYPE_OOM.to_string(),
+            id: id.to_string(),
+            data: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub cpu: Cpu,
+    pub memory: Memory,
+    pub pids: Pids,
+    pub blkio: Blkio,
+    pub hugetlb: HashMap<String, Hugetlb>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Cpu {
+    pub usage: CpuUsage,
+    pub throttling: Throttling,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct CpuUsage {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub total: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub percpu: Vec<u64>,
+    pub kernel: u64,
+    pub user: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Throttling {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub periods: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub throttled_periods: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub throttled_time: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Memory {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub cache: u64,
+    pub usage: MemoryEntry,
+    pub swap: MemoryEntry,
+    pub kernel: MemoryEntry,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub raw: HashMap<String, u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct MemoryEntry {
+    pub limit: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub usage: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub max: u64,
+    pub failcnt: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Pids {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub current: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub limit: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Blkio {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub io_service_bytes_recursive: Vec<BlkioEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub io_serviced_recursive: Vec<BlkioEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub io_queued_recursive: Vec<BlkioEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub io_service_time_recursive: Vec<BlkioEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub io_wait_time_recursive: Vec<BlkioEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub io_merged_recursive: Vec<BlkioEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub io_time_recursive: Vec<BlkioEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sectors_recursive: Vec<BlkioEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct BlkioEntry {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub major: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub minor: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub op: String,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub value: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Hugetlb {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub usage: u64,
+    pub max: u64,
+    pub failcnt: u64,
+}
+
+fn memory_entry(data: &MemoryData) -> MemoryEntry {
+    MemoryEntry {
+        limit: data.get_limit(),
+        usage: data.get_usage(),
+        max: data.get_max_usage(),
+        failcnt: data.get_failcnt(),
+    }
+}
+
+fn blkio_entries(entries: &[BlkioStatsEntry]) -> Vec<BlkioEntry> {
+    entries
+        .iter()
+        .map(|e| BlkioEntry {
+            major: e.get_major(),
+            minor: e.get_minor(),
+            op: e.get_op().to_string(),
+            value: e.get_value(),
+        })
+        .collect()
+}
+
+impl From<&CgroupStats> for Stats {
+    fn from(stats: &CgroupStats) -> Self {
+        let cpu = stats.get_cpu_stats();
+        let memory = stats.get_memory_stats();
+        let pids = stats.get_pids_stats();
+        let blkio = stats.get_blkio_stats();
+
+        Stats {
+            cpu: Cpu {
+                usage: CpuUsage {
+                    total: cpu.get_cpu_usage().get_total_usage(),
+                    percpu: cpu.get_cpu_usage().get_percpu_usage().to_vec(),
+                    kernel: cpu.get_cpu_usage().get_usage_in_kernelmode(),
+                    user: cpu.get_cpu_usage().get_usage_in_usermode(),
+                },
+                throttling: Throttling {
+                    periods: cpu.get_throttling_data().get_periods(),
+                    throttled_periods: cpu.get_throttling_data().get_throttled_periods(),
+                    throttled_time: cpu.get_throttling_data().get_throttled_time(),
+                },
+            },
+            memory: Memory {
+                cache: memory.get_cache(),
+                usage: memory_entry(memory.get_usage()),
+                swap: memory_entry(memory.get_swap_usage()),
+                kernel: memory_entry(memory.get_kernel_usage()),
+                raw: memory.get_stats().clone(),
+            },
+            pids: Pids {
+                current: pids.get_current(),
+                limit: pids.get_limit(),
+            },
+            blkio: Blkio {
+                io_service_bytes_recursive: blkio_entries(blkio.get_io_service_bytes_recursive()),
+                io_serviced_recursive: blkio_entries(blkio.get_io_serviced_recursive()),
+                io_queued_recursive: blkio_entries(blkio.get_io_queued_recursive()),
+                io_service_time_recursive: blkio_entries(blkio.get_io_service_time_recursive()),
+                io_wait_time_recursive: blkio_entries(blkio.get_io_wait_time_recursive()),
+                io_merged_recursive: blkio_entries(blkio.get_io_merged_recursive()),
+                io_time_recursive: blkio_entries(blkio.get_io_time_recursive()),
+                sectors_recursive: blkio_entries(blkio.get_sectors_recursive()),
+            },
+            hugetlb: stats
+                .get_hugetlb_stats()
+                .iter()
+                .map(|(size, h)| {
+                    (
+                        size.clone(),
+                        Hugetlb {
+                            usage: h.get_usage(),
+                            max: h.get_max_usage(),
+                            failcnt: h.get_failcnt(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocols::agent::{CpuStats, CpuUsage as AgentCpuUsage, MemoryStats, PidsStats};
+
+    #[test]
+    fn test_stats_from_cgroup_stats() {
+        let mut cpu_usage = AgentCpuUsage::new();
+        cpu_usage.set_total_usage(100);
+        cpu_usage.set_percpu_usage(vec![60, 40]);
+        let mut cpu_stats = CpuStats::new();
+        cpu_stats.set_cpu_usage(cpu_usage);
+
+        let mut usage = MemoryData::new();
+        usage.set_usage(4096);
+        usage.set_limit(8192);
+        let mut memory_stats = MemoryStats::new();
+        memory_stats.set_usage(usage);
+
+        let mut pids_stats = PidsStats::new();
+        pids_stats.set_current(3);
+        pids_stats.set_limit(10);
+
+        let mut cgroup_stats = CgroupStats::new();
+        cgroup_stats.set_cpu_stats(cpu_stats);
+        cgroup_stats.set_memory_stats(memory_stats);
+        cgroup_stats.set_pids_stats(pids_stats);
+
+        let stats = Stats::from(&cgroup_stats);
+        assert_eq!(stats.cpu.usage.total, 100);
+        assert_eq!(stats.cpu.usage.percpu, vec![60, 40]);
+        assert_eq!(stats.memory.usage.usage, 4096);
+        assert_eq!(stats.memory.usage.limit, 8192);
+        assert_eq!(stats.pids.current, 3);
+        assert_eq!(stats.pids.limit, 10);
+    }
+
+    #[test]
+    fn test_event_json() {
+        let oom = serde_json::to_string(&Event::oom("ctr")).unwrap();
+        assert_eq!(oom, r#"{"type":"oom","id":"ctr"}"#);
+
+        let mut stats = Stats::default();
+        stats.pids.current = 2;
+        let event = serde_json::to_value(&Event::stats("ctr", stats)).unwrap();
+        assert_eq!(event["type"], "stats");
+        assert_eq!(event["data"]["pids"]["current"], 2);
+        assert_eq!(event["data"]["memory"]["usage"]["limit"], 0);
+        assert!(event["data"]["blkio"]
+            .as_object()
+            .map(|o| o.is_empty())
+            .unwrap());
+    }
+}
@@ -4,15 +4,20 @@
 //
 
 use crate::container::{create_linux_container, get_config_path, ContainerLauncher};
+use crate::rootless::{delegated_cgroup_path, setup_user_namespace};
 use crate::status::Status;
 use crate::utils::{canonicalize_spec_root, validate_spec};
 use anyhow::{anyhow, Result};
+use cgroups::hierarchies::is_cgroup2_unified_mode;
 use derive_builder::Builder;
+use nix::unistd::{getgid, getuid};
 use oci::Spec;
 use rustjail::specconv::CreateOpts;
 use slog::{debug, Logger};
 use std::path::PathBuf;
 
+const SYSTEMD_CGROUP_PREFIX: &str = "runk";
+
 /// Used for create and run commands. It will prepare the options used for creating a new container.
 #[derive(Default, Builder, Debug, Clone)]
 #[builder(build_fn(validate = "Self::validate"))]
@@ -22,6 +27,10 @@ pub struct InitContainer {
     root: PathBuf,
     console_socket: Option<PathBuf>,
     pid_file: Option<PathBuf>,
+    #[builder(default)]
+    systemd_cgroup: bool,
+    #[builder(default)]
+    rootless: bool,
 }
 
 impl InitContainerBuilder {
@@ -55,20 +64,21 @@ impl InitContainer {
         )?;
         // Only absolute rootfs path is valid when creating LinuxContainer later.
         canonicalize_spec_root(&mut spec, &bundle_canon)?;
+        self.prepare_spec(&mut spec)?;
         debug!(logger, "load spec from config file: {:?}", spec);
         validate_spec(&spec, &self.console_socket)?;
 
         let config = CreateOpts {
             cgroup_name: "".to_string(),
-            use_systemd_cgroup: false,
+            use_systemd_cgroup: self.systemd_cgroup,
             // TODO: liboci-cli does not support --no-pivot option for create and run command.
             // After liboci-cli supports the option, we will change the following code.
             // no_pivot_root: self.no_pivot,
             no_pivot_root: false,
             no_new_keyring: false,
             spec: Some(spec),
-            rootless_euid: false,
-            rootless_cgroup: false,
+            rootless_euid: self.rootless,
+            rootless_cgroup: self.rootless,
         };
         debug!(logger, "create LinuxContainer with config: {:?}", config);
         let container =
@@ -83,6 +93,30 @@ impl InitContainer {
             self.pid_file,
         ))
     }
+
+    /// Set up the user namespace and the cgroups path for the cgroup driver and rootless mode.
+    fn prepare_spec(&self, spec: &mut Spec) -> Result<()> {
+        if self.rootless {
+            if self.systemd_cgroup {
+                return Err(anyhow!(
+                    "systemd cgroup driver is not supported in rootless mode"
+                ));
+            }
+            setup_user_namespace(spec, getuid().as_raw(), getgid().as_raw())?;
+        }
+
+        if let Some(linux) = spec.linux.as_mut() {
+            if self.systemd_cgroup {
+                // The format of the systemd cgroups path is "slice:prefix:name".
+                if linux.cgroups_path.is_empty() {
+                    linux.cgroups_path = format!(":{}:{}", SYSTEMD_CGROUP_PREFIX, self.id);
+                }
+            } else if self.rootless && is_cgroup2_unified_mode() {
+                linux.cgroups_path = delegated_cgroup_path(&linux.cgroups_path, &self.id)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -169,6 +203,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_init_container_prepare_spec() {
+        let init = InitContainerBuilder::default()
+            .id(TEST_CONTAINER_ID.to_string())
+            .root(PathBuf::from(TEST_STATE_ROOT_PATH))
+            .bundle(PathBuf::from(TEST_BUNDLE_PATH))
+            .pid_file(None)
+            .console_socket(None)
+            .systemd_cgroup(true)
+            .build()
+            .unwrap();
+        let mut spec = create_dummy_spec();
+        init.prepare_spec(&mut spec).unwrap();
+        assert_eq!(spec.linux.as_ref().unwrap().cgroups_path, ":runk:test");
+
+        // the given cgroups path is kept
+        spec.linux.as_mut().unwrap().cgroups_path = "system.slice:foo:bar".to_string();
+        init.prepare_spec(&mut spec).unwrap();
+        assert_eq!(
+            spec.linux.as_ref().unwrap().cgroups_path,
+            "system.slice:foo:bar"
+        );
+
+        let mut init = init;
+        init.rootless = true;
+        assert!(init.prepare_spec(&mut create_dummy_spec()).is_err());
+    }
+
     #[test]
     fn test_init_container_tty_err() {
         let logger = slog::Logger::root(slog::Discard, o!());
@@ -7,6 +7,9 @@ pub mod activated_builder;
 pub mod cgroup;
 pub mod container;
 pub mod created_builder;
+pub mod criu;
+pub mod events;
 pub mod init_builder;
+pub mod rootless;
 pub mod status;
 pub mod utils;
@@ -0,0 +1,176 @@
+// Copyright 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+// Prepare the spec of a container which runs without root privileges.
+// Ref: https://github.com/opencontainers/runc/blob/main/docs/cgroup-v2.md
+
+use anyhow::{anyhow, Result};
+use oci::{LinuxIdMapping, LinuxNamespace, Spec};
+use std::{fs, path::Path};
+
+const PROC_SELF_CGROUP: &str = "/proc/self/cgroup";
+const USER_NAMESPACE: &str = "user";
+const SCOPE_SUFFIX: &str = ".scope";
+
+/// Add a user namespace which maps the current user to root in the container, unless the spec
+/// already has one. The uid and gid options of the mounts which are not mapped are removed, since
+/// they are invalid in the user namespace.
+pub fn setup_user_namespace(spec: &mut Spec, uid: u32, gid: u32) -> Result<()> {
+    let linux = spec
+        .linux
+        .as_mut()
+        .ok_or_else(|| anyhow!("linux config was not present"))?;
+
+    if !linux
+        .namespaces
+        .iter()
+        .any(|ns| ns.r#type == USER_NAMESPACE)
+    {
+        linux.namespaces.push(LinuxNamespace {
+            r#type: USER_NAMESPACE.to_string(),
+            path: "".to_string(),
+        });
+    }
+    if linux.uid_mappings.is_empty() {
+        linux.uid_mappings = vec![LinuxIdMapping {
+            container_id: 0,
+            host_id: uid,
+            size: 1,
+        }];
+    }
+    if linux.gid_mappings.is_empty() {
+        linux.gid_mappings = vec![LinuxIdMapping {
+            container_id: 0,
+            host_id: gid,
+            size: 1,
+        }];
+    }
+
+    let (uid_mappings, gid_mappings) = (&linux.uid_mappings, &linux.gid_mappings);
+    for m in spec.mounts.iter_mut() {
+        m.options.retain(|o| {
+            if let Some(id) = o.strip_prefix("uid=") {
+                is_mapped(uid_mappings, id)
+            } else if let Some(id) = o.strip_prefix("gid=") {
+                is_mapped(gid_mappings, id)
+            } else {
+                true
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn is_mapped(mappings: &[LinuxIdMapping], id: &str) -> bool {
+    match id.trim().parse::<u32>() {
+        Ok(id) => mappings
+            .iter()
+            .any(|m| id >= m.container_id && id - m.container_id < m.size),
+        Err(_) => false,
+    }
+}
+
+/// Get the cgroup path of a rootless container in the cgroup v2 subtree delegated to the current
+/// user. An absolute path is kept as it is, and a relative one (or the container id if it's
+/// empty) is placed under the cgroup of the current process.
+pub fn delegated_cgroup_path(cgroups_path: &str, id: &str) -> Result<String> {
+    if cgroups_path.starts_with('/') {
+        return Ok(cgroups_path.to_string());
+    }
+    let own_cgroup = own_cgroup(&fs::read_to_string(PROC_SELF_CGROUP)?)?;
+    let inner = if cgroups_path.is_empty() {
+        id
+    } else {
+        cgroups_path
+    };
+
+    Ok(Path::new(&own_cgroup).join(inner).display().to_string())
+}
+
+// Parse the cgroup v2 path of the current process. The scope of the current process already has
+// processes in it, so controllers can't be enabled for its children, use its parent instead.
+fn own_cgroup(content: &str) -> Result<String> {
+    let path = content
+        .lines()
+        .find_map(|l| l.strip_prefix("0::"))
+        .ok_or_else(|| anyhow!("cgroup v2 path was not found in {}", PROC_SELF_CGROUP))?;
+    let path = Path::new(path);
+
+    match path.parent() {
+        Some(parent) if path.to_string_lossy().ends_with(SCOPE_SUFFIX) => {
+            Ok(parent.display().to_string())
+        }
+        _ => Ok(path.display().to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::*;
+
+    #[test]
+    fn test_setup_user_namespace() {
+        let mut spec = create_dummy_spec();
+        spec.mounts = vec![oci::Mount {
+            destination: "/dev/pts".to_string(),
+            r#type: "devpts".to_string(),
+            source: "devpts".to_string(),
+            options: vec![
+                "nosuid".to_string(),
+                "gid=5".to_string(),
+                "uid=0".to_string(),
+            ],
+        }];
+
+        setup_user_namespace(&mut spec, 1000, 1001).unwrap();
+        let linux = spec.linux.as_ref().unwrap();
+        assert_eq!(
+            linux
+                .namespaces
+                .iter()
+                .filter(|ns| ns.r#type == USER_NAMESPACE)
+                .count(),
+            1
+        );
+        assert_eq!(linux.uid_mappings[0].host_id, 1000);
+        assert_eq!(linux.gid_mappings[0].host_id, 1001);
+        assert_eq!(linux.gid_mappings[0].size, 1);
+        assert_eq!(spec.mounts[0].options, vec!["nosuid", "uid=0"]);
+
+        // the existing user namespace and mappings are kept
+        setup_user_namespace(&mut spec, 2000, 2000).unwrap();
+        let linux = spec.linux.as_ref().unwrap();
+        assert_eq!(
+            linux.namespaces.len(),
+            create_dummy_spec().linux.unwrap().namespaces.len() + 1
+        );
+        assert_eq!(linux.uid_mappings[0].host_id, 1000);
+    }
+
+    #[test]
+    fn test_own_cgroup() {
+        let content = "0::/user.slice/user-1000.slice/user@1000.service/app.slice/run-1.scope\n";
+        assert_eq!(
+            own_cgroup(content).unwrap(),
+            "/user.slice/user-1000.slice/user@1000.service/app.slice"
+        );
+
+        let content = "0::/user.slice/user-1000.slice/user@1000.service/runk\n";
+        assert_eq!(
+            own_cgroup(content).unwrap(),
+            "/user.slice/user-1000.slice/user@1000.service/runk"
+        );
+
+        let content = "12:pids:/user.slice\n1:name=systemd:/user.slice\n";
+        assert!(own_cgroup(content).is_err());
+
+        assert_eq!(
+            delegated_cgroup_path("/runk/test", "test").unwrap(),
+            "/runk/test"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::Result;
+use clap::Parser;
+use libcontainer::{container::Container, criu::CriuOpts};
+use slog::{info, Logger};
+use std::path::{Path, PathBuf};
+
+/// Checkpoint a running container with CRIU
+#[derive(Parser, Debug)]
+pub struct Checkpoint {
+    /// Path for saving the CRIU image files
+    #[clap(long, default_value = "checkpoint")]
+    pub image_path: PathBuf,
+    /// Path for saving the CRIU work files and logs
+    #[clap(long)]
+    pub work_path: Option<PathBuf>,
+    /// Leave the processes running after checkpointing
+    #[clap(long)]
+    pub leave_running: bool,
+    /// Allow open TCP connections
+    #[clap(long)]
+    pub tcp_established: bool,
+    /// Allow external unix sockets
+    #[clap(long)]
+    pub ext_unix_sk: bool,
+    /// Allow shell jobs
+    #[clap(long)]
+    pub shell_job: bool,
+    /// Handle file locks
+    #[clap(long)]
+    pub file_locks: bool,
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+}
+
+impl From<&Checkpoint> for CriuOpts {
+    fn from(opts: &Checkpoint) -> Self {
+        CriuOpts {
+            image_path: opts.image_path.clone(),
+            work_path: opts.work_path.clone(),
+            tcp_established: opts.tcp_established,
+            file_locks: opts.file_locks,
+            ext_unix_sk: opts.ext_unix_sk,
+            shell_job: opts.shell_job,
+        }
+    }
+}
+
+pub fn run(opts: Checkpoint, root: &Path, logger: &Logger) -> Result<()> {
+    let container = Container::load(root, &opts.container_id)?;
+    container.checkpoint(&CriuOpts::from(&opts), opts.leave_running, logger)?;
+
+    info!(&logger, "checkpoint command finished successfully");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Parser, Debug)]
+    struct Cli {
+        #[clap(flatten)]
+        checkpoint: Checkpoint,
+    }
+
+    #[test]
+    fn test_criu_opts() {
+        let cli = Cli::parse_from([
+            "checkpoint",
+            "--image-path",
+            "/tmp/image",
+            "--tcp-established",
+            "--file-locks",
+            "ctr",
+        ]);
+        let opts = CriuOpts::from(&cli.checkpoint);
+        assert_eq!(opts.image_path, PathBuf::from("/tmp/image"));
+        assert!(opts.work_path.is_none());
+        assert!(opts.tcp_established);
+        assert!(opts.file_locks);
+        assert!(!opts.ext_unix_sk);
+        assert!(!opts.shell_job);
+        assert!(!cli.checkpoint.leave_running);
+        assert_eq!(cli.checkpoint.container_id, "ctr");
+    }
+}
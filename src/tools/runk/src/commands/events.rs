@@ -0,0 +1,59 @@
+// Copyright 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::{anyhow, Result};
+use libcontainer::container::Container;
+use libcontainer::events::Event;
+use liboci_cli::Events;
+use oci::ContainerState;
+use rustjail::cgroups::notifier;
+use slog::{info, warn, Logger};
+use std::path::Path;
+use std::time::Duration;
+
+pub async fn run(opts: Events, root: &Path, logger: &Logger) -> Result<()> {
+    let id = opts.container_id.as_str();
+    let container = Container::load(root, id)?;
+    if container.state == ContainerState::Stopped {
+        return Err(anyhow!("container {} is not running", id));
+    }
+
+    if opts.stats {
+        print_event(&Event::stats(id, container.stats()?))?;
+        info!(&logger, "events command finished successfully");
+        return Ok(());
+    }
+
+    if opts.interval == 0 {
+        return Err(anyhow!("the interval must be greater than 0"));
+    }
+
+    let mut oom = notifier::notify_oom(id, container.cgroup_path("memory")?).await?;
+    let mut ticker = tokio::time::interval(Duration::from_secs(opts.interval.into()));
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                // reload the container to catch up with its state
+                let container = Container::load(root, id)?;
+                if container.state == ContainerState::Stopped {
+                    break;
+                }
+                match container.stats() {
+                    Ok(stats) => print_event(&Event::stats(id, stats))?,
+                    Err(e) => warn!(&logger, "failed to get stats: {:?}", e),
+                }
+            }
+            Some(_) = oom.recv() => print_event(&Event::oom(id))?,
+        }
+    }
+
+    info!(&logger, "events command finished successfully");
+    Ok(())
+}
+
+fn print_event(event: &Event) -> Result<()> {
+    println!("{}", serde_json::to_string(event)?);
+    Ok(())
+}
@@ -3,15 +3,28 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+pub mod checkpoint;
 pub mod create;
 pub mod delete;
+pub mod events;
 pub mod exec;
 pub mod kill;
 pub mod list;
 pub mod pause;
 pub mod ps;
+pub mod restore;
 pub mod resume;
 pub mod run;
 pub mod spec;
 pub mod start;
 pub mod state;
+pub mod update;
+
+/// Global options for creating containers.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RuntimeOpts {
+    /// Use the systemd cgroup driver instead of the cgroupfs.
+    pub systemd_cgroup: bool,
+    /// Run containers without root privileges.
+    pub rootless: bool,
+}
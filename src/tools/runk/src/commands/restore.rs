@@ -0,0 +1,71 @@
+// Copyright 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::Result;
+use clap::Parser;
+use libcontainer::{container::Container, criu::CriuOpts};
+use slog::{info, Logger};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Restore a container from a checkpoint with CRIU
+#[derive(Parser, Debug)]
+pub struct Restore {
+    /// Path to the bundle directory of the container
+    #[clap(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+    /// Path to the CRIU image files
+    #[clap(long, default_value = "checkpoint")]
+    pub image_path: PathBuf,
+    /// Path for saving the CRIU work files and logs
+    #[clap(long)]
+    pub work_path: Option<PathBuf>,
+    /// Allow open TCP connections
+    #[clap(long)]
+    pub tcp_established: bool,
+    /// Allow external unix sockets
+    #[clap(long)]
+    pub ext_unix_sk: bool,
+    /// Allow shell jobs
+    #[clap(long)]
+    pub shell_job: bool,
+    /// Handle file locks
+    #[clap(long)]
+    pub file_locks: bool,
+    /// File to write the pid of the restored init process to
+    #[clap(long)]
+    pub pid_file: Option<PathBuf>,
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+}
+
+impl From<&Restore> for CriuOpts {
+    fn from(opts: &Restore) -> Self {
+        CriuOpts {
+            image_path: opts.image_path.clone(),
+            work_path: opts.work_path.clone(),
+            tcp_established: opts.tcp_established,
+            file_locks: opts.file_locks,
+            ext_unix_sk: opts.ext_unix_sk,
+            shell_job: opts.shell_job,
+        }
+    }
+}
+
+pub fn run(opts: Restore, root: &Path, logger: &Logger) -> Result<()> {
+    let container = Container::restore(
+        root,
+        &opts.container_id,
+        &opts.bundle,
+        &CriuOpts::from(&opts),
+        logger,
+    )?;
+    if let Some(pid_file) = opts.pid_file.as_ref() {
+        fs::write(pid_file, format!("{}", container.status.pid))?;
+    }
+
+    info!(&logger, "restore command finished successfully");
+    Ok(())
+}
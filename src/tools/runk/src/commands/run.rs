@@ -3,19 +3,27 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+use super::RuntimeOpts;
 use anyhow::Result;
 use libcontainer::{container::ContainerAction, init_builder::InitContainerBuilder};
 use liboci_cli::Run;
 use slog::{info, Logger};
 use std::path::Path;
 
-pub async fn run(opts: Run, root: &Path, logger: &Logger) -> Result<()> {
+pub async fn run(
+    opts: Run,
+    root: &Path,
+    runtime_opts: &RuntimeOpts,
+    logger: &Logger,
+) -> Result<()> {
     let mut launcher = InitContainerBuilder::default()
         .id(opts.container_id)
         .bundle(opts.bundle)
         .root(root.to_path_buf())
         .console_socket(opts.console_socket)
         .pid_file(opts.pid_file)
+        .systemd_cgroup(runtime_opts.systemd_cgroup)
+        .rootless(runtime_opts.rootless)
         .build()?
         .create_launcher(logger)?;
 
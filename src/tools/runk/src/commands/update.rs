@@ -0,0 +1,225 @@
+// Copyright 2023 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use libcontainer::container::Container;
+use oci::{LinuxBlockIo, LinuxCpu, LinuxMemory, LinuxPids, LinuxResources};
+use slog::{info, Logger};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+// The update command of liboci-cli only accepts the resources file and the pids limit, the
+// options below are the same as runc.
+/// Update container resource constraints
+#[derive(Parser, Debug)]
+pub struct Update {
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+    /// Read the new resource limits from the given json file. Use - to read from stdin.
+    /// If this option is used, all other options are ignored.
+    #[clap(short, long)]
+    pub resources: Option<PathBuf>,
+    /// Set a new I/O weight
+    #[clap(long)]
+    pub blkio_weight: Option<u64>,
+    /// Set CPU CFS period to be used for hardcapping (in microseconds)
+    #[clap(long)]
+    pub cpu_period: Option<u64>,
+    /// Set CPU usage limit within a given period (in microseconds)
+    #[clap(long)]
+    pub cpu_quota: Option<u64>,
+    /// Set CPU realtime period to be used for hardcapping (in microseconds)
+    #[clap(long)]
+    pub cpu_rt_period: Option<u64>,
+    /// Set CPU realtime hardcap limit (in microseconds)
+    #[clap(long)]
+    pub cpu_rt_runtime: Option<u64>,
+    /// Set CPU shares (relative weight vs. other containers)
+    #[clap(long)]
+    pub cpu_share: Option<u64>,
+    /// Set CPU(s) to use. The list can contain commas and ranges. For example: 0-3,7
+    #[clap(long)]
+    pub cpuset_cpus: Option<String>,
+    /// Set memory node(s) to use. The list format is the same as for --cpuset-cpus.
+    #[clap(long)]
+    pub cpuset_mems: Option<String>,
+    /// Set memory limit to num bytes
+    #[clap(long)]
+    pub memory: Option<u64>,
+    /// Set memory reservation (or soft limit) to num bytes
+    #[clap(long)]
+    pub memory_reservation: Option<u64>,
+    /// Set total memory + swap usage to num bytes. Use -1 to unset the limit (i.e. use
+    /// unlimited swap).
+    #[clap(long, allow_hyphen_values = true)]
+    pub memory_swap: Option<i64>,
+    /// Set the maximum number of processes allowed in the container
+    #[clap(long)]
+    pub pids_limit: Option<i64>,
+}
+
+pub fn run(opts: Update, root: &Path, logger: &Logger) -> Result<()> {
+    let mut container = Container::load(root, &opts.container_id)?;
+
+    // Same as runc, the other options are ignored if the resources file is given.
+    let resources = match opts.resources.as_ref() {
+        Some(path) => read_resources(path)?,
+        None => apply_options(container.resources(), &opts)?,
+    };
+    container.update(&resources)?;
+
+    info!(&logger, "update command finished successfully");
+    Ok(())
+}
+
+// Read the resources in the OCI format, "-" is for the stdin.
+fn read_resources(path: &Path) -> Result<LinuxResources> {
+    let content = if path == Path::new("-") {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)?;
+        content
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("failed to read resources file {}", path.display()))?
+    };
+
+    serde_json::from_str(&content).context("failed to parse resources")
+}
+
+fn to_i64(name: &str, v: u64) -> Result<i64> {
+    i64::try_from(v).map_err(|_| anyhow!("{} {} is out of range", name, v))
+}
+
+fn apply_options(mut resources: LinuxResources, opts: &Update) -> Result<LinuxResources> {
+    if let Some(weight) = opts.blkio_weight {
+        let weight = u16::try_from(weight)
+            .map_err(|_| anyhow!("blkio weight {} is out of range", weight))?;
+        resources
+            .block_io
+            .get_or_insert_with(LinuxBlockIo::default)
+            .weight = Some(weight);
+    }
+
+    let cpu_updated = opts.cpu_period.is_some()
+        || opts.cpu_quota.is_some()
+        || opts.cpu_rt_period.is_some()
+        || opts.cpu_rt_runtime.is_some()
+        || opts.cpu_share.is_some()
+        || opts.cpuset_cpus.is_some()
+        || opts.cpuset_mems.is_some();
+    if cpu_updated {
+        let cpu = resources.cpu.get_or_insert_with(LinuxCpu::default);
+        if let Some(period) = opts.cpu_period {
+            cpu.period = Some(period);
+        }
+        if let Some(quota) = opts.cpu_quota {
+            cpu.quota = Some(to_i64("cpu quota", quota)?);
+        }
+        if let Some(period) = opts.cpu_rt_period {
+            cpu.realtime_period = Some(period);
+        }
+        if let Some(runtime) = opts.cpu_rt_runtime {
+            cpu.realtime_runtime = Some(to_i64("cpu realtime runtime", runtime)?);
+        }
+        if let Some(shares) = opts.cpu_share {
+            cpu.shares = Some(shares);
+        }
+        if let Some(cpus) = opts.cpuset_cpus.as_ref() {
+            cpu.cpus = cpus.clone();
+        }
+        if let Some(mems) = opts.cpuset_mems.as_ref() {
+            cpu.mems = mems.clone();
+        }
+    }
+
+    if opts.memory.is_some() || opts.memory_reservation.is_some() || opts.memory_swap.is_some() {
+        let memory = resources.memory.get_or_insert_with(LinuxMemory::default);
+        if let Some(limit) = opts.memory {
+            memory.limit = Some(to_i64("memory", limit)?);
+        }
+        if let Some(reservation) = opts.memory_reservation {
+            memory.reservation = Some(to_i64("memory reservation", reservation)?);
+        }
+        if let Some(swap) = opts.memory_swap {
+            memory.swap = Some(swap);
+        }
+    }
+
+    if let Some(limit) = opts.pids_limit {
+        resources.pids = Some(LinuxPids { limit });
+    }
+
+    Ok(resources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser, Debug)]
+    struct Cli {
+        #[clap(flatten)]
+        update: Update,
+    }
+
+    fn parse(args: &[&str]) -> Update {
+        Cli::parse_from(std::iter::once("update").chain(args.iter().copied())).update
+    }
+
+    #[test]
+    fn test_apply_options() {
+        let mut resources = LinuxResources::default();
+        resources.pids = Some(LinuxPids { limit: 10 });
+        resources.memory = Some(LinuxMemory {
+            limit: Some(1 << 20),
+            ..Default::default()
+        });
+
+        let opts = parse(&[
+            "--cpu-quota",
+            "50000",
+            "--cpu-period",
+            "100000",
+            "--memory",
+            "2097152",
+            "--blkio-weight",
+            "500",
+            "ctr",
+        ]);
+        let resources = apply_options(resources, &opts).unwrap();
+        let cpu = resources.cpu.unwrap();
+        assert_eq!(cpu.quota, Some(50000));
+        assert_eq!(cpu.period, Some(100000));
+        assert_eq!(resources.memory.unwrap().limit, Some(2 << 20));
+        assert_eq!(resources.block_io.unwrap().weight, Some(500));
+        // untouched resources are kept
+        assert_eq!(resources.pids.unwrap().limit, 10);
+
+        let opts = parse(&["--pids-limit", "5", "ctr"]);
+        let resources = apply_options(LinuxResources::default(), &opts).unwrap();
+        assert_eq!(resources.pids.unwrap().limit, 5);
+        assert!(resources.cpu.is_none());
+        assert!(resources.memory.is_none());
+
+        let opts = parse(&["--blkio-weight", "70000", "ctr"]);
+        assert!(apply_options(LinuxResources::default(), &opts).is_err());
+    }
+
+    #[test]
+    fn test_read_resources() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("resources.json");
+        fs::write(&path, r#"{"pids":{"limit":20},"cpu":{"shares":512}}"#).unwrap();
+
+        let resources = read_resources(&path).unwrap();
+        assert_eq!(resources.pids.unwrap().limit, 20);
+        assert_eq!(resources.cpu.unwrap().shares, Some(512));
+        assert!(read_resources(&dir.path().join("missing")).is_err());
+    }
+}
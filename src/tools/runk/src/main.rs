@@ -5,17 +5,25 @@
 
 use anyhow::{anyhow, Result};
 use clap::{crate_description, crate_name, Parser};
-use liboci_cli::{CommonCmd, GlobalOpts};
-use liboci_cli::{Create, Delete, Kill, Start, State};
+use commands::RuntimeOpts;
+use liboci_cli::GlobalOpts;
+use liboci_cli::{
+    Create, Delete, Events, Exec, Kill, List, Pause, Ps, Resume, Run, Spec, Start, State,
+};
+use logging::{LogSink, LOG_SINK_JOURNALD, LOG_SINK_JSON, LOG_SINK_LOGFMT, LOG_SINK_SYSLOG};
+use nix::unistd::Uid;
 use slog::{o, Logger};
 use slog_async::AsyncGuard;
 use std::{
+    env,
     fs::OpenOptions,
+    io,
     path::{Path, PathBuf},
     process::exit,
 };
 
 const DEFAULT_ROOT_DIR: &str = "/run/runk";
+const XDG_RUNTIME_DIR: &str = "XDG_RUNTIME_DIR";
 const DEFAULT_LOG_LEVEL: slog::Level = slog::Level::Info;
 
 mod commands;
@@ -26,6 +34,8 @@ enum SubCommand {
     Standard(StandardCmd),
     #[clap(flatten)]
     Common(CommonCmd),
+    Checkpoint(commands::checkpoint::Checkpoint),
+    Restore(commands::restore::Restore),
     /// Launch an init process (do not call it outside of runk)
     Init {},
 }
@@ -40,61 +50,115 @@ pub enum StandardCmd {
     Kill(Kill),
 }
 
+// Copy from https://github.com/containers/youki/blob/v0.0.4/crates/liboci-cli/src/lib.rs#L49-L63
+// with the update command of runk, and without the checkpoint command which is implemented by
+// runk too.
+#[derive(Parser, Debug)]
+pub enum CommonCmd {
+    Events(Events),
+    Exec(Exec),
+    List(List),
+    Pause(Pause),
+    #[clap(allow_hyphen_values = true)]
+    Ps(Ps),
+    Resume(Resume),
+    Run(Run),
+    Update(commands::update::Update),
+    Spec(Spec),
+}
+
 #[derive(Parser, Debug)]
 #[clap(version, author, about = crate_description!())]
 struct Cli {
     #[clap(flatten)]
     global: GlobalOpts,
+    /// Ignore cgroup permission errors and run containers in a user namespace
+    #[clap(long, default_value = "auto", possible_values = &["true", "false", "auto"])]
+    rootless: String,
     #[clap(subcommand)]
     subcmd: SubCommand,
 }
 
-async fn cmd_run(subcmd: SubCommand, root_path: &Path, logger: &Logger) -> Result<()> {
+async fn cmd_run(
+    subcmd: SubCommand,
+    root_path: &Path,
+    runtime_opts: &RuntimeOpts,
+    logger: &Logger,
+) -> Result<()> {
     match subcmd {
         SubCommand::Standard(cmd) => match cmd {
-            StandardCmd::Create(create) => commands::create::run(create, root_path, logger).await,
+            StandardCmd::Create(create) => {
+                commands::create::run(create, root_path, runtime_opts, logger).await
+            }
             StandardCmd::Start(start) => commands::start::run(start, root_path, logger).await,
             StandardCmd::Delete(delete) => commands::delete::run(delete, root_path, logger).await,
             StandardCmd::State(state) => commands::state::run(state, root_path, logger),
             StandardCmd::Kill(kill) => commands::kill::run(kill, root_path, logger),
         },
         SubCommand::Common(cmd) => match cmd {
-            CommonCmd::Run(run) => commands::run::run(run, root_path, logger).await,
+            CommonCmd::Run(run) => commands::run::run(run, root_path, runtime_opts, logger).await,
             CommonCmd::Spec(spec) => commands::spec::run(spec, logger),
             CommonCmd::List(list) => commands::list::run(list, root_path, logger),
             CommonCmd::Exec(exec) => commands::exec::run(exec, root_path, logger).await,
             CommonCmd::Ps(ps) => commands::ps::run(ps, root_path, logger),
             CommonCmd::Pause(pause) => commands::pause::run(pause, root_path, logger),
             CommonCmd::Resume(resume) => commands::resume::run(resume, root_path, logger),
-            _ => {
-                return Err(anyhow!("command is not implemented yet"));
-            }
+            CommonCmd::Update(update) => commands::update::run(update, root_path, logger),
+            CommonCmd::Events(events) => commands::events::run(events, root_path, logger).await,
         },
+        SubCommand::Checkpoint(checkpoint) => {
+            commands::checkpoint::run(checkpoint, root_path, logger)
+        }
+        SubCommand::Restore(restore) => commands::restore::run(restore, root_path, logger),
         _ => unreachable!(),
     }
 }
 
+// Non-root users have no permission to write the default root directory, so use the runtime
+// directory of the user instead.
+fn default_root_dir(rootless: bool) -> PathBuf {
+    match env::var_os(XDG_RUNTIME_DIR) {
+        Some(dir) if rootless && !dir.is_empty() => PathBuf::from(dir).join(crate_name!()),
+        _ => PathBuf::from(DEFAULT_ROOT_DIR),
+    }
+}
+
 fn setup_logger(
     log_file: Option<PathBuf>,
+    log_format: Option<String>,
     log_level: slog::Level,
 ) -> Result<(Logger, Option<AsyncGuard>)> {
-    if let Some(ref file) = log_file {
-        let log_writer = OpenOptions::new()
-            .write(true)
-            .read(true)
-            .create(true)
-            .truncate(true)
-            .open(file)?;
-
-        // TODO: Support 'text' log format.
-        let (logger_local, logger_async_guard_local) =
-            logging::create_logger(crate_name!(), crate_name!(), log_level, log_writer);
-
-        Ok((logger_local, Some(logger_async_guard_local)))
-    } else {
-        let logger = slog::Logger::root(slog::Discard, o!());
-        Ok((logger, None))
+    // Same as runc, the log format is "text" or "json", and the sinks sending the records to
+    // journald or syslog are accepted too, without a log file.
+    let sink = match (log_format.as_deref(), log_file) {
+        (Some(format @ (LOG_SINK_JOURNALD | LOG_SINK_SYSLOG)), _) => {
+            LogSink::from_name(format, io::sink())
+        }
+        (format, Some(file)) => {
+            let log_writer = OpenOptions::new()
+                .write(true)
+                .read(true)
+                .create(true)
+                .truncate(true)
+                .open(file)?;
+            let sink_name = match format {
+                Some("text") => LOG_SINK_LOGFMT,
+                Some(format) => format,
+                None => LOG_SINK_JSON,
+            };
+            LogSink::from_name(sink_name, log_writer)
+        }
+        (_, None) => {
+            let logger = slog::Logger::root(slog::Discard, o!());
+            return Ok((logger, None));
+        }
     }
+    .map_err(|e| anyhow!(e))?;
+
+    let (logger_local, logger_async_guard_local) =
+        logging::create_logger_with_sink(crate_name!(), crate_name!(), log_level, sink)?;
+
+    Ok((logger_local, Some(logger_async_guard_local)))
 }
 
 async fn real_main() -> Result<()> {
@@ -105,10 +169,21 @@ async fn real_main() -> Result<()> {
         exit(0);
     }
 
+    // Same as runc, "auto" means rootless mode is enabled for non-root users.
+    let rootless = match cli.rootless.as_str() {
+        "true" => true,
+        "false" => false,
+        _ => !Uid::effective().is_root(),
+    };
+    let runtime_opts = RuntimeOpts {
+        systemd_cgroup: cli.global.systemd_cgroup,
+        rootless,
+    };
+
     let root_path = if let Some(path) = cli.global.root {
         path
     } else {
-        PathBuf::from(DEFAULT_ROOT_DIR)
+        default_root_dir(rootless)
     };
 
     let log_level = if cli.global.debug {
@@ -117,9 +192,9 @@ async fn real_main() -> Result<()> {
         DEFAULT_LOG_LEVEL
     };
 
-    let (logger, _async_guard) = setup_logger(cli.global.log, log_level)?;
+    let (logger, _async_guard) = setup_logger(cli.global.log, cli.global.log_format, log_level)?;
 
-    cmd_run(cli.subcmd, &root_path, &logger).await?;
+    cmd_run(cli.subcmd, &root_path, &runtime_opts, &logger).await?;
 
     Ok(())
 }
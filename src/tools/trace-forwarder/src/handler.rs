@@ -3,14 +3,17 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+use crate::server::SharedExporter;
+use crate::tracer;
 use anyhow::{anyhow, Context, Result};
 use byteorder::{ByteOrder, NetworkEndian};
-use futures::executor::block_on;
-use opentelemetry::sdk::export::trace::{SpanData, SpanExporter};
+use opentelemetry::sdk::export::trace::SpanData;
+use opentelemetry::sdk::Resource;
 use slog::{debug, info, o, Logger};
 use std::fs::File;
 use std::io::{ErrorKind, Read};
 use std::os::unix::io::{FromRawFd, RawFd};
+use tokio::runtime::Handle;
 
 // The VSOCK "packet" protocol used comprises two elements:
 //
@@ -32,14 +35,15 @@ fn mk_io_err(msg: &str) -> std::io::Error {
 async fn handle_async_connection<'a>(
     logger: Logger,
     mut conn: &'a mut dyn Read,
-    exporter: &'a mut dyn SpanExporter,
+    exporter: SharedExporter,
+    resource: &'a Resource,
     dump_only: bool,
 ) -> Result<()> {
     let logger = logger.new(o!("subsystem" => "handler"));
 
     debug!(logger, "handling connection");
 
-    handle_trace_data(logger.clone(), &mut conn, exporter, dump_only)
+    handle_trace_data(logger.clone(), &mut conn, exporter, resource, dump_only)
         .await
         .map_err(|e| mk_io_err(&format!("failed to handle data: {:}", e)))?;
 
@@ -51,7 +55,8 @@ async fn handle_async_connection<'a>(
 async fn handle_trace_data<'a>(
     logger: Logger,
     reader: &'a mut dyn Read,
-    exporter: &'a mut dyn SpanExporter,
+    exporter: SharedExporter,
+    resource: &'a Resource,
     dump_only: bool,
 ) -> Result<()> {
     loop {
@@ -81,18 +86,21 @@ async fn handle_trace_data<'a>(
 
         debug!(logger, "read payload");
 
-        let span_data: SpanData =
+        let mut span_data: SpanData =
             bincode::deserialize(&encoded_payload[..]).expect("failed to deserialise payload");
 
         debug!(logger, "deserialised payload");
 
+        tracer::update_span_resource(&mut span_data, resource);
+
         if dump_only {
             debug!(logger, "dump-only: {:?}", span_data);
         } else {
             let batch = vec![span_data];
 
-            // Call low-level Jaeger exporter to send the trace span immediately.
-            let result = exporter.export(batch).await;
+            // Call low-level exporter to send the trace span immediately.
+            // The exporter is shared by the connections of all the sandboxes.
+            let result = exporter.lock().await.export(batch).await;
 
             if result.is_err() {
                 return Err(anyhow!("failed to export trace spans: {:?}", result));
@@ -105,17 +113,20 @@ async fn handle_trace_data<'a>(
     Ok(())
 }
 
+// Note: takes ownership of the file descriptor of the connection.
 pub fn handle_connection(
     logger: Logger,
+    runtime: &Handle,
     fd: RawFd,
-    exporter: &mut dyn SpanExporter,
+    exporter: SharedExporter,
+    resource: &Resource,
     dump_only: bool,
 ) -> Result<()> {
     let mut file = unsafe { File::from_raw_fd(fd) };
 
-    let conn = handle_async_connection(logger, &mut file, exporter, dump_only);
+    let conn = handle_async_connection(logger, &mut file, exporter, resource, dump_only);
 
-    block_on(conn)?;
+    runtime.block_on(conn)?;
 
     Ok(())
 }
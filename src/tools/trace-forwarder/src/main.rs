@@ -5,7 +5,7 @@
 
 #![warn(unused_extern_crates)]
 use anyhow::{anyhow, Result};
-use clap::{crate_name, crate_version, App, Arg};
+use clap::{crate_name, crate_version, App, Arg, ArgMatches};
 use slog::{error, info, Logger};
 use std::env;
 use std::io;
@@ -26,22 +26,34 @@ const DEFAULT_KATA_VSOCK_TRACING_PORT: &str = "10240";
 const DEFAULT_JAEGER_HOST: &str = "127.0.0.1";
 const DEFAULT_JAEGER_PORT: &str = "6831";
 
+const DEFAULT_OTLP_GRPC_ENDPOINT: &str = "http://127.0.0.1:4317";
+const DEFAULT_OTLP_HTTP_ENDPOINT: &str = "http://127.0.0.1:4318/v1/traces";
+
+// Rotate the trace file every 10MiB, keeping the last 5 rotated files.
+const DEFAULT_FILE_MAX_SIZE: &str = "10485760";
+const DEFAULT_FILE_MAX_FILES: &str = "5";
+
 mod handler;
 mod server;
 mod tracer;
 mod utils;
 
 use crate::utils::{
-    make_hybrid_socket_path, str_to_vsock_cid, str_to_vsock_port, VSOCK_CID_ANY_STR,
+    check_sandbox_ids, make_hybrid_socket_path, str_to_sandbox_id, str_to_vsock_cid,
+    str_to_vsock_port, SANDBOX_ID_TAG, VSOCK_CID_ANY_STR,
+};
+use server::{ExporterType, VsockType};
+use tracer::{
+    EXPORTER_FILE, EXPORTER_JAEGER, EXPORTER_OTLP, OTLP_PROTOCOL_GRPC, OTLP_PROTOCOL_HTTP,
 };
-use server::VsockType;
 
-fn announce(logger: &Logger, version: &str, dump_only: bool) {
+fn announce(logger: &Logger, version: &str, exporter: &str, dump_only: bool) {
     let commit = env::var("VERSION_COMMIT").map_or(String::new(), |s| s);
 
     info!(logger, "announce";
     "commit-version" => commit.as_str(),
     "version" =>  version,
+    "exporter" => exporter,
     "dump-only" => dump_only);
 }
 
@@ -108,10 +120,29 @@ fn make_examples_text(program_name: &str) -> String {
 
     $ sandbox_id={sandbox_id:?}
     $ sudo {program} --trace-name {trace_name:?} --socket-path /run/vc/firecracker/{sandbox_id}/root/kata.hvsock
+
+- Example exporting the spans to an OpenTelemetry collector using OTLP over gRPC:
+
+    $ {program} --exporter otlp --otlp-endpoint http://127.0.0.1:4317
+
+- Example writing the spans to a JSON lines file for offline analysis:
+
+    $ {program} --exporter file --file-path /var/log/kata-traces.json
+
+- Example assuming cloud-hypervisor is the Kata configured hypervisor
+  and the sandboxes _about_ to be created will be called {sandbox_id:?} and "bar":
+
+    $ sudo {program} --sandbox-id {sandbox_id} --sandbox-id bar --socket-path '/run/vc/vm/{id_tag}/clh.sock'
+
+- Example assuming QEMU is the Kata configured hypervisor and the sandboxes
+  {sandbox_id:?} and "bar" use VSOCK CIDs 3 and 4:
+
+    $ {program} --sandbox-id {sandbox_id}=3 --sandbox-id bar=4
   "#,
         program = program_name,
         trace_name = DEFAULT_TRACE_NAME,
-        sandbox_id = "foo"
+        sandbox_id = "foo",
+        id_tag = SANDBOX_ID_TAG
     )
 }
 
@@ -132,6 +163,94 @@ fn handle_standard_vsock(cid: Option<&str>, port: Option<&str>) -> Result<VsockT
     Ok(vsock)
 }
 
+fn make_exporter_type(args: &ArgMatches) -> Result<ExporterType> {
+    // Cannot fail as a default has been specified
+    let exporter = args.value_of("exporter").unwrap();
+
+    match exporter {
+        EXPORTER_OTLP => {
+            // Cannot fail as a default has been specified
+            let protocol = args.value_of("otlp-protocol").unwrap();
+
+            let default_endpoint = if protocol == OTLP_PROTOCOL_HTTP {
+                DEFAULT_OTLP_HTTP_ENDPOINT
+            } else {
+                DEFAULT_OTLP_GRPC_ENDPOINT
+            };
+
+            let endpoint = args.value_of("otlp-endpoint").unwrap_or(default_endpoint);
+
+            if endpoint.is_empty() {
+                return Err(anyhow!("OTLP endpoint cannot be blank"));
+            }
+
+            Ok(ExporterType::Otlp {
+                endpoint: endpoint.to_string(),
+                protocol: protocol.to_string(),
+            })
+        }
+        EXPORTER_FILE => {
+            let path = args
+                .value_of("file-path")
+                .ok_or("Need file path")
+                .map_err(|e| anyhow!(e))?;
+
+            if path.is_empty() {
+                return Err(anyhow!("File path cannot be blank"));
+            }
+
+            let max_size: u64 = args
+                .value_of("file-max-size")
+                .ok_or("Need file maximum size")
+                .map_err(|e| anyhow!(e))?
+                .parse()
+                .map_err(|e| anyhow!("File maximum size must be an integer: {:?}", e))?;
+
+            if max_size == 0 {
+                return Err(anyhow!("File maximum size cannot be zero"));
+            }
+
+            let max_files: usize = args
+                .value_of("file-max-files")
+                .ok_or("Need number of rotated files")
+                .map_err(|e| anyhow!(e))?
+                .parse()
+                .map_err(|e| anyhow!("Number of rotated files must be an integer: {:?}", e))?;
+
+            Ok(ExporterType::File {
+                path: path.to_string(),
+                max_size,
+                max_files,
+            })
+        }
+        _ => {
+            let jaeger_port: u32 = args
+                .value_of("jaeger-port")
+                .ok_or("Need Jaeger port number")
+                .map(|p| p.parse::<u32>().unwrap())
+                .map_err(|e| anyhow!("Jaeger port number must be an integer: {:?}", e))?;
+
+            if jaeger_port == 0 {
+                return Err(anyhow!("Jaeger port number cannot be zero"));
+            }
+
+            let jaeger_host = args
+                .value_of("jaeger-host")
+                .ok_or("Need Jaeger host")
+                .map_err(|e| anyhow!(e))?;
+
+            if jaeger_host.is_empty() {
+                return Err(anyhow!("Jaeger host cannot be blank"));
+            }
+
+            Ok(ExporterType::Jaeger {
+                host: jaeger_host.to_string(),
+                port: jaeger_port,
+            })
+        }
+    }
+}
+
 fn real_main() -> Result<()> {
     let version = crate_version!();
     let name = crate_name!();
@@ -155,6 +274,14 @@ fn real_main() -> Result<()> {
                 .takes_value(true)
                 .default_value(DEFAULT_TRACE_NAME),
         )
+        .arg(
+            Arg::with_name("exporter")
+                .long("exporter")
+                .help("Trace exporter to forward the spans to")
+                .possible_values(&[EXPORTER_JAEGER, EXPORTER_OTLP, EXPORTER_FILE])
+                .takes_value(true)
+                .default_value(EXPORTER_JAEGER),
+        )
         .arg(
             Arg::with_name("jaeger-host")
                 .long("jaeger-host")
@@ -169,6 +296,59 @@ fn real_main() -> Result<()> {
                 .takes_value(true)
                 .default_value(DEFAULT_JAEGER_PORT),
         )
+        .arg(
+            Arg::with_name("otlp-endpoint")
+                .long("otlp-endpoint")
+                .help(&format!(
+                    "OTLP collector endpoint (default: {:?} for gRPC, {:?} for HTTP)",
+                    DEFAULT_OTLP_GRPC_ENDPOINT, DEFAULT_OTLP_HTTP_ENDPOINT
+                ))
+                .takes_value(true)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("otlp-protocol")
+                .long("otlp-protocol")
+                .help("OTLP protocol")
+                .possible_values(&[OTLP_PROTOCOL_GRPC, OTLP_PROTOCOL_HTTP])
+                .takes_value(true)
+                .default_value(OTLP_PROTOCOL_GRPC),
+        )
+        .arg(
+            Arg::with_name("file-path")
+                .long("file-path")
+                .help("Path to the JSON lines file to write the spans to (file exporter only)")
+                .takes_value(true)
+                .required_if("exporter", EXPORTER_FILE),
+        )
+        .arg(
+            Arg::with_name("file-max-size")
+                .long("file-max-size")
+                .help("Size in bytes of the file of spans before it is rotated")
+                .takes_value(true)
+                .default_value(DEFAULT_FILE_MAX_SIZE),
+        )
+        .arg(
+            Arg::with_name("file-max-files")
+                .long("file-max-files")
+                .help("Number of rotated files of spans to keep")
+                .takes_value(true)
+                .default_value(DEFAULT_FILE_MAX_FILES),
+        )
+        .arg(
+            Arg::with_name("sandbox-id")
+                .long("sandbox-id")
+                .help(&format!(
+                    "ID of a sandbox to serve, added to the resource of its spans (may be repeated). \
+                    Use \"ID=CID\" to identify the sandbox by its VSOCK CID with standard VSOCK, \
+                    or the {} tag in the Hybrid VSOCK socket path",
+                    SANDBOX_ID_TAG
+                ))
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(false),
+        )
         .arg(
             Arg::with_name("log-level")
                 .long("log-level")
@@ -217,7 +397,10 @@ fn real_main() -> Result<()> {
 
     let dump_only = args.is_present("dump-only");
 
-    announce(&logger, version, dump_only);
+    // Cannot fail as a default has been specified
+    let exporter_name = args.value_of("exporter").unwrap();
+
+    announce(&logger, version, exporter_name, dump_only);
 
     let trace_name: &str = args
         .value_of("trace-name")
@@ -241,33 +424,16 @@ fn real_main() -> Result<()> {
         handle_standard_vsock(args.value_of("vsock-cid"), args.value_of("vsock-port"))
     }?;
 
-    let jaeger_port: u32 = args
-        .value_of("jaeger-port")
-        .ok_or("Need Jaeger port number")
-        .map(|p| p.parse::<u32>().unwrap())
-        .map_err(|e| anyhow!("Jaeger port number must be an integer: {:?}", e))?;
+    let sandboxes = args
+        .values_of("sandbox-id")
+        .map_or(Ok(vec![]), |ids| ids.map(str_to_sandbox_id).collect())?;
 
-    if jaeger_port == 0 {
-        return Err(anyhow!("Jaeger port number cannot be zero"));
-    }
-
-    let jaeger_host = args
-        .value_of("jaeger-host")
-        .ok_or("Need Jaeger host")
-        .map_err(|e| anyhow!(e))?;
+    check_sandbox_ids(args.value_of("socket-path"), &sandboxes)?;
 
-    if jaeger_host.is_empty() {
-        return Err(anyhow!("Jaeger host cannot be blank"));
-    }
+    let exporter = make_exporter_type(&args)?;
 
-    let server = server::VsockTraceServer::new(
-        &logger,
-        vsock,
-        jaeger_host,
-        jaeger_port,
-        trace_name,
-        dump_only,
-    );
+    let server =
+        server::VsockTraceServer::new(&logger, vsock, exporter, trace_name, sandboxes, dump_only);
 
     let result = server.start();
 
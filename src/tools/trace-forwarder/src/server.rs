@@ -4,12 +4,19 @@
 //
 
 use crate::handler;
+use crate::utils::{SandboxId, SANDBOX_ID_TAG};
 use anyhow::{anyhow, Result};
 use opentelemetry::sdk::export::trace::SpanExporter;
+use opentelemetry::sdk::Resource;
 use privdrop::PrivDrop;
-use slog::{debug, o, Logger};
-use std::os::unix::io::AsRawFd;
+use slog::{debug, error, o, Logger};
+use std::collections::HashMap;
+use std::os::unix::io::{IntoRawFd, RawFd};
 use std::os::unix::net::UnixListener;
+use std::sync::Arc;
+use std::thread;
+use tokio::runtime::Handle;
+use tokio::sync::Mutex;
 use vsock::{SockAddr, VsockListener};
 
 use crate::tracer;
@@ -20,19 +27,39 @@ pub const NON_PRIV_USER: &str = "nobody";
 
 const ROOT_DIR: &str = "/";
 
+// Exporter shared by the connections of all the sandboxes served.
+pub type SharedExporter = Arc<Mutex<Box<dyn SpanExporter>>>;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum VsockType {
     Standard { port: u32, cid: u32 },
     Hybrid { socket_path: String },
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExporterType {
+    Jaeger {
+        host: String,
+        port: u32,
+    },
+    Otlp {
+        endpoint: String,
+        protocol: String,
+    },
+    File {
+        path: String,
+        max_size: u64,
+        max_files: usize,
+    },
+}
+
 #[derive(Debug)]
 pub struct VsockTraceServer {
     pub vsock: VsockType,
+    pub exporter: ExporterType,
 
-    pub jaeger_host: String,
-    pub jaeger_port: u32,
-    pub jaeger_service_name: String,
+    pub service_name: String,
+    pub sandboxes: Vec<SandboxId>,
 
     pub logger: Logger,
     pub dump_only: bool,
@@ -42,50 +69,142 @@ impl VsockTraceServer {
     pub fn new(
         logger: &Logger,
         vsock: VsockType,
-        jaeger_host: &str,
-        jaeger_port: u32,
-        jaeger_service_name: &str,
+        exporter: ExporterType,
+        service_name: &str,
+        sandboxes: Vec<SandboxId>,
         dump_only: bool,
     ) -> Self {
         let logger = logger.new(o!("subsystem" => "server"));
 
         VsockTraceServer {
             vsock,
-            jaeger_host: jaeger_host.to_string(),
-            jaeger_port,
-            jaeger_service_name: jaeger_service_name.to_string(),
+            exporter,
+            service_name: service_name.to_string(),
+            sandboxes,
             logger,
             dump_only,
         }
     }
 
+    fn create_exporter(&self) -> Result<Box<dyn SpanExporter>> {
+        let exporter: Box<dyn SpanExporter> = match &self.exporter {
+            ExporterType::Jaeger { host, port } => Box::new(tracer::create_jaeger_trace_exporter(
+                self.service_name.clone(),
+                host.clone(),
+                *port,
+            )?),
+            ExporterType::Otlp { endpoint, protocol } => Box::new(
+                tracer::create_otlp_trace_exporter(endpoint.clone(), protocol)?,
+            ),
+            ExporterType::File {
+                path,
+                max_size,
+                max_files,
+            } => Box::new(tracer::create_file_trace_exporter(
+                path, *max_size, *max_files,
+            )?),
+        };
+
+        Ok(exporter)
+    }
+
     pub fn start(&self) -> Result<()> {
-        let result = tracer::create_jaeger_trace_exporter(
-            self.jaeger_service_name.clone(),
-            self.jaeger_host.clone(),
-            self.jaeger_port,
-        );
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+
+        let exporter = {
+            // The OTLP gRPC exporter spawns its connection task on the runtime.
+            let _guard = runtime.enter();
+
+            self.create_exporter()?
+        };
 
-        let mut exporter = result?;
+        let ctx = ConnectionContext {
+            logger: self.logger.clone(),
+            runtime: runtime.handle().clone(),
+            exporter: Arc::new(Mutex::new(exporter)),
+            dump_only: self.dump_only,
+        };
 
         match &self.vsock {
-            VsockType::Standard { port, cid } => start_std_vsock(
-                self.logger.clone(),
-                &mut exporter,
-                *port,
-                *cid,
-                self.dump_only,
-            ),
-            VsockType::Hybrid { socket_path } => start_hybrid_vsock(
-                self.logger.clone(),
-                &mut exporter,
-                socket_path,
-                self.dump_only,
-            ),
+            VsockType::Standard { port, cid } => {
+                let mut default_resource = None;
+                let mut cid_resources = HashMap::new();
+
+                for sandbox in self.sandboxes.iter() {
+                    let resource = Arc::new(tracer::create_span_resource(
+                        &self.service_name,
+                        Some(&sandbox.id),
+                    ));
+
+                    match sandbox.cid {
+                        Some(cid) => {
+                            cid_resources.insert(cid, resource);
+                        }
+                        None => default_resource = Some(resource),
+                    }
+                }
+
+                let default_resource = default_resource.unwrap_or_else(|| {
+                    Arc::new(tracer::create_span_resource(&self.service_name, None))
+                });
+
+                start_std_vsock(ctx, *port, *cid, default_resource, cid_resources)
+            }
+            VsockType::Hybrid { socket_path } => {
+                let mut sockets = Vec::new();
+
+                if self.sandboxes.is_empty() {
+                    let resource = tracer::create_span_resource(&self.service_name, None);
+                    sockets.push((socket_path.clone(), Arc::new(resource)));
+                }
+
+                for sandbox in self.sandboxes.iter() {
+                    let resource =
+                        tracer::create_span_resource(&self.service_name, Some(&sandbox.id));
+                    let path = socket_path.replace(SANDBOX_ID_TAG, &sandbox.id);
+                    sockets.push((path, Arc::new(resource)));
+                }
+
+                start_hybrid_vsock(ctx, sockets)
+            }
         }
     }
 }
 
+// Everything needed to handle a connection from an agent.
+#[derive(Clone)]
+struct ConnectionContext {
+    logger: Logger,
+    runtime: Handle,
+    exporter: SharedExporter,
+    dump_only: bool,
+}
+
+impl ConnectionContext {
+    // Handle the connection in its own thread, so that the agents of
+    // several sandboxes can send their spans at the same time.
+    fn spawn_handler(&self, logger: Logger, fd: RawFd, resource: Arc<Resource>) {
+        let ctx = self.clone();
+
+        thread::spawn(move || {
+            let result = handler::handle_connection(
+                logger.clone(),
+                &ctx.runtime,
+                fd,
+                ctx.exporter,
+                &resource,
+                ctx.dump_only,
+            );
+
+            if let Err(e) = result {
+                error!(logger, "failed to handle connection"; "error" => format!("{:?}", e));
+            }
+        });
+    }
+}
+
 fn drop_privs(logger: &Logger) -> Result<()> {
     debug!(logger, "Dropping privileges"; "new-user" => NON_PRIV_USER);
 
@@ -100,53 +219,69 @@ fn drop_privs(logger: &Logger) -> Result<()> {
     Ok(())
 }
 
-fn start_hybrid_vsock(
-    logger: Logger,
-    exporter: &mut dyn SpanExporter,
-    socket_path: &str,
-    dump_only: bool,
-) -> Result<()> {
-    let logger =
-        logger.new(o!("vsock-type" => "hybrid", "vsock-socket-path" => socket_path.to_string()));
-
+fn start_hybrid_vsock(ctx: ConnectionContext, sockets: Vec<(String, Arc<Resource>)>) -> Result<()> {
     let effective = nix::unistd::Uid::effective();
 
     if !effective.is_root() {
         return Err(anyhow!("You need to be root"));
     }
 
-    // Remove the socket if it already exists
-    let _ = std::fs::remove_file(socket_path);
+    let mut listeners = Vec::new();
 
-    let listener = UnixListener::bind(socket_path)?;
+    for (socket_path, resource) in sockets {
+        // Remove the socket if it already exists
+        let _ = std::fs::remove_file(&socket_path);
 
-    // Having bound to the socket, drop privileges
-    drop_privs(&logger)?;
+        let listener = UnixListener::bind(&socket_path)?;
 
-    debug!(logger, "Waiting for connections");
+        listeners.push((socket_path, listener, resource));
+    }
 
-    for conn in listener.incoming() {
-        let conn = conn?;
+    // Having bound to the sockets, drop privileges
+    drop_privs(&ctx.logger)?;
+
+    let mut threads = Vec::new();
+
+    for (socket_path, listener, resource) in listeners {
+        let ctx = ctx.clone();
 
-        let fd = conn.as_raw_fd();
+        threads.push(thread::spawn(move || -> Result<()> {
+            let logger = ctx
+                .logger
+                .new(o!("vsock-type" => "hybrid", "vsock-socket-path" => socket_path));
 
-        handler::handle_connection(logger.clone(), fd, exporter, dump_only)?;
+            debug!(logger, "Waiting for connections");
+
+            for conn in listener.incoming() {
+                let conn = conn?;
+
+                ctx.spawn_handler(logger.clone(), conn.into_raw_fd(), resource.clone());
+            }
+
+            Ok(())
+        }));
+    }
+
+    for thread in threads {
+        thread
+            .join()
+            .map_err(|e| anyhow!("listener thread panicked: {:?}", e))??;
     }
 
     Ok(())
 }
 
 fn start_std_vsock(
-    logger: Logger,
-    exporter: &mut dyn SpanExporter,
+    ctx: ConnectionContext,
     port: u32,
     cid: u32,
-    dump_only: bool,
+    default_resource: Arc<Resource>,
+    cid_resources: HashMap<u32, Arc<Resource>>,
 ) -> Result<()> {
     let sock_addr = SockAddr::new_vsock(cid, port);
     let listener = VsockListener::bind(&sock_addr)?;
 
-    debug!(logger, "Waiting for connections";
+    debug!(ctx.logger, "Waiting for connections";
         "vsock-type" => "standard",
         "vsock-cid" => cid,
         "vsock-port" => port);
@@ -154,9 +289,22 @@ fn start_std_vsock(
     for conn in listener.incoming() {
         let conn = conn?;
 
-        let fd = conn.as_raw_fd();
+        // Identify the sandbox by the CID of its VM
+        let peer_cid = match conn.peer_addr() {
+            Ok(SockAddr::Vsock(addr)) => Some(addr.cid()),
+            _ => None,
+        };
+
+        let resource = peer_cid
+            .and_then(|cid| cid_resources.get(&cid))
+            .unwrap_or(&default_resource)
+            .clone();
+
+        let logger = ctx
+            .logger
+            .new(o!("vsock-peer-cid" => format!("{:?}", peer_cid)));
 
-        handler::handle_connection(logger.clone(), fd, exporter, dump_only)?;
+        ctx.spawn_handler(logger, conn.into_raw_fd(), resource);
     }
 
     Ok(())
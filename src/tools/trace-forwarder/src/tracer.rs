@@ -3,8 +3,31 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+use async_trait::async_trait;
+use logging::FileRotator;
+use opentelemetry::sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry::sdk::export::ExportError;
+use opentelemetry::sdk::Resource;
 use opentelemetry::KeyValue;
+use opentelemetry_otlp::{HttpConfig, Protocol, TonicConfig, TraceExporter};
+use serde_json::{json, Map, Value};
+use std::io::Write;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const EXPORTER_JAEGER: &str = "jaeger";
+pub const EXPORTER_OTLP: &str = "otlp";
+pub const EXPORTER_FILE: &str = "file";
+
+pub const OTLP_PROTOCOL_GRPC: &str = "grpc";
+pub const OTLP_PROTOCOL_HTTP: &str = "http";
+
+// Resource attributes of the spans.
+pub const SERVICE_NAME_ATTRIBUTE: &str = "service.name";
+pub const SANDBOX_ID_ATTRIBUTE: &str = "kata.sandbox.id";
+
+const OTLP_EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub fn create_jaeger_trace_exporter(
     jaeger_service_name: String,
@@ -42,3 +65,298 @@ pub fn create_jaeger_trace_exporter(
 
     Ok(exporter)
 }
+
+// Note: the gRPC exporter must be created in the context of a tokio runtime.
+pub fn create_otlp_trace_exporter(
+    endpoint: String,
+    protocol: &str,
+) -> Result<TraceExporter, std::io::Error> {
+    let (protocol, use_http) = match protocol {
+        OTLP_PROTOCOL_GRPC => (Protocol::Grpc, false),
+        OTLP_PROTOCOL_HTTP => (Protocol::HttpBinary, true),
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid OTLP protocol: {:?}", protocol),
+            ))
+        }
+    };
+
+    let config = opentelemetry_otlp::ExporterConfig {
+        endpoint,
+        protocol,
+        timeout: OTLP_EXPORT_TIMEOUT,
+    };
+
+    let result = if use_http {
+        TraceExporter::new_http(config, HttpConfig::default())
+    } else {
+        TraceExporter::new_tonic(config, TonicConfig::default())
+    };
+
+    result.map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("failed to create exporter: {:?}", e.to_string()),
+        )
+    })
+}
+
+pub fn create_file_trace_exporter(
+    path: &str,
+    max_size: u64,
+    max_files: usize,
+) -> Result<FileExporter, std::io::Error> {
+    let mut writer = FileRotator::new(path)?;
+    writer
+        .truncate_mode(false)
+        .rotate_threshold(max_size)
+        .rotate_count(max_files);
+
+    Ok(FileExporter { writer })
+}
+
+// The resource of the spans of a sandbox, named after the traces.
+pub fn create_span_resource(service_name: &str, sandbox_id: Option<&str>) -> Resource {
+    let mut attributes = vec![KeyValue::new(
+        SERVICE_NAME_ATTRIBUTE,
+        service_name.to_string(),
+    )];
+
+    if let Some(sandbox_id) = sandbox_id {
+        attributes.push(KeyValue::new(SANDBOX_ID_ATTRIBUTE, sandbox_id.to_string()));
+    }
+
+    Resource::new(attributes)
+}
+
+// Merge the resource into the one of the span, replacing the attributes of
+// the same names.
+pub fn update_span_resource(span: &mut SpanData, resource: &Resource) {
+    let resource = match &span.resource {
+        Some(span_resource) => span_resource.merge(resource),
+        None => resource.clone(),
+    };
+
+    span.resource = Some(Arc::new(resource));
+}
+
+#[derive(Debug)]
+struct FileExportError(String);
+
+impl std::fmt::Display for FileExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for FileExportError {}
+
+impl ExportError for FileExportError {
+    fn exporter_name(&self) -> &'static str {
+        EXPORTER_FILE
+    }
+}
+
+/// Exporter writing the spans to a file as JSON lines, for offline analysis.
+///
+/// The file is rotated when it reaches its maximum size.
+#[derive(Debug)]
+pub struct FileExporter {
+    writer: FileRotator,
+}
+
+#[async_trait]
+impl SpanExporter for FileExporter {
+    async fn export(&mut self, batch: Vec<SpanData>) -> ExportResult {
+        for span in batch.iter() {
+            let line = format!("{}\n", span_to_json(span));
+
+            // Write a line at a time so a span is never split across rotated files.
+            self.writer
+                .write_all(line.as_bytes())
+                .and_then(|_| self.writer.flush())
+                .map_err(|e| FileExportError(format!("failed to write span: {:?}", e)))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn unix_time_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+fn key_values_to_json<'a, I>(key_values: I) -> Value
+where
+    I: Iterator<Item = (&'a opentelemetry::Key, &'a opentelemetry::Value)>,
+{
+    let map: Map<String, Value> = key_values
+        .map(|(k, v)| (k.as_str().to_string(), Value::from(v.as_str().to_string())))
+        .collect();
+
+    Value::Object(map)
+}
+
+fn span_to_json(span: &SpanData) -> Value {
+    let events: Vec<Value> = span
+        .events
+        .iter()
+        .map(|event| {
+            let attributes = event.attributes.iter().map(|kv| (&kv.key, &kv.value));
+
+            json!({
+                "name": event.name.to_string(),
+                "time_unix_nano": unix_time_nanos(event.timestamp),
+                "attributes": key_values_to_json(attributes),
+            })
+        })
+        .collect();
+
+    let resource = match &span.resource {
+        Some(resource) => key_values_to_json(resource.iter()),
+        None => json!({}),
+    };
+
+    json!({
+        "trace_id": span.span_context.trace_id().to_hex(),
+        "span_id": span.span_context.span_id().to_hex(),
+        "parent_span_id": span.parent_span_id.to_hex(),
+        "name": span.name.to_string(),
+        "kind": format!("{:?}", span.span_kind),
+        "start_time_unix_nano": unix_time_nanos(span.start_time),
+        "end_time_unix_nano": unix_time_nanos(span.end_time),
+        "attributes": key_values_to_json(span.attributes.iter()),
+        "events": events,
+        "status_code": format!("{:?}", span.status_code),
+        "status_message": span.status_message.to_string(),
+        "resource": resource,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::sdk::trace::{EvictedHashMap, EvictedQueue};
+    use opentelemetry::sdk::InstrumentationLibrary;
+    use opentelemetry::trace::{
+        Event, SpanContext, SpanId, SpanKind, StatusCode, TraceId, TraceState,
+    };
+    use std::fs;
+
+    fn test_span(name: &'static str) -> SpanData {
+        let start_time = UNIX_EPOCH + Duration::from_secs(1);
+
+        let mut attributes = EvictedHashMap::new(8, 8);
+        attributes.insert(KeyValue::new("container_id", "c1"));
+
+        let mut events = EvictedQueue::new(8);
+        events.append_vec(&mut vec![Event::new(
+            "started",
+            start_time,
+            vec![KeyValue::new("pid", "42")],
+            0,
+        )]);
+
+        SpanData {
+            span_context: SpanContext::new(
+                TraceId::from_u128(1),
+                SpanId::from_u64(2),
+                0,
+                false,
+                TraceState::default(),
+            ),
+            parent_span_id: SpanId::from_u64(3),
+            span_kind: SpanKind::Server,
+            name: name.into(),
+            start_time,
+            end_time: start_time + Duration::from_secs(1),
+            attributes,
+            events,
+            links: EvictedQueue::new(8),
+            status_code: StatusCode::Ok,
+            status_message: "".into(),
+            resource: Some(Arc::new(create_span_resource("kata-agent", Some("sb1")))),
+            instrumentation_lib: InstrumentationLibrary::default(),
+        }
+    }
+
+    #[test]
+    fn test_span_to_json() {
+        let json = span_to_json(&test_span("create_container"));
+
+        assert_eq!(json["trace_id"], TraceId::from_u128(1).to_hex());
+        assert_eq!(json["span_id"], SpanId::from_u64(2).to_hex());
+        assert_eq!(json["parent_span_id"], SpanId::from_u64(3).to_hex());
+        assert_eq!(json["name"], "create_container");
+        assert_eq!(json["kind"], "Server");
+        assert_eq!(json["start_time_unix_nano"], 1_000_000_000u64);
+        assert_eq!(json["end_time_unix_nano"], 2_000_000_000u64);
+        assert_eq!(json["attributes"]["container_id"], "c1");
+        assert_eq!(json["events"][0]["name"], "started");
+        assert_eq!(json["events"][0]["time_unix_nano"], 1_000_000_000u64);
+        assert_eq!(json["events"][0]["attributes"]["pid"], "42");
+        assert_eq!(json["resource"][SERVICE_NAME_ATTRIBUTE], "kata-agent");
+        assert_eq!(json["resource"][SANDBOX_ID_ATTRIBUTE], "sb1");
+    }
+
+    #[test]
+    fn test_update_span_resource() {
+        let mut span = test_span("create_container");
+        update_span_resource(&mut span, &create_span_resource("kata-runtime", None));
+
+        let json = span_to_json(&span);
+        assert_eq!(json["resource"][SERVICE_NAME_ATTRIBUTE], "kata-runtime");
+        assert_eq!(json["resource"][SANDBOX_ID_ATTRIBUTE], "sb1");
+    }
+
+    #[test]
+    fn test_file_exporter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spans.json");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        // The file is rotated after each span, keeping one rotated file.
+        let mut exporter = create_file_trace_exporter(path.to_str().unwrap(), 1, 1).unwrap();
+        for name in ["first", "second", "third"] {
+            rt.block_on(exporter.export(vec![test_span(name)])).unwrap();
+        }
+
+        let rotated = fs::read_to_string(dir.path().join("spans.json.1")).unwrap();
+        let lines: Vec<&str> = rotated.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let json: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(json["name"], "third");
+        assert!(!dir.path().join("spans.json.2").exists());
+
+        // All the spans are in the file without rotation.
+        let path = dir.path().join("all.json");
+        let mut exporter = create_file_trace_exporter(path.to_str().unwrap(), 1 << 20, 1).unwrap();
+        rt.block_on(exporter.export(vec![test_span("first"), test_span("second")]))
+            .unwrap();
+
+        let names: Vec<String> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap()["name"].to_string())
+            .collect();
+        assert_eq!(names, vec!["\"first\"", "\"second\""]);
+    }
+
+    #[test]
+    fn test_create_otlp_trace_exporter() {
+        let endpoint = "http://127.0.0.1:4317".to_string();
+
+        let err = create_otlp_trace_exporter(endpoint.clone(), "udp").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+        create_otlp_trace_exporter(endpoint.clone(), OTLP_PROTOCOL_HTTP).unwrap();
+
+        // The gRPC exporter connects lazily, in the tokio runtime.
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _guard = rt.enter();
+        create_otlp_trace_exporter(endpoint, OTLP_PROTOCOL_GRPC).unwrap();
+    }
+}
@@ -19,6 +19,28 @@ pub const ERR_VSOCK_CID_NOT_NUMERIC: &str = "VSOCK CID must be an integer";
 
 pub const ERR_HVSOCK_SOC_PATH_EMPTY: &str = "Hybrid VSOCK socket path cannot be empty";
 
+pub const ERR_SANDBOX_ID_EMPTY: &str = "sandbox ID cannot be empty";
+pub const ERR_SANDBOX_CID_NOT_NUMERIC: &str = "sandbox VSOCK CID must be an integer";
+pub const ERR_SANDBOX_ID_TAG_MISSING: &str =
+    "Hybrid VSOCK socket path must contain the {ID} tag to serve several sandboxes";
+pub const ERR_SANDBOX_ID_REQUIRED: &str =
+    "sandbox ID must be specified when the Hybrid VSOCK socket path contains the {ID} tag";
+pub const ERR_SANDBOX_CID_HYBRID: &str = "sandbox VSOCK CID cannot be used with Hybrid VSOCK";
+pub const ERR_SANDBOX_CID_REQUIRED: &str =
+    "sandbox VSOCK CID must be specified to serve several sandboxes";
+pub const ERR_SANDBOX_CID_DUPLICATE: &str = "sandbox VSOCK CID cannot be used more than once";
+
+// Tag replaced by the sandbox ID in the Hybrid VSOCK socket path
+pub const SANDBOX_ID_TAG: &str = "{ID}";
+
+// Sandbox the trace spans are received from, identified by the VSOCK CID of
+// its VM when several sandboxes are served using standard VSOCK.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxId {
+    pub id: String,
+    pub cid: Option<u32>,
+}
+
 // Parameters:
 //
 // 1: expected Result
@@ -120,6 +142,65 @@ fn port_str_to_port(port: &str) -> Result<u32> {
     Ok(port)
 }
 
+// Convert a sandbox string, either "<ID>" or "<ID>=<CID>", to a sandbox ID.
+pub fn str_to_sandbox_id(sandbox: &str) -> Result<SandboxId> {
+    let (id, cid) = match sandbox.split_once('=') {
+        Some((id, cid)) => {
+            let cid = cid
+                .parse::<u32>()
+                .map_err(|_| anyhow!(ERR_SANDBOX_CID_NOT_NUMERIC))?;
+
+            (id, Some(cid))
+        }
+        None => (sandbox, None),
+    };
+
+    if id.is_empty() {
+        return Err(anyhow!(ERR_SANDBOX_ID_EMPTY));
+    }
+
+    Ok(SandboxId {
+        id: id.to_string(),
+        cid,
+    })
+}
+
+// Check the sandboxes can be told apart: by their socket path with Hybrid
+// VSOCK, or by their VSOCK CID with standard VSOCK (where a sandbox without a
+// CID matches any other CID).
+pub fn check_sandbox_ids(hybrid_socket_path: Option<&str>, sandboxes: &[SandboxId]) -> Result<()> {
+    if let Some(socket_path) = hybrid_socket_path {
+        if sandboxes.iter().any(|s| s.cid.is_some()) {
+            return Err(anyhow!(ERR_SANDBOX_CID_HYBRID));
+        }
+
+        if socket_path.contains(SANDBOX_ID_TAG) {
+            if sandboxes.is_empty() {
+                return Err(anyhow!(ERR_SANDBOX_ID_REQUIRED));
+            }
+        } else if sandboxes.len() > 1 {
+            return Err(anyhow!(ERR_SANDBOX_ID_TAG_MISSING));
+        }
+
+        return Ok(());
+    }
+
+    if sandboxes.iter().filter(|s| s.cid.is_none()).count() > 1 {
+        return Err(anyhow!(ERR_SANDBOX_CID_REQUIRED));
+    }
+
+    let mut cids: Vec<u32> = sandboxes.iter().filter_map(|s| s.cid).collect();
+    let count = cids.len();
+    cids.sort_unstable();
+    cids.dedup();
+
+    if cids.len() != count {
+        return Err(anyhow!(ERR_SANDBOX_CID_DUPLICATE));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -354,4 +435,135 @@ mod tests {
             assert_result!(d.result, result, msg);
         }
     }
+
+    #[test]
+    fn test_str_to_sandbox_id() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            sandbox: &'a str,
+            result: Result<SandboxId>,
+        }
+
+        let tests = &[
+            TestData {
+                sandbox: "",
+                result: Err(anyhow!(ERR_SANDBOX_ID_EMPTY)),
+            },
+            TestData {
+                sandbox: "=3",
+                result: Err(anyhow!(ERR_SANDBOX_ID_EMPTY)),
+            },
+            TestData {
+                sandbox: "foo=",
+                result: Err(anyhow!(ERR_SANDBOX_CID_NOT_NUMERIC)),
+            },
+            TestData {
+                sandbox: "foo=bar",
+                result: Err(anyhow!(ERR_SANDBOX_CID_NOT_NUMERIC)),
+            },
+            TestData {
+                sandbox: "foo",
+                result: Ok(SandboxId {
+                    id: "foo".into(),
+                    cid: None,
+                }),
+            },
+            TestData {
+                sandbox: "foo=3",
+                result: Ok(SandboxId {
+                    id: "foo".into(),
+                    cid: Some(3),
+                }),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let msg = format!("test[{}]: {:?}", i, d);
+
+            let result = str_to_sandbox_id(d.sandbox);
+
+            let msg = format!("{}: result: {:?}", msg, result);
+
+            assert_result!(d.result, result, msg);
+        }
+    }
+
+    #[test]
+    fn test_check_sandbox_ids() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            socket_path: Option<&'a str>,
+            sandboxes: Vec<&'a str>,
+            result: Result<()>,
+        }
+
+        let tests = &[
+            TestData {
+                socket_path: None,
+                sandboxes: vec![],
+                result: Ok(()),
+            },
+            TestData {
+                socket_path: None,
+                sandboxes: vec!["foo", "bar=3"],
+                result: Ok(()),
+            },
+            TestData {
+                socket_path: None,
+                sandboxes: vec!["foo", "bar"],
+                result: Err(anyhow!(ERR_SANDBOX_CID_REQUIRED)),
+            },
+            TestData {
+                socket_path: None,
+                sandboxes: vec!["foo=3", "bar=3"],
+                result: Err(anyhow!(ERR_SANDBOX_CID_DUPLICATE)),
+            },
+            TestData {
+                socket_path: Some("/foo"),
+                sandboxes: vec![],
+                result: Ok(()),
+            },
+            TestData {
+                socket_path: Some("/foo"),
+                sandboxes: vec!["foo"],
+                result: Ok(()),
+            },
+            TestData {
+                socket_path: Some("/foo"),
+                sandboxes: vec!["foo", "bar"],
+                result: Err(anyhow!(ERR_SANDBOX_ID_TAG_MISSING)),
+            },
+            TestData {
+                socket_path: Some("/foo/{ID}/bar"),
+                sandboxes: vec![],
+                result: Err(anyhow!(ERR_SANDBOX_ID_REQUIRED)),
+            },
+            TestData {
+                socket_path: Some("/foo/{ID}/bar"),
+                sandboxes: vec!["foo", "bar"],
+                result: Ok(()),
+            },
+            TestData {
+                socket_path: Some("/foo/{ID}/bar"),
+                sandboxes: vec!["foo=3"],
+                result: Err(anyhow!(ERR_SANDBOX_CID_HYBRID)),
+            },
+        ];
+
+        for (i, d) in tests.iter().enumerate() {
+            let msg = format!("test[{}]: {:?}", i, d);
+
+            let sandboxes: Vec<SandboxId> = d
+                .sandboxes
+                .iter()
+                .map(|s| str_to_sandbox_id(s).unwrap())
+                .collect();
+
+            let result = check_sandbox_ids(d.socket_path, &sandboxes);
+
+            let msg = format!("{}: result: {:?}", msg, result);
+
+            assert_result!(d.result, result, msg);
+        }
+    }
 }